@@ -30,8 +30,48 @@ pub const LUA_OPEQ: u8 = 0; // ==
 pub const LUA_OPLT: u8 = 1; // <
 pub const LUA_OPLE: u8 = 2; // <=
 
+/* thread status */
+pub const LUA_OK: i32 = 0;
+pub const LUA_YIELD: i32 = 1;
+pub const LUA_ERRRUN: i32 = 2;
+pub const LUA_ERRSYNTAX: i32 = 3;
+pub const LUA_ERRMEM: i32 = 4;
+pub const LUA_ERRGCMM: i32 = 5;
+pub const LUA_ERRERR: i32 = 6;
+pub const LUA_ERRFILE: i32 = 7;
+
+/* garbage-collection options */
+pub const LUA_GCSTOP: i32 = 0;
+pub const LUA_GCRESTART: i32 = 1;
+pub const LUA_GCCOLLECT: i32 = 2;
+pub const LUA_GCCOUNT: i32 = 3;
+pub const LUA_GCCOUNTB: i32 = 4;
+pub const LUA_GCSTEP: i32 = 5;
+pub const LUA_GCSETPAUSE: i32 = 6;
+pub const LUA_GCSETSTEPMUL: i32 = 7;
+pub const LUA_GCISRUNNING: i32 = 9;
+
+/* event codes of hooks */
+pub const LUA_HOOKCALL: i32 = 0;
+pub const LUA_HOOKRET: i32 = 1;
+pub const LUA_HOOKLINE: i32 = 2;
+pub const LUA_HOOKCOUNT: i32 = 3;
+pub const LUA_HOOKTAILCALL: i32 = 4;
+
+/* event masks of hooks */
+pub const LUA_MASKCALL: i32 = 1 << LUA_HOOKCALL;
+pub const LUA_MASKRET: i32 = 1 << LUA_HOOKRET;
+pub const LUA_MASKLINE: i32 = 1 << LUA_HOOKLINE;
+pub const LUA_MASKCOUNT: i32 = 1 << LUA_HOOKCOUNT;
+
 /* registry list */
 pub const LUA_MINSTACK: i64 = 20;
 pub const LUAI_MAXSTACK: i64 = 1_000_000;
 pub const LUA_REGISTRYINDEX: i64 = -LUAI_MAXSTACK - 1000;
-pub const LUA_RIDX_GLOBALS: i64 = 2;
\ No newline at end of file
+pub const LUA_RIDX_MAINTHREAD: i64 = 1;
+pub const LUA_RIDX_GLOBALS: i64 = 2;
+
+/* maximum depth of nested calls through the Rust stack */
+pub const LUAI_MAXCCALLS: usize = 200;
+/* maximum number of call frames of a thread */
+pub const LUAI_MAXFRAMES: usize = 100_000;
\ No newline at end of file
@@ -0,0 +1,46 @@
+use std::{any::Any, cell::RefCell, rc::Rc};
+use crate::state::{lua_thread::LuaThread, lua_userdata::UserDataRef};
+use crate::stdlib::os_host::OsHost;
+use super::lua_state::{LuaAPI, RustFn};
+
+pub type FuncReg = (&'static str, RustFn);
+
+pub trait LuaAuxLib: LuaAPI {
+    /* error-report functions */
+    fn Error2(&mut self, msg: String) -> i32;
+    fn ArgError(&mut self, arg: i32, extraMsg: &str) -> i32;
+    fn Where(&mut self, level: i32);
+    /* argument check functions */
+    fn ArgCheck(&mut self, cond: bool, arg: i32, extraMsg: &str);
+    fn CheckAny(&mut self, arg: i32);
+    fn CheckType(&mut self, arg: i32, t: i8);
+    fn CheckInteger(&mut self, arg: i32) -> i64;
+    fn CheckNumber(&mut self, arg: i32) -> f64;
+    fn OptNumber(&mut self, arg: i32, def: f64) -> f64;
+    fn OptInteger(&mut self, arg: i32, def: i64) -> i64;
+    fn CheckString(&mut self, arg: i32) -> String;
+    fn CheckBytes(&mut self, arg: i32) -> Vec<u8>;
+    fn OptString(&mut self, arg: i32, def: &str) -> String;
+    fn CheckOption(&mut self, arg: i32, def: Option<&str>, lst: &[&str]) -> usize;
+    fn CheckUData<T: Any>(&mut self, arg: i32, tname: &'static str) -> UserDataRef<T> where Self: Sized;
+    fn TestUData<T: Any>(&mut self, arg: i32, tname: &'static str) -> Option<UserDataRef<T>> where Self: Sized;
+    /* metatables of userdata types */
+    fn NewMetatable(&mut self, tname: &'static str) -> bool;
+    fn GetMetatableByName(&mut self, tname: &'static str) -> i8;
+    fn SetMetatableByName(&mut self, tname: &'static str);
+    /* other functions */
+    fn GetMetafield(&mut self, obj: i32, e: &'static str) -> i8;
+    fn CallMeta(&mut self, obj: i32, e: &'static str) -> bool;
+    fn ToString2(&mut self, idx: i32) -> String;
+    fn TypeName2(&self, idx: i32) -> &'static str;
+    fn Len2(&mut self, idx: i32) -> i64;
+    fn Traceback(&mut self, co: Option<Rc<RefCell<LuaThread>>>, msg: Option<&str>, level: i32);
+    fn GetSubTable(&mut self, idx: i32, fname: &'static str) -> bool;
+    fn RequireF(&mut self, modname: &'static str, openf: RustFn, glb: bool);
+    fn NewLib(&mut self, l: &[FuncReg]);
+    fn SetFuncs(&mut self, l: &[FuncReg], nup: i32);
+    fn LoadFileX(&mut self, filename: Option<&str>, mode: &str) -> i32;
+    fn RegisterModule(&mut self, modname: &str, openf: RustFn);
+    fn SetOsHost(&mut self, host: Box<dyn OsHost>);
+    fn OpenLibs(&mut self);
+}
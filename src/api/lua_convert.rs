@@ -0,0 +1,484 @@
+use std::{collections::HashMap, hash::Hash};
+use crate::state::{lua_state::LuaState, lua_value::LuaValue};
+use super::{consts::*, lua_auxlib::LuaAuxLib, lua_state::{LuaAPI, LuaError}};
+
+// Typed access to Lua values from Rust, on top of the stack API:
+//
+//     ls.globals().set("limit", 10)?;
+//     let f: LuaFunction = ls.globals().get("clamp")?;
+//     let (lo, hi) = ls.call::<_, (i64, i64)>(&f, (3, vec![1, 20]))?;
+//
+// Conversions from Lua fail with a message like the one of a bad
+// argument, e.g. "integer expected, got table".
+
+// A Rust value that can be pushed on the stack as one Lua value.
+pub trait IntoLua {
+    fn into_lua(self, ls: &mut LuaState);
+}
+
+// A Rust value that can be read from one Lua value on the stack.
+pub trait FromLua: Sized {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError>;
+}
+
+// A list of values, like the arguments of a call: a single value, a
+// tuple or `()`.
+pub trait IntoLuaMulti {
+    const COUNT: i32;
+    // Pushes the values, returns how many.
+    fn into_lua_multi(self, ls: &mut LuaState) -> i32;
+}
+
+// A list of values read from `COUNT` slots starting at `first`, like the
+// results of a call.
+pub trait FromLuaMulti: Sized {
+    const COUNT: i32;
+    fn from_lua_multi(ls: &mut LuaState, first: i32) -> Result<Self, LuaError>;
+}
+
+fn typeError(ls: &LuaState, idx: i32, expected: &str) -> LuaError {
+    LuaError::from(format!("{} expected, got {}", expected, ls.TypeName2(idx)))
+}
+
+/* primitives */
+
+impl IntoLua for bool {
+    fn into_lua(self, ls: &mut LuaState) {
+        ls.CheckStack(1);
+        ls.PushBoolean(self);
+    }
+}
+
+// Any value converts to a boolean, as in a condition.
+impl FromLua for bool {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+        Ok(ls.ToBoolean(idx))
+    }
+}
+
+macro_rules! integer_conv {
+    ($($t:ty),*) => {$(
+        // Integers too large for a Lua integer are pushed as floats.
+        impl IntoLua for $t {
+            fn into_lua(self, ls: &mut LuaState) {
+                ls.CheckStack(1);
+                match i64::try_from(self) {
+                    Ok(i) => ls.PushInteger(i),
+                    Err(_) => ls.PushNumber(self as f64),
+                }
+            }
+        }
+
+        impl FromLua for $t {
+            fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+                match ls.ToIntegerX(idx) {
+                    Some(i) => <$t>::try_from(i).map_err(|_| {
+                        LuaError::from(format!("integer {} out of range for {}", i, stringify!($t)))
+                    }),
+                    None if ls.IsNumber(idx) => Err(LuaError::from("number has no integer representation")),
+                    None => Err(typeError(ls, idx, "integer")),
+                }
+            }
+        }
+    )*};
+}
+
+integer_conv!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! float_conv {
+    ($($t:ty),*) => {$(
+        impl IntoLua for $t {
+            fn into_lua(self, ls: &mut LuaState) {
+                ls.CheckStack(1);
+                ls.PushNumber(self as f64);
+            }
+        }
+
+        impl FromLua for $t {
+            fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+                match ls.ToNumberX(idx) {
+                    Some(n) => Ok(n as $t),
+                    None => Err(typeError(ls, idx, "number")),
+                }
+            }
+        }
+    )*};
+}
+
+float_conv!(f32, f64);
+
+impl IntoLua for String {
+    fn into_lua(self, ls: &mut LuaState) {
+        ls.CheckStack(1);
+        ls.PushString(self);
+    }
+}
+
+impl IntoLua for &str {
+    fn into_lua(self, ls: &mut LuaState) {
+        String::from(self).into_lua(ls);
+    }
+}
+
+// Strings and numbers, which are converted; the bytes must be UTF-8.
+impl FromLua for String {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+        let t = ls.Type(idx);
+        if t != LUA_TSTRING && t != LUA_TNUMBER {
+            return Err(typeError(ls, idx, "string"));
+        }
+        String::from_utf8(ls.ToBytes(idx)).map_err(|_| LuaError::from("string is not valid UTF-8"))
+    }
+}
+
+/* any value, and functions to call */
+
+impl IntoLua for LuaValue {
+    fn into_lua(self, ls: &mut LuaState) {
+        ls.CheckStack(1);
+        ls.stack_mut().push(self);
+    }
+}
+
+impl FromLua for LuaValue {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+        Ok(ls.stack().get(idx))
+    }
+}
+
+// A Lua or Rust function held by the host, see `LuaState::call`.
+#[derive(Clone)]
+pub struct LuaFunction(LuaValue);
+
+impl IntoLua for LuaFunction {
+    fn into_lua(self, ls: &mut LuaState) {
+        self.0.into_lua(ls);
+    }
+}
+
+impl FromLua for LuaFunction {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+        if !ls.IsFunction(idx) {
+            return Err(typeError(ls, idx, "function"));
+        }
+        Ok(LuaFunction(ls.stack().get(idx)))
+    }
+}
+
+/* containers */
+
+// None is nil.
+impl<T: IntoLua> IntoLua for Option<T> {
+    fn into_lua(self, ls: &mut LuaState) {
+        match self {
+            Some(v) => v.into_lua(ls),
+            None => {
+                ls.CheckStack(1);
+                ls.PushNil();
+            },
+        }
+    }
+}
+
+impl<T: FromLua> FromLua for Option<T> {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+        if ls.IsNoneOrNil(idx) {
+            Ok(None)
+        } else {
+            T::from_lua(ls, idx).map(Some)
+        }
+    }
+}
+
+// A sequence.
+impl<T: IntoLua> IntoLua for Vec<T> {
+    fn into_lua(self, ls: &mut LuaState) {
+        ls.CheckStack(2);
+        ls.CreateTable(self.len() as i32, 0);
+        for (i, v) in self.into_iter().enumerate() {
+            v.into_lua(ls);
+            ls.RawSetI(-2, i as i64 + 1);
+        }
+    }
+}
+
+// The elements 1..#t of a table.
+impl<T: FromLua> FromLua for Vec<T> {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+        let idx = ls.AbsIndex(idx);
+        if !ls.IsTable(idx) {
+            return Err(typeError(ls, idx, "table"));
+        }
+        let n = ls.RawLen(idx) as i64;
+        let mut vec = Vec::with_capacity(n as usize);
+        ls.CheckStack(1);
+        for i in 1..=n {
+            ls.RawGetI(idx, i);
+            let v = T::from_lua(ls, -1);
+            ls.pop(1);
+            vec.push(v?);
+        }
+        Ok(vec)
+    }
+}
+
+impl<K: IntoLua, V: IntoLua> IntoLua for HashMap<K, V> {
+    fn into_lua(self, ls: &mut LuaState) {
+        ls.CheckStack(3);
+        ls.CreateTable(0, self.len() as i32);
+        for (k, v) in self {
+            k.into_lua(ls);
+            v.into_lua(ls);
+            ls.RawSet(-3);
+        }
+    }
+}
+
+impl<K: FromLua + Eq + Hash, V: FromLua> FromLua for HashMap<K, V> {
+    fn from_lua(ls: &mut LuaState, idx: i32) -> Result<Self, LuaError> {
+        let idx = ls.AbsIndex(idx);
+        if !ls.IsTable(idx) {
+            return Err(typeError(ls, idx, "table"));
+        }
+        let mut map = HashMap::new();
+        ls.CheckStack(2);
+        ls.PushNil();           /* first key */
+        while ls.Next(idx) {
+            let kv = K::from_lua(ls, -2).and_then(|k| Ok((k, V::from_lua(ls, -1)?)));
+            ls.pop(1);          /* keep key for next iteration */
+            match kv {
+                Ok((k, v)) => map.insert(k, v),
+                Err(e) => {
+                    ls.pop(1);  /* remove key */
+                    return Err(e);
+                },
+            };
+        }
+        Ok(map)
+    }
+}
+
+/* lists of values */
+
+impl<T: IntoLua> IntoLuaMulti for T {
+    const COUNT: i32 = 1;
+    fn into_lua_multi(self, ls: &mut LuaState) -> i32 {
+        self.into_lua(ls);
+        1
+    }
+}
+
+impl<T: FromLua> FromLuaMulti for T {
+    const COUNT: i32 = 1;
+    fn from_lua_multi(ls: &mut LuaState, first: i32) -> Result<Self, LuaError> {
+        T::from_lua(ls, first)
+    }
+}
+
+macro_rules! tuple_conv {
+    ($n:expr; $($name:ident $i:expr),*) => {
+        impl<$($name: IntoLua),*> IntoLuaMulti for ($($name,)*) {
+            const COUNT: i32 = $n;
+            #[allow(non_snake_case, unused_variables)]
+            fn into_lua_multi(self, ls: &mut LuaState) -> i32 {
+                let ($($name,)*) = self;
+                $($name.into_lua(ls);)*
+                $n
+            }
+        }
+
+        impl<$($name: FromLua),*> FromLuaMulti for ($($name,)*) {
+            const COUNT: i32 = $n;
+            #[allow(unused_variables)]
+            fn from_lua_multi(ls: &mut LuaState, first: i32) -> Result<Self, LuaError> {
+                Ok(($($name::from_lua(ls, first + $i)?,)*))
+            }
+        }
+    };
+}
+
+tuple_conv!(0;);
+tuple_conv!(1; A 0);
+tuple_conv!(2; A 0, B 1);
+tuple_conv!(3; A 0, B 1, C 2);
+tuple_conv!(4; A 0, B 1, C 2, D 3);
+tuple_conv!(5; A 0, B 1, C 2, D 3, E 4);
+tuple_conv!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_conv!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_conv!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/* globals and calls */
+
+// The global table, as given by `LuaState::globals`.
+pub struct Globals<'a> {
+    ls: &'a mut LuaState,
+}
+
+impl Globals<'_> {
+    // The global `name`, with the `__index` metamethod of the table. An
+    // error raised by the metamethod is returned.
+    pub fn get<T: FromLua>(&mut self, name: &str) -> Result<T, LuaError> {
+        let ls = &mut *self.ls;
+        let base = ls.GetTop();
+        ls.CheckStack(3);
+        ls.PushRustFunction(|ls| { ls.GetTable(1); 1 });
+        ls.PushGlobalTable();
+        ls.PushString(String::from(name));
+        pcall(ls, base, 2, 1)?;
+        let v = T::from_lua(ls, -1);
+        ls.SetTop(base);
+        v
+    }
+
+    // Sets the global `name`, with the `__newindex` metamethod of the table.
+    pub fn set<T: IntoLua>(&mut self, name: &str, v: T) -> Result<(), LuaError> {
+        let ls = &mut *self.ls;
+        let base = ls.GetTop();
+        ls.CheckStack(4);
+        ls.PushRustFunction(|ls| { ls.SetTable(1); 0 });
+        ls.PushGlobalTable();
+        ls.PushString(String::from(name));
+        v.into_lua(ls);
+        pcall(ls, base, 3, 0)?;
+        ls.SetTop(base);
+        Ok(())
+    }
+}
+
+// Calls the function below the `nargs` arguments in protected mode. If it
+// fails, the stack is set back to `base` and the error object returned.
+fn pcall(ls: &mut LuaState, base: i32, nargs: i32, nresults: i32) -> Result<(), LuaError> {
+    let status = ls.PCall(nargs, nresults, 0);
+    if status != LUA_OK {
+        let value = ls.stack().get(-1);
+        ls.SetTop(base);
+        return Err(LuaError { status, value });
+    }
+    Ok(())
+}
+
+impl LuaState {
+    pub fn globals(&mut self) -> Globals<'_> {
+        Globals { ls: self }
+    }
+
+    // Calls `f` in protected mode. Missing results are nil, extra ones
+    // are dropped; the stack is left as it was.
+    pub fn call<A: IntoLuaMulti, R: FromLuaMulti>(&mut self, f: &LuaFunction, args: A) -> Result<R, LuaError> {
+        let base = self.GetTop();
+        self.CheckStack(1 + A::COUNT);
+        f.clone().into_lua(self);
+        let nargs = args.into_lua_multi(self);
+        pcall(self, base, nargs, -1)?;
+        let nres = self.GetTop() - base;
+        self.CheckStack(R::COUNT);
+        for _ in nres..R::COUNT {
+            self.PushNil();
+        }
+        let res = R::from_lua_multi(self, base + 1);
+        self.SetTop(base);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+    use crate::state::lua_state::LuaState;
+    use super::LuaFunction;
+
+    fn newState(src: &str) -> LuaState {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        ls.Load(src.as_bytes().to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_OK);
+        ls
+    }
+
+    #[test]
+    fn test_globals() {
+        let mut ls = newState("n, f, s, t, m, mixed = 42, 2.5, 'héllo', {1, 2, 3}, {a = 1, b = 2}, {1, 'x'}; big = 1000");
+        let mut g = ls.globals();
+        assert_eq!(g.get::<i64>("n"), Ok(42));
+        assert_eq!(g.get::<u8>("n"), Ok(42));
+        assert_eq!(g.get::<f64>("f"), Ok(2.5));
+        assert_eq!(g.get::<f64>("n"), Ok(42.0));
+        assert_eq!(g.get::<String>("s"), Ok(String::from("héllo")));
+        assert_eq!(g.get::<String>("n"), Ok(String::from("42")));
+        assert_eq!(g.get::<Vec<i32>>("t"), Ok(vec![1, 2, 3]));
+        assert_eq!(g.get::<HashMap<String, i64>>("m"),
+            Ok(HashMap::from([(String::from("a"), 1), (String::from("b"), 2)])));
+        assert_eq!(g.get::<Option<i64>>("missing"), Ok(None));
+        assert_eq!(g.get::<Option<i64>>("n"), Ok(Some(42)));
+        assert!(g.get::<bool>("n").unwrap());
+
+        assert_eq!(g.get::<i64>("f").unwrap_err().to_string(), "number has no integer representation");
+        assert_eq!(g.get::<i64>("t").unwrap_err().to_string(), "integer expected, got table");
+        assert_eq!(g.get::<Vec<i64>>("mixed").unwrap_err().to_string(), "integer expected, got string");
+        assert_eq!(g.get::<HashMap<i64, i64>>("m").unwrap_err().to_string(), "integer expected, got string");
+        assert_eq!(g.get::<Vec<String>>("mixed"), Ok(vec![String::from("1"), String::from("x")]));
+        assert_eq!(g.get::<Vec<i64>>("m"), Ok(vec![]));
+        assert_eq!(g.get::<u8>("big").unwrap_err().to_string(), "integer 1000 out of range for u8");
+        assert_eq!(g.get::<i8>("x").unwrap_err().to_string(), "integer expected, got nil");
+
+        g.set("v", vec!["x", "y"]).unwrap();
+        g.set("h", HashMap::from([("k", 1.5)])).unwrap();
+        g.set("o", None::<i64>).unwrap();
+        g.set("big", u64::MAX).unwrap();
+        assert_eq!(ls.GetTop(), 0);
+        ls.Load(b"return v[2] .. #v, h.k, o, math.type(big)".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 4, 0), LUA_OK);
+        assert_eq!(ls.ToString(1), "y2");
+        assert_eq!(ls.ToNumber(2), 1.5);
+        assert!(ls.IsNil(3));
+        assert_eq!(ls.ToString(4), "float");
+    }
+
+    #[test]
+    fn test_call() {
+        let mut ls = newState("
+            function add(a, b) return a + b end
+            function minmax(t) return math.min(table.unpack(t)), math.max(table.unpack(t)) end
+            function fail(msg) error(msg, 0) end
+            function none() end");
+        let add: LuaFunction = ls.globals().get("add").unwrap();
+        assert_eq!(ls.call::<_, i64>(&add, (1, 2)), Ok(3));
+        assert_eq!(ls.call::<_, f64>(&add, (1, 0.5)), Ok(1.5));
+        let minmax: LuaFunction = ls.globals().get("minmax").unwrap();
+        assert_eq!(ls.call::<_, (i64, i64)>(&minmax, vec![3, 9, -2]), Ok((-2, 9)));
+        assert_eq!(ls.call::<_, (i64, Option<i64>)>(&minmax, vec![7]), Ok((7, Some(7))));
+        let none: LuaFunction = ls.globals().get("none").unwrap();
+        assert_eq!(ls.call::<_, (Option<i64>, Option<String>)>(&none, ()), Ok((None, None)));
+        assert_eq!(ls.call::<_, ()>(&none, (1, "two", 3.0, true)), Ok(()));
+
+        let fail: LuaFunction = ls.globals().get("fail").unwrap();
+        let err = ls.call::<_, ()>(&fail, "boom").unwrap_err();
+        assert_eq!((err.status, err.to_string()), (LUA_ERRRUN, String::from("boom")));
+        assert_eq!(ls.call::<_, i64>(&add, ("x", 1)).unwrap_err().to_string(),
+            "test:2: attempt to perform arithmetic on a string value (local 'a')");
+
+        // functions go both ways
+        ls.globals().set("f", add.clone()).unwrap();
+        let f: LuaFunction = ls.globals().get("f").unwrap();
+        assert_eq!(ls.call::<_, i64>(&f, (20, 22)), Ok(42));
+        assert!(ls.globals().get::<LuaFunction>("undefined").is_err());
+        assert_eq!(ls.GetTop(), 0);
+    }
+
+    #[test]
+    fn test_globals_with_metamethods() {
+        let mut ls = newState("
+            setmetatable(_G, {
+                __index = function (_, k) if k == 'bad' then error('no ' .. k, 0) end return k .. '!' end,
+                __newindex = function (t, k, v) if k == 'ro' then error('read-only', 0) end rawset(t, k, v) end,
+            })");
+        let mut g = ls.globals();
+        assert_eq!(g.get::<String>("x"), Ok(String::from("x!")));
+        assert_eq!(g.get::<String>("bad").unwrap_err().to_string(), "no bad");
+        assert_eq!(g.set("ro", 1).unwrap_err().to_string(), "read-only");
+        assert_eq!(g.set("rw", 1), Ok(()));
+        assert_eq!(g.get::<i64>("rw"), Ok(1));
+        assert_eq!(ls.GetTop(), 0);
+    }
+}
@@ -1,7 +1,85 @@
-use crate::{state::lua_state::LuaState, api::consts::*};
+use std::{any::Any, cell::RefCell, ffi::c_void, fmt, rc::Rc};
+use crate::{number::format::FloatToString, state::{lua_state::LuaState, lua_thread::LuaThread, lua_userdata::UserDataRef, lua_value::LuaValue}, api::consts::*};
 
 pub type RustFn = fn(&mut LuaState) -> i32;
 
+// Rust functions that capture state of the host (handles, channels,
+// configuration...). They return their errors instead of raising them.
+pub type RustClosure = Box<dyn Fn(&mut LuaState) -> Result<i32, LuaError>>;
+pub type RustClosureMut = Box<dyn FnMut(&mut LuaState) -> Result<i32, LuaError>>;
+
+// Continuation of a Rust function, called with the status of the call and
+// the context given to `CallK`/`PCallK` once the coroutine is resumed.
+pub type KFunction = fn(&mut LuaState, i32, i64) -> i32;
+
+// A debug hook, called with the event in `ar.event` and, for line events,
+// the new line in `ar.currentline`. Hooks are off while it runs.
+pub type LuaHook = fn(&mut LuaState, &mut LuaDebug);
+
+// A Lua error with its status, as returned by Rust closures and the
+// conversions of `lua_convert`.
+#[derive(Debug, PartialEq)]
+pub struct LuaError {
+    pub status: i32,
+    pub value: LuaValue,
+}
+
+// The message of the error, if the error object is a string or a number.
+impl fmt::Display for LuaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            LuaValue::Str(s) => write!(f, "{}", String::from_utf8_lossy(s)),
+            LuaValue::Integer(i) => write!(f, "{}", i),
+            LuaValue::Number(n) => write!(f, "{}", FloatToString(*n)),
+            v => {
+                let tn = match v {
+                    LuaValue::Nil => "nil",
+                    LuaValue::Bool(_) => "boolean",
+                    LuaValue::Table(_) => "table",
+                    LuaValue::Function(_) => "function",
+                    LuaValue::Thread(_) => "thread",
+                    _ => "userdata",
+                };
+                write!(f, "(error object is a {} value)", tn)
+            },
+        }
+    }
+}
+
+// A runtime error with a message, e.g. `Err("no such user".into())`.
+impl From<String> for LuaError {
+    fn from(msg: String) -> Self {
+        LuaError { status: LUA_ERRRUN, value: LuaValue::Str(msg.into_bytes()) }
+    }
+}
+
+impl From<&str> for LuaError {
+    fn from(msg: &str) -> Self {
+        LuaError::from(String::from(msg))
+    }
+}
+
+// Information about a function or an active call, like C's `lua_Debug`.
+// `GetStack` finds the call, `GetInfo` fills in the fields asked for.
+#[derive(Clone, Default)]
+pub struct LuaDebug {
+    pub event: i32,                 // LUA_HOOKCALL etc, for hooks
+    pub name: Option<String>,
+    pub namewhat: &'static str,     // "global", "local", "method", "field", "upvalue", "metamethod", "for iterator" or ""
+    pub what: &'static str,         // "Lua", "C" or "main"
+    pub source: String,
+    pub short_src: String,
+    pub currentline: i32,
+    pub linedefined: i32,
+    pub lastlinedefined: i32,
+    pub nups: u8,
+    pub nparams: u8,
+    pub isvararg: bool,
+    pub istailcall: bool,
+    pub(crate) thread: Option<Rc<RefCell<LuaThread>>>,     // `None` for the running thread
+    pub(crate) ci: usize,                                  // index of the call in the frames of the thread
+}
+
 pub fn LuaUpValueIndex(i: i32) -> i32 {
     LUA_REGISTRYINDEX as i32 - i
 }
@@ -31,6 +109,8 @@ pub trait LuaAPI {
     fn IsString(&self, idx: i32) -> bool;
     fn IsTable(&self, idx: i32) -> bool;
     fn IsThread(&self, idx: i32) -> bool;
+    fn IsUserData(&self, idx: i32) -> bool;
+    fn IsLightUserData(&self, idx: i32) -> bool;
     fn IsFunction(&self, idx: i32) -> bool;
     fn ToBoolean(&self, idx: i32) -> bool;
     fn ToInteger(&self, idx: i32) -> i64;
@@ -39,17 +119,21 @@ pub trait LuaAPI {
     fn ToNumberX(&self, idx: i32) -> Option<f64>;
     fn ToString(&self, idx: i32) -> String;
     fn ToStringX(&self, idx: i32) -> Option<String>;
+    fn ToBytes(&self, idx: i32) -> Vec<u8>;
+    fn ToBytesX(&self, idx: i32) -> Option<Vec<u8>>;
     /* push functions (rust -> stack) */
     fn PushNil(&mut self);
     fn PushBoolean(&mut self, b: bool);
     fn PushInteger(&mut self, n: i64);
     fn PushNumber(&mut self, n: f64);
     fn PushString(&mut self, s: String);
+    fn PushBytes(&mut self, b: Vec<u8>);
 
     fn ArithOp(&mut self, op: u8);
     fn Compare(&mut self, idx1: i32, idx2: i32, op: u8) -> bool;
     fn Len(&mut self, idx: i32);
     fn Concat(&mut self, n: i32);
+    fn StringToNumber(&mut self, s: &str) -> bool;
 
     // get function
     fn NewTable(&mut self);
@@ -65,18 +149,29 @@ pub trait LuaAPI {
 
     // closure
     fn Load(&mut self, chunk: Vec<u8>, chunkName: &str, mode: &str) -> i32;
+    fn Dump(&mut self, strip: bool) -> Option<Vec<u8>>;
     fn Call(&mut self, nArgs: i32, nResults: i32);
+    fn PCall(&mut self, nArgs: i32, nResults: i32, msgh: i32) -> i32;
+    fn CallK(&mut self, nArgs: i32, nResults: i32, ctx: i64, k: KFunction);
+    fn PCallK(&mut self, nArgs: i32, nResults: i32, msgh: i32, ctx: i64, k: KFunction) -> i32;
+
+    // error
+    fn Error(&mut self) -> i32;
 
     // rust function
     fn PushRustFunction(&mut self, f: RustFn);
     fn IsRustFunction(&self, idx: i32) -> bool;
     fn ToGoFunction(&self, idx: i32) -> Option<RustFn>;
+    fn PushRustClosure(&mut self, f: RustClosure, n: i32);
+    fn PushRustClosureMut(&mut self, f: RustClosureMut, n: i32);
 
     // global environment
     fn PushGlobalTable(&mut self);
     fn GetGlobal(&mut self, name: &'static str) -> i8;
     fn SetGlobal(&mut self, name: &'static str);
     fn Register(&mut self, name: &'static str, f: RustFn);
+    fn RegisterClosure(&mut self, name: &'static str, f: RustClosure);
+    fn RegisterClosureMut(&mut self, name: &'static str, f: RustClosureMut);
 
     // ch10 added
     fn PushGoClosure(&mut self, f: RustFn, n: i32);
@@ -93,4 +188,35 @@ pub trait LuaAPI {
 
     // ch12 added
     fn Next(&mut self, idx: i32) -> bool;
+
+    // coroutine
+    fn NewThread(&mut self);
+    fn XMove(&mut self, idx: i32, n: i32);
+    fn Resume(&mut self, nArgs: i32) -> i32;
+    fn Yield(&mut self, nResults: i32) -> i32;
+    fn IsYieldable(&self) -> bool;
+    fn PushThread(&mut self) -> bool;
+    fn ToThread(&self, idx: i32) -> Option<Rc<RefCell<LuaThread>>>;
+
+    // userdata
+    fn NewUserData<T: Any>(&mut self, data: T) where Self: Sized;
+    fn ToUserData<T: Any>(&self, idx: i32) -> Option<UserDataRef<T>> where Self: Sized;
+    fn PushLightUserData(&mut self, p: *mut c_void);
+    fn ToLightUserData(&self, idx: i32) -> Option<*mut c_void>;
+    fn ToPointer(&self, idx: i32) -> *const c_void;
+
+    // garbage collection
+    fn GC(&mut self, what: i32, data: i32) -> i32;
+
+    // debug
+    fn GetStack(&self, co: Option<&Rc<RefCell<LuaThread>>>, level: i32) -> Option<LuaDebug>;
+    fn GetInfo(&mut self, what: &str, ar: &mut LuaDebug) -> bool;
+    fn GetUpvalue(&mut self, funcIdx: i32, n: i32) -> Option<String>;
+    fn SetUpvalue(&mut self, funcIdx: i32, n: i32) -> Option<String>;
+    fn UpvalueId(&self, funcIdx: i32, n: i32) -> Option<*mut c_void>;
+    fn UpvalueJoin(&mut self, funcIdx1: i32, n1: i32, funcIdx2: i32, n2: i32);
+    fn GetLocal(&mut self, ar: Option<&LuaDebug>, n: i32) -> Option<String>;
+    fn SetLocal(&mut self, ar: &LuaDebug, n: i32) -> Option<String>;
+    fn SetHook(&mut self, co: Option<&Rc<RefCell<LuaThread>>>, f: Option<LuaHook>, mask: i32, count: i32);
+    fn GetHook(&self, co: Option<&Rc<RefCell<LuaThread>>>) -> (Option<LuaHook>, i32, i32);
 }
\ No newline at end of file
@@ -1,7 +1,6 @@
 use super::lua_state::LuaAPI;
 
 pub trait LuaVM: LuaAPI {
-    fn PC(&self) -> i32;
     fn AddPC(&mut self, n: i32);
     fn Fetch(&mut self) -> u32;
     fn GetConst(&mut self, idx: i32);
@@ -9,5 +8,6 @@ pub trait LuaVM: LuaAPI {
     fn RegisterCount(&self) -> i32;
     fn LoadVararg(&mut self, n: i32);
     fn LoadProto(&mut self, idx: i32);
+    fn PreCall(&mut self, nArgs: i32, nResults: i32) -> bool;
     fn CloseUpvalues(&mut self, a: i32);
 }
\ No newline at end of file
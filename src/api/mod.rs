@@ -1,3 +1,5 @@
 pub mod consts;
 pub mod lua_state;
-pub mod lua_vm;
\ No newline at end of file
+pub mod lua_vm;
+pub mod lua_auxlib;
+pub mod lua_convert;
\ No newline at end of file
@@ -0,0 +1,245 @@
+use std::{env, fs::File, io::prelude::*, io::{self, IsTerminal}, process};
+
+use lua_complier::{compile, disassembly, dump, is_binary_chunk, undump, LuaAPI, LuaAuxLib, LuaState, Prototype, LUA_ERRSYNTAX, LUA_OK, LUA_TSTRING};
+
+fn compile_or_exit(chunk: Vec<u8>, chunk_name: &str) -> Prototype {
+    compile(chunk, chunk_name.to_owned()).unwrap_or_else(|err| {
+        eprintln!("lua: {}", err);
+        process::exit(1);
+    })
+}
+
+// ================================================================
+// Function for disassembling.
+// ================================================================
+fn disassemble_file(chunk: Vec<u8>, chunk_name: &str) {
+    let proto: Prototype = if is_binary_chunk(&chunk) {
+        undump(chunk).unwrap_or_else(|msg| {
+            eprintln!("lua: {}: {}", &chunk_name[1..], msg);
+            process::exit(1);
+        })
+    } else {
+        compile_or_exit(chunk, chunk_name)
+    };
+    disassembly(&proto);
+}
+
+// ================================================================
+// Function for compiling to a binary chunk, like `luac -o`.
+// ================================================================
+fn compile_file(chunk: Vec<u8>, chunk_name: &str, output: &str) -> io::Result<()> {
+    let proto = compile_or_exit(chunk, chunk_name);
+    File::create(output)?.write_all(&dump(&proto))
+}
+
+// ================================================================
+// Functions for running chunks.
+// ================================================================
+
+// The message of the error object on the top of the stack, which is popped.
+fn error_message(ls: &mut LuaState) -> String {
+    let msg = if ls.IsString(-1) {
+        ls.ToString(-1)
+    } else if ls.CallMeta(-1, "__tostring") && ls.IsString(-1) {
+        let msg = ls.ToString(-1);
+        ls.pop(1);
+        msg
+    } else {
+        format!("(error object is a {} value)", ls.TypeName2(-1))
+    };
+    ls.pop(1);
+    msg
+}
+
+// The message handler of protected calls: turns the error object into a
+// string and appends a traceback of the stack where the error happened.
+fn msg_handler(ls: &mut LuaState) -> i32 {
+    let msg = match ls.ToStringX(1) {
+        Some(msg) => msg,
+        None => {               /* is error object not a string? */
+            if ls.CallMeta(1, "__tostring") && ls.Type(-1) == LUA_TSTRING {
+                return 1;       /* that is the message */
+            }
+            format!("(error object is a {} value)", ls.TypeName2(1))
+        },
+    };
+    ls.Traceback(None, Some(&msg), 1);  /* append a standard traceback */
+    1                           /* return the traceback */
+}
+
+// Calls the chunk on the top of the stack with `msg_handler` below it.
+fn do_call(ls: &mut LuaState, nresults: i32) -> i32 {
+    let base = ls.GetTop();     /* function index */
+    ls.PushRustFunction(msg_handler);
+    ls.Insert(base);            /* put it under function and args */
+    let status = ls.PCall(0, nresults, base);
+    ls.Remove(base);            /* remove message handler from the stack */
+    status
+}
+
+fn run_file(ls: &mut LuaState, chunk: Vec<u8>, chunk_name: &str) -> bool {
+    let ok = ls.Load(chunk, chunk_name, "bt") == LUA_OK && do_call(ls, 0) == LUA_OK;
+    if !ok {
+        eprintln!("lua: {}", error_message(ls));
+    }
+    ok
+}
+
+fn load_string(ls: &mut LuaState, chunk: &str) -> i32 {
+    ls.Load(chunk.as_bytes().to_vec(), "=stdin", "t")
+}
+
+// A syntax error is due to an incomplete chunk if it was found at the end
+// of the input.
+fn incomplete(ls: &mut LuaState, status: i32) -> bool {
+    if status == LUA_ERRSYNTAX {
+        let msg = ls.ToString(-1);
+        if msg.ends_with("<eof>") {
+            ls.pop(1);
+            return true;
+        }
+    }
+    false
+}
+
+fn read_line(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(n) if n > 0 => Some(line.trim_end_matches(['\n', '\r']).to_owned()),
+        _ => None,
+    }
+}
+
+// Reads a chunk, asking for more lines while it is incomplete. A line is
+// first tried as an expression to print, `=expr` is the same as
+// `return expr`. Leaves the compiled chunk or the syntax error on the stack.
+fn load_line(ls: &mut LuaState, line: String) -> Option<i32> {
+    let line = match line.strip_prefix('=') {
+        Some(exp) => format!("return {}", exp),
+        None => line,
+    };
+    if load_string(ls, &format!("return {}", line)) == LUA_OK {
+        return Some(LUA_OK);
+    }
+    ls.pop(1);
+    let mut chunk = line;
+    loop {
+        let status = load_string(ls, &chunk);
+        if !incomplete(ls, status) {
+            return Some(status);
+        }
+        chunk.push('\n');
+        chunk.push_str(&read_line(">> ")?);
+    }
+}
+
+fn print_results(ls: &mut LuaState) {
+    let n = ls.GetTop();
+    if n > 0 {
+        ls.CheckStack(1);
+        ls.GetGlobal("print");
+        ls.Insert(1);
+        if ls.PCall(n, 0, 0) != LUA_OK {
+            let msg = error_message(ls);
+            eprintln!("error calling 'print' ({})", msg);
+        }
+    }
+}
+
+fn repl(ls: &mut LuaState) {
+    while let Some(line) = read_line("> ") {
+        let status = match load_line(ls, line) {
+            Some(LUA_OK) => do_call(ls, -1),
+            Some(status) => status,
+            None => break,
+        };
+        if status == LUA_OK {
+            print_results(ls);
+        } else {
+            eprintln!("{}", error_message(ls));
+        }
+        ls.SetTop(0);
+    }
+    println!();
+}
+
+// ================================================================
+// Function for parsing cmd codes and entry point of program
+// ================================================================
+fn main() -> io::Result<()> {
+    let mut filename = "".to_owned();
+    let mut interactive = env::args().count() == 1 && io::stdin().is_terminal();
+    if env::args().count() == 2 {
+        filename = env::args().nth(1).unwrap();
+        if filename.starts_with("-h") || filename.starts_with("--help") {
+            print!("Usage: lua <filename>\n");
+            print!("       lua [Optional] <filename>\n");
+            print!("Options:\n");
+            print!("\t-h or --help\t\thelps\n");
+            print!("\t-i [filename]\t\tenter interactive mode after running the program\n");
+            print!("\t-l or --asm\t\tdisassemble programs\n");
+            print!("\t-o <output>\t\tcompile programs to a binary chunk\n");
+            print!("\t-v or --version\t\tshow version of complier\n");
+            return Ok(());
+        } else if filename.starts_with("-v") || filename.starts_with("--version") {
+            print!("Lua_complier v0.1.0\n");
+            return Ok(());
+        } else if filename == "-i" {
+            filename.clear();
+            interactive = true;
+        }
+    } else if env::args().count() == 3 {
+        let options = env::args().nth(1).unwrap();
+        filename = env::args().nth(2).unwrap();
+        if options.starts_with("-l") || options.starts_with("--asm") {
+            let mut file = File::open(&filename)?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            disassemble_file(data, &format!("@{}", filename));
+            return Ok(());
+        } else if options == "-i" {
+            interactive = true;
+        } else {
+            panic!("Invalid cmd options.\n");
+        }
+    } else if env::args().count() == 4 {
+        let options = env::args().nth(1).unwrap();
+        let output = env::args().nth(2).unwrap();
+        filename = env::args().nth(3).unwrap();
+        if options == "-o" {
+            let mut file = File::open(&filename)?;
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            compile_file(data, &format!("@{}", filename), &output)?;
+            return Ok(());
+        } else {
+            panic!("Invalid cmd options.\n");
+        }
+    }
+
+    let mut ls = LuaState::new();
+    ls.OpenLibs();
+    if !filename.is_empty() {
+        let mut file = File::open(&filename)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        if !run_file(&mut ls, data, &format!("@{}", filename)) && !interactive {
+            process::exit(1);
+        }
+    } else if !interactive {
+        // `lua < script.lua`
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        if !run_file(&mut ls, data, "=stdin") {
+            process::exit(1);
+        }
+    }
+
+    if interactive {
+        println!("Lua_complier v0.1.0");
+        repl(&mut ls);
+    }
+    Ok(())
+}
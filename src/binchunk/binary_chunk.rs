@@ -41,7 +41,7 @@ struct Header {
 }
 
 // function prototype
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Prototype {
     pub source: Option<String>, // debug
     pub lineDefined: u32,
@@ -78,13 +78,13 @@ impl Prototype {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Upvalue {
     pub instack: u8,
     pub idx: u8,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LocVar {
     pub varName: String,
     pub startPC: u32,
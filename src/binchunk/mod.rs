@@ -1,12 +1,90 @@
 use binary_chunk::Prototype;
 use reader::Reader;
+use writer::Writer;
 
 pub mod binary_chunk;
 mod reader;
+mod writer;
 
-pub fn undump(data: Vec<u8>) -> Prototype {
+// Reads a binary chunk written by `dump` or `luac` 5.3. A malformed chunk
+// gives a message like "truncated precompiled chunk".
+pub fn undump(data: Vec<u8>) -> Result<Prototype, String> {
     let mut reader = Reader::new(data);
-    reader.checkHeader();
-    reader.readByte();
-    reader.readProto(String::from(""))
-}
\ No newline at end of file
+    reader.checkHeader()?;
+    reader.readByte()?;         /* size of upvalues */
+    reader.readProto(None)
+}
+
+// Writes `proto` as a binary chunk in the format of `luac` 5.3.
+pub fn dump(proto: &Prototype) -> Vec<u8> {
+    dump_strip(proto, false)
+}
+
+// Like `dump`, leaving out the debug information if `strip` is set.
+pub fn dump_strip(proto: &Prototype, strip: bool) -> Vec<u8> {
+    let mut writer = Writer::new(strip);
+    writer.writeHeader();
+    writer.writeByte(proto.upvalues.len() as u8);
+    writer.writeProto(proto, None);
+    writer.data()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::codegen::compile;
+    use super::{binary_chunk::*, dump, dump_strip, undump};
+
+    #[test]
+    fn test_dump_round_trip() {
+        let src = format!(r#"
+            local t = {{1, 2.5, "short", "{}", true, false}}
+            local function f(a, ...)
+                local g = function() return a, t end
+                return g, ...
+            end
+            return f(-7, 1 << 62)"#, "long".repeat(20));
+        let proto = compile(src.into_bytes(), String::from("test")).unwrap();
+        let chunk = dump(&proto);
+        assert_eq!(undump(chunk.clone()), Ok(proto));
+        assert_eq!(dump(&undump(chunk.clone()).unwrap()), chunk);
+    }
+
+    #[test]
+    fn test_malformed_chunks() {
+        let chunk = dump(&compile(b"return 1".to_vec(), String::from("test")).unwrap());
+        assert_eq!(undump(b"\x1bLua garbage".to_vec()), Err(String::from("version mismatch in precompiled chunk")));
+        assert_eq!(undump(b"\x1bLuc".to_vec()), Err(String::from("not a precompiled chunk")));
+        let mut bad = chunk.clone();
+        bad[12] = 8;            /* size of int */
+        assert_eq!(undump(bad), Err(String::from("int size mismatch in precompiled chunk")));
+        for n in [3, 20, chunk.len() - 1] {
+            assert_eq!(undump(chunk[..n].to_vec()), Err(String::from("truncated precompiled chunk")));
+        }
+    }
+
+    #[test]
+    fn test_dump_matches_luac() {
+        // `luac -s` of an empty file
+        let mut proto = Prototype::FakeProto();
+        proto.isVararg = 1;
+        proto.maxStackSize = 2;
+        proto.code = vec![0x00800026];          // RETURN 0 1
+        proto.upvalues = vec![Upvalue { instack: 1, idx: 0 }];
+        let expected: Vec<u8> = vec![
+            0x1b, 0x4c, 0x75, 0x61, 0x53, 0x00, 0x19, 0x93, 0x0d, 0x0a, 0x1a, 0x0a,
+            0x04, 0x08, 0x04, 0x08, 0x08,
+            0x78, 0x56, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x28, 0x77, 0x40,
+            0x01,                                   // size of upvalues
+            0x00,                                   // source
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x01, 0x02,
+            0x01, 0x00, 0x00, 0x00, 0x26, 0x00, 0x80, 0x00,
+            0x00, 0x00, 0x00, 0x00,                 // constants
+            0x01, 0x00, 0x00, 0x00, 0x01, 0x00,     // upvalues
+            0x00, 0x00, 0x00, 0x00,                 // protos
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(dump_strip(&proto, true), expected);
+    }
+}
@@ -1,184 +1,194 @@
-use std::env::set_current_dir;
-
 use crate::{binchunk::binary_chunk::*, state::lua_value::LuaValue};
 
+// Reads a binary chunk. A malformed chunk is reported with a message like
+// the ones of `lundump.c`, e.g. "truncated precompiled chunk".
 pub struct Reader {
     data: Vec<u8>,
+    pos: usize,
+}
+
+type ReadResult<T> = Result<T, String>;
+
+fn error<T>(why: &str) -> ReadResult<T> {
+    Err(format!("{} precompiled chunk", why))
 }
 
 impl Reader {
     pub fn new(data: Vec<u8>) -> Self {
-        Self { data: data }
+        Self { data: data, pos: 0 }
+    }
+
+    pub fn readByte(&mut self) -> ReadResult<u8> {
+        match self.data.get(self.pos) {
+            Some(b) => {
+                self.pos += 1;
+                Ok(*b)
+            },
+            None => error("truncated"),
+        }
     }
 
-    pub fn readByte(&mut self) -> u8 {
-        self.data.remove(0)
+    pub fn readUint32(&mut self) -> ReadResult<u32> {
+        let b = self.readBytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
     }
 
-    pub fn readUint32(&mut self) -> u32 {
-        let a0 = self.readByte() as u32;
-        let a1 = self.readByte() as u32;
-        let a2 = self.readByte() as u32;
-        let a3 = self.readByte() as u32;
-        (a3 << 24) | (a2 << 16) | (a1 << 8) | a0
+    pub fn readUint64(&mut self) -> ReadResult<u64> {
+        let a0 = self.readUint32()? as u64;
+        let a1 = self.readUint32()? as u64;
+        Ok((a1 << 32) | a0)
     }
 
-    pub fn readUint64(&mut self) -> u64 {
-        let a0 = self.readUint32() as u64;
-        let a1 = self.readUint32() as u64;
-        (a1 << 32) | a0
+    pub fn readLuaInteger(&mut self) -> ReadResult<i64> {
+        Ok(self.readUint64()? as i64)
     }
 
-    pub fn readLuaInteger(&mut self) -> i64 {
-        self.readUint64() as i64
+    pub fn readLuaNumber(&mut self) -> ReadResult<f64> {
+        Ok(f64::from_bits(self.readUint64()?))
     }
 
-    pub fn readLuaNumber(&mut self) -> f64 {
-        use std::f64;
-        f64::from_bits(self.readUint64())
+    pub fn readString(&mut self) -> ReadResult<String> {
+        Ok(String::from_utf8_lossy(&self.readLuaString()?).into_owned())
     }
 
-    pub fn readString(&mut self) -> String {
-        let mut length = self.readByte() as usize;
+    // A string constant, which may hold any bytes.
+    pub fn readLuaString(&mut self) -> ReadResult<Vec<u8>> {
+        let mut length = self.readByte()? as u64;
         if length == 0 {
-            return String::from("");
+            return Ok(vec![]);
         }
         if length == 0xFF {
-            length = self.readUint64() as usize;
+            length = self.readUint64()?;
         }
-        let bytes = self.readBytes(length - 1);
-        if let Ok(res) = String::from_utf8(bytes) {
-            res
-        } else {
-            String::from("")
+        match usize::try_from(length - 1) {
+            Ok(n) => self.readBytes(n),
+            Err(_) => error("truncated"),
         }
     }
 
-    pub fn readBytes(&mut self, n: usize) -> Vec<u8> {
-        let mut vec = vec![];
-        for _ in 0..n {
-            vec.push(self.readByte());
+    pub fn readBytes(&mut self, n: usize) -> ReadResult<Vec<u8>> {
+        if n > self.data.len() - self.pos {
+            return error("truncated");
         }
-        vec
-    }
-
-    pub fn checkHeader(&mut self) {
-        assert_eq!(self.readBytes(4), LUA_SIGNATURE, "not a precompiled chunk!");
-        assert_eq!(self.readByte(), LUAC_VERSION, "version mismatch!");
-        assert_eq!(self.readByte(), LUAC_FORMAT, "format mismatch!");
-        assert_eq!(self.readBytes(6), LUAC_DATA, "corrupted!");
-        assert_eq!(self.readByte(), CINT_SIZE, "int size mismatch!");
-        assert_eq!(self.readByte(), CSIZET_SIZE, "size_t size mismatch!");
-        assert_eq!(self.readByte(), INSTRUCTION_SIZE, "instruction size mismatch!");
-        assert_eq!(self.readByte(), LUA_INTEGER_SIZE, "lua_Integer size mismatch!");
-        assert_eq!(self.readByte(), LUA_NUMBER_SIZE, "lua_Number size mismatch!");
-        assert_eq!(self.readLuaInteger(), LUAC_INT, "endianness mismatch!");
-        assert_eq!(self.readLuaNumber(), LUAC_NUM, "float format mismatch!");
-    }
-
-    pub fn readProto(&mut self, parentSource: String) -> Prototype {
-        let mut source = self.readString();
-        if source == "" {
-            source = parentSource;
+        self.pos += n;
+        Ok(self.data[self.pos - n..self.pos].to_vec())
+    }
+
+    pub fn checkHeader(&mut self) -> ReadResult<()> {
+        let literals: [(&[u8], &str); 4] = [
+            (&LUA_SIGNATURE, "not a"),
+            (&[LUAC_VERSION], "version mismatch in"),
+            (&[LUAC_FORMAT], "format mismatch in"),
+            (&LUAC_DATA, "corrupted"),
+        ];
+        for (literal, why) in literals {
+            if self.readBytes(literal.len())? != literal {
+                return error(why);
+            }
         }
-
-        Prototype {
-            source: Some(source.clone()),
-            lineDefined: self.readUint32(),
-            lastLineDefined: self.readUint32(),
-            numParams: self.readByte(),
-            isVararg: self.readByte(),
-            maxStackSize: self.readByte(),
-            code: self.readCode(),
-            constants: self.readConstants(),
-            upvalues: self.readUpvalues(),
-            protos: self.readProtos(source.clone()),
-            lineInfo: self.readLineInfo(),
-            locVars: self.readLocVars(),
-            upvalueNames: self.readUpvalueNames(),
+        let sizes = [
+            (CINT_SIZE, "int"),
+            (CSIZET_SIZE, "size_t"),
+            (INSTRUCTION_SIZE, "Instruction"),
+            (LUA_INTEGER_SIZE, "lua_Integer"),
+            (LUA_NUMBER_SIZE, "lua_Number"),
+        ];
+        for (size, tname) in sizes {
+            if self.readByte()? != size {
+                return error(&format!("{} size mismatch in", tname));
+            }
         }
+        if self.readLuaInteger()? != LUAC_INT {
+            return error("endianness mismatch in");
+        }
+        if self.readLuaNumber()? != LUAC_NUM {
+            return error("float format mismatch in");
+        }
+        Ok(())
+    }
+
+    pub fn readProto(&mut self, parentSource: Option<String>) -> ReadResult<Prototype> {
+        let source = self.readString()?;
+        let source = if source.is_empty() { parentSource } else { Some(source) };
+
+        Ok(Prototype {
+            source: source.clone(),
+            lineDefined: self.readUint32()?,
+            lastLineDefined: self.readUint32()?,
+            numParams: self.readByte()?,
+            isVararg: self.readByte()?,
+            maxStackSize: self.readByte()?,
+            code: self.readCode()?,
+            constants: self.readConstants()?,
+            upvalues: self.readUpvalues()?,
+            protos: self.readProtos(source.clone())?,
+            lineInfo: self.readLineInfo()?,
+            locVars: self.readLocVars()?,
+            upvalueNames: self.readUpvalueNames()?,
+        })
+    }
+
+    // Reads a vector of `read` items, preceded by their number. The number
+    // is not trusted for the allocation, the chunk may be truncated.
+    fn readVec<T>(&mut self, read: fn(&mut Self) -> ReadResult<T>) -> ReadResult<Vec<T>> {
+        let n = self.readUint32()? as usize;
+        let mut vec = Vec::<T>::with_capacity(n.min(self.data.len() - self.pos));
+        for _ in 0..n {
+            vec.push(read(self)?);
+        }
+        Ok(vec)
     }
 
-    fn readCode(&mut self) -> Vec<u32> {
-        let num_codes = self.readUint32() as usize;
-        let mut code = Vec::<u32>::with_capacity(num_codes);
-        for _ in 0..num_codes {
-            code.push(self.readUint32());
-        }
-        code
+    fn readCode(&mut self) -> ReadResult<Vec<u32>> {
+        self.readVec(Self::readUint32)
     }
 
-    fn readConstant(&mut self) -> LuaValue {
-        match self.readByte() {
+    fn readConstant(&mut self) -> ReadResult<LuaValue> {
+        Ok(match self.readByte()? {
             TAG_NIL => LuaValue::Nil,
-            TAG_BOOLEAN => LuaValue::Bool(self.readByte() != 0),
-            TAG_INTEGER => LuaValue::Integer(self.readLuaInteger()),
-            TAG_NUMBER => LuaValue::Number(self.readLuaNumber()),
-            TAG_SHORT_STR => LuaValue::Str(self.readString()),
-            TAG_LONG_STR => LuaValue::Str(self.readString()),
-            _ => panic!("corrupted!"),
-        }
+            TAG_BOOLEAN => LuaValue::Bool(self.readByte()? != 0),
+            TAG_INTEGER => LuaValue::Integer(self.readLuaInteger()?),
+            TAG_NUMBER => LuaValue::Number(self.readLuaNumber()?),
+            TAG_SHORT_STR => LuaValue::Str(self.readLuaString()?),
+            TAG_LONG_STR => LuaValue::Str(self.readLuaString()?),
+            _ => return error("corrupted"),
+        })
     }
 
-    fn readConstants(&mut self) -> Vec<LuaValue> {
-        let num_constants = self.readUint32() as usize;
-        let mut res = Vec::<LuaValue>::with_capacity(num_constants);
-        for _ in 0..num_constants {
-            res.push(self.readConstant());
-        }
-        res
+    fn readConstants(&mut self) -> ReadResult<Vec<LuaValue>> {
+        self.readVec(Self::readConstant)
     }
 
-    fn readUpvalues(&mut self) -> Vec<Upvalue> {
-        let num_upvalues = self.readUint32() as usize;
-        let mut upvalues = Vec::<Upvalue>::with_capacity(num_upvalues);
-        for _ in 0..num_upvalues {
-            upvalues.push(Upvalue {
-                instack: self.readByte(),
-                idx: self.readByte(),
-            });
-        }
-        upvalues
+    fn readUpvalues(&mut self) -> ReadResult<Vec<Upvalue>> {
+        self.readVec(|r| Ok(Upvalue {
+            instack: r.readByte()?,
+            idx: r.readByte()?,
+        }))
     }
 
-    fn readProtos(&mut self, parentSource: String) -> Vec<Prototype> {
-        let num_protos = self.readUint32() as usize;
-        let mut protos = Vec::<Prototype>::with_capacity(num_protos);
+    fn readProtos(&mut self, parentSource: Option<String>) -> ReadResult<Vec<Prototype>> {
+        let num_protos = self.readUint32()? as usize;
+        let mut protos = vec![];
         for _ in 0..num_protos {
-            protos.push(self.readProto(parentSource.clone()));
+            protos.push(self.readProto(parentSource.clone())?);
         }
-        protos
+        Ok(protos)
     }
 
-    fn readLineInfo(&mut self) -> Vec<u32> {
-        let num_lineinfos = self.readUint32() as usize;
-        let mut lineInfo = Vec::<u32>::with_capacity(num_lineinfos);
-        for _ in 0..num_lineinfos {
-            lineInfo.push(self.readUint32());
-        }
-        lineInfo
-    }
-
-    fn readLocVars(&mut self) -> Vec<LocVar> {
-        let num_locvars = self.readUint32() as usize;
-        let mut locVars = Vec::<LocVar>::with_capacity(num_locvars);
-        for _ in 0..num_locvars {
-            locVars.push(LocVar {
-                varName: self.readString(),
-                startPC: self.readUint32(),
-                endPC: self.readUint32(),
-            });
-        }
-        locVars
+    fn readLineInfo(&mut self) -> ReadResult<Vec<u32>> {
+        self.readVec(Self::readUint32)
     }
 
-    fn readUpvalueNames(&mut self) -> Vec<String> {
-        let num_names = self.readUint32() as usize;
-        let mut names = Vec::<String>::with_capacity(num_names);
-        for _ in 0..num_names {
-            names.push(self.readString());
-        }
-        names
+    fn readLocVars(&mut self) -> ReadResult<Vec<LocVar>> {
+        self.readVec(|r| Ok(LocVar {
+            varName: r.readString()?,
+            startPC: r.readUint32()?,
+            endPC: r.readUint32()?,
+        }))
     }
 
-}
\ No newline at end of file
+    fn readUpvalueNames(&mut self) -> ReadResult<Vec<String>> {
+        self.readVec(Self::readString)
+    }
+
+}
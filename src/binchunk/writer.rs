@@ -0,0 +1,174 @@
+use crate::{binchunk::binary_chunk::*, state::lua_value::LuaValue};
+
+// Longest string that `luac` tags as a short string.
+const LUAI_MAXSHORTLEN: usize = 40;
+
+pub struct Writer {
+    data: Vec<u8>,
+    strip: bool,        // leave out debug information
+}
+
+impl Writer {
+    pub fn new(strip: bool) -> Self {
+        Self { data: vec![], strip }
+    }
+
+    pub fn data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn writeByte(&mut self, b: u8) {
+        self.data.push(b);
+    }
+
+    pub fn writeBytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn writeUint32(&mut self, n: u32) {
+        self.writeBytes(&n.to_le_bytes());
+    }
+
+    pub fn writeUint64(&mut self, n: u64) {
+        self.writeBytes(&n.to_le_bytes());
+    }
+
+    pub fn writeLuaInteger(&mut self, n: i64) {
+        self.writeUint64(n as u64);
+    }
+
+    pub fn writeLuaNumber(&mut self, n: f64) {
+        self.writeUint64(n.to_bits());
+    }
+
+    // `None` is written as the empty (NULL) string.
+    pub fn writeString(&mut self, s: Option<&[u8]>) {
+        let bytes = match s {
+            Some(s) => s,
+            None => {
+                self.writeByte(0);
+                return;
+            },
+        };
+        let size = bytes.len() + 1;     // includes the trailing '\0' of C strings
+        if size < 0xFF {
+            self.writeByte(size as u8);
+        } else {
+            self.writeByte(0xFF);
+            self.writeUint64(size as u64);
+        }
+        self.writeBytes(bytes);
+    }
+
+    pub fn writeHeader(&mut self) {
+        self.writeBytes(&LUA_SIGNATURE);
+        self.writeByte(LUAC_VERSION);
+        self.writeByte(LUAC_FORMAT);
+        self.writeBytes(&LUAC_DATA);
+        self.writeByte(CINT_SIZE);
+        self.writeByte(CSIZET_SIZE);
+        self.writeByte(INSTRUCTION_SIZE);
+        self.writeByte(LUA_INTEGER_SIZE);
+        self.writeByte(LUA_NUMBER_SIZE);
+        self.writeLuaInteger(LUAC_INT);
+        self.writeLuaNumber(LUAC_NUM);
+    }
+
+    pub fn writeProto(&mut self, proto: &Prototype, parentSource: Option<&str>) {
+        let source = proto.source.as_deref();
+        if self.strip || source == parentSource {
+            self.writeString(None);
+        } else {
+            self.writeString(source.map(str::as_bytes));
+        }
+        self.writeUint32(proto.lineDefined);
+        self.writeUint32(proto.lastLineDefined);
+        self.writeByte(proto.numParams);
+        self.writeByte(proto.isVararg);
+        self.writeByte(proto.maxStackSize);
+        self.writeCode(&proto.code);
+        self.writeConstants(&proto.constants);
+        self.writeUpvalues(&proto.upvalues);
+        self.writeProtos(&proto.protos, source);
+        self.writeDebug(proto);
+    }
+
+    fn writeCode(&mut self, code: &[u32]) {
+        self.writeUint32(code.len() as u32);
+        for inst in code {
+            self.writeUint32(*inst);
+        }
+    }
+
+    fn writeConstant(&mut self, k: &LuaValue) {
+        match k {
+            LuaValue::Nil => self.writeByte(TAG_NIL),
+            LuaValue::Bool(b) => {
+                self.writeByte(TAG_BOOLEAN);
+                self.writeByte(*b as u8);
+            },
+            LuaValue::Integer(i) => {
+                self.writeByte(TAG_INTEGER);
+                self.writeLuaInteger(*i);
+            },
+            LuaValue::Number(n) => {
+                self.writeByte(TAG_NUMBER);
+                self.writeLuaNumber(*n);
+            },
+            LuaValue::Str(s) => {
+                if s.len() <= LUAI_MAXSHORTLEN {
+                    self.writeByte(TAG_SHORT_STR);
+                } else {
+                    self.writeByte(TAG_LONG_STR);
+                }
+                self.writeString(Some(s));
+            },
+            _ => panic!("constant of type {} can not be dumped!", k.typeOf()),
+        }
+    }
+
+    fn writeConstants(&mut self, constants: &[LuaValue]) {
+        self.writeUint32(constants.len() as u32);
+        for k in constants {
+            self.writeConstant(k);
+        }
+    }
+
+    fn writeUpvalues(&mut self, upvalues: &[Upvalue]) {
+        self.writeUint32(upvalues.len() as u32);
+        for uv in upvalues {
+            self.writeByte(uv.instack);
+            self.writeByte(uv.idx);
+        }
+    }
+
+    fn writeProtos(&mut self, protos: &[Prototype], source: Option<&str>) {
+        self.writeUint32(protos.len() as u32);
+        for p in protos {
+            self.writeProto(p, source);
+        }
+    }
+
+    fn writeDebug(&mut self, proto: &Prototype) {
+        if self.strip {
+            self.writeUint32(0);        // lineInfo
+            self.writeUint32(0);        // locVars
+            self.writeUint32(0);        // upvalueNames
+            return;
+        }
+        self.writeUint32(proto.lineInfo.len() as u32);
+        for line in &proto.lineInfo {
+            self.writeUint32(*line);
+        }
+        self.writeUint32(proto.locVars.len() as u32);
+        for var in &proto.locVars {
+            self.writeString(Some(var.varName.as_bytes()));
+            self.writeUint32(var.startPC);
+            self.writeUint32(var.endPC);
+        }
+        self.writeUint32(proto.upvalueNames.len() as u32);
+        for name in &proto.upvalueNames {
+            self.writeString(Some(name.as_bytes()));
+        }
+    }
+}
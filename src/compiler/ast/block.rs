@@ -4,7 +4,7 @@ use super::{stat::Stat, exp::Exp};
 pub struct Block {
     pub last_line: i32,
     pub stats: Vec<Stat>,
-    pub ret_exps: Vec<Exp>,
+    pub ret_exps: Option<Vec<Exp>>,     // `None` if the block has no return statement
 }
 
 
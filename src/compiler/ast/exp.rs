@@ -9,7 +9,7 @@ pub enum Exp {
     VarargExp { line: i32 },
     IntegerExp { line: i32, val: i64 },
     FloatExp { line: i32, val: f64 },
-    StringExp { line: i32, str: String },
+    StringExp { line: i32, str: Vec<u8> },
     NameExp { line: i32, str: String },
     UnopExp { 
         line: i32,
@@ -10,10 +10,12 @@ pub enum Stat {
     },
     // `::` Name `::`
     LabelStat {
+        line: i32,
         name: String,
     },
     // goto Name
     GotoStat {
+        line: i32,
         name: String,
     },
     // do block end
@@ -45,6 +47,7 @@ pub enum Stat {
         block: Rc<Block>,
     },
     ForInStat { 
+        line_of_for: i32,
         line_of_do: i32,
         name_list: Vec<String>,
         exp_list: Vec<Exp>,
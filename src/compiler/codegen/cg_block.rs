@@ -1,5 +1,5 @@
 use super::super::{
-    ast::block::Block,
+    ast::{block::Block, stat::Stat},
     codegen::{
         func_info::FuncInfo,
         cg_stat::{cg_ret_stat, cg_stat}
@@ -7,11 +7,59 @@ use super::super::{
 };
 
 pub fn cg_block(fi: &mut FuncInfo, node: &Block) {
-    for stat in node.stats.iter() {
-        cg_stat(fi, stat);
+    _cg_block(fi, node, false);
+}
+
+// The body of `repeat ... until exp`, its locals are still visible
+// in `exp`, so a label at its end does not leave their scope.
+pub fn cg_repeat_block(fi: &mut FuncInfo, node: &Block) {
+    _cg_block(fi, node, true);
+}
+
+fn _cg_block(fi: &mut FuncInfo, node: &Block, with_until: bool) {
+    fi.enter_block();
+    for (i, stat) in node.stats.iter().enumerate() {
+        if let Stat::LabelStat { line, name } = stat {
+            let last = !with_until && node.ret_exps.is_none()
+                && node.stats[i + 1..].iter().all(|s| matches!(s, Stat::LabelStat { .. }));
+            fi.add_label(name, *line, last);
+        } else {
+            cg_stat(fi, stat);
+        }
+    }
+
+    if let Some(ret_exps) = &node.ret_exps {
+        cg_ret_stat(fi, ret_exps, node.last_line);
+    }
+    fi.leave_block();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::codegen::compile;
+
+    fn compile_error(src: &str) -> String {
+        compile(src.as_bytes().to_vec(), String::from("=test")).unwrap_err().to_string()
     }
-    
-    if node.ret_exps.len() > 0 {
-        cg_ret_stat(fi, &node.ret_exps);
+
+    #[test]
+    fn test_goto_into_local_scope() {
+        assert_eq!(compile_error("goto l local a ::l:: print(a)"), "test:1: <goto l> at line 1 jumps into the scope of local 'a'");
+    }
+
+    #[test]
+    fn test_goto_undefined_label() {
+        assert_eq!(compile_error("do ::l:: end\ngoto l"), "test:2: no visible label 'l' for <goto> at line 2");
+    }
+
+    #[test]
+    fn test_duplicate_label() {
+        assert_eq!(compile_error("::l:: do ::l:: end\n::l::"), "test:2: label 'l' already defined on line 1");
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        assert_eq!(compile_error("do\n  break\nend"), "test:2: <break> at line 2 not inside a loop");
+        assert_eq!(compile_error("function f()\n  return ...\nend"), "test:2: cannot use '...' outside a vararg function near '...'");
     }
-}
\ No newline at end of file
+}
@@ -7,6 +7,7 @@ use super::super::{
         stat::Stat
     },
     codegen::{
+        compile_error,
         func_info::FuncInfo,
         cg_stat::*,
         cg_block::cg_block
@@ -16,17 +17,17 @@ use super::super::{
 
 pub fn cg_tail_call_exp(fi: &mut FuncInfo, exp: &Exp, a: i32) {
     let n_args = prep_func_call(fi, exp, a);
-    fi.emit_tail_call(a, n_args);
+    fi.emit_tail_call(line_of(exp), a, n_args);
 }
 
 pub fn cg_exp(fi: &mut FuncInfo, exp: &Exp, a: i32, n: i32) {
     match exp {
-        NilExp { .. } => fi.emit_load_nil(a, n),
-        FalseExp { .. } => fi.emit_load_bool(a, 0, 0),
-        TrueExp { .. } => fi.emit_load_bool(a, 1, 0),
-        IntegerExp { line, val } => fi.emit_load_K(a, &LuaValue::Integer(*val)),
-        FloatExp { line, val } => fi.emit_load_K(a, &LuaValue::Number(*val)),
-        StringExp { line, str } => fi.emit_load_K(a, &LuaValue::Str(str.to_owned())),
+        NilExp { line } => fi.emit_load_nil(*line, a, n),
+        FalseExp { line } => fi.emit_load_bool(*line, a, 0, 0),
+        TrueExp { line } => fi.emit_load_bool(*line, a, 1, 0),
+        IntegerExp { line, val } => fi.emit_load_K(*line, a, &LuaValue::Integer(*val)),
+        FloatExp { line, val } => fi.emit_load_K(*line, a, &LuaValue::Number(*val)),
+        StringExp { line, str } => fi.emit_load_K(*line, a, &LuaValue::Str(str.to_owned())),
         ParensExp { exp: exp0 } => cg_exp(fi, exp0.as_ref(), a, 1),
         VarargExp { .. } => cg_vararg_exp(fi, exp, a, n),
         FuncDefExp { .. } => cg_func_def_exp(fi, exp, a),
@@ -43,11 +44,13 @@ pub fn cg_exp(fi: &mut FuncInfo, exp: &Exp, a: i32, n: i32) {
     }
 }
 
-fn cg_vararg_exp(fi: &mut FuncInfo, _exp: &Exp, a: i32, n: i32) {
-    if !fi.is_vararg {
-        panic!(r"cannot use '...' outside a vararg function");
+fn cg_vararg_exp(fi: &mut FuncInfo, exp: &Exp, a: i32, n: i32) {
+    if let VarargExp { line } = exp {
+        if !fi.is_vararg {
+            compile_error(*line, String::from("cannot use '...' outside a vararg function"), Some("'...'"));
+        }
+        fi.emit_vararg(*line, a, n);
     }
-    fi.emit_vararg(a, n);
 }
 
 pub fn cg_func_def_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
@@ -58,15 +61,15 @@ pub fn cg_func_def_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
             let mut_sub_FI = &mut *ptr_sub_FI;
             // mut_sub_FI.ref_set_parent(fi);
             for param in par_list.iter() {
-                mut_sub_FI.add_local_var(param);
+                mut_sub_FI.add_local_var(param, 0);
             }
             cg_block(mut_sub_FI, block.as_ref());
-            mut_sub_FI.exit_scope();
-            mut_sub_FI.emit_return(0, 0);
+            mut_sub_FI.emit_return(*last_line, 0, 0);
+            mut_sub_FI.exit_scope(mut_sub_FI.pc() + 1);
 
             // fi.sub_funcs.push(Rc::from(sub_FI));
             let bx = fi.sub_funcs.len() - 1;
-            fi.emit_closure(a, bx as i32);
+            fi.emit_closure(*last_line, a, bx as i32);
         }
     }
 }
@@ -86,7 +89,7 @@ fn cg_table_constructor_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
         let n_exps = key_exps.len();
         let mult_ret = n_exps > 0 && is_vararg_or_func_call(&val_exps[n_exps - 1]);
         
-        fi.emit_new_table(a, n_arr, n_exps as i32 - n_arr);
+        fi.emit_new_table(*line, a, n_arr, n_exps as i32 - n_arr);
         
         let mut arr_idx = 0;
         for (i, key_exp) in key_exps.iter().enumerate() {
@@ -107,10 +110,12 @@ fn cg_table_constructor_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
                     }
                     let c = (arr_idx - 1) / 50 + 1;
                     fi.free_regs(n);
+                    /* the last items are stored at the closing brace */
+                    let line = if arr_idx == n_arr { *last_line } else { last_line_of(val_exp) };
                     if i == n_exps - 1 && mult_ret {
-                        fi.emit_set_list(a, 0, c);
+                        fi.emit_set_list(line, a, 0, c);
                     } else {
-                        fi.emit_set_list(a, n, c);
+                        fi.emit_set_list(line, a, n, c);
                     }
                 }
                 continue;
@@ -122,7 +127,7 @@ fn cg_table_constructor_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
             cg_exp(fi, val_exp, c, 1);
             fi.free_regs(2);
             
-            fi.emit_set_table(a, b, c);
+            fi.emit_set_table(last_line_of(val_exp), a, b, c);
         }
     }
 }
@@ -131,7 +136,7 @@ fn cg_unop_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
     if let UnopExp { line, op, exp } =  node {
         let b = fi.alloc_reg();
         cg_exp(fi, exp, b, 1);
-        fi.emit_unary_op(*op, a, b);
+        fi.emit_unary_op(*line, *op, a, b);
         fi.free_reg();
     }
 }
@@ -146,7 +151,7 @@ fn cg_concat_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
         let c = fi.used_regs - 1;
         let b = c - exps.len() as i32 + 1;
         fi.free_regs(c - b + 1);
-        fi.emit_ABC(OP_CONCAT as i32, a, b, c);
+        fi.emit_ABC(*line, OP_CONCAT as i32, a, b, c);
     }
 }
 
@@ -158,15 +163,15 @@ fn cg_binop_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
                 cg_exp(fi, exp1.as_ref(), b, 1);
                 fi.free_reg();
                 if *op == TOKEN_OP_AND {
-                    fi.emit_test_set(a, b, 0);
+                    fi.emit_test_set(*line, a, b, 0);
                 } else {
-                    fi.emit_test_set(a, b, 1);
+                    fi.emit_test_set(*line, a, b, 1);
                 }
-                let pc_of_jmp = fi.emit_jmp(0, 0);
+                let pc_of_jmp = fi.emit_jmp(*line, 0, 0);
                 let b = fi.alloc_reg();
                 cg_exp(fi, exp2, b, 1);
                 fi.free_reg();
-                fi.emit_move(a, b);
+                fi.emit_move(last_line_of(exp2), a, b);
                 fi.fix_sBx(pc_of_jmp, fi.pc() - pc_of_jmp);
             },
             _ => {
@@ -174,7 +179,7 @@ fn cg_binop_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
                 cg_exp(fi, exp1, b, 1);
                 let c = fi.alloc_reg();
                 cg_exp(fi, exp2, c, 1);
-                fi.emit_binary_op(*op, a, b, c);
+                fi.emit_binary_op(*line, *op, a, b, c);
                 fi.free_regs(2);
             }
         }
@@ -185,22 +190,22 @@ fn cg_name_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
     if let NameExp { line, str } = node {
         let r = fi.slot_of_local_var(str);
         if r >= 0 {
-            fi.emit_move(a, r);
+            fi.emit_move(*line, a, r);
         } else {
             let idx = fi.index_of_upVal(str);
             if idx >= 0 {
-                fi.emit_get_upval(a, idx);
+                fi.emit_get_upval(*line, a, idx);
             } else {    // x => _ENV['x']
                 let ta_exp = TableAccessExp {
                     prefix_exp: Box::new(NameExp {
-                        line: 0,
+                        line: *line,
                         str: "_ENV".to_owned(),
                     }),
                     key_exp: Box::new(StringExp {
-                        line: 0,
-                        str: str.to_owned(),
+                        line: *line,
+                        str: str.as_bytes().to_vec(),
                     }),
-                    last_line: 0,
+                    last_line: *line,
                 };
                 cg_table_access_exp(fi, &ta_exp, a);
             }
@@ -214,20 +219,20 @@ pub fn cg_table_access_exp(fi: &mut FuncInfo, node: &Exp, a: i32) {
         cg_exp(fi, prefix_exp.as_ref(), b, 1);
         let c = fi.alloc_reg();
         cg_exp(fi, key_exp.as_ref(), c, 1);
-        fi.emit_get_table(a, b, c);
+        fi.emit_get_table(*last_line, a, b, c);
         fi.free_regs(2);
     }
 }
 
 fn cg_func_call_exp_(fi: &mut FuncInfo, exp: &Exp, a: i32, n: i32) {
     let n_args = prep_func_call(fi, exp, a);
-    fi.emit_call(a, n_args, n);
+    fi.emit_call(line_of(exp), a, n_args, n);
 }
 
 pub fn cg_func_call_exp(fi: &mut FuncInfo, node: &Stat, a: i32, n: i32) {
     if let Stat::FuncCallStat(exp) = node {
         let n_args = prep_func_call(fi, exp, a);
-        fi.emit_call(a, n_args, n);
+        fi.emit_call(line_of(exp), a, n_args, n);
     }
 }
 
@@ -237,9 +242,10 @@ fn prep_func_call(fi: &mut FuncInfo, node: &Exp, a: i32) -> i32 {
         let mut last_arg_is_vararg_or_func_call = false;
         
         cg_exp(fi, prefix_exp, a, 1);
-        if let NilExp { .. } = name_exp.as_ref() {} else if let NameExp { line, str } = name_exp.as_ref() {
+        if let StringExp { str, .. } = name_exp.as_ref() {
+            fi.alloc_reg();     /* register for `self` */
             let c = 0x100 + fi.index_of_constant(&LuaValue::Str(str.to_owned()));
-            fi.emit_self(a, a, c);
+            fi.emit_self(*line, a, a, c);
         }
         
         for (i, arg) in args.iter().enumerate() {
@@ -254,6 +260,7 @@ fn prep_func_call(fi: &mut FuncInfo, node: &Exp, a: i32) -> i32 {
         
         fi.free_regs(n_args);
         if let NilExp { .. } = name_exp.as_ref() {} else {
+            fi.free_reg();
             n_args += 1;
         }
         if last_arg_is_vararg_or_func_call {
@@ -264,6 +271,31 @@ fn prep_func_call(fi: &mut FuncInfo, node: &Exp, a: i32) -> i32 {
     panic!("Exp is not FuncCallExp.");
 }
 
+// The line of a call, where its arguments start.
+fn line_of(exp: &Exp) -> i32 {
+    if let FuncCallExp { line, .. } = exp {
+        return *line;
+    }
+    last_line_of(exp)
+}
+
+// The line where an expression ends.
+pub fn last_line_of(exp: &Exp) -> i32 {
+    match exp {
+        NilExp { line } | TrueExp { line } | FalseExp { line } | VarargExp { line } => *line,
+        IntegerExp { line, .. } | FloatExp { line, .. } => *line,
+        StringExp { line, .. } | NameExp { line, .. } => *line,
+        UnopExp { exp, .. } => last_line_of(exp),
+        BinopExp { exp2, .. } => last_line_of(exp2),
+        ConcatExp { exps, .. } => last_line_of(exps.last().unwrap()),
+        ParensExp { exp } => last_line_of(exp),
+        TableConstructorExp { last_line, .. } => *last_line,
+        FuncDefExp { last_line, .. } => *last_line,
+        TableAccessExp { last_line, .. } => *last_line,
+        FuncCallExp { last_line, .. } => *last_line,
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -8,7 +8,7 @@ use super::super::{
     codegen::{
         func_info::FuncInfo,
         cg_exp::*,
-        cg_block::cg_block
+        cg_block::{cg_block, cg_repeat_block}
     }
 };
 
@@ -57,33 +57,37 @@ pub fn cg_stat(fi: &mut FuncInfo, node: &Stat) {
             cg_local_func_def_stat(fi, node);
             return;
         },
+        GotoStat { .. } => {
+            cg_goto_stat(fi, node);
+            return;
+        },
         _ => {
-            // LabelStat, GotoStat
-            panic!("not supported!");
+            // EmptyStat, LabelStat (handled by cg_block)
         }
     }
 }
 
-pub fn cg_ret_stat(fi: &mut FuncInfo, exps: &Vec<Exp>) {
+pub fn cg_ret_stat(fi: &mut FuncInfo, exps: &Vec<Exp>, last_line: i32) {
     let n_exps = exps.len();
 
     if n_exps == 0 {
-        fi.emit_return(0, 0);
+        fi.emit_return(last_line, 0, 0);
         return;
     }
 
+    let line = last_line_of(&exps[n_exps - 1]);
     if n_exps == 1 {
         if let Exp::NameExp { line: _line, str} = &exps[0] {
             let r = fi.slot_of_local_var(str);
             if r >= 0 {
-                fi.emit_return(r, 1);
+                fi.emit_return(line, r, 1);
                 return;
             }
         } else if let Exp::FuncCallExp { .. } = &exps[0] {
             let r = fi.alloc_reg();
             cg_tail_call_exp(fi, &exps[0], r);
             fi.free_reg();
-            fi.emit_return(r, -1);
+            fi.emit_return(line, r, -1);
             return;
         }
     }
@@ -101,9 +105,9 @@ pub fn cg_ret_stat(fi: &mut FuncInfo, exps: &Vec<Exp>) {
     
     let a = fi.used_regs;
     if mult_ret {
-        fi.emit_return(a, -1);
+        fi.emit_return(line, a, -1);
     } else {
-        fi.emit_return(a, n_exps as i32);
+        fi.emit_return(line, a, n_exps as i32);
     }
 }
 
@@ -117,7 +121,8 @@ pub fn is_vararg_or_func_call(exp: &Exp) -> bool {
 
 fn cg_local_func_def_stat(fi: &mut FuncInfo, node: &Stat) {
     if let LocalFuncDefStat {name, exp} = node {
-        let r = fi.add_local_var(name);
+        /* the function can refer to itself, it is active after its closure */
+        let r = fi.add_local_var(name, fi.pc() + 2);
         cg_func_def_exp(fi, exp.as_ref(), r);
     }
 }
@@ -129,16 +134,25 @@ fn cg_func_call_stat(fi: &mut FuncInfo, node: &Stat) {
 }
 
 fn cg_break_stat(fi: &mut FuncInfo, node: &Stat) {
-    let pc = fi.emit_jmp(0, 0);
-    fi.add_break_jmp(pc);
+    if let BreakStat { line } = node {
+        let pc = fi.emit_jmp(*line, 0, 0);
+        fi.add_break_jmp(pc, *line);
+    }
+}
+
+fn cg_goto_stat(fi: &mut FuncInfo, node: &Stat) {
+    if let GotoStat { line, name } = node {
+        let pc = fi.emit_jmp(*line, 0, 0);
+        fi.add_goto(name, *line, pc);
+    }
 }
 
 fn cg_do_stat(fi: &mut FuncInfo, node: &Stat) {
     if let DoStat { block } = node {
         fi.enter_scope(false);
         cg_block(fi, block.as_ref());
-        fi.close_open_upvals();
-        fi.exit_scope();
+        fi.close_open_upvals(block.last_line);
+        fi.exit_scope(fi.pc() + 1);
     }
 }
 
@@ -150,14 +164,15 @@ fn cg_while_stat(fi: &mut FuncInfo, node: &Stat) {
         cg_exp(fi, exp, r, 1);
         fi.free_reg();
         // step 3
-        fi.emit_test(r, 0);
-        let pc_jmp_to_end = fi.emit_jmp(0, 0);
+        let line = last_line_of(exp);
+        fi.emit_test(line, r, 0);
+        let pc_jmp_to_end = fi.emit_jmp(line, 0, 0);
         // step 4
         fi.enter_scope(true);
         cg_block(fi, block.as_ref());
-        fi.close_open_upvals();
-        fi.emit_jmp(0, pc_before_exp - fi.pc() - 1);
-        fi.exit_scope();
+        fi.close_open_upvals(block.last_line);
+        fi.emit_jmp(block.last_line, 0, pc_before_exp - fi.pc() - 1);
+        fi.exit_scope(fi.pc());
         // step 5
         fi.fix_sBx(pc_jmp_to_end, fi.pc() - pc_jmp_to_end);
     }
@@ -168,18 +183,19 @@ fn cg_repeat_stat(fi: &mut FuncInfo, node: &Stat) {
         fi.enter_scope(true);
         
         let pc_before_block = fi.pc();
-        cg_block(fi, block.as_ref());
+        cg_repeat_block(fi, block.as_ref());
         
         let r = fi.alloc_reg();
         cg_exp(fi, exp, r, 1);
         fi.free_reg();
         
-        fi.emit_test(r, 0);
+        let line = last_line_of(exp);
+        fi.emit_test(line, r, 0);
         let tmp_ = fi.get_jmp_argA();
-        fi.emit_jmp(tmp_, pc_before_block - fi.pc() - 1);
-        fi.close_open_upvals();
+        fi.emit_jmp(line, tmp_, pc_before_block - fi.pc() - 1);
+        fi.close_open_upvals(line);
         
-        fi.exit_scope();
+        fi.exit_scope(fi.pc() + 1);
     }
 }
 
@@ -195,15 +211,16 @@ fn cg_if_stat(fi: &mut FuncInfo, node: &Stat) {
             let r = fi.alloc_reg();
             cg_exp(fi, exp, r, 1);
             fi.free_reg();
-            fi.emit_test(r, 0);
-            pc_jmp_to_next_exp = fi.emit_jmp(0, 0);
+            let line = last_line_of(exp);
+            fi.emit_test(line, r, 0);
+            pc_jmp_to_next_exp = fi.emit_jmp(line, 0, 0);
             
             fi.enter_scope(false);
             cg_block(fi, blocks[i].as_ref());
-            fi.close_open_upvals();
-            fi.exit_scope();
+            fi.close_open_upvals(blocks[i].last_line);
+            fi.exit_scope(fi.pc() + 1);
             if i < exps.len() - 1 {
-                pc_jmp_to_ends[i] = fi.emit_jmp(0, 0);
+                pc_jmp_to_ends[i] = fi.emit_jmp(blocks[i].last_line, 0, 0);
             } else {
                 pc_jmp_to_ends[i] = pc_jmp_to_next_exp;
             }
@@ -221,49 +238,57 @@ fn cg_for_num_stat(fi: &mut FuncInfo, node: &Stat) {
         cg_local_var_decl_stat(fi, &LocalVarDeclStat {
             name_list: vec!["(for index)".to_owned(), "(for limit)".to_owned(), "(for step)".to_owned()],
             exp_list: vec![init_exp.clone(), limit_exp.clone(), step_exp.clone()],
-            last_line: 0,
+            last_line: *line_of_do,
         });
-        fi.add_local_var(var_name);
+        fi.add_local_var(var_name, fi.pc() + 2);
         // step 2
         let a = fi.used_regs - 4;
-        let pc_for_prep = fi.emit_for_prep(a, 0);
+        let pc_for_prep = fi.emit_for_prep(*line_of_do, a, 0);
         cg_block(fi, block.as_ref());
-        fi.close_open_upvals();
-        let pc_for_loop = fi.emit_for_loop(a, 0);
+        fi.close_open_upvals(block.last_line);
+        let pc_for_loop = fi.emit_for_loop(*line_of_for, a, 0);
         // step 3
         fi.fix_sBx(pc_for_prep, pc_for_loop - pc_for_prep - 1);
         fi.fix_sBx(pc_for_loop, pc_for_prep - pc_for_loop);
-        fi.exit_scope();
+        /* the loop variable ends with the body, the hidden ones after FORLOOP */
+        fi.exit_scope(fi.pc());
+        fi.fix_end_pc("(for index)", 1);
+        fi.fix_end_pc("(for limit)", 1);
+        fi.fix_end_pc("(for step)", 1);
     }
 }
 
 fn cg_for_in_stat(fi: &mut FuncInfo, node: &Stat) {
-    if let ForInStat { line_of_do, name_list, exp_list, block } = node {
+    if let ForInStat { line_of_for, line_of_do, name_list, exp_list, block } = node {
         fi.enter_scope(true);
         // step 1
         cg_local_var_decl_stat(fi, &LocalVarDeclStat {
             name_list: vec!["(for generator)".to_owned(), "(for state)".to_owned(), "(for control)".to_owned()],
             exp_list: exp_list.clone(),
-            last_line: 0,
+            last_line: *line_of_do,
         });
         for name in name_list.iter() {
-            fi.add_local_var(name);
+            fi.add_local_var(name, fi.pc() + 2);
         }
         // step 2
-        let pc_jmp_to_TFC = fi.emit_jmp(0, 0);
+        let pc_jmp_to_TFC = fi.emit_jmp(*line_of_do, 0, 0);
         cg_block(fi, block.as_ref());
-        fi.close_open_upvals();
+        fi.close_open_upvals(block.last_line);
         fi.fix_sBx(pc_jmp_to_TFC, fi.pc() - pc_jmp_to_TFC);
         // step 3
         let r_generator = fi.slot_of_local_var("(for generator)");
-        fi.emit_tfor_call(r_generator, name_list.len() as i32);
-        fi.emit_tfor_loop(r_generator + 2, pc_jmp_to_TFC - fi.pc() - 1);
-        fi.exit_scope();
+        fi.emit_tfor_call(*line_of_for, r_generator, name_list.len() as i32);
+        fi.emit_tfor_loop(*line_of_for, r_generator + 2, pc_jmp_to_TFC - fi.pc() - 1);
+        /* the loop variables end with the body, the hidden ones after TFORLOOP */
+        fi.exit_scope(fi.pc() - 1);
+        fi.fix_end_pc("(for generator)", 2);
+        fi.fix_end_pc("(for state)", 2);
+        fi.fix_end_pc("(for control)", 2);
     }
 }
 
 fn cg_local_var_decl_stat(fi: &mut FuncInfo, node: &Stat) {
-    if let LocalVarDeclStat { last_line: _last_line, name_list, exp_list } = node {
+    if let LocalVarDeclStat { last_line, name_list, exp_list } = node {
         let exps = remove_tail_nils(exp_list);
         let n_exps = exps.len();
         let n_names = name_list.len();
@@ -299,12 +324,12 @@ fn cg_local_var_decl_stat(fi: &mut FuncInfo, node: &Stat) {
             if !mult_ret {
                 let n = n_names - n_exps;
                 let a = fi.alloc_regs(n as i32);
-                fi.emit_load_nil(a, n as i32);
+                fi.emit_load_nil(*last_line, a, n as i32);
             }
         }
         fi.used_regs = old_regs;
         for name in name_list.iter() {
-            fi.add_local_var(name);
+            fi.add_local_var(name, fi.pc() + 1);
         }
     }
 }
@@ -323,7 +348,7 @@ pub fn remove_tail_nils(exps: &Vec<Exp>) -> Vec<Exp> {
 }
 
 fn cg_assign_stat(fi: &mut FuncInfo, node: &Stat) {
-    if let AssignStat { last_line: _, var_list, exp_list } = node {
+    if let AssignStat { last_line, var_list, exp_list } = node {
         let exps = remove_tail_nils(exp_list);
         let n_exps = exps.len();
         let n_vars = var_list.len();
@@ -370,7 +395,7 @@ fn cg_assign_stat(fi: &mut FuncInfo, node: &Stat) {
             if !mult_ret {
                 let n = n_vars - n_exps;
                 let a = fi.alloc_regs(n as i32);
-                fi.emit_load_nil(a, n as i32);
+                fi.emit_load_nil(*last_line, a, n as i32);
             }
         }
         
@@ -379,19 +404,19 @@ fn cg_assign_stat(fi: &mut FuncInfo, node: &Stat) {
                 let var_name = str;
                 let a = fi.slot_of_local_var(var_name);
                 if a >= 0 {
-                    fi.emit_move(a, v_regs[i]);
+                    fi.emit_move(*last_line, a, v_regs[i]);
                 } else {
                     let b = fi.index_of_upVal(var_name);
                     if b >= 0 {
-                        fi.emit_set_upval(v_regs[i], b);
+                        fi.emit_set_upval(*last_line, v_regs[i], b);
                     } else {
                         let a = fi.index_of_upVal("_ENV");
-                        let b = 0x100 + fi.index_of_constant(&LuaValue::Str(var_name.to_owned()));
-                        fi.emit_set_tab_up(a, b, v_regs[i]);
+                        let b = 0x100 + fi.index_of_constant(&LuaValue::Str(var_name.as_bytes().to_vec()));
+                        fi.emit_set_tab_up(*last_line, a, b, v_regs[i]);
                     }
                 }
             } else {
-                fi.emit_set_table(t_regs[i], k_regs[i], v_regs[i]);
+                fi.emit_set_table(*last_line, t_regs[i], k_regs[i], v_regs[i]);
             }
         }
         fi.used_regs = old_regs;
@@ -2,21 +2,21 @@ use crate::binchunk::binary_chunk::{Prototype, Upvalue};
 use crate::compiler::codegen::func_info::FuncInfo;
 use crate::state::lua_value::LuaValue;
 
-pub fn to_proto(fi: &FuncInfo) -> Prototype {
+pub fn to_proto(fi: &FuncInfo, source: &str) -> Prototype {
     let mut proto = Prototype {
-        source: None,
-        lineDefined: 0,
-        lastLineDefined: 0,
+        source: Some(source.to_owned()),
+        lineDefined: fi.line as u32,
+        lastLineDefined: fi.last_line as u32,
         numParams: fi.num_params as u8,
         isVararg: 0,
         maxStackSize: fi.max_regs as u8,
         code: fi.insts.clone(),
         constants: get_constants(fi),
         upvalues: get_upvalues(fi),
-        protos: to_protos(&fi.sub_funcs),
-        lineInfo: vec![],
-        locVars: vec![],
-        upvalueNames: vec![],
+        protos: to_protos(&fi.sub_funcs, source),
+        lineInfo: fi.line_nums.clone(),
+        locVars: fi.local_vars.clone(),
+        upvalueNames: get_upvalue_names(fi),
     };
     if fi.is_vararg {
         proto.isVararg = 1;
@@ -24,12 +24,12 @@ pub fn to_proto(fi: &FuncInfo) -> Prototype {
     proto
 }
 
-fn to_protos(fis: &Vec<*mut FuncInfo>) -> Vec<Prototype> {
+fn to_protos(fis: &Vec<*mut FuncInfo>, source: &str) -> Vec<Prototype> {
     let mut protos = vec![];
     for fi in fis.iter() {
         unsafe {
             if !fi.is_null() {
-                protos.push(to_proto(&(**fi)));
+                protos.push(to_proto(&(**fi), source));
             }
         }
     }
@@ -62,4 +62,36 @@ fn get_upvalues(fi: &FuncInfo) -> Vec<Upvalue> {
         }
     }
     upvals
-}
\ No newline at end of file
+}
+fn get_upvalue_names(fi: &FuncInfo) -> Vec<String> {
+    let mut names = vec![String::new(); fi.up_values.len()];
+    for (name, uv) in fi.up_values.iter() {
+        names[uv.index as usize] = name.clone();
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::codegen::compile;
+
+    #[test]
+    fn test_debug_info() {
+        let src = "local a = 1\nlocal function f(x)\n  for i = 1, x do\n    a = a + i\n  end\nend\ng = f(2)\n";
+        let proto = compile(src.as_bytes().to_vec(), String::from("@test.lua")).unwrap();
+        assert_eq!(proto.source.as_deref(), Some("@test.lua"));
+        assert_eq!((proto.lineDefined, proto.lastLineDefined), (0, 0));
+        assert_eq!(proto.lineInfo, vec![1, 6, 7, 7, 7, 7, 7]);
+        let locals: Vec<_> = proto.locVars.iter().map(|v| (v.varName.as_str(), v.startPC, v.endPC)).collect();
+        assert_eq!(locals, vec![("a", 1, 7), ("f", 2, 7)]);
+        assert_eq!(proto.upvalueNames, vec!["_ENV"]);
+
+        let f = &proto.protos[0];
+        assert_eq!(f.source.as_deref(), Some("@test.lua"));
+        assert_eq!((f.lineDefined, f.lastLineDefined), (2, 6));
+        assert_eq!(f.lineInfo, vec![3, 3, 3, 3, 4, 4, 4, 4, 3, 6]);
+        let locals: Vec<_> = f.locVars.iter().map(|v| (v.varName.as_str(), v.startPC, v.endPC)).collect();
+        assert_eq!(locals, vec![("x", 0, 10), ("(for index)", 3, 9), ("(for limit)", 3, 9), ("(for step)", 3, 9), ("i", 4, 8)]);
+        assert_eq!(f.upvalueNames, vec!["a"]);
+    }
+}
@@ -4,9 +4,11 @@ use std::{
 };
 use super::super::{
     ast::exp::Exp,
+    codegen::compile_error,
     lexer::token::*,
 };
 use crate::{
+    binchunk::binary_chunk::LocVar,
     vm::{
         instruction::*,
         opcodes::*,
@@ -72,20 +74,26 @@ pub struct FuncInfo {
     pub used_regs: i32,
     pub max_regs: i32,
     pub scope_level: i32,
-    pub local_vars: Vec<*mut LocalVarInfo>,
+    pub local_vars: Vec<LocVar>,        // debug info of all locals, in order of declaration
     pub local_names: HashMap<String, *mut LocalVarInfo>,
     pub breaks: Vec<Option<Vec<i32>>>,
+    pub blocks: Vec<BlockCnt>,
+    pub labels: Vec<LabelDesc>,         // active labels
+    pub gotos: Vec<LabelDesc>,          // pending gotos
     pub parent: *mut FuncInfo,
     pub up_values: HashMap<String, UpValInfo>,
     pub insts: Vec<u32>,
+    pub line_nums: Vec<u32>,            // source line of each instruction
     pub sub_funcs: Vec<*mut FuncInfo>,
     pub num_params: i32,
     pub is_vararg: bool,
+    pub line: i32,
+    pub last_line: i32,
 }
 
 impl FuncInfo {
     pub fn new(fd: &Exp) -> Self {
-        if let Exp::FuncDefExp { par_list, is_vararg, .. } = fd {
+        if let Exp::FuncDefExp { line, last_line, par_list, is_vararg, .. } = fd {
             return FuncInfo {
                 parent: null_mut(),
                 sub_funcs: vec![],
@@ -94,8 +102,14 @@ impl FuncInfo {
                 local_names: HashMap::new(),
                 local_vars: vec![],
                 breaks: vec![],
+                blocks: vec![],
+                labels: vec![],
+                gotos: vec![],
                 insts: vec![],
+                line_nums: vec![],
                 is_vararg: *is_vararg,
+                line: *line,
+                last_line: *last_line,
                 num_params: par_list.len() as i32,
                 used_regs: 0,
                 max_regs: 0,
@@ -122,7 +136,7 @@ impl FuncInfo {
     }
     
     pub fn new_ptr(parent: *mut FuncInfo, fd: &Exp) -> *mut Self {
-        if let Exp::FuncDefExp { par_list, is_vararg, .. } = fd {
+        if let Exp::FuncDefExp { line, last_line, par_list, is_vararg, .. } = fd {
             let func_info_ret = Box::into_raw(Box::new(FuncInfo {
                 parent,
                 sub_funcs: vec![],
@@ -131,8 +145,14 @@ impl FuncInfo {
                 local_names: HashMap::new(),
                 local_vars: vec![],
                 breaks: vec![None],
+                blocks: vec![],
+                labels: vec![],
+                gotos: vec![],
                 insts: vec![],
+                line_nums: vec![],
                 is_vararg: *is_vararg,
+                line: *line,
+                last_line: *last_line,
                 num_params: par_list.len() as i32,
                 used_regs: 0,
                 max_regs: 0,
@@ -159,7 +179,6 @@ impl FuncInfo {
     }
     
     fn _self_drop_ptr(ptr_self: *mut Self) {
-        // pub local_vars: Vec<*mut LocalVarInfo>,
         // pub local_names: HashMap<String, *mut LocalVarInfo>,
         // pub parent: *mut FuncInfo,
         // pub sub_funcs: Vec<*mut FuncInfo>,
@@ -185,7 +204,7 @@ impl FuncInfo {
     pub fn alloc_reg(&mut self) -> i32 {
         self.used_regs += 1;
         if self.used_regs >= 255 {
-            panic!("function or expression needs too many registers.");
+            compile_error(self.line, String::from("function or expression needs too many registers"), None);
         }
         if self.used_regs > self.max_regs {
             self.max_regs = self.used_regs;
@@ -219,7 +238,8 @@ impl FuncInfo {
         }
     }
     
-    pub fn add_local_var(&mut self, name: &str) -> i32 {
+    // Declares a local which is active from instruction `start_pc` on.
+    pub fn add_local_var(&mut self, name: &str, start_pc: i32) -> i32 {
         let _prev_ = if let Some(val) = self.local_names.get(name) {
             *val
         } else {
@@ -231,9 +251,14 @@ impl FuncInfo {
             prev: _prev_,
             scope_level: self.scope_level,
             slot: self.alloc_reg(),
+            index: self.local_vars.len(),
             captured: false,
         }));
-        self.local_vars.push(new_var);
+        self.local_vars.push(LocVar {
+            varName: name.to_owned(),
+            startPC: start_pc as u32,
+            endPC: 0,
+        });
         self.local_names.insert(name.to_owned(), new_var);
         unsafe {
             (*new_var).slot
@@ -251,7 +276,8 @@ impl FuncInfo {
         -1
     }
     
-    pub fn exit_scope(&mut self) {
+    // Leaves the current scope, its locals are active until `end_pc`.
+    pub fn exit_scope(&mut self, end_pc: i32) {
         let pending_break_jmps = self.breaks.pop();
         let a = self.get_jmp_argA();
         if let Some(Some(_pending_break_jmps)) = pending_break_jmps {
@@ -268,15 +294,16 @@ impl FuncInfo {
         unsafe {
             for (_, local_var) in (&*self_ptr).local_names.iter() {
                 if !local_var.is_null() && (**local_var).scope_level > self.scope_level {
-                    self.remove_local_var(*local_var);
+                    self.remove_local_var(*local_var, end_pc);
                 }
             }
         }
     }
     
-    pub fn remove_local_var(&mut self, local_var: *mut LocalVarInfo) {
+    pub fn remove_local_var(&mut self, local_var: *mut LocalVarInfo, end_pc: i32) {
         self.free_reg();
         unsafe {
+            self.local_vars[(*local_var).index].endPC = end_pc as u32;
             if (*local_var).prev.is_null() {
                 if let Some(res) = self.local_names.remove(&(*local_var).name) {
                     if !res.is_null() {
@@ -284,14 +311,22 @@ impl FuncInfo {
                     }
                 }
             } else if (*(*local_var).prev).scope_level == (*local_var).scope_level {
-                self.remove_local_var((*local_var).prev);
+                self.remove_local_var((*local_var).prev, end_pc);
             } else {
                 self.local_names.insert((*local_var).name.clone(), (*local_var).prev);
             }
         }
     }
     
-    pub fn add_break_jmp(&mut self, pc: i32) {
+    // Moves the end of the latest local `name` by `delta` instructions, for
+    // the hidden loop variables which outlive the body of their loop.
+    pub fn fix_end_pc(&mut self, name: &str, delta: i32) {
+        if let Some(var) = self.local_vars.iter_mut().rev().find(|v| v.varName == name) {
+            var.endPC = (var.endPC as i32 + delta) as u32;
+        }
+    }
+
+    pub fn add_break_jmp(&mut self, pc: i32, line: i32) {
         let tmp_scope_level = self.scope_level;
         for i in (0..=tmp_scope_level).rev() {
             if i < self.breaks.len() as i32 {
@@ -301,9 +336,124 @@ impl FuncInfo {
                 }
             }
         }
-        panic!("<break> at line ? not inside a loop!");
+        compile_error(line, format!("<break> at line {} not inside a loop", line), None);
     }
     
+    pub fn enter_block(&mut self) {
+        self.blocks.push(BlockCnt {
+            first_label: self.labels.len(),
+            first_goto: self.gotos.len(),
+            n_act_var: self.used_regs,
+        });
+    }
+
+    pub fn leave_block(&mut self) {
+        let bl = self.blocks.pop().unwrap();
+        self.labels.truncate(bl.first_label);      /* remove local labels */
+        if !self.blocks.is_empty() {
+            self.move_gotos_out(&bl);           /* update pending gotos to outer block */
+        } else if let Some(gt) = self.gotos.get(bl.first_goto) {
+            compile_error(gt.line, format!("no visible label '{}' for <goto> at line {}", gt.name, gt.line), None);
+        }
+    }
+
+    pub fn add_label(&mut self, name: &str, line: i32, last: bool) {
+        let bl = self.blocks.last().unwrap().clone();
+        for lb in self.labels[bl.first_label..].iter() {
+            if lb.name == name {
+                compile_error(line, format!("label '{}' already defined on line {}", name, lb.line), None);
+            }
+        }
+        let lb = LabelDesc {
+            name: name.to_owned(),
+            pc: self.pc() + 1,
+            line,
+            // a label at the end of a block is outside the scope of its locals
+            n_act_var: if last { bl.n_act_var } else { self.used_regs },
+        };
+        /* solve pending gotos of the current block */
+        let mut i = bl.first_goto;
+        while i < self.gotos.len() {
+            if self.gotos[i].name == name {
+                self.close_goto(i, &lb);
+            } else {
+                i += 1;
+            }
+        }
+        self.labels.push(lb);
+    }
+
+    pub fn add_goto(&mut self, name: &str, line: i32, pc: i32) {
+        self.gotos.push(LabelDesc {
+            name: name.to_owned(),
+            pc,
+            line,
+            n_act_var: self.used_regs,
+        });
+        self.find_label(self.gotos.len() - 1);
+    }
+
+    // Tries to close goto `g` with a label visible in the current block.
+    fn find_label(&mut self, g: usize) -> bool {
+        let first_label = self.blocks.last().unwrap().first_label;
+        for i in first_label..self.labels.len() {
+            if self.labels[i].name == self.gotos[g].name {
+                let lb = self.labels[i].clone();
+                if self.gotos[g].n_act_var > lb.n_act_var {
+                    self.patch_close(self.gotos[g].pc, lb.n_act_var);
+                }
+                self.close_goto(g, &lb);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn close_goto(&mut self, g: usize, lb: &LabelDesc) {
+        let gt = self.gotos.remove(g);
+        if gt.n_act_var < lb.n_act_var {
+            let var_name = self.name_of_local_var(gt.n_act_var).unwrap_or_default();
+            compile_error(gt.line, format!("<goto {}> at line {} jumps into the scope of local '{}'", gt.name, gt.line, var_name), None);
+        }
+        self.fix_sBx(gt.pc, lb.pc - gt.pc - 1);
+    }
+
+    fn move_gotos_out(&mut self, bl: &BlockCnt) {
+        let mut i = bl.first_goto;
+        while i < self.gotos.len() {
+            if self.gotos[i].n_act_var > bl.n_act_var {
+                /* leaving the block closes the upvalues of its locals */
+                self.patch_close(self.gotos[i].pc, bl.n_act_var);
+                self.gotos[i].n_act_var = bl.n_act_var;
+            }
+            if !self.find_label(i) {
+                i += 1;
+            }
+        }
+    }
+
+    // Lets the jump at `pc` close upvalues from register `level` on.
+    fn patch_close(&mut self, pc: i32, level: i32) {
+        let i = self.insts[pc as usize];
+        let i = (i & !(0xff << 6)) | (((level + 1) as u32) << 6);
+        self.insts[pc as usize] = i;
+    }
+
+    fn name_of_local_var(&self, slot: i32) -> Option<String> {
+        for (_, local_var) in self.local_names.iter() {
+            let mut v = *local_var;
+            unsafe {
+                while !v.is_null() {
+                    if (*v).slot == slot {
+                        return Some((*v).name.clone());
+                    }
+                    v = (*v).prev;
+                }
+            }
+        }
+        None
+    }
+
     pub fn index_of_upVal(&mut self, name: &str) -> i32 {
         if let Some(upval) = self.up_values.get(name) {
             return upval.index;
@@ -365,24 +515,28 @@ impl FuncInfo {
         }
     }
     
-    pub fn emit_ABC(&mut self, opcode: i32, a: i32, b: i32, c: i32) {
+    pub fn emit_ABC(&mut self, line: i32, opcode: i32, a: i32, b: i32, c: i32) {
         let i = b << 23 | c << 14 | a << 6 | opcode;
         self.insts.push(i as u32);
+        self.line_nums.push(line as u32);
     }
 
-    pub fn emit_ABx(&mut self, opcode: i32, a: i32, bx: i32) {
+    pub fn emit_ABx(&mut self, line: i32, opcode: i32, a: i32, bx: i32) {
         let i = bx << 14 | a << 6 | opcode;
         self.insts.push(i as u32);
+        self.line_nums.push(line as u32);
     }
 
-    pub fn emit_AsBx(&mut self, opcode: i32, a: i32, b: i32) {
+    pub fn emit_AsBx(&mut self, line: i32, opcode: i32, a: i32, b: i32) {
         let i = (b + MAXARG_sBx) << 14 | a << 6 | opcode;
         self.insts.push(i as u32);
+        self.line_nums.push(line as u32);
     }
 
-    pub fn emit_Ax(&mut self, opcode: i32, ax: i32) {
+    pub fn emit_Ax(&mut self, line: i32, opcode: i32, ax: i32) {
         let i = ax << 6 | opcode;
         self.insts.push(i as u32);
+        self.line_nums.push(line as u32);
     }
     
     pub fn pc(&self) -> i32 {
@@ -396,146 +550,146 @@ impl FuncInfo {
         self.insts[pc as usize] = i;
     }
 
-    pub fn emit_move(&mut self, a: i32, b: i32) {
-        self.emit_ABC(OP_MOVE as i32, a, b, 0)
+    pub fn emit_move(&mut self, line: i32, a: i32, b: i32) {
+        self.emit_ABC(line, OP_MOVE as i32, a, b, 0)
     }
 
-    pub fn emit_load_nil(&mut self, a: i32, n: i32) {
-        self.emit_ABC(OP_LOADNIL as i32, a, n - 1, 0)
+    pub fn emit_load_nil(&mut self, line: i32, a: i32, n: i32) {
+        self.emit_ABC(line, OP_LOADNIL as i32, a, n - 1, 0)
     }
 
-    pub fn emit_load_bool(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_LOADBOOL as i32, a, b, c);
+    pub fn emit_load_bool(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_LOADBOOL as i32, a, b, c);
     }
 
-    pub fn emit_load_K(&mut self, a: i32, k: &LuaValue) {
+    pub fn emit_load_K(&mut self, line: i32, a: i32, k: &LuaValue) {
         let idx = self.index_of_constant(k);
         if idx < (1 << 18) {
-            self.emit_ABx(OP_LOADK as i32, a, idx);
+            self.emit_ABx(line, OP_LOADK as i32, a, idx);
         } else {
-            self.emit_ABx(OP_LOADKX as i32, a, 0);
-            self.emit_Ax(OP_EXTRAARG as i32, idx);
+            self.emit_ABx(line, OP_LOADKX as i32, a, 0);
+            self.emit_Ax(line, OP_EXTRAARG as i32, idx);
         }
     }
 
     // r[a], r[a+1], ..., r[a+b-2] = vararg
-    pub fn emit_vararg(&mut self, a: i32, n: i32) {
-        self.emit_ABC(OP_VARARG as i32, a, n + 1, 0);
+    pub fn emit_vararg(&mut self, line: i32, a: i32, n: i32) {
+        self.emit_ABC(line, OP_VARARG as i32, a, n + 1, 0);
     }
 
     // r[a] = emitClosure(proto[bx])
-    pub fn emit_closure(&mut self, a: i32, bx: i32) {
-        self.emit_ABx(OP_CLOSURE as i32, a, bx);
+    pub fn emit_closure(&mut self, line: i32, a: i32, bx: i32) {
+        self.emit_ABx(line, OP_CLOSURE as i32, a, bx);
     }
 
     // r[a] = {}
-    pub fn emit_new_table(&mut self, a: i32, n_arr: i32, n_rec: i32) {
-        self.emit_ABC(OP_NEWTABLE as i32, a, int2fb(n_arr as usize) as i32, int2fb(n_rec as usize) as i32);
+    pub fn emit_new_table(&mut self, line: i32, a: i32, n_arr: i32, n_rec: i32) {
+        self.emit_ABC(line, OP_NEWTABLE as i32, a, int2fb(n_arr as usize) as i32, int2fb(n_rec as usize) as i32);
     }
 
     // r[a][(c-1)*FPF+i] := r[a+i], 1 <= i <= b
-    pub fn emit_set_list(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_SETLIST as i32, a, b, c);
+    pub fn emit_set_list(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_SETLIST as i32, a, b, c);
     }
 
     // r[a] := r[b][rk(c)]
-    pub fn emit_get_table(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_GETTABLE as i32, a, b, c);
+    pub fn emit_get_table(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_GETTABLE as i32, a, b, c);
     }
 
     // r[a][rk(b)] = rk(c)
-    pub fn emit_set_table(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_SETTABLE as i32, a, b, c);
+    pub fn emit_set_table(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_SETTABLE as i32, a, b, c);
     }
 
     // r[a] = upval[b]
-    pub fn emit_get_upval(&mut self, a: i32, b: i32) {
-        self.emit_ABC(OP_GETUPVAL as i32, a, b, 0);
+    pub fn emit_get_upval(&mut self, line: i32, a: i32, b: i32) {
+        self.emit_ABC(line, OP_GETUPVAL as i32, a, b, 0);
     }
 
     // upval[b] = r[a]
-    pub fn emit_set_upval(&mut self, a: i32, b: i32) {
-        self.emit_ABC(OP_SETUPVAL as i32, a, b, 0);
+    pub fn emit_set_upval(&mut self, line: i32, a: i32, b: i32) {
+        self.emit_ABC(line, OP_SETUPVAL as i32, a, b, 0);
     }
 
     // r[a] = upval[b][rk(c)]
-    pub fn emit_get_tab_up(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_GETTABUP as i32, a, b, c);
+    pub fn emit_get_tab_up(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_GETTABUP as i32, a, b, c);
     }
 
     // upval[a][rk(b)] = rk(c)
-    pub fn emit_set_tab_up(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_SETTABUP as i32, a, b, c);
+    pub fn emit_set_tab_up(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_SETTABUP as i32, a, b, c);
     }
 
     // r[a], ..., r[a+c-2] = r[a](r[a+1], ..., r[a+b-1])
-    pub fn emit_call(&mut self, a: i32, n_args: i32, n_rec: i32) {
-        self.emit_ABC(OP_CALL as i32, a, n_args + 1, n_rec + 1);
+    pub fn emit_call(&mut self, line: i32, a: i32, n_args: i32, n_rec: i32) {
+        self.emit_ABC(line, OP_CALL as i32, a, n_args + 1, n_rec + 1);
     }
 
     // return r[a](r[a+1], ... ,r[a+b-1])
-    pub fn emit_tail_call(&mut self, a: i32, n_args: i32) {
-        self.emit_ABC(OP_TAILCALL as i32, a, n_args + 1, 0);
+    pub fn emit_tail_call(&mut self, line: i32, a: i32, n_args: i32) {
+        self.emit_ABC(line, OP_TAILCALL as i32, a, n_args + 1, 0);
     }
 
     // return r[a], ... ,r[a+b-2]
-    pub fn emit_return(&mut self, a: i32, n: i32) {
-        self.emit_ABC(OP_RETURN as i32, a, n + 1, 0);
+    pub fn emit_return(&mut self, line: i32, a: i32, n: i32) {
+        self.emit_ABC(line, OP_RETURN as i32, a, n + 1, 0);
     }
 
     // r[a+1] := r[b]; r[a] := r[b][rk(c)]
-    pub fn emit_self(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_SELF as i32, a, b, c);
+    pub fn emit_self(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_SELF as i32, a, b, c);
     }
 
     // pc+=sBx; if (a) close all upvalues >= r[a - 1]
-    pub fn emit_jmp(&mut self, a: i32, sbx: i32) -> i32 {
-        self.emit_AsBx(OP_JMP as i32, a, sbx);
+    pub fn emit_jmp(&mut self, line: i32, a: i32, sbx: i32) -> i32 {
+        self.emit_AsBx(line, OP_JMP as i32, a, sbx);
         self.insts.len() as i32 - 1
     }
     
     // if not (r[a] <=> c) then pc++
-    pub fn emit_test(&mut self, a: i32, c: i32) {
-        self.emit_ABC(OP_TEST as i32, a, 0, c);
+    pub fn emit_test(&mut self, line: i32, a: i32, c: i32) {
+        self.emit_ABC(line, OP_TEST as i32, a, 0, c);
     }
     
     // if (r[b] <=> c) then r[a] := r[b] else pc++
-    pub fn emit_test_set(&mut self, a: i32, b: i32, c: i32) {
-        self.emit_ABC(OP_TESTSET as i32, a, b, c);
+    pub fn emit_test_set(&mut self, line: i32, a: i32, b: i32, c: i32) {
+        self.emit_ABC(line, OP_TESTSET as i32, a, b, c);
     }
 
-    pub fn emit_for_prep(&mut self, a: i32, sbx: i32) -> i32 {
-        self.emit_AsBx(OP_FORPREP as i32, a, sbx);
+    pub fn emit_for_prep(&mut self, line: i32, a: i32, sbx: i32) -> i32 {
+        self.emit_AsBx(line, OP_FORPREP as i32, a, sbx);
         self.insts.len() as i32 - 1
     }
 
-    pub fn emit_for_loop(&mut self, a: i32, sbx: i32) -> i32 {
-        self.emit_AsBx(OP_FORLOOP as i32, a, sbx);
+    pub fn emit_for_loop(&mut self, line: i32, a: i32, sbx: i32) -> i32 {
+        self.emit_AsBx(line, OP_FORLOOP as i32, a, sbx);
         self.insts.len() as i32 - 1
     }
 
-    pub fn emit_tfor_call(&mut self, a: i32, c: i32) {
-        self.emit_ABC(OP_TFORCALL as i32, a, 0, c);
+    pub fn emit_tfor_call(&mut self, line: i32, a: i32, c: i32) {
+        self.emit_ABC(line, OP_TFORCALL as i32, a, 0, c);
     }
 
-    pub fn emit_tfor_loop(&mut self, a: i32, sbx: i32) {
-        self.emit_AsBx(OP_TFORLOOP as i32, a, sbx);
+    pub fn emit_tfor_loop(&mut self, line: i32, a: i32, sbx: i32) {
+        self.emit_AsBx(line, OP_TFORLOOP as i32, a, sbx);
     }
     
     // r[a] = op r[b]
-    pub fn emit_unary_op(&mut self, op: i32, a: i32, b: i32) {
+    pub fn emit_unary_op(&mut self, line: i32, op: i32, a: i32, b: i32) {
         match op{
             TOKEN_OP_NOT => {
-                self.emit_ABC(OP_NOT as i32, a, b, 0);
+                self.emit_ABC(line, OP_NOT as i32, a, b, 0);
             },
             TOKEN_OP_BNOT => {
-                self.emit_ABC(OP_BNOT as i32, a, b, 0);
+                self.emit_ABC(line, OP_BNOT as i32, a, b, 0);
             },
             TOKEN_OP_LEN => {
-                self.emit_ABC(OP_LEN as i32, a, b, 0);
+                self.emit_ABC(line, OP_LEN as i32, a, b, 0);
             },
             TOKEN_OP_UNM => {
-                self.emit_ABC(OP_UNM as i32, a, b, 0);
+                self.emit_ABC(line, OP_UNM as i32, a, b, 0);
             },
             _ => {},
         }
@@ -543,29 +697,29 @@ impl FuncInfo {
     
     // r[a] = rk[b] op rk[c]
     // arith & bitwise & relational
-    pub fn emit_binary_op(&mut self, op: i32, a: i32, b: i32, c: i32) {
+    pub fn emit_binary_op(&mut self, line: i32, op: i32, a: i32, b: i32, c: i32) {
         if let Some(opcode) = arith_to_bitwise_binops(op) {
-            self.emit_ABC(opcode as i32, a, b, c);
+            self.emit_ABC(line, opcode as i32, a, b, c);
         } else {
             match op {
-                TOKEN_OP_EQ => self.emit_ABC(OP_EQ as i32, 1, b, c),
-                TOKEN_OP_NE => self.emit_ABC(OP_EQ as i32, 0, b, c),
-                TOKEN_OP_LT => self.emit_ABC(OP_LT as i32, 1, b, c),
-                TOKEN_OP_GT => self.emit_ABC(OP_LT as i32, 1, c, b),
-                TOKEN_OP_LE => self.emit_ABC(OP_LE as i32, 1, b, c),
-                TOKEN_OP_GE => self.emit_ABC(OP_LE as i32, 1, c, b),
+                TOKEN_OP_EQ => self.emit_ABC(line, OP_EQ as i32, 1, b, c),
+                TOKEN_OP_NE => self.emit_ABC(line, OP_EQ as i32, 0, b, c),
+                TOKEN_OP_LT => self.emit_ABC(line, OP_LT as i32, 1, b, c),
+                TOKEN_OP_GT => self.emit_ABC(line, OP_LT as i32, 1, c, b),
+                TOKEN_OP_LE => self.emit_ABC(line, OP_LE as i32, 1, b, c),
+                TOKEN_OP_GE => self.emit_ABC(line, OP_LE as i32, 1, c, b),
                 _ => {},
             }
-            self.emit_jmp(0, 1);
-            self.emit_load_bool(a, 0, 1);
-            self.emit_load_bool(a, 1, 0);
+            self.emit_jmp(line, 0, 1);
+            self.emit_load_bool(line, a, 0, 1);
+            self.emit_load_bool(line, a, 1, 0);
         }
     }
     
-    pub fn close_open_upvals(&mut self) {
+    pub fn close_open_upvals(&mut self, line: i32) {
         let a = self.get_jmp_argA();
         if a > 0 {
-            self.emit_jmp(a, 0);
+            self.emit_jmp(line, a, 0);
         }
     }
 }
@@ -582,9 +736,25 @@ pub struct LocalVarInfo {
     name: String,
     scope_level: i32,
     slot: i32,
+    index: usize,                       // of its debug info in `local_vars`
     captured: bool,
 }
 
+#[derive(Clone, Debug)]
+pub struct LabelDesc {
+    pub name: String,
+    pub pc: i32,
+    pub line: i32,
+    pub n_act_var: i32,                 // number of active locals at that position
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockCnt {
+    pub first_label: usize,             // index of first label in this block
+    pub first_goto: usize,              // index of first pending goto in this block
+    pub n_act_var: i32,                 // number of active locals outside the block
+}
+
 #[derive(Clone, Debug)]
 pub struct UpValInfo {
     pub local_var_slot: i32,
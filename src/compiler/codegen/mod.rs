@@ -1,3 +1,4 @@
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 use crate::binchunk::binary_chunk::Prototype;
 use crate::compiler::ast::block::Block;
 use crate::compiler::ast::exp::Exp::FuncDefExp;
@@ -5,6 +6,7 @@ use std::rc::Rc;
 use crate::compiler::codegen::cg_exp::cg_func_def_exp;
 use crate::compiler::codegen::fi2proto::to_proto;
 use crate::compiler::codegen::func_info::FuncInfo;
+use crate::compiler::error::CompileError;
 use crate::compiler::parser::parse;
 
 pub mod func_info;
@@ -13,24 +15,48 @@ pub mod cg_stat;
 mod cg_exp;
 mod fi2proto;
 
-fn gen_proto(chunk: Rc<Block>) -> Prototype {
+fn gen_proto(chunk: Rc<Block>, chunk_name: &str) -> Prototype {
     let fd = FuncDefExp {
         line: 0,
-        last_line: 0,
+        last_line: chunk.last_line,
         par_list: vec![],
         is_vararg: true,
         block: chunk.clone(),
     };
     let mut fi = FuncInfo::new(&fd);
-    fi.add_local_var("_ENV");
+    fi.add_local_var("_ENV", 0);
     cg_func_def_exp(&mut fi, &fd, 0);
-    unsafe {
-        to_proto(&*fi.sub_funcs[0])
-    }
+    let mut proto = unsafe {
+        to_proto(&*fi.sub_funcs[0], chunk_name)
+    };
+    proto.lastLineDefined = 0;      /* the main function is <chunk:0,0> */
+    proto
 }
 
-pub fn compile(chunk: String, chunk_name: String) -> Prototype {
-    let ast = parse(chunk, chunk_name);
+pub fn compile(chunk: Vec<u8>, chunk_name: String) -> Result<Prototype, CompileError> {
+    let ast = parse(chunk, chunk_name.clone())?;
     // println!("{:#?}", *ast);
-    gen_proto(ast.clone())
+    match catch_unwind(AssertUnwindSafe(|| gen_proto(ast, &chunk_name))) {
+        Ok(proto) => Ok(proto),
+        Err(payload) => match payload.downcast::<CompileError>() {
+            Ok(mut err) => {
+                err.chunk_name = chunk_name;
+                Err(*err)
+            },
+            Err(payload) => resume_unwind(payload),
+        },
+    }
+}
+
+// Errors found while generating code (a `goto` without a visible label,
+// too many registers...) are raised from deep in the generator, they
+// unwind up to `compile` like Lua errors do up to `PCall`.
+pub fn compile_error(line: i32, msg: String, token: Option<&str>) -> ! {
+    resume_unwind(Box::new(CompileError {
+        chunk_name: String::new(),
+        line,
+        column: 0,
+        token: token.map(String::from),
+        msg,
+    }))
 }
\ No newline at end of file
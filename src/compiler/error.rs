@@ -0,0 +1,101 @@
+use std::fmt;
+
+// An error found while compiling a chunk. It is shown like the ones of
+// the reference compiler, e.g. `file.lua:3: '=' expected near 'x'`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub chunk_name: String,     // as given to `compile`, e.g. "@file.lua"
+    pub line: i32,
+    pub column: i32,            // 1-based, 0 if the error is not at a token
+    pub token: Option<String>,  // the offending token, e.g. "'x'" or "<eof>"
+    pub msg: String,            // e.g. "'=' expected"
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", chunk_id(&self.chunk_name), self.line, self.msg)?;
+        if let Some(token) = &self.token {
+            write!(f, " near {}", token)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+const LUA_IDSIZE: usize = 60;
+
+// The name of a chunk for messages, like `luaO_chunkid`: "=name" is shown
+// as is, "@file" as the file name, and other sources as `[string "..."]`.
+pub fn chunk_id(source: &str) -> String {
+    let bufflen = LUA_IDSIZE - 1;
+    if let Some(name) = source.strip_prefix('=') {     /* 'literal' source */
+        name.chars().take(bufflen).collect()
+    } else if let Some(file) = source.strip_prefix('@') {  /* file name */
+        let n = file.chars().count();
+        if n <= bufflen {
+            file.to_owned()
+        } else {    /* keep its end */
+            format!("...{}", file.chars().skip(n - (bufflen - 3)).collect::<String>())
+        }
+    } else {        /* string; format as [string "source"] */
+        let bufflen = bufflen - "[string \"...\"]".len();
+        let line = source.split(['\n', '\r']).next().unwrap_or("");
+        if line.len() == source.len() && source.chars().count() < bufflen {
+            format!("[string \"{}\"]", source)
+        } else {
+            format!("[string \"{}...\"]", line.chars().take(bufflen).collect::<String>())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::codegen::compile;
+    use super::chunk_id;
+
+    fn compile_error(src: &str) -> String {
+        compile(src.as_bytes().to_vec(), String::from("=test")).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let err = compile(b"a = 1\nb = 2\n  c x".to_vec(), String::from("@file.lua")).unwrap_err();
+        assert_eq!(err.to_string(), "file.lua:3: '=' expected near 'x'");
+        assert_eq!((err.line, err.column, err.token.as_deref()), (3, 5, Some("'x'")));
+
+        assert_eq!(compile_error("x = 1\ny = = 2"), "test:2: unexpected symbol near '='");
+        assert_eq!(compile_error("f() = 1"), "test:1: syntax error near '='");
+        assert_eq!(compile_error("local 1"), "test:1: <name> expected near '1'");
+        assert_eq!(compile_error("x = 1 end"), "test:1: <eof> expected near 'end'");
+        assert_eq!(compile_error("print((1)"), "test:1: ')' expected near <eof>");
+        assert_eq!(compile_error("t = {1,\n2"), "test:2: '}' expected (to close '{' at line 1) near <eof>");
+        assert_eq!(compile_error("function f()\n  return 1\n"), "test:3: 'end' expected (to close 'function' at line 1) near <eof>");
+        assert_eq!(compile_error("a:b"), "test:1: function arguments expected near <eof>");
+    }
+
+    #[test]
+    fn test_lexical_errors() {
+        assert_eq!(compile_error("x = @"), "test:1: unexpected symbol near '@'");
+        assert_eq!(compile_error("x = \x01"), "test:1: unexpected symbol near '<\\1>'");
+        assert_eq!(compile_error("x = 3..2"), "test:1: malformed number near '3..2'");
+        assert_eq!(compile_error("x = \"abc"), "test:1: unfinished string near <eof>");
+        assert_eq!(compile_error("x = \"abc\ny\""), "test:1: unfinished string near '\"abc'");
+        assert_eq!(compile_error("x = [[\nabc"), "test:2: unfinished long string (starting at line 1) near <eof>");
+        assert_eq!(compile_error("--[==[\n"), "test:2: unfinished long comment (starting at line 1) near <eof>");
+        assert_eq!(compile_error("x = [=a"), "test:1: invalid long string delimiter near '[='");
+        assert_eq!(compile_error("x = \"\\q\""), "test:1: invalid escape sequence near '\"\\q'");
+        assert_eq!(compile_error("x = \"\\300\""), "test:1: decimal escape too large near '\"\\300'");
+        assert_eq!(compile_error("x = \"\\xZZ\""), "test:1: hexadecimal digit expected near '\"\\xZ'");
+        assert_eq!(compile_error("x = \"\\u123\""), "test:1: missing '{' near '\"\\u1'");
+    }
+
+    #[test]
+    fn test_chunk_id() {
+        assert_eq!(chunk_id("=stdin"), "stdin");
+        assert_eq!(chunk_id("@dir/file.lua"), "dir/file.lua");
+        assert_eq!(chunk_id(&format!("@{}", "d/".repeat(40))), format!("...{}", "d/".repeat(28)));
+        assert_eq!(chunk_id("return 1"), "[string \"return 1\"]");
+        assert_eq!(chunk_id("x = 1\nreturn x"), "[string \"x = 1...\"]");
+    }
+}
@@ -1,141 +1,162 @@
-use regex::Regex;
+use regex::bytes::Regex;
+use super::super::error::CompileError;
 use super::token::*;
 
 #[allow(dead_code)]
 pub struct Lexer {
-    chunk: String,
+    chunk: Vec<u8>,             // source bytes, string literals may hold any of them
+    pos: usize,                 // start of the part not scanned yet
     chunk_name: String,
     line: i32,
+    token_pos: (usize, usize),  // source range of the last token read, for errors
     next_token_: String,
     next_token_kind: Token,
     next_token_line: i32,
+    next_token_pos: (usize, usize),
+    string_: Vec<u8>,           // bytes of the last string literal scanned
 }
 
 impl Lexer {
-    pub fn new(chunk: String, chunk_name: String) -> Self {
+    pub fn new(chunk: Vec<u8>, chunk_name: String) -> Self {
         Lexer {
             chunk: chunk,
+            pos: 0,
             chunk_name: chunk_name,
             line: 1,
+            token_pos: (0, 0),
             next_token_: "".to_owned(),
             next_token_kind: TOKEN_INIT_VOID,
             next_token_line: -1,
+            next_token_pos: (0, 0),
+            string_: vec![],
         }
     }
 
+    fn rest(&self) -> &[u8] {
+        &self.chunk[self.pos..]
+    }
+
     fn get_nth_char(&self, inx: usize) -> char {
-        self.chunk.chars().nth(inx).unwrap()
+        self.rest()[inx] as char
     }
     
-    pub fn next_token(&mut self) -> (i32, Token, String) {
+    pub fn next_token(&mut self) -> Result<(i32, Token, String), CompileError> {
         if self.next_token_line > 0 {
             let line = self.next_token_line;
             let kind = self.next_token_kind;
             let token = self.next_token_.clone();
             self.line = self.next_token_line;
+            self.token_pos = self.next_token_pos;
             self.next_token_line = 0;
-            return (line, kind, token);
+            return Ok((line, kind, token));
         }
         
-        self.skip_whitespaces();
-        if self.chunk.len() == 0 {
-            return (self.line, TOKEN_EOF, "EOF".to_string());
+        self.skip_whitespaces()?;
+        self.token_pos = (self.pos, self.pos);
+        let token = self.scan_token()?;
+        self.token_pos.1 = self.pos;
+        Ok(token)
+    }
+
+    fn scan_token(&mut self) -> Result<(i32, Token, String), CompileError> {
+        if self.rest().is_empty() {
+            return Ok((self.line, TOKEN_EOF, "EOF".to_string()));
         }
         match self.get_nth_char(0) {
             ';' => {
                 self.next(1);
-                return (self.line, TOKEN_SEP_SEMI, ";".to_string());
+                return Ok((self.line, TOKEN_SEP_SEMI, ";".to_string()));
             },
             ',' => {
                 self.next(1);
-                return (self.line, TOKEN_SEP_COMMA, ",".to_string());
+                return Ok((self.line, TOKEN_SEP_COMMA, ",".to_string()));
             },
             '(' => {
                 self.next(1);
-                return (self.line, TOKEN_SEP_LPAREN, "(".to_string());
+                return Ok((self.line, TOKEN_SEP_LPAREN, "(".to_string()));
             },
             ')' => {
                 self.next(1);
-                return (self.line, TOKEN_SEP_RPAREN, ")".to_string());
+                return Ok((self.line, TOKEN_SEP_RPAREN, ")".to_string()));
             },
             ']' => {
                 self.next(1);
-                return (self.line, TOKEN_SEP_RBRACK, "]".to_string());
+                return Ok((self.line, TOKEN_SEP_RBRACK, "]".to_string()));
             },
             '{' => {
                 self.next(1);
-                return (self.line, TOKEN_SEP_LCURLY, "{".to_string());
+                return Ok((self.line, TOKEN_SEP_LCURLY, "{".to_string()));
             },
             '}' => {
                 self.next(1);
-                return (self.line, TOKEN_SEP_RCURLY, "}".to_string());
+                return Ok((self.line, TOKEN_SEP_RCURLY, "}".to_string()));
             },
             '+' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_ADD, "+".to_string());
+                return Ok((self.line, TOKEN_OP_ADD, "+".to_string()));
             },
             '-' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_MINUS, "-".to_string());
+                return Ok((self.line, TOKEN_OP_MINUS, "-".to_string()));
             },
             '*' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_MUL, "*".to_string());
+                return Ok((self.line, TOKEN_OP_MUL, "*".to_string()));
             },
             '^' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_POW, "^".to_string());
+                return Ok((self.line, TOKEN_OP_POW, "^".to_string()));
             },
             '%' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_MOD, "%".to_string());
+                return Ok((self.line, TOKEN_OP_MOD, "%".to_string()));
             },
             '&' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_BAND, "&".to_string());
+                return Ok((self.line, TOKEN_OP_BAND, "&".to_string()));
             },
             '|' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_BOR, "|".to_string());
+                return Ok((self.line, TOKEN_OP_BOR, "|".to_string()));
             },
             '#' => {
                 self.next(1);
-                return (self.line, TOKEN_OP_LEN, "#".to_string());
+                return Ok((self.line, TOKEN_OP_LEN, "#".to_string()));
             },
             ':' => {
                 if self.test("::") {
                     self.next(2);
-                    return (self.line, TOKEN_SEP_LABEL, "::".to_string());
+                    return Ok((self.line, TOKEN_SEP_LABEL, "::".to_string()));
                 }
                 self.next(1);
-                return (self.line, TOKEN_SEP_COLON, ":".to_string());
+                return Ok((self.line, TOKEN_SEP_COLON, ":".to_string()));
             },
             '/' => {
                 if self.test("//") {
                     self.next(2);
-                    return (self.line, TOKEN_OP_IDIV, "//".to_string());
+                    return Ok((self.line, TOKEN_OP_IDIV, "//".to_string()));
                 }
                 self.next(1);
-                return (self.line, TOKEN_OP_DIV, "/".to_string());
+                return Ok((self.line, TOKEN_OP_DIV, "/".to_string()));
             },
             '~' => {
                 if self.test("~=") {
                     self.next(2);
-                    return (self.line, TOKEN_OP_NE, "~=".to_string());
+                    return Ok((self.line, TOKEN_OP_NE, "~=".to_string()));
                 }
                 self.next(1);
-                return (self.line, TOKEN_OP_WAVE, "~".to_string());
+                return Ok((self.line, TOKEN_OP_WAVE, "~".to_string()));
             },
             '=' => {
                 if self.test("==") {
                     self.next(2);
-                    return (self.line, TOKEN_OP_EQ, "==".to_string());
+                    return Ok((self.line, TOKEN_OP_EQ, "==".to_string()));
                 }
                 self.next(1);
-                return (self.line, TOKEN_OP_ASSIGN, "=".to_string());
+                return Ok((self.line, TOKEN_OP_ASSIGN, "=".to_string()));
             },
             '<' => {
-                return if self.test("<<") {
+                return Ok(if self.test("<<") {
                     self.next(2);
                     (self.line, TOKEN_OP_SHL, "<<".to_string())
                 } else if self.test("<=") {
@@ -144,10 +165,10 @@ impl Lexer {
                 } else {
                     self.next(1);
                     (self.line, TOKEN_OP_LT, "<".to_string())
-                }
+                });
             },
             '>' => {
-                return if self.test(">>") {
+                return Ok(if self.test(">>") {
                     self.next(2);
                     (self.line, TOKEN_OP_SHR, ">>".to_string())
                 } else if self.test(">=") {
@@ -156,30 +177,32 @@ impl Lexer {
                 } else {
                     self.next(1);
                     (self.line, TOKEN_OP_GT, ">".to_string())
-                }
+                });
             },
             '.' => {
                 if self.test("...") {
                     self.next(3);
-                    return (self.line, TOKEN_VARARG, "...".to_string());
+                    return Ok((self.line, TOKEN_VARARG, "...".to_string()));
                 } else if self.test("..") {
                     self.next(2);
-                    return (self.line, TOKEN_OP_CONCAT, "..".to_string());
-                } else if self.chunk.len() == 1 || !is_digit(self.get_nth_char(1)) { 
+                    return Ok((self.line, TOKEN_OP_CONCAT, "..".to_string()));
+                } else if self.rest().len() == 1 || !is_digit(self.get_nth_char(1)) { 
                     self.next(1);
-                    return (self.line, TOKEN_SEP_DOT, ".".to_string());
+                    return Ok((self.line, TOKEN_SEP_DOT, ".".to_string()));
                 }
             },
             '[' => {
-                return if self.test("[[") || self.test("[=") {
-                    (self.line, TOKEN_STRING, self.scan_long_string())
+                return Ok(if self.test("[[") || self.test("[=") {
+                    self.string_ = self.scan_long_string(false)?;
+                    (self.line, TOKEN_STRING, String::from_utf8_lossy(&self.string_).into_owned())
                 } else {
                     self.next(1);
                     (self.line, TOKEN_SEP_LBRACK, "[".to_string())
-                };
+                });
             },
             '\'' | '\"' => {
-                return (self.line, TOKEN_STRING, self.scan_short_string());
+                self.string_ = self.scan_short_string()?;
+                return Ok((self.line, TOKEN_STRING, String::from_utf8_lossy(&self.string_).into_owned()));
             },
             _ => {},
         }
@@ -187,53 +210,113 @@ impl Lexer {
         let c = self.get_nth_char(0);
         if c == '.' || is_digit(c) {
             let token = self.scan_number();
-            return (self.line, TOKEN_NUMBER, token);
+            return Ok((self.line, TOKEN_NUMBER, token));
         }
         if c == '_' || is_letter(c) {
             let token = self.scan_identifier();
             if let Some(kind) = find_keyword(&token) {
-                return (self.line, kind, token);
+                return Ok((self.line, kind, token));
             } else {
-                return (self.line, TOKEN_IDENTIFIER, token);
+                return Ok((self.line, TOKEN_IDENTIFIER, token));
             }
         }
         
-        panic!("Unexpected symbol near {}", c);
+        self.next(1);
+        self.token_pos.1 = self.pos;
+        Err(self.syntax_error("unexpected symbol"))
     }
     
-    pub fn look_ahead(&mut self) -> Token {
+    pub fn look_ahead(&mut self) -> Result<Token, CompileError> {
         if self.next_token_line > 0 {
-            return self.next_token_kind;
+            return Ok(self.next_token_kind);
         }
         let current_line = self.line;
-        let (line, kind, token) = self.next_token();
+        let current_pos = self.token_pos;
+        let (line, kind, token) = self.next_token()?;
         self.line = current_line;
         self.next_token_line = line;
         self.next_token_kind = kind;
         self.next_token_ = token;
-        kind
+        self.next_token_pos = self.token_pos;
+        self.token_pos = current_pos;
+        Ok(kind)
     }
     
-    pub fn next_token_of_kind(&mut self, kind: Token) -> (i32, String) {
-        let (line, _kind, token) = self.next_token();
+    pub fn next_token_of_kind(&mut self, kind: Token) -> Result<(i32, String), CompileError> {
+        let (line, _kind, token) = self.next_token()?;
         if _kind != kind {
-            panic!("Syntax error near <{}> in line {}", token, line);
+            return Err(self.syntax_error(&format!("{} expected", token_to_str(kind))));
         }
-        (line, token)
+        Ok((line, token))
+    }
+
+    // Next token, which must be `kind` closing the `what` opened at `line`,
+    // like `check_match`.
+    pub fn next_token_of_match(&mut self, kind: Token, what: Token, line: i32) -> Result<(i32, String), CompileError> {
+        if self.look_ahead()? != kind && line != self.next_token_line {
+            self.next_token()?;
+            let msg = format!("{} expected (to close {} at line {})", token_to_str(kind), token_to_str(what), line);
+            return Err(self.syntax_error(&msg));
+        }
+        self.next_token_of_kind(kind)
     }
     
-    pub fn next_identifier(&mut self) -> (i32, String) {
+    pub fn next_identifier(&mut self) -> Result<(i32, String), CompileError> {
         self.next_token_of_kind(TOKEN_IDENTIFIER)
     }
+
+    // Next token, which must be a string literal, as its bytes.
+    pub fn next_string(&mut self) -> Result<(i32, Vec<u8>), CompileError> {
+        let (line, _) = self.next_token_of_kind(TOKEN_STRING)?;
+        Ok((line, self.string_.clone()))
+    }
     
     pub fn line(&self) -> i32 {
         self.line
     }
+
+    // An error at the current line about the last token read.
+    pub fn syntax_error(&self, msg: &str) -> CompileError {
+        let (start, end) = self.token_pos;
+        let token = if start == self.chunk.len() {
+            String::from("<eof>")
+        } else if end - start == 1 && !self.chunk[start].is_ascii_graphic() {
+            format!("'<\\{}>'", self.chunk[start])
+        } else {
+            format!("'{}'", String::from_utf8_lossy(&self.chunk[start..end]))
+        };
+        self.error_near(msg, Some(token))
+    }
+
+    fn error_near(&self, msg: &str, token: Option<String>) -> CompileError {
+        let start = self.token_pos.0;
+        let line_start = self.chunk[..start].iter().rposition(|&b| b == b'\n' || b == b'\r').map_or(0, |i| i + 1);
+        CompileError {
+            chunk_name: self.chunk_name.clone(),
+            line: self.line,
+            column: (start - line_start) as i32 + 1,
+            token,
+            msg: msg.to_owned(),
+        }
+    }
+
+    // An error in the token being scanned, which ends at `end`; the line is
+    // the one reached at `end`.
+    fn token_error(&mut self, msg: &str, end: usize) -> CompileError {
+        let start = self.token_pos.0;
+        let end = end.min(self.chunk.len());
+        self.line += count_new_lines(&self.chunk[self.pos..end.max(self.pos)]);
+        if end >= self.chunk.len() {
+            return self.error_near(msg, Some(String::from("<eof>")));
+        }
+        let token = format!("'{}'", String::from_utf8_lossy(&self.chunk[start..end]));
+        self.error_near(msg, Some(token))
+    }
     
-    fn skip_whitespaces(&mut self) {
-        while self.chunk.len() > 0 {
+    fn skip_whitespaces(&mut self) -> Result<(), CompileError> {
+        while !self.rest().is_empty() {
             if self.test("--") {
-                self.skip_comment();
+                self.skip_comment()?;
             } else if self.test("\r\n") || self.test("\n\r") {
                 self.next(2);
                 self.line += 1;
@@ -246,37 +329,39 @@ impl Lexer {
                 break;
             }
         }
+        Ok(())
     }
     
     fn test(&self, s: &str) -> bool {
-        self.chunk.starts_with(s)
+        self.rest().starts_with(s.as_bytes())
     }
     
     fn next(&mut self, n: usize) {
-        let val = self.chunk.as_str();
-        self.chunk = String::from(&val[n..]);
+        self.pos += n;
     }
     
-    fn skip_comment(&mut self) {
+    fn skip_comment(&mut self) -> Result<(), CompileError> {
         self.next(2);           // skip --
         if self.test("[") {     // long comment ?
             let re = Regex::new(r"^\[=*\[").unwrap();
-            let result = re.captures(self.chunk.as_str());
+            let result = re.captures(self.rest());
             if let Some(_) = result{
-                self.scan_long_string();
-                return;
+                self.token_pos = (self.pos, self.pos);
+                self.scan_long_string(true)?;
+                return Ok(());
             }
         }
 
         // short comment
-        while self.chunk.len() > 0 && !is_new_line(self.get_nth_char(0)) {
+        while !self.rest().is_empty() && !is_new_line(self.get_nth_char(0)) {
             self.next(1);
         }
+        Ok(())
     }
 
-    fn scan_long_string(&mut self) -> String {
+    fn scan_long_string(&mut self, comment: bool) -> Result<Vec<u8>, CompileError> {
         let re_opening_long_bracket =  Regex::new(r"^\[=*\[").unwrap();
-        if let Some(cat) = re_opening_long_bracket.find(&self.chunk) {
+        if let Some(cat) = re_opening_long_bracket.find(self.rest()) {
             let len_end_flag = cat.end() - cat.start();
             
             // create the end flag of long string.
@@ -287,171 +372,233 @@ impl Lexer {
             }
             str_end_flag.push(']');
             
-            if let Some(pos) = self.chunk.find(&str_end_flag) {
-                let str_tmp = String::from(&self.chunk[cat.end()..pos]);
-                self.next(pos + len_end_flag);
+            let body = &self.rest()[cat.end()..];
+            if let Some(pos) = body.windows(len_end_flag).position(|w| w == str_end_flag.as_bytes()) {
+                let str_tmp = body[..pos].to_vec();
+                self.next(cat.end() + pos + len_end_flag);
                 let re_new_line = Regex::new(r"\r\n|\n\r|\n|\r").unwrap();
                 let count_backslash = re_new_line.find_iter(&str_tmp).count();
                 self.line += count_backslash as i32;
-                if str_tmp.len() > 0 && str_tmp.chars().nth(0).unwrap() == '\n' {
-                    return (&str_tmp[1..]).to_owned();
+                // skip the first newline, if any
+                if let Some(cap) = re_new_line.find(&str_tmp).filter(|cap| cap.start() == 0) {
+                    return Ok(str_tmp[cap.end()..].to_vec());
                 }
-                str_tmp
+                Ok(str_tmp)
             } else {
-                panic!("Unfinished long string or comment!\n");
+                let what = if comment { "comment" } else { "string" };
+                let msg = format!("unfinished long {} (starting at line {})", what, self.line);
+                Err(self.token_error(&msg, self.chunk.len()))
             }
         } else {
-            panic!("Invalid long string delimiter near {}!\n", (self.chunk.as_str())[0..2].to_owned());
+            let n = self.rest()[1..].iter().take_while(|&&b| b == b'=').count();
+            Err(self.token_error("invalid long string delimiter", self.pos + 1 + n))
         }
     }
     
-    fn scan_short_string(&mut self) -> String {
-        let re_short_str = Regex::new(r#"(?s)(^"(\\\\|\\"|\\\n|\\z\s*|[^"\n])*")|(^'(\\\\|\\'|\\\n|\\z\s*|[^'\n])*')"#).unwrap();
-        if let Some(cap) = re_short_str.find(&self.chunk) {
+    fn scan_short_string(&mut self) -> Result<Vec<u8>, CompileError> {
+        let re_short_str = Regex::new(r#"(?s-u)(^"(\\\\|\\"|\\\r\n|\\\n\r|\\[\n\r]|\\z\s*|[^"\n])*")|(^'(\\\\|\\'|\\\r\n|\\\n\r|\\[\n\r]|\\z\s*|[^'\n])*')"#).unwrap();
+        if let Some(cap) = re_short_str.find(self.rest()) {
             let step = cap.end() - cap.start();
-            let mut str_ = String::from(&self.chunk[1..(step - 1)]);
-            self.next(step);
-            if str_.contains("\\") {
+            let mut str_ = self.rest()[1..(step - 1)].to_vec();
+            if str_.contains(&b'\\') {
+                str_ = self.escape(self.pos + 1, &str_)?;
                 let re_new_line =  Regex::new(r"\r\n|\n\r|\n|\r").unwrap();
-                let count = re_new_line.find_iter(&str_).count();
+                let count = re_new_line.find_iter(&self.rest()[..step]).count();
                 self.line += count as i32;
-                str_ = self.escape(&str_);
             }
-            return str_;
+            self.next(step);
+            return Ok(str_);
         }
-        
-        panic!("Unfinished string");
+        Err(self.unfinished_string())
+    }
+
+    // The string being scanned ends at a line break or at the end of the chunk.
+    fn unfinished_string(&mut self) -> CompileError {
+        let rest = self.rest();
+        let mut i = 1;
+        while i < rest.len() && !is_new_line(rest[i] as char) {
+            i += if rest[i] == b'\\' { 2 } else { 1 };
+        }
+        self.token_error("unfinished string", self.pos + i)
     }
     
-    fn escape(&self, mut s: &str) -> String {
-        let mut buf: Vec<char> = vec![];
+    // Replaces the escape sequences of the string body `s`, which starts at
+    // `start` in the chunk.
+    fn escape(&mut self, start: usize, mut s: &[u8]) -> Result<Vec<u8>, CompileError> {
+        let len = s.len();
+        let mut buf: Vec<u8> = vec![];
         while s.len() > 0 {
-            if s.chars().nth(0).unwrap() != '\\' {
-                buf.push(s.chars().nth(0).unwrap());
+            if s[0] != b'\\' {
+                buf.push(s[0]);
                 s = &s[1..];
                 continue;
             }
             if s.len() == 1 {
-                panic!("Unfinished string");
+                return Err(self.unfinished_string());
             }
-            match s.chars().nth(1).unwrap() { 
-                'a' => {
+            // an error shows the string up to the end of the escape sequence
+            let at = start + len - s.len();
+            match s[1] { 
+                b'a' => {
                     // belling char ASCII code: 7
-                    buf.push(7 as char);
+                    buf.push(7);
                     s = &s[2..];
                 },
-                'b' => {
-                    buf.push(8 as char);
+                b'b' => {
+                    buf.push(8);
                     s = &s[2..];
                 },
-                'f' => {
-                    buf.push(12 as char);
+                b'f' => {
+                    buf.push(12);
                     s = &s[2..];
                 },
-                'n' => {
-                    buf.push('\n');
+                b'n' => {
+                    buf.push(b'\n');
                     s = &s[2..];
                 },
-                '\n' => {
-                    buf.push('\n');
+                b'\n' | b'\r' => {
+                    // `\` followed by a newline, which may be two chars
+                    buf.push(b'\n');
+                    let pair = s.len() > 2 && (s[2] == b'\n' || s[2] == b'\r') && s[2] != s[1];
+                    s = &s[if pair { 3 } else { 2 }..];
+                },
+                b'r' => {
+                    buf.push(b'\r');
                     s = &s[2..];
                 },
-                'r' => {
-                    buf.push('\r');
+                b't' => {
+                    buf.push(9);
                     s = &s[2..];
                 },
-                't' => {
-                    buf.push(9 as char);
+                b'v' => {
+                    buf.push(11);
                     s = &s[2..];
                 },
-                'v' => {
-                    buf.push(11 as char);
+                b'\\' => {
+                    buf.push(b'\\');
                     s = &s[2..];
                 },
-                '\"' => {
-                    buf.push('\"');
+                b'\"' => {
+                    buf.push(b'\"');
                     s = &s[2..];
                 },
-                '\'' => {
-                    buf.push('\'');
+                b'\'' => {
+                    buf.push(b'\'');
                     s = &s[2..];
                 },
-                '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
+                b'0'..=b'9' => {
                     // \ddd
                     let re_dec_escape_seq = Regex::new(r"^\\[0-9]{1,3}").unwrap();
-                    if let Some(cap) = re_dec_escape_seq.find(s) {
-                        let found = cap.as_str();
-                        if let Ok(d) = (&found[1..]).parse::<u32>() {
-                            if d <= 0xFF {
-                                buf.push(d as u8 as char);
-                                s = &s[found.len()..];
-                                continue;
-                            }
-                        }
-                        panic!("Decimal escape too large near \'{}\'", found);
+                    let found = re_dec_escape_seq.find(s).unwrap().as_bytes();
+                    let d: u32 = String::from_utf8_lossy(&found[1..]).parse().unwrap();
+                    if d > 0xFF {
+                        return Err(self.token_error("decimal escape too large", at + found.len()));
                     }
+                    buf.push(d as u8);
+                    s = &s[found.len()..];
                 },
-                'x' => {
+                b'x' => {
                     // \xXX
-                    let re_hex_escape_seq = Regex::new(r"^\\x[0-9a-fA-F]{2}").unwrap();
-                    if let Some(cap) = re_hex_escape_seq.find(s) {
-                        let found = cap.as_str();
-                        if let Ok(d) = (&found[2..]).parse::<u32>() {
-                            buf.push(d as u8 as char);
-                            s = &s[found.len()..];
-                            continue;
-                        }
+                    let n = s[2..].iter().take(2).take_while(|b| b.is_ascii_hexdigit()).count();
+                    if n < 2 {
+                        return Err(self.token_error("hexadecimal digit expected", (at + 3 + n).min(start + len)));
                     }
+                    buf.push(u8::from_str_radix(&String::from_utf8_lossy(&s[2..4]), 16).unwrap());
+                    s = &s[4..];
                 },
-                'u' => {
+                b'u' => {
                     // \u{XXX}
-                    let re_unicode_escape_seq = Regex::new(r"^\\u\{[0-9a-fA-F]+\}").unwrap();
-                    if let Some(cap) = re_unicode_escape_seq.find(s) {
-                        let found = cap.as_str();
-                        if let Ok(d) = (&found[3..found.len() - 1]).parse::<u32>() {
-                            if d <= 0x10FFFF {
-                                buf.push(((d >> 24) & 0xFF) as u8 as char);
-                                buf.push(((d >> 16) & 0xFF) as u8 as char);
-                                buf.push(((d >> 8) & 0xFF) as u8 as char);
-                                buf.push((d & 0xFF) as u8 as char);
-                                s = &s[found.len()..];
-                                continue;
-                            }
-                        }
-                        panic!("UTF-8 value too large near \"{}\"", found);
+                    if s.get(2) != Some(&b'{') {
+                        return Err(self.token_error("missing '{'", (at + 3).min(start + len)));
+                    }
+                    let n = s[3..].iter().take_while(|b| b.is_ascii_hexdigit()).count();
+                    if n == 0 {
+                        return Err(self.token_error("hexadecimal digit expected", (at + 4).min(start + len)));
+                    }
+                    let hex = String::from_utf8_lossy(&s[3..3 + n]);
+                    match u32::from_str_radix(&hex, 16) {
+                        Ok(d) if d <= 0x10FFFF => buf.extend(utf8_esc(d)),
+                        _ => return Err(self.token_error("UTF-8 value too large", at + 3 + n)),
                     }
+                    if s.get(3 + n) != Some(&b'}') {
+                        return Err(self.token_error("missing '}'", (at + 4 + n).min(start + len)));
+                    }
+                    s = &s[4 + n..];
                 },
-                'z' => {
+                b'z' => {
                     s = &s[2..];
-                    while s.len() > 0 && is_white_space(s.chars().nth(0).unwrap()) {
+                    while !s.is_empty() && is_white_space(s[0] as char) {
                         s = &s[1..];
                     }
-                    continue;
                 },
-                _ =>panic!("Invalid escape sequence near \'\\{}\'", s.chars().nth(1).unwrap()),
+                _ => return Err(self.token_error("invalid escape sequence", at + 2)),
             }
         }
-        buf.iter().collect()
+        Ok(buf)
     }
     
+    // A numeral, as read by `read_numeral`: digits, dots and exponents
+    // (with their sign), which may not make a valid number.
     fn scan_number(&mut self) -> String {
-        let re_number = Regex::new(r"^0[xX][0-9a-fA-F]*(\.[0-9a-fA-F]*)?([pP][+|-]?[0-9]+)?|^[0-9]*(\.[0-9]*)?([eE][+|-]?[0-9]+)?").unwrap();
-        self.scan(&re_number)
+        let expo: &[u8] = if self.test("0x") || self.test("0X") { b"Pp" } else { b"Ee" };
+        let start = self.pos;
+        if expo == b"Pp" {
+            self.next(2);
+        }
+        while let Some(&c) = self.rest().first() {
+            if expo.contains(&c) {
+                self.next(1);
+                if self.test("+") || self.test("-") {
+                    self.next(1);
+                }
+            } else if c.is_ascii_hexdigit() || c == b'.' {
+                self.next(1);
+            } else {
+                break;
+            }
+        }
+        String::from_utf8_lossy(&self.chunk[start..self.pos]).into_owned()
     }
     
     fn scan_identifier(&mut self) -> String {
-        let re_identifier = Regex::new(r"^[_\d\w]+").unwrap();
+        let re_identifier = Regex::new(r"(?-u)^[_\d\w]+").unwrap();
         self.scan(&re_identifier)
     }
     
     fn scan(&mut self, re: &Regex) -> String {
-        if let Some(cap) = re.find(&self.chunk) {
-            let token = cap.as_str().to_owned();
+        if let Some(cap) = re.find(self.rest()) {
+            let token = String::from_utf8_lossy(cap.as_bytes()).into_owned();
             self.next(token.len());
             return token;
         }
-        panic!("Unreachable!");
+        unreachable!();
+    }
+}
+
+fn count_new_lines(s: &[u8]) -> i32 {
+    let re_new_line = Regex::new(r"\r\n|\n\r|\n|\r").unwrap();
+    re_new_line.find_iter(s).count() as i32
+}
+
+// The UTF-8 encoding of `x`, which may be any value up to 0x7FFFFFFF
+// (surrogates included), like `luaO_utf8esc`.
+pub fn utf8_esc(mut x: u32) -> Vec<u8> {
+    if x < 0x80 {               // ascii?
+        return vec![x as u8];
+    }
+    let mut buf = vec![];
+    let mut mfb = 0x3f;         // maximum that fits in first byte
+    loop {                      // add continuation bytes
+        buf.push(0x80 | (x & 0x3f) as u8);
+        x >>= 6;                // remove added bits
+        mfb >>= 1;              // now there is one less bit available in first byte
+        if x <= mfb {
+            break;
+        }
     }
+    buf.push(((!mfb << 1) | x) as u8);     // add first byte
+    buf.reverse();
+    buf
 }
 
 fn is_white_space(c: char) -> bool {
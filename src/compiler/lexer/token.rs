@@ -110,4 +110,48 @@ pub fn find_keyword(name: &str) -> Option<Token> {
         }
     }
     None
-}
\ No newline at end of file
+}
+// How a token kind is shown in error messages, like `luaX_token2str`.
+pub fn token_to_str(kind: Token) -> String {
+    let s = match kind {
+        TOKEN_EOF => return String::from("<eof>"),
+        TOKEN_IDENTIFIER => return String::from("<name>"),
+        TOKEN_NUMBER => return String::from("<number>"),
+        TOKEN_STRING => return String::from("<string>"),
+        TOKEN_VARARG => "...",
+        TOKEN_SEP_SEMI => ";",
+        TOKEN_SEP_COMMA => ",",
+        TOKEN_SEP_DOT => ".",
+        TOKEN_SEP_COLON => ":",
+        TOKEN_SEP_LABEL => "::",
+        TOKEN_SEP_LPAREN => "(",
+        TOKEN_SEP_RPAREN => ")",
+        TOKEN_SEP_LBRACK => "[",
+        TOKEN_SEP_RBRACK => "]",
+        TOKEN_SEP_LCURLY => "{",
+        TOKEN_SEP_RCURLY => "}",
+        TOKEN_OP_ASSIGN => "=",
+        TOKEN_OP_MINUS => "-",
+        TOKEN_OP_WAVE => "~",
+        TOKEN_OP_ADD => "+",
+        TOKEN_OP_MUL => "*",
+        TOKEN_OP_DIV => "/",
+        TOKEN_OP_IDIV => "//",
+        TOKEN_OP_POW => "^",
+        TOKEN_OP_MOD => "%",
+        TOKEN_OP_BAND => "&",
+        TOKEN_OP_BOR => "|",
+        TOKEN_OP_SHR => ">>",
+        TOKEN_OP_SHL => "<<",
+        TOKEN_OP_CONCAT => "..",
+        TOKEN_OP_LT => "<",
+        TOKEN_OP_LE => "<=",
+        TOKEN_OP_GT => ">",
+        TOKEN_OP_GE => ">=",
+        TOKEN_OP_EQ => "==",
+        TOKEN_OP_NE => "~=",
+        TOKEN_OP_LEN => "#",
+        _ => KEYWORDS.iter().find(|k| k.code == kind).map_or("?", |k| k.name),
+    };
+    format!("'{}'", s)
+}
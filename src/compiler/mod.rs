@@ -2,6 +2,7 @@ pub mod ast;
 pub mod lexer;
 pub mod parser;
 pub mod codegen;
+pub mod error;
 
 use crate::state::lua_value::LuaValue;
 use crate::binchunk::binary_chunk::Prototype;
@@ -23,7 +24,12 @@ fn list(f: &Prototype) {
 fn print_header(f: &Prototype) {
     let func_type = if f.lineDefined > 0 { "function" } else { "main" };
     let vararg_flag = if f.isVararg > 0 { "+" } else { "" };
-    let source = f.source.as_ref().map(|x| x.as_str()).unwrap_or("");
+    // like luac: the file or literal name, otherwise `(string)`
+    let source = match f.source.as_deref() {
+        Some(s) if s.starts_with('@') || s.starts_with('=') => &s[1..],
+        Some(_) => "(string)",
+        None => "=?",
+    };
 
     print!("\n{}", func_type);
     print!(" <{}:{},{}>", source, f.lineDefined, f.lastLineDefined);
@@ -125,9 +131,11 @@ fn print_const(n: usize, k: &LuaValue) {
         LuaValue::Bool(b) => println!("\t{}\t{}", n, b),
         LuaValue::Number(x) => println!("\t{}\t{}", n, x),
         LuaValue::Integer(i) => println!("\t{}\t{}", n, i),
-        LuaValue::Str(s) => println!("\t{}\t{:?}", n, s),
+        LuaValue::Str(s) => println!("\t{}\t{:?}", n, String::from_utf8_lossy(s)),
         LuaValue::Table(table) => println!("\t{}\t{:#?}", n, *(table.borrow())),
         LuaValue::Function(f) => println!("\t{}\t{:#?}", n, **f),
+        LuaValue::Thread(_) => println!("\t{}\tthread", n),
+        LuaValue::UserData(_) | LuaValue::LightUserData(_) => println!("\t{}\tuserdata", n),
     }
 }
 
@@ -3,14 +3,15 @@ mod parse_exp;
 
 use std::rc::Rc;
 use super::ast::block::Block;
+use super::error::CompileError;
 use super::lexer::lexer::Lexer;
 
 use parse_block::parse_block;
 use super::lexer::token::TOKEN_EOF;
 
-pub fn parse(chunk: String, chunk_name: String) -> Rc<Block> {
+pub fn parse(chunk: Vec<u8>, chunk_name: String) -> Result<Rc<Block>, CompileError> {
     let mut lexer = Lexer::new(chunk, chunk_name);
-    let block = parse_block(&mut lexer);
-    lexer.next_token_of_kind(TOKEN_EOF);
-    block
+    let block = parse_block(&mut lexer)?;
+    lexer.next_token_of_kind(TOKEN_EOF)?;
+    Ok(block)
 }
\ No newline at end of file
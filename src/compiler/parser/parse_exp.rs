@@ -1,200 +1,202 @@
+use crate::number::parser::{ParseFloat, ParseInteger};
 use super::super::ast::exp::Exp;
 use super::super::ast::exp::Exp::*;
+use super::super::error::CompileError;
 use super::super::lexer::lexer::Lexer;
 use super::super::lexer::token::*;
 use super::parse_block::*;
 
-pub fn parse_exp_list(lexer: &mut Lexer) -> Vec<Exp> {
+pub fn parse_exp_list(lexer: &mut Lexer) -> Result<Vec<Exp>, CompileError> {
     let mut exps: Vec<Exp> = vec![];
-    exps.push(parse_exp(lexer));                        // exp
-    while lexer.look_ahead() == TOKEN_SEP_COMMA {       // {
-        lexer.next_token();                             // `,`
-        exps.push(parse_exp(lexer));                    // exp
+    exps.push(parse_exp(lexer)?);                       // exp
+    while lexer.look_ahead()? == TOKEN_SEP_COMMA {      // {
+        lexer.next_token()?;                            // `,`
+        exps.push(parse_exp(lexer)?);                   // exp
     }                                                   // }
-    exps
+    Ok(exps)
 }
 
 // exp ::= exp12
-pub fn parse_exp(lexer: &mut Lexer) -> Exp {
+pub fn parse_exp(lexer: &mut Lexer) -> Result<Exp, CompileError> {
     parse_exp12(lexer)
 }
 
 // exp12 ::= exp11 {or exp11}
-fn parse_exp12(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp11(lexer);
-    while lexer.look_ahead() == TOKEN_OP_OR {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp12(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp11(lexer)?;
+    while lexer.look_ahead()? == TOKEN_OP_OR {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp11(lexer)),
+            exp2: Box::new(parse_exp11(lexer)?),
         };
     }
-    exp
+    Ok(exp)
 }
 
 // exp11 ::= exp10 {and exp10}
-fn parse_exp11(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp10(lexer);
-    while lexer.look_ahead() == TOKEN_OP_AND {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp11(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp10(lexer)?;
+    while lexer.look_ahead()? == TOKEN_OP_AND {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp10(lexer)),
+            exp2: Box::new(parse_exp10(lexer)?),
         };
     }
-    exp
+    Ok(exp)
 }
 
 // exp10 ::= exp9 {(‘<’ | ‘>’ | ‘<=’ | ‘>=’ | ‘~=’ | ‘==’) exp9}
-fn parse_exp10(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp9(lexer);
+fn parse_exp10(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp9(lexer)?;
     loop {
-        match lexer.look_ahead() {
+        match lexer.look_ahead()? {
             TOKEN_OP_LT | TOKEN_OP_GT | TOKEN_OP_NE | TOKEN_OP_LE | TOKEN_OP_GE | TOKEN_OP_EQ => {
-                let (line, op, _) = lexer.next_token();
+                let (line, op, _) = lexer.next_token()?;
                 exp = BinopExp {
                     line,
                     op,
                     exp1: Box::new(exp),
-                    exp2: Box::new(parse_exp9(lexer)),
+                    exp2: Box::new(parse_exp9(lexer)?),
                 };
             },
             _ => {
-                return exp;
+                return Ok(exp);
             }
         }
     }
 }
 
 // exp9  ::= exp8 {‘|’ exp8}
-fn parse_exp9(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp8(lexer);
-    while lexer.look_ahead() == TOKEN_OP_BOR {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp9(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp8(lexer)?;
+    while lexer.look_ahead()? == TOKEN_OP_BOR {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp8(lexer)),
+            exp2: Box::new(parse_exp8(lexer)?),
         };
     }
-    exp
+    Ok(exp)
 }
 
 // exp8  ::= exp7 {‘~’ exp7}
-fn parse_exp8(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp7(lexer);
-    while lexer.look_ahead() == TOKEN_OP_BXOR {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp8(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp7(lexer)?;
+    while lexer.look_ahead()? == TOKEN_OP_BXOR {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp7(lexer)),
+            exp2: Box::new(parse_exp7(lexer)?),
         };
     }
-    exp
+    Ok(exp)
 }
 
 // exp7  ::= exp6 {‘&’ exp6}
-fn parse_exp7(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp6(lexer);
-    while lexer.look_ahead() == TOKEN_OP_BAND {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp7(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp6(lexer)?;
+    while lexer.look_ahead()? == TOKEN_OP_BAND {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp6(lexer)),
+            exp2: Box::new(parse_exp6(lexer)?),
         };
     }
-    exp
+    Ok(exp)
 }
 
 // exp6  ::= exp5 {(‘<<’ | ‘>>’) exp5}
-fn parse_exp6(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp5(lexer);
-    while lexer.look_ahead() == TOKEN_OP_SHL || lexer.look_ahead() == TOKEN_OP_SHR  {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp6(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp5(lexer)?;
+    while lexer.look_ahead()? == TOKEN_OP_SHL || lexer.look_ahead()? == TOKEN_OP_SHR  {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp5(lexer)),
+            exp2: Box::new(parse_exp5(lexer)?),
         };
     }
-    exp
+    Ok(exp)
 }
 
 // exp5  ::= exp4 {‘..’ exp4}
-fn parse_exp5(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp4(lexer);
-    if lexer.look_ahead() != TOKEN_OP_CONCAT {
-        return exp;
+fn parse_exp5(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let exp = parse_exp4(lexer)?;
+    if lexer.look_ahead()? != TOKEN_OP_CONCAT {
+        return Ok(exp);
     }
-    
+
     let mut line = 0;
     let mut exps = vec![exp];
-    while lexer.look_ahead() == TOKEN_OP_CONCAT {
-        (line, _, _) = lexer.next_token();
-        exps.push(parse_exp4(lexer));
+    while lexer.look_ahead()? == TOKEN_OP_CONCAT {
+        (line, _, _) = lexer.next_token()?;
+        exps.push(parse_exp4(lexer)?);
     }
-    ConcatExp {
+    Ok(ConcatExp {
         line,
         exps
-    }
+    })
 }
 
 // exp4  ::= exp3 {(‘+’ | ‘-’) exp3}
-fn parse_exp4(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp3(lexer);
-    while lexer.look_ahead() == TOKEN_OP_ADD || lexer.look_ahead() == TOKEN_OP_SUB  {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp4(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp3(lexer)?;
+    while lexer.look_ahead()? == TOKEN_OP_ADD || lexer.look_ahead()? == TOKEN_OP_SUB  {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp3(lexer)),
+            exp2: Box::new(parse_exp3(lexer)?),
         };
     }
-    exp
+    Ok(exp)
 }
 
 // exp3  ::= exp2 {(‘*’ | ‘/’ | ‘//’ | ‘%’) exp2}
-fn parse_exp3(lexer: &mut Lexer) -> Exp {
-    let mut exp = parse_exp2(lexer);
+fn parse_exp3(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let mut exp = parse_exp2(lexer)?;
     loop {
-        match lexer.look_ahead() {
+        match lexer.look_ahead()? {
             TOKEN_OP_MUL | TOKEN_OP_MOD | TOKEN_OP_DIV | TOKEN_OP_IDIV  => {
-                let (line, op, _) = lexer.next_token();
+                let (line, op, _) = lexer.next_token()?;
                 exp = BinopExp {
                     line,
                     op,
                     exp1: Box::new(exp),
-                    exp2: Box::new(parse_exp2(lexer)),
+                    exp2: Box::new(parse_exp2(lexer)?),
                 };
             },
             _ => {
-                return exp;
+                return Ok(exp);
             }
         }
     }
 }
 
 // exp2  ::= {(‘not’ | ‘#’ | ‘-’ | ‘~’)} exp1
-fn parse_exp2(lexer: &mut Lexer) -> Exp {   // not | # | - | ~
-    match lexer.look_ahead() { 
+fn parse_exp2(lexer: &mut Lexer) -> Result<Exp, CompileError> {   // not | # | - | ~
+    match lexer.look_ahead()? {
         TOKEN_OP_UNM | TOKEN_OP_BNOT | TOKEN_OP_LEN | TOKEN_OP_NOT => {
-            let (line, op, _) = lexer.next_token();
-            UnopExp {
-                line, 
+            let (line, op, _) = lexer.next_token()?;
+            Ok(UnopExp {
+                line,
                 op,
-                exp: Box::new(parse_exp2(lexer)),
-            }
+                exp: Box::new(parse_exp2(lexer)?),
+            })
         },
         _ => {
             parse_exp1(lexer)
@@ -203,54 +205,54 @@ fn parse_exp2(lexer: &mut Lexer) -> Exp {   // not | # | - | ~
 }
 
 // exp1  ::= exp0 {‘^’ exp2}
-fn parse_exp1(lexer: &mut Lexer) -> Exp {   // exp0 ^ exp2
-    let mut exp = parse_exp0(lexer);
-    if lexer.look_ahead() == TOKEN_OP_POW {
-        let (line, op, _) = lexer.next_token();
+fn parse_exp1(lexer: &mut Lexer) -> Result<Exp, CompileError> {   // exp0 ^ exp2
+    let mut exp = parse_exp0(lexer)?;
+    if lexer.look_ahead()? == TOKEN_OP_POW {
+        let (line, op, _) = lexer.next_token()?;
         exp = BinopExp {
             line,
             op,
             exp1: Box::new(exp),
-            exp2: Box::new(parse_exp2(lexer)),
+            exp2: Box::new(parse_exp2(lexer)?),
         }
     }
-    exp
+    Ok(exp)
 }
 
 // exp0  ::= nil | false | true | Numeral | LiteralString
 // 		| ‘...’ | functiondef | prefixexp | tableconstructor
-fn parse_exp0(lexer: &mut Lexer) -> Exp {
-    match lexer.look_ahead() {
+fn parse_exp0(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    match lexer.look_ahead()? {
         TOKEN_VARARG => { // ...
-            let (line, _, _) = lexer.next_token();
-            VarargExp { 
+            let (line, _, _) = lexer.next_token()?;
+            Ok(VarargExp {
                 line
-            }
+            })
         },
         TOKEN_KW_NIL => { // nil
-            let (line, _, _) = lexer.next_token();
-            NilExp { 
-                line 
-            }
+            let (line, _, _) = lexer.next_token()?;
+            Ok(NilExp {
+                line
+            })
         },
         TOKEN_KW_TRUE => { // true
-            let (line, _, _) = lexer.next_token();
-            TrueExp { 
-                line 
-            }
+            let (line, _, _) = lexer.next_token()?;
+            Ok(TrueExp {
+                line
+            })
         },
         TOKEN_KW_FALSE => { // false
-            let (line, _, _) = lexer.next_token();
-            FalseExp { 
-                line 
-            }
+            let (line, _, _) = lexer.next_token()?;
+            Ok(FalseExp {
+                line
+            })
         },
         TOKEN_STRING => { // LiteralString
-            let (line, _, token) = lexer.next_token();
-            StringExp { 
-                line, 
-                str: token 
-            }
+            let (line, str) = lexer.next_string()?;
+            Ok(StringExp {
+                line,
+                str
+            })
         },
         TOKEN_NUMBER => { // Numeral
             parse_number_exp(lexer)
@@ -259,7 +261,7 @@ fn parse_exp0(lexer: &mut Lexer) -> Exp {
             parse_table_constructor_exp(lexer)
         },
         TOKEN_KW_FUNCTION => { // functiondef
-            lexer.next_token();
+            lexer.next_token()?;
             parse_func_def_exp(lexer)
         },
         _ => { // prefixexp
@@ -268,103 +270,93 @@ fn parse_exp0(lexer: &mut Lexer) -> Exp {
     }
 }
 
-fn parse_number_exp(lexer: &mut Lexer) -> Exp {
-    let (line, _, token) = lexer.next_token();
-    if let Ok(i_val) = (&token).parse::<i64>() {        // only decimal!
-        IntegerExp {
+fn parse_number_exp(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let (line, _, token) = lexer.next_token()?;
+    if let (i_val, true) = ParseInteger(&token) {
+        Ok(IntegerExp {
             line,
             val: i_val,
-        }
-    } else if let Ok(i_val) = i64::from_str_radix(&token, 2){
-        IntegerExp {
-            line,
-            val: i_val,
-        }
-    } else if let Ok(i_val) = i64::from_str_radix(&token, 16){
-        IntegerExp {
-            line,
-            val: i_val,
-        }
-    } else if  let Ok(f_val) = (&token).parse::<f64>() {
-        FloatExp {
+        })
+    } else if let (f_val, true) = ParseFloat(&token) {
+        Ok(FloatExp {
             line,
             val: f_val,
-        }
+        })
     } else {
-        panic!("not a number: {}", token);
+        Err(lexer.syntax_error("malformed number"))
     }
 }
 
-pub fn parse_func_def_exp(lexer: &mut Lexer) -> Exp {
+pub fn parse_func_def_exp(lexer: &mut Lexer) -> Result<Exp, CompileError> {
     let line = lexer.line();        // function
-    lexer.next_token_of_kind(TOKEN_SEP_LPAREN);
-    let (par_list, is_vararg) = _parse_par_list(lexer);
-    lexer.next_token_of_kind(TOKEN_SEP_RPAREN);
-    let block = parse_block(lexer);
-    let (last_line, _) = lexer.next_token_of_kind(TOKEN_KW_END);
-    FuncDefExp {
+    lexer.next_token_of_kind(TOKEN_SEP_LPAREN)?;
+    let (par_list, is_vararg) = _parse_par_list(lexer)?;
+    lexer.next_token_of_kind(TOKEN_SEP_RPAREN)?;
+    let block = parse_block(lexer)?;
+    let (last_line, _) = lexer.next_token_of_match(TOKEN_KW_END, TOKEN_KW_FUNCTION, line)?;
+    Ok(FuncDefExp {
         line,
         last_line,
         par_list,
         block,
         is_vararg,
-    }
+    })
 }
 
-fn _parse_par_list(lexer: &mut Lexer) -> (Vec<String>, bool) {
-    match lexer.look_ahead() { 
+fn _parse_par_list(lexer: &mut Lexer) -> Result<(Vec<String>, bool), CompileError> {
+    match lexer.look_ahead()? {
         TOKEN_SEP_RPAREN => {
-            return (vec![], false);
+            return Ok((vec![], false));
         },
         TOKEN_VARARG => {
-            lexer.next_token();
-            return (vec![], true);
+            lexer.next_token()?;
+            return Ok((vec![], true));
         },
         _ => {}
     }
-    
-    let (_, name) = lexer.next_identifier();
+
+    let (_, name) = lexer.next_identifier()?;
     let mut is_vararg = false;
     let mut names = vec![name];
-    while lexer.look_ahead() == TOKEN_SEP_COMMA {
-        lexer.next_token();
-        if lexer.look_ahead() == TOKEN_IDENTIFIER {
-            let (_, name) = lexer.next_identifier();
+    while lexer.look_ahead()? == TOKEN_SEP_COMMA {
+        lexer.next_token()?;
+        if lexer.look_ahead()? == TOKEN_IDENTIFIER {
+            let (_, name) = lexer.next_identifier()?;
             names.push(name);
         } else {
-            lexer.next_token_of_kind(TOKEN_VARARG);
+            lexer.next_token_of_kind(TOKEN_VARARG)?;
             is_vararg = true;
             break;
         }
     }
-    (names, is_vararg)
+    Ok((names, is_vararg))
 }
 
-fn parse_table_constructor_exp(lexer: &mut Lexer) -> Exp {
+fn parse_table_constructor_exp(lexer: &mut Lexer) -> Result<Exp, CompileError> {
     let line = lexer.line();
-    lexer.next_token_of_kind(TOKEN_SEP_LCURLY);
-    let (key_exps, val_exps) = _parse_field_list(lexer);
-    lexer.next_token_of_kind(TOKEN_SEP_RCURLY);
+    let (line_of_curly, _) = lexer.next_token_of_kind(TOKEN_SEP_LCURLY)?;
+    let (key_exps, val_exps) = _parse_field_list(lexer)?;
+    lexer.next_token_of_match(TOKEN_SEP_RCURLY, TOKEN_SEP_LCURLY, line_of_curly)?;
     let last_line = lexer.line();
-    TableConstructorExp {
+    Ok(TableConstructorExp {
         line,
         last_line,
         key_exps,
         val_exps,
-    }
+    })
 }
 
-fn _parse_field_list(lexer: &mut Lexer) -> (Vec<Exp>, Vec<Exp>) {
+fn _parse_field_list(lexer: &mut Lexer) -> Result<(Vec<Exp>, Vec<Exp>), CompileError> {
     let mut ks = vec![];
     let mut vs = vec![];
-    if lexer.look_ahead() != TOKEN_SEP_RCURLY {
-        let (k, v) = _parse_field(lexer);
+    if lexer.look_ahead()? != TOKEN_SEP_RCURLY {
+        let (k, v) = _parse_field(lexer)?;
         ks.push(k);
         vs.push(v);
-        while _is_field_sep(lexer.look_ahead()) {
-            lexer.next_token();
-            if lexer.look_ahead() != TOKEN_SEP_RCURLY {
-                let (k, v) = _parse_field(lexer);
+        while _is_field_sep(lexer.look_ahead()?) {
+            lexer.next_token()?;
+            if lexer.look_ahead()? != TOKEN_SEP_RCURLY {
+                let (k, v) = _parse_field(lexer)?;
                 ks.push(k);
                 vs.push(v);
             } else {
@@ -372,59 +364,66 @@ fn _parse_field_list(lexer: &mut Lexer) -> (Vec<Exp>, Vec<Exp>) {
             }
         }
     }
-    (ks, vs)
+    Ok((ks, vs))
 }
 
 fn _is_field_sep(token_kind: Token) -> bool {
     token_kind == TOKEN_SEP_COMMA || token_kind == TOKEN_SEP_SEMI
 }
 
-fn _parse_field(lexer: &mut Lexer) -> (Exp, Exp) {
+fn _parse_field(lexer: &mut Lexer) -> Result<(Exp, Exp), CompileError> {
     let k: Exp;
     let v: Exp;
-    if lexer.look_ahead() == TOKEN_SEP_LBRACK {
-        lexer.next_token();
-        k = parse_exp(lexer);
-        lexer.next_token_of_kind(TOKEN_SEP_RBRACK);
-        lexer.next_token_of_kind(TOKEN_OP_ASSIGN);
-        v = parse_exp(lexer);
-        return (k, v);
+    if lexer.look_ahead()? == TOKEN_SEP_LBRACK {
+        lexer.next_token()?;
+        k = parse_exp(lexer)?;
+        lexer.next_token_of_kind(TOKEN_SEP_RBRACK)?;
+        lexer.next_token_of_kind(TOKEN_OP_ASSIGN)?;
+        v = parse_exp(lexer)?;
+        return Ok((k, v));
     }
-    let exp = parse_exp(lexer);
+    let exp = parse_exp(lexer)?;
     if let NameExp { ref line, ref str} = &exp {
-        if lexer.look_ahead() == TOKEN_OP_ASSIGN {
-            lexer.next_token();
+        if lexer.look_ahead()? == TOKEN_OP_ASSIGN {
+            lexer.next_token()?;
             k = StringExp {
                 line: *line,
-                str: str.clone(),
+                str: str.clone().into_bytes(),
             };
-            v = parse_exp(lexer);
-            return (k, v);
+            v = parse_exp(lexer)?;
+            return Ok((k, v));
         }
     }
-    (NilExp { line: 0 }, exp)
+    Ok((NilExp { line: 0 }, exp))
 }
 
-pub fn parse_prefix_exp(lexer: &mut Lexer) -> Exp {
-    let exp: Exp = if lexer.look_ahead() == TOKEN_IDENTIFIER {
-        let (line, name) = lexer.next_identifier();
-        NameExp {
-            line, 
-            str: name,
-        }
-    } else {
-        parse_parens_exp(lexer)
+pub fn parse_prefix_exp(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let exp: Exp = match lexer.look_ahead()? {
+        TOKEN_IDENTIFIER => {
+            let (line, name) = lexer.next_identifier()?;
+            NameExp {
+                line,
+                str: name,
+            }
+        },
+        TOKEN_SEP_LPAREN => {
+            parse_parens_exp(lexer)?
+        },
+        _ => {
+            lexer.next_token()?;
+            return Err(lexer.syntax_error("unexpected symbol"));
+        },
     };
     _finish_prefix_exp(lexer, exp)
 }
 
-fn _finish_prefix_exp(lexer: &mut Lexer, mut exp: Exp) -> Exp {
+fn _finish_prefix_exp(lexer: &mut Lexer, mut exp: Exp) -> Result<Exp, CompileError> {
     loop {
-        match lexer.look_ahead() {
+        match lexer.look_ahead()? {
             TOKEN_SEP_LBRACK => {
-                lexer.next_token();
-                let key_exp = parse_exp(lexer);
-                lexer.next_token_of_kind(TOKEN_SEP_RBRACK);
+                lexer.next_token()?;
+                let key_exp = parse_exp(lexer)?;
+                lexer.next_token_of_kind(TOKEN_SEP_RBRACK)?;
                 exp = TableAccessExp {
                     last_line: lexer.line(),
                     prefix_exp: Box::new(exp),
@@ -432,11 +431,11 @@ fn _finish_prefix_exp(lexer: &mut Lexer, mut exp: Exp) -> Exp {
                 };
             },
             TOKEN_SEP_DOT => {
-                lexer.next_token();
-                let (line, name) = lexer.next_identifier();
+                lexer.next_token()?;
+                let (line, name) = lexer.next_identifier()?;
                 let key_exp = StringExp {
                     line,
-                    str: name,
+                    str: name.into_bytes(),
                 };
                 exp = TableAccessExp {
                     last_line: line,
@@ -445,80 +444,86 @@ fn _finish_prefix_exp(lexer: &mut Lexer, mut exp: Exp) -> Exp {
                 };
             },
             TOKEN_SEP_COLON | TOKEN_SEP_LPAREN |TOKEN_SEP_LCURLY | TOKEN_STRING => {
-                exp = _finish_func_call_exp(lexer, exp);            // [`:`Name] args
+                exp = _finish_func_call_exp(lexer, exp)?;           // [`:`Name] args
             },
             _ => {
-                return exp;
+                return Ok(exp);
             },
         }
     }
 }
 
-fn parse_parens_exp(lexer: &mut Lexer) -> Exp {
-    lexer.next_token_of_kind(TOKEN_SEP_LPAREN);
-    let exp = parse_exp(lexer);
-    lexer.next_token_of_kind(TOKEN_SEP_RPAREN);
-    match exp { 
+fn parse_parens_exp(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    let (line, _) = lexer.next_token_of_kind(TOKEN_SEP_LPAREN)?;
+    let exp = parse_exp(lexer)?;
+    lexer.next_token_of_match(TOKEN_SEP_RPAREN, TOKEN_SEP_LPAREN, line)?;
+    match exp {
         VarargExp { .. } | FuncCallExp { .. } | NameExp { .. } | TableAccessExp { .. } => {
-            ParensExp {
+            Ok(ParensExp {
                 exp: Box::new(exp),
-            }
+            })
         },
         _ => {
-            exp
+            Ok(exp)
         }
     }
 }
 
-fn _finish_func_call_exp(lexer: &mut Lexer, prefix_exp: Exp) -> Exp {
-    let name_exp = _parse_name_exp(lexer);      // [`:` Name]
+fn _finish_func_call_exp(lexer: &mut Lexer, prefix_exp: Exp) -> Result<Exp, CompileError> {
+    let name_exp = _parse_name_exp(lexer)?;     // [`:` Name]
     let line = lexer.line();
-    let args = _parse_args(lexer);
+    let args = _parse_args(lexer)?;
     let last_line = lexer.line();
-    FuncCallExp {
+    Ok(FuncCallExp {
         line,
         last_line,
         prefix_exp: Box::new(prefix_exp),
         name_exp: Box::new(name_exp),
         args,
-    }
+    })
 }
 
-fn _parse_name_exp(lexer: &mut Lexer) -> Exp {
-    if lexer.look_ahead() == TOKEN_SEP_COLON {
-        lexer.next_token();
-        let (line, name) = lexer.next_identifier();
-        StringExp {
+fn _parse_name_exp(lexer: &mut Lexer) -> Result<Exp, CompileError> {
+    if lexer.look_ahead()? == TOKEN_SEP_COLON {
+        lexer.next_token()?;
+        let (line, name) = lexer.next_identifier()?;
+        Ok(StringExp {
             line,
-            str: name,
-        }
+            str: name.into_bytes(),
+        })
     } else {
-        NilExp { line: 0 }
+        Ok(NilExp { line: 0 })
     }
 }
 
-fn _parse_args(lexer: &mut Lexer) -> Vec<Exp> {
-    let mut args = vec![];
-    match lexer.look_ahead() { 
+fn _parse_args(lexer: &mut Lexer) -> Result<Vec<Exp>, CompileError> {
+    let args;
+    match lexer.look_ahead()? {
         TOKEN_SEP_LPAREN => {
-            lexer.next_token();
-            if lexer.look_ahead() != TOKEN_SEP_RPAREN {
-                args = parse_exp_list(lexer);
-            }
-            lexer.next_token_of_kind(TOKEN_SEP_RPAREN);
+            let (line, _) = lexer.next_token_of_kind(TOKEN_SEP_LPAREN)?;
+            args = if lexer.look_ahead()? != TOKEN_SEP_RPAREN {
+                parse_exp_list(lexer)?
+            } else {
+                vec![]
+            };
+            lexer.next_token_of_match(TOKEN_SEP_RPAREN, TOKEN_SEP_LPAREN, line)?;
         },
         TOKEN_SEP_LCURLY => {
-            args = vec![parse_table_constructor_exp(lexer)];
+            args = vec![parse_table_constructor_exp(lexer)?];
         },
-        _ => {
-            let (line, str) = lexer.next_token_of_kind(TOKEN_STRING);
+        TOKEN_STRING => {
+            let (line, str) = lexer.next_string()?;
             args = vec![
                 StringExp {
                     line,
                     str,
                 }
             ];
-        }
+        },
+        _ => {
+            lexer.next_token()?;
+            return Err(lexer.syntax_error("function arguments expected"));
+        },
     }
-    args
-}
\ No newline at end of file
+    Ok(args)
+}
@@ -0,0 +1,47 @@
+// A Lua 5.3 compiler and virtual machine.
+//
+// The embedding surface is re-exported here: the state and its stack API
+// (`LuaAPI`, `LuaAuxLib`), the typed conversions, the chunk compiler and
+// the standard library openers. The modules behind it are internals.
+//
+//     let mut ls = LuaState::new();
+//     ls.OpenLibs();
+//     ls.Load(b"print('hello')".to_vec(), "hello", "t");
+//     ls.Call(0, 0);
+//
+// Lua errors and yields unwind the Rust stack up to the protected call or
+// `Resume` that catches them, as do compile errors, so the crate needs
+// `panic = "unwind"`: a profile with `panic = "abort"` does not build.
+
+#[cfg(panic = "abort")]
+compile_error!("lua_complier requires panic = \"unwind\": Lua errors and yields unwind the Rust stack");
+
+mod api;
+mod binchunk;
+mod compiler;
+mod number;
+mod state;
+mod stdlib;
+mod vm;
+
+pub use api::consts::*;
+pub use api::lua_auxlib::{FuncReg, LuaAuxLib};
+pub use api::lua_convert::{FromLua, FromLuaMulti, Globals, IntoLua, IntoLuaMulti, LuaFunction};
+pub use api::lua_state::{KFunction, LuaAPI, LuaDebug, LuaError, LuaHook, LuaUpValueIndex, RustClosure, RustClosureMut, RustFn};
+pub use binchunk::{binary_chunk::Prototype, dump, dump_strip, undump};
+pub use compiler::{codegen::compile, disassembly, error::CompileError};
+pub use state::lua_state::{is_binary_chunk, LuaState};
+pub use state::lua_thread::LuaThread;
+pub use state::lua_userdata::UserDataRef;
+pub use state::lua_value::LuaValue;
+pub use stdlib::lib_base::open_base;
+pub use stdlib::lib_coroutine::open_coroutine;
+pub use stdlib::lib_debug::open_debug;
+pub use stdlib::lib_io::open_io;
+pub use stdlib::lib_math::open_math;
+pub use stdlib::lib_os::open_os;
+pub use stdlib::lib_package::open_package;
+pub use stdlib::lib_string::open_string;
+pub use stdlib::lib_table::open_table;
+pub use stdlib::lib_utf8::open_utf8;
+pub use stdlib::os_host::{OsHost, StdHost};
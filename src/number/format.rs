@@ -0,0 +1,160 @@
+// Conversions of floats to text as done by C's `printf`, without flags
+// and width. `conv` is one of the conversion characters `e E f F g G a A`.
+pub fn FormatFloat(f: f64, conv: char, precision: Option<usize>, alt: bool) -> String {
+    let upper = conv.is_ascii_uppercase();
+    let s = if f.is_infinite() {
+        String::from(if f < 0.0 { "-inf" } else { "inf" })
+    } else if f.is_nan() {
+        String::from(if f.is_sign_negative() { "-nan" } else { "nan" })
+    } else {
+        match conv.to_ascii_lowercase() {
+            'e' => formatE(f, precision.unwrap_or(6), alt),
+            'f' => formatF(f, precision.unwrap_or(6), alt),
+            'g' => formatG(f, precision.unwrap_or(6), alt),
+            'a' => formatA(f, precision, alt),
+            _ => panic!("invalid conversion '{}'", conv),
+        }
+    };
+    if upper {
+        s.to_ascii_uppercase()
+    } else {
+        s
+    }
+}
+
+// Converts a float like `tostring` does ("%.14g"), making sure that it
+// does not look like an integer.
+pub fn FloatToString(f: f64) -> String {
+    let mut s = FormatFloat(f, 'g', Some(14), false);
+    if s.bytes().all(|b| b == b'-' || b.is_ascii_digit()) {
+        s.push_str(".0");
+    }
+    s
+}
+
+// Splits the "%.{precision}e" form of a finite `f` into its digits (with
+// the decimal point) and its exponent.
+fn decimalExp(f: f64, precision: usize) -> (String, i32) {
+    let s = format!("{:.*e}", precision, f);
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+    (String::from(mantissa), exp[1..].parse().unwrap())
+}
+
+fn formatE(f: f64, precision: usize, alt: bool) -> String {
+    let (mut mantissa, exp) = decimalExp(f, precision);
+    if alt && precision == 0 {
+        mantissa.push('.');
+    }
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exp.abs())
+}
+
+fn formatF(f: f64, precision: usize, alt: bool) -> String {
+    let mut s = format!("{:.*}", precision, f);
+    if alt && precision == 0 {
+        s.push('.');
+    }
+    s
+}
+
+fn formatG(f: f64, precision: usize, alt: bool) -> String {
+    let p = if precision == 0 { 1 } else { precision };
+    let (_, x) = decimalExp(f, p - 1);
+    let mut s = if x < -4 || x >= p as i32 {
+        formatE(f, p - 1, alt)
+    } else {
+        formatF(f, (p as i32 - 1 - x) as usize, alt)
+    };
+    if !alt {
+        // remove trailing zeros of the fraction, and the point if it is left alone
+        let exp_at = s.find('e').unwrap_or(s.len());
+        let (num, exp) = s.split_at(exp_at);
+        if num.contains('.') {
+            let num = num.trim_end_matches('0').trim_end_matches('.');
+            s = format!("{}{}", num, exp);
+        }
+    }
+    s
+}
+
+fn formatA(f: f64, precision: Option<usize>, alt: bool) -> String {
+    let sign = if f.is_sign_negative() { "-" } else { "" };
+    let bits = f.abs().to_bits();
+    let mut exp = ((bits >> 52) & 0x7ff) as i32;
+    let mut mant = bits & ((1 << 52) - 1);
+    let mut lead = 1u64;
+    if exp == 0 {
+        if mant == 0 {
+            lead = 0;
+        } else {
+            lead = 0;           // subnormal
+            exp = -1022;
+        }
+    } else {
+        exp -= 1023;
+    }
+    if mant == 0 && lead == 0 {
+        exp = 0;
+    }
+
+    let mut digits = 13;        // hexadecimal digits of the fraction
+    if let Some(p) = precision {
+        if p < 13 {
+            let shift = (13 - p) * 4;
+            let rest = mant & ((1 << shift) - 1);
+            let half = 1 << (shift - 1);
+            mant >>= shift;
+            if rest > half || (rest == half && mant & 1 == 1) {
+                mant += 1;
+                if mant >> (p * 4) != 0 {   // carry into the leading digit
+                    mant &= (1 << (p * 4)) - 1;
+                    lead += 1;
+                }
+            }
+            digits = p;
+        }
+    }
+    let mut frac = if digits == 0 { String::new() } else { format!("{:01$x}", mant, digits) };
+    if precision.is_none() {
+        frac = String::from(frac.trim_end_matches('0'));
+    } else if let Some(p) = precision {
+        while frac.len() < p {
+            frac.push('0');
+        }
+    }
+    let point = if !frac.is_empty() || alt { "." } else { "" };
+    let esign = if exp < 0 { '-' } else { '+' };
+    format!("{}0x{}{}{}p{}{}", sign, lead, point, frac, esign, exp.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FloatToString, FormatFloat};
+
+    #[test]
+    fn test_float_to_string() {
+        assert_eq!(FloatToString(3.0), "3.0");
+        assert_eq!(FloatToString(-0.5), "-0.5");
+        assert_eq!(FloatToString(0.1), "0.1");
+        assert_eq!(FloatToString(1e15), "1e+15");
+        assert_eq!(FloatToString(1e100), "1e+100");
+        assert_eq!(FloatToString(123456789012.0), "123456789012.0");
+        assert_eq!(FloatToString(2.0f64.powi(63)), "9.2233720368548e+18");
+        assert_eq!(FloatToString(1.0 / 3.0), "0.33333333333333");
+        assert_eq!(FloatToString(f64::INFINITY), "inf");
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(FormatFloat(2.3456, 'f', Some(2), false), "2.35");
+        assert_eq!(FormatFloat(0.000123, 'e', None, false), "1.230000e-04");
+        assert_eq!(FormatFloat(1e-5, 'g', None, false), "1e-05");
+        assert_eq!(FormatFloat(100000.0, 'g', None, false), "100000");
+        assert_eq!(FormatFloat(1000000.0, 'G', None, false), "1E+06");
+        assert_eq!(FormatFloat(2.0, 'g', None, true), "2.00000");
+        assert_eq!(FormatFloat(1.0, 'a', None, false), "0x1p+0");
+        assert_eq!(FormatFloat(-0.5, 'a', None, false), "-0x1p-1");
+        assert_eq!(FormatFloat(0.1, 'a', None, false), "0x1.999999999999ap-4");
+        assert_eq!(FormatFloat(1.5, 'a', Some(3), false), "0x1.800p+0");
+    }
+}
@@ -1,6 +1,8 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 pub fn IFloorDiv(a: i64, b: i64) -> i64 {
-    if (a > 0 && b > 0) || (a < 0 && b < 0) || (a % b == 0) {
-        return a / b;
+    if (a > 0 && b > 0) || (a < 0 && b < 0) || (a.wrapping_rem(b) == 0) {
+        return a.wrapping_div(b);
     } else {
         return a / b - 1;
     }
@@ -11,7 +13,7 @@ pub fn FFloorDiv(a: f64, b: f64) -> f64 {
 }
 
 pub fn IMod(a: i64, b: i64) -> i64 {
-    a - IFloorDiv(a, b) * b
+    a.wrapping_sub(IFloorDiv(a, b).wrapping_mul(b))
 }
 
 pub fn FMod(a: f64, b: f64) -> f64 {
@@ -19,7 +21,9 @@ pub fn FMod(a: f64, b: f64) -> f64 {
 }
 
 pub fn ShiftLeft(a: i64, n: i64) -> i64 {
-    if n >= 0 {
+    if n >= 64 || n <= -64 {
+        return 0;
+    } else if n >= 0 {
         return a << n;
     } else {
         return ShiftRight(a, -n);
@@ -27,7 +31,9 @@ pub fn ShiftLeft(a: i64, n: i64) -> i64 {
 }
 
 pub fn ShiftRight(a: i64, n: i64) -> i64 {
-    if n >= 0 {
+    if n >= 64 || n <= -64 {
+        return 0;
+    } else if n >= 0 {
         return (a as u64 >> n) as i64;
     } else {
         return ShiftLeft(a, -n);
@@ -36,10 +42,12 @@ pub fn ShiftRight(a: i64, n: i64) -> i64 {
 
 pub fn FloatToInteger(f: f64) -> (i64, bool) {
     let i = f as i64;
-    (i, f == (i as f64))
+    /* `as` saturates, so 2^63 must be rejected explicitly */
+    (i, f == (i as f64) && f < 9223372036854775808.0)
 }
 
+// A number no other call returned, to hash tables and closures by.
 pub fn random() -> usize {
-    let ptr = Box::into_raw(Box::new(123));
-    ptr as usize
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
 }
@@ -1,2 +1,3 @@
 pub mod math;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod format;
\ No newline at end of file
@@ -1,19 +1,124 @@
-use super::math::FloatToInteger;
+// Conversions of Lua numerals (as in the source code and in strings
+// converted to numbers) to integers and floats.
 
-pub fn ParseInteger(s: &str) -> (i64, bool) {
-    if let Ok(val) = s.parse::<i64>() {
-        return (val, true);
-    } else if let Ok(val) = s.parse::<f64>() {
-        return FloatToInteger(val);
+fn trimSpace(s: &str) -> &str {
+    s.trim_matches(|c: char| c == ' ' || ('\t'..='\r').contains(&c))
+}
+
+// Splits off the sign of a numeral, returns whether it is negative.
+fn splitSign(s: &str) -> (bool, &str) {
+    if let Some(rest) = s.strip_prefix('-') {
+        (true, rest)
     } else {
+        (false, s.strip_prefix('+').unwrap_or(s))
+    }
+}
+
+fn stripHexPrefix(s: &str) -> Option<&str> {
+    s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))
+}
+
+// Decimal integers must fit in an integer, hexadecimal ones wrap around.
+pub fn ParseInteger(s: &str) -> (i64, bool) {
+    let (neg, digits) = splitSign(trimSpace(s));
+    if let Some(hex) = stripHexPrefix(digits) {
+        if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return (0, false);
+        }
+        let mut i: u64 = 0;
+        for b in hex.bytes() {
+            i = i.wrapping_mul(16).wrapping_add((b as char).to_digit(16).unwrap() as u64);
+        }
+        let i = i as i64;
+        return (if neg { i.wrapping_neg() } else { i }, true);
+    }
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
         return (0, false);
     }
+    let digits = if neg { format!("-{}", digits) } else { String::from(digits) };
+    match digits.parse::<i64>() {
+        Ok(i) => (i, true),
+        Err(_) => (0, false),       /* overflow, it is read as a float */
+    }
 }
 
 pub fn ParseFloat(s: &str) -> (f64, bool) {
-    if let Ok(val) = s.parse::<f64>() {
-        return (val, true);
+    let (neg, digits) = splitSign(trimSpace(s));
+    let f = if let Some(hex) = stripHexPrefix(digits) {
+        match parseHexFloat(hex) {
+            Some(f) => f,
+            None => return (0.0, false),
+        }
     } else {
-        return (0.0, false);
+        /* reject what Rust accepts but Lua does not, like "inf" and "nan" */
+        if !digits.bytes().all(|b| b.is_ascii_digit() || b".eE+-".contains(&b)) {
+            return (0.0, false);
+        }
+        match digits.parse::<f64>() {
+            Ok(f) => f,
+            Err(_) => return (0.0, false),
+        }
+    };
+    (if neg { -f } else { f }, true)
+}
+
+// Hexadecimal digits with an optional fraction and binary exponent,
+// like "1.8p3" (0x1.8p3 == 12.0).
+fn parseHexFloat(s: &str) -> Option<f64> {
+    let (mantissa, exp) = match s.find(['p', 'P']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let mut f = 0.0;
+    let mut e: i32 = 0;
+    let mut any_digit = false;
+    let mut seen_point = false;
+    for c in mantissa.chars() {
+        if c == '.' && !seen_point {
+            seen_point = true;
+        } else if let Some(d) = c.to_digit(16) {
+            f = f * 16.0 + d as f64;
+            any_digit = true;
+            if seen_point {
+                e -= 4;
+            }
+        } else {
+            return None;
+        }
+    }
+    if !any_digit {
+        return None;
+    }
+    if let Some(exp) = exp {
+        let (neg, digits) = splitSign(exp);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let x: i32 = digits.parse().unwrap_or(i32::MAX / 2);
+        e = e.saturating_add(if neg { -x } else { x });
     }
-}
\ No newline at end of file
+    Some(f * 2f64.powi(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseFloat, ParseInteger};
+
+    #[test]
+    fn test_parse_numerals() {
+        assert_eq!(ParseInteger(" 42 "), (42, true));
+        assert_eq!(ParseInteger("0x10"), (16, true));
+        assert_eq!(ParseInteger("0xffffffffffffffff"), (-1, true));
+        assert_eq!(ParseInteger("-0x1"), (-1, true));
+        assert!(!ParseInteger("9223372036854775808").1);
+        assert!(!ParseInteger("1e20").1);
+        assert_eq!(ParseFloat("1e20"), (1e20, true));
+        assert_eq!(ParseFloat(".5"), (0.5, true));
+        assert_eq!(ParseFloat("0x1.8p3"), (12.0, true));
+        assert_eq!(ParseFloat("0xA"), (10.0, true));
+        assert!(!ParseFloat("inf").1);
+        assert!(!ParseFloat("nan").1);
+        assert!(!ParseFloat("0x").1);
+        assert!(!ParseFloat("1e").1);
+    }
+}
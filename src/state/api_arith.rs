@@ -2,7 +2,7 @@ use crate::number::math;
 use super::lua_value::LuaValue;
 
 fn iadd(a: i64, b: i64) -> i64 {
-    a + b
+    a.wrapping_add(b)
 }
 
 fn fadd(a: f64, b: f64) -> f64 {
@@ -10,13 +10,13 @@ fn fadd(a: f64, b: f64) -> f64 {
 }
 
 fn isub(a: i64, b: i64) -> i64 {
-    a - b
+    a.wrapping_sub(b)
 }
 fn fsub(a: f64, b: f64) -> f64 {
     a - b
 }
 fn imul(a: i64, b: i64) -> i64 {
-    a * b
+    a.wrapping_mul(b)
 }
 fn fmul(a: f64, b: f64) -> f64 {
     a * b
@@ -55,7 +55,7 @@ fn shr(a: i64, b: i64) -> i64 {
     math::ShiftRight(a, b)
 }
 fn iunm(a: i64, _: i64) -> i64 {
-    -a
+    a.wrapping_neg()
 }
 fn funm(a: f64, _: f64) -> f64 {
     -a
@@ -4,19 +4,13 @@ use crate::api::consts::*;
 pub fn compare_meta(a: &LuaValue, b: &LuaValue, op: u8, ls: &mut LuaState) -> Option<bool> {
     match op {
         LUA_OPEQ => {
-            match a {
-                LuaValue::Table(_) => {
-                    match b {
-                        LuaValue::Table(_) => {
-                            let _res_ = callMetamethod(a.clone(), b.clone(), "__eq", ls);
-                            if let Some(res) = _res_ {
-                                return Some(res.ToBoolean());
-                            } else {
-                                return None;
-                            }
-                        },
-                        _ => None,
-                    }
+            if eq(a, b) {
+                return Some(true);
+            }
+            match (a, b) {
+                (LuaValue::Table(_), LuaValue::Table(_)) | (LuaValue::UserData(_), LuaValue::UserData(_)) => {
+                    let _res_ = callMetamethod(a.clone(), b.clone(), "__eq", ls);
+                    _res_.map(|res| res.ToBoolean())
                 },
                 _ => None,
             }
@@ -105,7 +99,7 @@ pub fn eq(a: &LuaValue, b: &LuaValue) -> bool {
                 LuaValue::Bool(y) => x == y,
                 _ => false,
             },
-            _ => false,
+            _ => a == b,            // tables, functions and threads compare by reference
         }
     }
 }
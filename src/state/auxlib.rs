@@ -0,0 +1,473 @@
+use std::{any::Any, cell::RefCell, fs, io::{self, Read}, rc::Rc};
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::{LuaAPI, LuaDebug, RustFn}};
+use crate::stdlib::{lib_base::open_base, lib_coroutine::open_coroutine, lib_debug::open_debug, lib_io::open_io, lib_math::open_math, lib_os::open_os, lib_package::open_package, lib_string::open_string, lib_table::open_table, lib_utf8::open_utf8, os_host::{OsHost, OS_HOST}};
+use super::{lua_state::LuaState, lua_thread::LuaThread, lua_userdata::UserDataRef, lua_value::LuaValue};
+
+impl LuaAuxLib for LuaState {
+    fn Error2(&mut self, msg: String) -> i32 {
+        self.Where(1);
+        self.PushString(msg);
+        self.Concat(2);
+        self.Error()
+    }
+
+    fn ArgError(&mut self, mut arg: i32, extraMsg: &str) -> i32 {
+        let mut ar = match self.GetStack(None, 0) {
+            Some(ar) => ar,
+            None => return self.Error2(format!("bad argument #{} ({})", arg, extraMsg)),  /* no stack frame? */
+        };
+        self.GetInfo("n", &mut ar);
+        if ar.namewhat == "method" {
+            arg -= 1;                   /* do not count 'self' */
+            if arg == 0 {               /* error is in the self argument itself? */
+                let name = ar.name.unwrap_or_default();
+                return self.Error2(format!("calling '{}' on bad self ({})", name, extraMsg));
+            }
+        }
+        let name = match ar.name.take() {
+            Some(name) => name,
+            None => self.globalFuncName(&mut ar).unwrap_or(String::from("?")),
+        };
+        self.Error2(format!("bad argument #{} to '{}' ({})", arg, name, extraMsg))
+    }
+
+    // Pushes the position of the function at `level` for error messages,
+    // e.g. "file.lua:12: ", or "" if it is not a Lua function.
+    fn Where(&mut self, level: i32) {
+        if let Some(mut ar) = self.GetStack(None, level) {
+            self.GetInfo("Sl", &mut ar);
+            if ar.currentline > 0 {     /* is there info? */
+                self.PushString(format!("{}:{}: ", ar.short_src, ar.currentline));
+                return;
+            }
+        }
+        self.PushString(String::new());    /* else, no information available... */
+    }
+
+    fn ArgCheck(&mut self, cond: bool, arg: i32, extraMsg: &str) {
+        if !cond {
+            self.ArgError(arg, extraMsg);
+        }
+    }
+
+    fn CheckAny(&mut self, arg: i32) {
+        if self.Type(arg) == LUA_TNONE {
+            self.ArgError(arg, "value expected");
+        }
+    }
+
+    fn CheckType(&mut self, arg: i32, t: i8) {
+        if self.Type(arg) != t {
+            self.tagError(arg, t);
+        }
+    }
+
+    fn CheckInteger(&mut self, arg: i32) -> i64 {
+        if let Some(i) = self.ToIntegerX(arg) {
+            i
+        } else if self.IsNumber(arg) {
+            self.ArgError(arg, "number has no integer representation") as i64
+        } else {
+            self.tagError(arg, LUA_TNUMBER) as i64
+        }
+    }
+
+    fn CheckNumber(&mut self, arg: i32) -> f64 {
+        match self.ToNumberX(arg) {
+            Some(n) => n,
+            None => self.tagError(arg, LUA_TNUMBER) as f64,
+        }
+    }
+
+    fn OptNumber(&mut self, arg: i32, def: f64) -> f64 {
+        if self.IsNoneOrNil(arg) {
+            def
+        } else {
+            self.CheckNumber(arg)
+        }
+    }
+
+    fn OptInteger(&mut self, arg: i32, def: i64) -> i64 {
+        if self.IsNoneOrNil(arg) {
+            def
+        } else {
+            self.CheckInteger(arg)
+        }
+    }
+
+    fn CheckString(&mut self, arg: i32) -> String {
+        if !self.IsString(arg) {
+            self.tagError(arg, LUA_TSTRING);
+        }
+        self.ToString(arg)
+    }
+
+    fn CheckBytes(&mut self, arg: i32) -> Vec<u8> {
+        if !self.IsString(arg) {
+            self.tagError(arg, LUA_TSTRING);
+        }
+        self.ToBytes(arg)
+    }
+
+    fn OptString(&mut self, arg: i32, def: &str) -> String {
+        if self.IsNoneOrNil(arg) {
+            String::from(def)
+        } else {
+            self.CheckString(arg)
+        }
+    }
+
+    fn CheckOption(&mut self, arg: i32, def: Option<&str>, lst: &[&str]) -> usize {
+        let name = match def {
+            Some(def) => self.OptString(arg, def),
+            None => self.CheckString(arg),
+        };
+        match lst.iter().position(|opt| *opt == name) {
+            Some(i) => i,
+            None => self.ArgError(arg, &format!("invalid option '{}'", name)) as usize,
+        }
+    }
+
+    fn CheckUData<T: Any>(&mut self, arg: i32, tname: &'static str) -> UserDataRef<T> {
+        match self.TestUData(arg, tname) {
+            Some(u) => u,
+            None => {
+                let msg = format!("{} expected, got {}", tname, self.TypeName2(arg));
+                self.ArgError(arg, &msg);
+                unreachable!()
+            },
+        }
+    }
+
+    // The userdata at `arg` if its metatable is the one registered as `tname`.
+    fn TestUData<T: Any>(&mut self, arg: i32, tname: &'static str) -> Option<UserDataRef<T>> {
+        let u = self.ToUserData(arg)?;
+        if !self.GetMetatable(arg) {    /* does it have a metatable? */
+            return None;
+        }
+        self.GetMetatableByName(tname); /* get correct metatable */
+        let same = self.RawEqual(-1, -2);   /* not the same? */
+        self.pop(2);                    /* remove both metatables */
+        if same { Some(u) } else { None }
+    }
+
+    // Creates the metatable for userdata of type `tname` in the registry,
+    // returns false (pushing the existing one) if there is one already.
+    fn NewMetatable(&mut self, tname: &'static str) -> bool {
+        if self.GetMetatableByName(tname) != LUA_TNIL {  /* name already in use? */
+            return false;               /* leave previous value on top, but return false */
+        }
+        self.pop(1);
+        self.CreateTable(0, 2);         /* create metatable */
+        self.PushString(String::from(tname));
+        self.SetField(-2, "__name");    /* metatable.__name = tname */
+        self.PushValue(-1);
+        self.SetField(LUA_REGISTRYINDEX as i32, tname);    /* registry.name = metatable */
+        true
+    }
+
+    fn GetMetatableByName(&mut self, tname: &'static str) -> i8 {
+        self.GetField(LUA_REGISTRYINDEX as i32, tname)
+    }
+
+    fn SetMetatableByName(&mut self, tname: &'static str) {
+        self.GetMetatableByName(tname);
+        self.SetMetatable(-2);
+    }
+
+    fn GetMetafield(&mut self, obj: i32, e: &'static str) -> i8 {
+        if !self.GetMetatable(obj) {    /* no metatable? */
+            return LUA_TNIL;
+        }
+        self.PushString(String::from(e));
+        let tt = self.RawGet(-2);
+        if tt == LUA_TNIL {             /* is metafield nil? */
+            self.pop(2);                /* remove metatable and metafield */
+        } else {
+            self.Remove(-2);            /* remove only metatable */
+        }
+        tt                              /* return metafield type */
+    }
+
+    fn CallMeta(&mut self, obj: i32, e: &'static str) -> bool {
+        let obj = self.AbsIndex(obj);
+        if self.GetMetafield(obj, e) == LUA_TNIL {     /* no metafield? */
+            return false;
+        }
+        self.PushValue(obj);
+        self.Call(1, 1);
+        true
+    }
+
+    // Converts any value to a string the way `tostring` does, and pushes it.
+    fn ToString2(&mut self, idx: i32) -> String {
+        if self.CallMeta(idx, "__tostring") {   /* metafield? */
+            if !self.IsString(-1) {
+                self.Error2(String::from("'__tostring' must return a string"));
+            }
+        } else {
+            match self.Type(idx) {
+                LUA_TNUMBER => {
+                    let s = self.ToBytes(idx);  /* "%d" or "%.14g" */
+                    self.PushBytes(s);
+                },
+                LUA_TSTRING => self.PushValue(idx),
+                LUA_TBOOLEAN => {
+                    let s = if self.ToBoolean(idx) { "true" } else { "false" };
+                    self.PushString(String::from(s));
+                },
+                LUA_TNIL => self.PushString(String::from("nil")),
+                _ => {
+                    let tt = self.GetMetafield(idx, "__name");  /* try name */
+                    let kind = if tt == LUA_TSTRING {
+                        self.ToString(-1)
+                    } else {
+                        String::from(self.TypeName2(idx))
+                    };
+                    let s = format!("{}: {:p}", kind, self.ToPointer(idx));
+                    if tt != LUA_TNIL {
+                        self.pop(1);            /* remove '__name' */
+                    }
+                    self.PushString(s);
+                },
+            }
+        }
+        self.ToString(-1)
+    }
+
+    fn TypeName2(&self, idx: i32) -> &'static str {
+        self.TypeName(self.Type(idx))
+    }
+
+    fn Len2(&mut self, idx: i32) -> i64 {
+        self.Len(idx);
+        match self.ToIntegerX(-1) {
+            Some(n) => {
+                self.pop(1);            /* remove object */
+                n
+            },
+            None => self.Error2(String::from("object length is not an integer")) as i64,
+        }
+    }
+
+    // Pushes a traceback of the calls of thread `co` (the running one if
+    // `None`) from `level` on, after `msg` if given.
+    fn Traceback(&mut self, co: Option<Rc<RefCell<LuaThread>>>, msg: Option<&str>, mut level: i32) {
+        const LEVELS1: i32 = 10;        /* size of the first part of the stack */
+        const LEVELS2: i32 = 11;        /* size of the second part of the stack */
+        let mut last = level.max(0);
+        while self.GetStack(co.as_ref(), last + 1).is_some() {
+            last += 1;
+        }
+        let mut n1 = if last - level > LEVELS1 + LEVELS2 { LEVELS1 } else { -1 };
+        let mut tb = match msg {
+            Some(msg) => format!("{}\n", msg),
+            None => String::new(),
+        };
+        tb.push_str("stack traceback:");
+        while let Some(mut ar) = self.GetStack(co.as_ref(), level) {
+            level += 1;
+            if n1 == 0 {                /* too many levels? */
+                tb.push_str("\n\t...");
+                level = last - LEVELS2 + 1;     /* and skip to last ones */
+            } else {
+                self.GetInfo("Slnt", &mut ar);
+                tb.push_str(&format!("\n\t{}:", ar.short_src));
+                if ar.currentline > 0 {
+                    tb.push_str(&format!("{}:", ar.currentline));
+                }
+                tb.push_str(" in ");
+                tb.push_str(&self.funcDescription(&mut ar));
+                if ar.istailcall {
+                    tb.push_str("\n\t(...tail calls...)");
+                }
+            }
+            n1 -= 1;
+        }
+        self.PushString(tb);
+    }
+
+    fn GetSubTable(&mut self, idx: i32, fname: &'static str) -> bool {
+        if self.GetField(idx, fname) == LUA_TTABLE {
+            return true;        /* table already there */
+        }
+        self.pop(1);
+        let idx = self.AbsIndex(idx);
+        self.NewTable();
+        self.PushValue(-1);     /* copy to be left at top */
+        self.SetField(idx, fname);      /* assign new table to field */
+        false                   /* false, because did not find table there */
+    }
+
+    fn RequireF(&mut self, modname: &'static str, openf: RustFn, glb: bool) {
+        self.GetSubTable(LUA_REGISTRYINDEX as i32, "_LOADED");
+        self.GetField(-1, modname);     /* LOADED[modname] */
+        if !self.ToBoolean(-1) {        /* package not already loaded? */
+            self.pop(1);
+            self.PushRustFunction(openf);
+            self.PushString(String::from(modname));     /* argument to open function */
+            self.Call(1, 1);            /* call 'openf' to open module */
+            self.PushValue(-1);         /* make copy of module (call result) */
+            self.SetField(-3, modname); /* LOADED[modname] = module */
+        }
+        self.Remove(-2);                /* remove LOADED table */
+        if glb {
+            self.PushValue(-1);         /* copy of module */
+            self.SetGlobal(modname);    /* _G[modname] = module */
+        }
+    }
+
+    fn NewLib(&mut self, l: &[FuncReg]) {
+        self.CreateTable(0, l.len() as i32);
+        self.SetFuncs(l, 0);
+    }
+
+    fn SetFuncs(&mut self, l: &[FuncReg], nup: i32) {
+        self.CheckStack(nup);
+        for (name, func) in l {         /* fill the table with given functions */
+            for _ in 0..nup {           /* copy upvalues to the top */
+                self.PushValue(-nup);
+            }
+            self.PushGoClosure(*func, nup);     /* closure with those upvalues */
+            self.SetField(-(nup + 2), name);
+        }
+        self.pop(nup);                  /* remove upvalues */
+    }
+
+    // Loads a file as a chunk, or the standard input if `filename` is
+    // None. A first line starting with '#' is skipped.
+    fn LoadFileX(&mut self, filename: Option<&str>, mode: &str) -> i32 {
+        let (chunkname, data) = match filename {
+            Some(f) => (format!("@{}", f), fs::read(f)),
+            None => {
+                let mut data = Vec::new();
+                let res = io::stdin().read_to_end(&mut data).map(|_| data);
+                (String::from("=stdin"), res)
+            },
+        };
+        let mut data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                let msg = format!("cannot open {}: {}", &chunkname[1..], strError(&e));
+                self.PushString(msg);
+                return LUA_ERRFILE;
+            },
+        };
+        if data.first() == Some(&b'#') {    /* first line is a comment (Unix exec. file)? */
+            let eol = data.iter().position(|&b| b == b'\n').unwrap_or(data.len());
+            data.drain(..eol);          /* skip it, keeping the newline for line numbers */
+        }
+        self.Load(data, &chunkname, mode)
+    }
+
+    // Makes a module implemented in Rust available to `require`, which
+    // calls `openf` the first time the module is required.
+    fn RegisterModule(&mut self, modname: &str, openf: RustFn) {
+        self.GetSubTable(LUA_REGISTRYINDEX as i32, "_NATIVE");
+        self.PushString(String::from(modname));
+        self.PushRustFunction(openf);
+        self.SetTable(-3);              /* NATIVE[modname] = openf */
+        self.pop(1);                    /* remove NATIVE table */
+    }
+
+    // Replaces the access to the system of the os library, see `OsHost`.
+    fn SetOsHost(&mut self, host: Box<dyn OsHost>) {
+        self.NewUserData(host);
+        self.SetField(LUA_REGISTRYINDEX as i32, OS_HOST);
+    }
+
+    // Opens all standard libraries into the state.
+    fn OpenLibs(&mut self) {
+        const LOADED_LIBS: &[FuncReg] = &[
+            ("_G", open_base),
+            ("package", open_package),
+            ("coroutine", open_coroutine),
+            ("table", open_table),
+            ("io", open_io),
+            ("os", open_os),
+            ("string", open_string),
+            ("math", open_math),
+            ("utf8", open_utf8),
+            ("debug", open_debug),
+        ];
+        for (name, openf) in LOADED_LIBS {
+            self.RequireF(name, *openf, true);
+            self.pop(1);                /* remove lib */
+        }
+    }
+}
+
+// The message of an I/O error without the "(os error N)" suffix, like
+// C's `strerror`.
+pub fn strError(e: &io::Error) -> String {
+    let msg = e.to_string();
+    match msg.find(" (os error") {
+        Some(i) => String::from(&msg[..i]),
+        None => msg,
+    }
+}
+
+impl LuaState {
+    fn tagError(&mut self, arg: i32, tag: i8) -> i32 {
+        let msg = format!("{} expected, got {}", self.TypeName(tag), self.TypeName2(arg));
+        self.ArgError(arg, &msg)
+    }
+
+    // How the function of `ar` is called in a traceback: by its name in a
+    // loaded module, by the name it was called with, or by where it is.
+    fn funcDescription(&mut self, ar: &mut LuaDebug) -> String {
+        if let Some(name) = self.globalFuncName(ar) {
+            format!("function '{}'", name)
+        } else if !ar.namewhat.is_empty() {
+            format!("{} '{}'", ar.namewhat, ar.name.as_deref().unwrap_or("?"))
+        } else if ar.what == "main" {
+            String::from("main chunk")
+        } else if ar.what != "C" {
+            format!("function <{}:{}>", ar.short_src, ar.linedefined)
+        } else {
+            String::from("?")
+        }
+    }
+
+    // The name of the function of `ar` in `package.loaded`, e.g. "string.rep",
+    // or "print" for the functions of the global table.
+    fn globalFuncName(&mut self, ar: &mut LuaDebug) -> Option<String> {
+        self.GetInfo("f", ar);
+        let f = match self.stack_mut().pop() {
+            LuaValue::Function(f) => f,
+            _ => return None,
+        };
+        let is_func = |v: &LuaValue| matches!(v, LuaValue::Function(c) if Rc::ptr_eq(c, &f));
+        let loaded = match &self.registry {
+            LuaValue::Table(reg) => reg.borrow().Get(&LuaValue::Str(b"_LOADED".to_vec())),
+            _ => return None,
+        };
+        let mut modules = match &loaded {
+            LuaValue::Table(loaded) => loaded.borrow().entries(),
+            _ => return None,
+        };
+        // the global table first, then the other modules by name
+        modules.sort_by_key(|(k, _)| match k {
+            LuaValue::Str(name) => (name != b"_G", name.clone()),
+            _ => (true, vec![]),
+        });
+        for (k, module) in &modules {
+            let modname = match k {
+                LuaValue::Str(name) => String::from_utf8_lossy(name).into_owned(),
+                _ => continue,
+            };
+            if is_func(module) {
+                return Some(modname);
+            }
+            if let LuaValue::Table(module) = module {
+                for (k, v) in module.borrow().entries() {
+                    if let (LuaValue::Str(name), true) = (&k, is_func(&v)) {
+                        let name = String::from_utf8_lossy(name);
+                        return Some(if modname == "_G" { name.into_owned() } else { format!("{}.{}", modname, name) });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
@@ -1,13 +1,39 @@
-use std::{fmt::Debug, hash::{Hash, Hasher}, rc::Rc, cell::RefCell};
-use crate::{binchunk::binary_chunk::Prototype, number::math::random, api::lua_state::RustFn};
+use std::{fmt::{self, Debug}, hash::{Hash, Hasher}, rc::Rc, cell::RefCell};
+use crate::{binchunk::binary_chunk::Prototype, number::math::random, api::lua_state::{RustClosure, RustClosureMut, RustFn}};
 
 use super::lua_value::LuaValue;
 
+// An upvalue. Closures that capture the same variable share its cell; while
+// the variable is still on the stack, its register is kept in the cell too.
+pub type UpVal = Rc<RefCell<LuaValue>>;
+
+pub fn newUpVal(val: LuaValue) -> UpVal {
+    Rc::new(RefCell::new(val))
+}
+
+// The code of a Rust function: a plain `fn`, or a boxed closure that
+// keeps state of the host.
+pub enum RustFunc {
+    Fn(RustFn),
+    Closure(RustClosure),
+    ClosureMut(RefCell<RustClosureMut>),
+}
+
+impl Debug for RustFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RustFunc::Fn(func) => write!(f, "Fn({:p})", *func as *const ()),
+            RustFunc::Closure(_) => write!(f, "Closure"),
+            RustFunc::ClosureMut(_) => write!(f, "ClosureMut"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Closure {
     pub proto: Rc<Prototype>,
-    pub rustFunc: Option<RustFn>,
-    pub upvals: RefCell<Vec<LuaValue>>,
+    pub rustFunc: Option<RustFunc>,
+    pub upvals: RefCell<Vec<UpVal>>,
     pub is_fake: bool,
     rdm: usize,
 }
@@ -24,7 +50,7 @@ impl Closure {
         let mut upvals = Vec::with_capacity(n_up_vals);
         if n_up_vals > 0 {
             for _ in 0..n_up_vals {
-                upvals.push(LuaValue::Nil);
+                upvals.push(newUpVal(LuaValue::Nil));
             }
         }
         Self {
@@ -36,11 +62,11 @@ impl Closure {
         }
     }
 
-    pub fn newRustClosure(f: RustFn, n_up_vals: i32) -> Self {
+    pub fn newRustClosure(f: RustFunc, n_up_vals: i32) -> Self {
         let mut upvals = Vec::with_capacity(n_up_vals as usize);
         if n_up_vals > 0 {
             for _ in 0..n_up_vals {
-                upvals.push(LuaValue::Nil);
+                upvals.push(newUpVal(LuaValue::Nil));
             }
         }
         Self {
@@ -0,0 +1,262 @@
+use crate::binchunk::binary_chunk::Prototype;
+use crate::compiler::error::chunk_id;
+use crate::vm::{instruction::Instruction, opcodes::*};
+use super::lua_value::LuaValue;
+
+// What the debug information of a prototype tells about it, like
+// `ldebug.c` does for the reference VM.
+
+// The chunk name of `proto` for messages, "?" if it was stripped.
+pub fn shortSrc(proto: &Prototype) -> String {
+    match &proto.source {
+        Some(source) => chunk_id(source),
+        None => String::from("?"),
+    }
+}
+
+// The line of the instruction before `pc`, the one running in a frame.
+pub fn currentLine(proto: &Prototype, pc: i32) -> i32 {
+    let pc = (pc - 1).max(0) as usize;
+    proto.lineInfo.get(pc).map_or(-1, |line| *line as i32)
+}
+
+// The name of the `n`th local active at `pc` (1-based), like `luaF_getlocalname`.
+pub fn localName(proto: &Prototype, mut n: i32, pc: i32) -> Option<&str> {
+    for var in proto.locVars.iter().take_while(|v| v.startPC as i32 <= pc) {
+        if pc < var.endPC as i32 {      /* is variable active? */
+            n -= 1;
+            if n == 0 {
+                return Some(&var.varName);
+            }
+        }
+    }
+    None
+}
+
+fn upvalName(proto: &Prototype, uv: i32) -> String {
+    proto.upvalueNames.get(uv as usize).cloned().unwrap_or(String::from("?"))
+}
+
+// Where an operand of an instruction is read from.
+#[derive(Clone, Copy)]
+pub enum Operand {
+    Register(i32),
+    Upvalue(i32),
+}
+
+// The operands whose type can make the instruction at `pc` fail, in the
+// order the operation checks them. `reg` gives the value of a register.
+pub fn typeErrorOperands(proto: &Prototype, pc: i32, reg: impl Fn(i32) -> LuaValue) -> Vec<Operand> {
+    let i = Instruction::new(proto.code[pc as usize]);
+    let (a, b, c) = i.ABC();
+    let rk = |x: i32| if x > 0xff { None } else { Some(Operand::Register(x)) };  /* constants have no name */
+    match i.Opcode() as u8 {
+        OP_GETTABUP => vec![Operand::Upvalue(b)],
+        OP_SETTABUP => vec![Operand::Upvalue(a)],
+        OP_GETTABLE | OP_SELF | OP_UNM | OP_BNOT | OP_LEN => vec![Operand::Register(b)],
+        OP_SETTABLE | OP_CALL | OP_TAILCALL => vec![Operand::Register(a)],
+        OP_ADD | OP_SUB | OP_MUL | OP_MOD | OP_POW | OP_DIV | OP_IDIV
+        | OP_BAND | OP_BOR | OP_BXOR | OP_SHL | OP_SHR => rk(b).into_iter().chain(rk(c)).collect(),
+        OP_CONCAT => {
+            /* values are concatenated from the right, so the failing pair
+               is the last one with a value that is not a string */
+            let isStr = |r: i32| matches!(reg(r), LuaValue::Str(_) | LuaValue::Integer(_) | LuaValue::Number(_));
+            match (b..=c).rev().find(|r| !isStr(*r)) {
+                Some(r) if r == c && r > b && !isStr(r - 1) => vec![Operand::Register(r - 1)],
+                Some(r) => vec![Operand::Register(r)],
+                None => vec![],
+            }
+        },
+        _ => vec![],
+    }
+}
+
+// The kind and the name of an operand of the instruction at `pc`.
+pub fn operandName(proto: &Prototype, pc: i32, operand: Operand) -> Option<(&'static str, String)> {
+    match operand {
+        Operand::Register(reg) => getObjName(proto, pc, reg),
+        Operand::Upvalue(uv) => Some(("upvalue", upvalName(proto, uv))),
+    }
+}
+
+// The name of the function called by the instruction at `pc`, with what
+// kind of name it is.
+pub fn funcNameFromCode(proto: &Prototype, pc: i32) -> Option<(&'static str, String)> {
+    let i = Instruction::new(proto.code[pc as usize]);
+    let tm = match i.Opcode() as u8 {
+        OP_CALL | OP_TAILCALL => return getObjName(proto, pc, i.ABC().0),
+        OP_TFORCALL => return Some(("for iterator", String::from("for iterator"))),
+        /* other instructions can do calls through metamethods */
+        OP_SELF | OP_GETTABUP | OP_GETTABLE => "__index",
+        OP_SETTABUP | OP_SETTABLE => "__newindex",
+        OP_ADD => "__add",
+        OP_SUB => "__sub",
+        OP_MUL => "__mul",
+        OP_MOD => "__mod",
+        OP_POW => "__pow",
+        OP_DIV => "__div",
+        OP_IDIV => "__idiv",
+        OP_BAND => "__band",
+        OP_BOR => "__bor",
+        OP_BXOR => "__bxor",
+        OP_SHL => "__shl",
+        OP_SHR => "__shr",
+        OP_UNM => "__unm",
+        OP_BNOT => "__bnot",
+        OP_LEN => "__len",
+        OP_CONCAT => "__concat",
+        OP_EQ => "__eq",
+        OP_LT => "__lt",
+        OP_LE => "__le",
+        _ => return None,       /* cannot find a reasonable name */
+    };
+    Some(("metamethod", String::from(tm)))
+}
+
+// The name of the value in register `reg` before the instruction at
+// `lastpc` runs, found by following the instructions that set it.
+pub fn getObjName(proto: &Prototype, lastpc: i32, reg: i32) -> Option<(&'static str, String)> {
+    if let Some(name) = localName(proto, reg + 1, lastpc) {
+        return Some(("local", name.to_owned()));
+    }
+    /* else try symbolic execution */
+    let pc = findSetReg(proto, lastpc, reg)?;
+    let i = Instruction::new(proto.code[pc as usize]);
+    match i.Opcode() as u8 {
+        OP_MOVE => {
+            let (a, b, _) = i.ABC();
+            if b < a {
+                return getObjName(proto, pc, b);    /* get name for 'b' */
+            }
+        },
+        op @ (OP_GETTABUP | OP_GETTABLE) => {
+            let (_, t, k) = i.ABC();
+            let vn = if op == OP_GETTABLE {     /* name of indexed variable */
+                /* globals are indexed after loading _ENV into a register */
+                localName(proto, t + 1, pc).map(String::from).or_else(|| match getObjName(proto, pc, t) {
+                    Some(("upvalue", name)) => Some(name),
+                    _ => None,
+                })
+            } else {
+                Some(upvalName(proto, t))
+            };
+            let what = if vn.as_deref() == Some("_ENV") { "global" } else { "field" };
+            return Some((what, kName(proto, pc, k)));
+        },
+        OP_GETUPVAL => {
+            let (_, b, _) = i.ABC();
+            return Some(("upvalue", upvalName(proto, b)));
+        },
+        op @ (OP_LOADK | OP_LOADKX) => {
+            let b = if op == OP_LOADK {
+                i.ABx().1
+            } else {
+                Instruction::new(proto.code[pc as usize + 1]).Ax()
+            };
+            if let Some(LuaValue::Str(s)) = proto.constants.get(b as usize) {
+                return Some(("constant", String::from_utf8_lossy(s).into_owned()));
+            }
+        },
+        OP_SELF => {
+            let (_, _, k) = i.ABC();
+            return Some(("method", kName(proto, pc, k)));
+        },
+        _ => {},
+    }
+    None                        /* could not find reasonable name */
+}
+
+// The name of the key `c` (a constant or a register) of a table access.
+fn kName(proto: &Prototype, pc: i32, c: i32) -> String {
+    if c > 0xff {               /* is 'c' a constant? */
+        if let Some(LuaValue::Str(s)) = proto.constants.get((c & 0xff) as usize) {
+            return String::from_utf8_lossy(s).into_owned();    /* literal constant is its own name */
+        }
+    } else if let Some(("constant", name)) = getObjName(proto, pc, c) {
+        return name;
+    }
+    String::from("?")           /* no reasonable name found */
+}
+
+// The last instruction before `lastpc` that set register `reg`, `None` if
+// it is unknown because code in between is conditional.
+fn findSetReg(proto: &Prototype, lastpc: i32, reg: i32) -> Option<i32> {
+    let mut setreg = None;      /* keep last instruction that changed 'reg' */
+    let mut jmptarget = 0;      /* any code before this address is conditional */
+    // code inside a jump cannot tell who sets the register
+    let filter = |pc: i32, jmptarget: i32| if pc < jmptarget { None } else { Some(pc) };
+    for pc in 0..lastpc {
+        let i = Instruction::new(proto.code[pc as usize]);
+        let op = i.Opcode() as u8;
+        let (a, b, _) = i.ABC();
+        match op {
+            OP_LOADNIL => {
+                if a <= reg && reg <= a + b {   /* set registers from 'a' to 'a+b' */
+                    setreg = filter(pc, jmptarget);
+                }
+            },
+            OP_TFORCALL => {
+                if reg >= a + 2 {               /* affect all regs above its base */
+                    setreg = filter(pc, jmptarget);
+                }
+            },
+            OP_CALL | OP_TAILCALL => {
+                if reg >= a {                   /* affect all registers above base */
+                    setreg = filter(pc, jmptarget);
+                }
+            },
+            OP_JMP => {
+                let dest = pc + 1 + i.AsBx().1;
+                /* jump is forward and do not skip 'lastpc'? */
+                if pc < dest && dest <= lastpc && dest > jmptarget {
+                    jmptarget = dest;
+                }
+            },
+            _ => {
+                if OPCODES[op as usize].setAFlag == 1 && reg == a {    /* any instruction that set A */
+                    setreg = filter(pc, jmptarget);
+                }
+            },
+        }
+    }
+    setreg
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+    use crate::state::lua_state::LuaState;
+
+    fn error_of(src: &str) -> String {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        ls.Load(src.as_bytes().to_vec(), "=test", "t");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        ls.ToString(-1)
+    }
+
+    #[test]
+    fn test_variable_names_in_type_errors() {
+        assert_eq!(error_of("foo()"), "test:1: attempt to call a nil value (global 'foo')");
+        assert_eq!(error_of("local t = {}\nt.x.y = 1"), "test:2: attempt to index a nil value (field 'x')");
+        assert_eq!(error_of("local s\nreturn #s"), "test:2: attempt to get length of a nil value (local 's')");
+        assert_eq!(error_of("local u\nreturn (function () return u + 1 end)()"),
+            "test:2: attempt to perform arithmetic on a nil value (upvalue 'u')");
+        assert_eq!(error_of("local t = {}\nt:nope()"), "test:2: attempt to call a nil value (method 'nope')");
+        assert_eq!(error_of("local x = 'a'\nreturn x .. g .. 'b'"), "test:2: attempt to concatenate a nil value (global 'g')");
+        assert_eq!(error_of("return 'a' .. {}"), "test:1: attempt to concatenate a table value");
+        assert_eq!(error_of("local f = 1.5\nreturn f | 1"), "test:2: number (local 'f') has no integer representation");
+        assert_eq!(error_of("return ('x')()"), "test:1: attempt to call a string value (constant 'x')");
+        assert_eq!(error_of("local t = setmetatable({}, {__index = 1})\nreturn t.k"), "test:2: attempt to index a number value");
+    }
+
+    #[test]
+    fn test_function_names_in_argument_errors() {
+        assert_eq!(error_of("local r = string.rep\nr()"), "test:2: bad argument #1 to 'r' (string expected, got no value)");
+        assert_eq!(error_of("return ('x'):rep({})"), "test:1: bad argument #1 to 'rep' (number expected, got table)");
+        assert_eq!(error_of("local t = {rep = string.rep}\nt:rep(2)"),
+            "test:2: calling 'rep' on bad self (string expected, got table)");
+        assert_eq!(error_of("local ok, e = pcall(string.rep)\nerror(e, 0)"),
+            "bad argument #1 to 'string.rep' (string expected, got no value)");
+    }
+}
@@ -0,0 +1,408 @@
+use std::{cell::RefCell, collections::{hash_map::Entry, HashMap, HashSet, VecDeque}, mem::size_of, rc::{Rc, Weak}};
+use super::{closure::{Closure, UpVal}, lua_table::LuaTable, lua_thread::LuaThread, lua_userdata::LuaUserData, lua_value::LuaValue};
+
+// Values are reference counted, so everything that is not part of a cycle
+// is freed as soon as the last reference to it goes away. The collector
+// only has to find tables, closures, threads and userdata that keep each
+// other alive although nothing can reach them anymore (`t.self = t`, a
+// closure stored in a table it captures, ...).
+//
+// The roots are all references from outside the heap: the registry, the
+// frames of the running thread and values held by Rust code. An object
+// is reachable if it has more references than the heap itself accounts
+// for, or if a reachable object refers to it. Unreachable objects are
+// cleared, which breaks the cycles and lets them be freed.
+//
+// Tables and userdata whose metatable has a `__gc` field when it is set
+// are held by the collector until they become unreachable. They are then
+// kept alive, with everything they refer to, for their finalizer to run.
+
+const GCMINOBJS: usize = 1024;      // no automatic collection below this
+const GCPAUSE: i32 = 200;           // wait for the heap to double
+const GCSTEPMUL: i32 = 200;
+
+pub struct GCState {
+    tables: Vec<Weak<RefCell<LuaTable>>>,
+    closures: Vec<Weak<Closure>>,
+    threads: Vec<Weak<RefCell<LuaThread>>>,
+    userdata: Vec<Weak<LuaUserData>>,
+    finobj: Vec<LuaValue>,          // objects with a finalizer, in the order they were marked
+    tobefnz: VecDeque<LuaValue>,    // unreachable objects whose finalizer has not run yet
+    threshold: usize,               // number of tracked objects that starts a collection
+    pub running: bool,              // false if stopped by `collectgarbage("stop")`
+    pub pause: i32,
+    pub stepmul: i32,
+}
+
+enum GCObject {
+    Table(Rc<RefCell<LuaTable>>),
+    Closure(Rc<Closure>),
+    Thread(Rc<RefCell<LuaThread>>),
+    UserData(Rc<LuaUserData>),
+    UpVal(UpVal),
+}
+
+impl GCState {
+    pub fn new() -> Self {
+        GCState {
+            tables: vec![],
+            closures: vec![],
+            threads: vec![],
+            userdata: vec![],
+            finobj: vec![],
+            tobefnz: VecDeque::new(),
+            threshold: GCMINOBJS,
+            running: true,
+            pause: GCPAUSE,
+            stepmul: GCSTEPMUL,
+        }
+    }
+
+    // Puts a newly created table, closure, thread or userdata under the
+    // collector.
+    pub fn track(&mut self, val: &LuaValue) {
+        match val {
+            LuaValue::Table(t) => self.tables.push(Rc::downgrade(t)),
+            LuaValue::Function(c) => self.closures.push(Rc::downgrade(c)),
+            LuaValue::Thread(co) => self.threads.push(Rc::downgrade(co)),
+            LuaValue::UserData(u) => self.userdata.push(Rc::downgrade(u)),
+            _ => {},
+        }
+    }
+
+    // Marks `val` for finalization if its new metatable `mt` has a `__gc`
+    // field, like `luaC_checkfinalizer`.
+    pub fn checkFinalizer(&mut self, val: &LuaValue, mt: Option<&Rc<RefCell<LuaTable>>>) {
+        let hasGC = match mt {
+            Some(mt) => !mt.borrow().Get(&LuaValue::Str("__gc".into())).IsNil(),
+            None => false,
+        };
+        if hasGC && GCObject::of(val).is_some() && !self.finobj.contains(val) {
+            self.finobj.push(val.clone());
+        }
+    }
+
+    // The next object to finalize, the most recently marked first.
+    pub fn nextToFinalize(&mut self) -> Option<LuaValue> {
+        self.tobefnz.pop_front()
+    }
+
+    // All objects with a finalizer are to be finalized, as when the state
+    // is closed.
+    pub fn separateAll(&mut self) {
+        while let Some(o) = self.finobj.pop() {
+            self.tobefnz.push_back(o);
+        }
+    }
+
+    // Clears every object, reachable or not, as when the state is closed:
+    // the registry, `_G` and the loaded modules refer to each other. `roots`
+    // hold what the collector does not track.
+    pub fn clearAll(&mut self, roots: &[LuaValue]) {
+        let mut objs: Vec<GCObject> = vec![];
+        objs.extend(self.tables.drain(..).filter_map(|w| w.upgrade()).map(GCObject::Table));
+        objs.extend(self.closures.drain(..).filter_map(|w| w.upgrade()).map(GCObject::Closure));
+        objs.extend(self.threads.drain(..).filter_map(|w| w.upgrade()).map(GCObject::Thread));
+        objs.extend(self.userdata.drain(..).filter_map(|w| w.upgrade()).map(GCObject::UserData));
+        objs.extend(roots.iter().chain(self.finobj.iter()).chain(self.tobefnz.iter()).filter_map(GCObject::of));
+        self.finobj.clear();
+        self.tobefnz.clear();
+
+        let mut seen: HashSet<usize> = objs.iter().map(GCObject::addr).collect();
+        let mut i = 0;
+        while i < objs.len() {
+            let mut children = vec![];
+            objs[i].traverse(&mut |o| if seen.insert(o.addr()) { children.push(o) });
+            objs.extend(children);
+            i += 1;
+        }
+        for o in objs.iter() {
+            o.clear();
+        }
+    }
+
+    pub fn needsCollect(&self) -> bool {
+        self.running && self.tracked() >= self.threshold
+    }
+
+    fn tracked(&self) -> usize {
+        self.tables.len() + self.closures.len() + self.threads.len() + self.userdata.len()
+    }
+
+    // Approximate size of the live objects, in bytes.
+    pub fn count(&self) -> usize {
+        let mut total = 0;
+        for t in self.tables.iter().filter_map(Weak::upgrade) {
+            total += t.try_borrow().map_or(size_of::<LuaTable>(), |t| t.memSize());
+        }
+        for c in self.closures.iter().filter_map(Weak::upgrade) {
+            total += size_of::<Closure>() + c.upvals.try_borrow().map_or(0, |uvs| uvs.len())
+                * size_of::<RefCell<LuaValue>>();
+        }
+        for co in self.threads.iter().filter_map(Weak::upgrade) {
+            total += size_of::<LuaThread>();
+            if let Ok(co) = co.try_borrow() {
+                for frame in co.frames.iter() {
+                    total += (frame.slots.len() + frame.varargs.len()) * size_of::<LuaValue>();
+                }
+            }
+        }
+        total += self.userdata.iter().filter(|u| u.strong_count() > 0).count() * size_of::<LuaUserData>();
+        total
+    }
+
+    // Frees all unreachable objects, returns how many were found.
+    pub fn collect(&mut self) -> usize {
+        let mut objs: Vec<GCObject> = vec![];
+        objs.extend(self.tables.iter().filter_map(Weak::upgrade).map(GCObject::Table));
+        objs.extend(self.closures.iter().filter_map(Weak::upgrade).map(GCObject::Closure));
+        objs.extend(self.threads.iter().filter_map(Weak::upgrade).map(GCObject::Thread));
+        objs.extend(self.userdata.iter().filter_map(Weak::upgrade).map(GCObject::UserData));
+
+        let mut index: HashMap<usize, usize> = HashMap::new();
+        for (i, o) in objs.iter().enumerate() {
+            index.insert(o.addr(), i);
+        }
+        for o in self.finobj.iter().filter_map(GCObject::of) {
+            if let Entry::Vacant(e) = index.entry(o.addr()) {
+                e.insert(objs.len());
+                objs.push(o);
+            }
+        }
+        // upvalue cells are not tracked, take the ones the heap refers to
+        let mut i = 0;
+        while i < objs.len() {
+            let mut cells = vec![];
+            objs[i].traverse(&mut |o| if let GCObject::UpVal(_) = o { cells.push(o) });
+            for cell in cells {
+                if let Entry::Vacant(e) = index.entry(cell.addr()) {
+                    e.insert(objs.len());
+                    objs.push(cell);
+                }
+            }
+            i += 1;
+        }
+
+        // references that do not come from the heap itself (one is `objs`)
+        let mut refs: Vec<usize> = objs.iter().map(|o| o.strongCount() - 1).collect();
+        for o in self.finobj.iter().filter_map(GCObject::of) {
+            refs[index[&o.addr()]] -= 1;    /* the collector's own reference */
+        }
+        let mut traversed = vec![false; objs.len()];
+        for (i, o) in objs.iter().enumerate() {
+            traversed[i] = o.traverse(&mut |child| {
+                if let Some(&j) = index.get(&child.addr()) {
+                    refs[j] -= 1;
+                }
+            });
+        }
+        // a borrowed object is in use, its children count as referenced
+        let mut marked: Vec<bool> = (0..objs.len()).map(|i| refs[i] > 0 || !traversed[i]).collect();
+        let gray: Vec<usize> = (0..objs.len()).filter(|&i| marked[i]).collect();
+        propagate(&objs, &index, &mut marked, gray);
+
+        // unreachable objects with a finalizer are resurrected until it runs
+        let (dead, alive): (Vec<LuaValue>, Vec<LuaValue>) = std::mem::take(&mut self.finobj)
+            .into_iter()
+            .partition(|v| GCObject::of(v).is_some_and(|o| !marked[index[&o.addr()]]));
+        self.finobj = alive;
+        let mut gray = vec![];
+        for v in dead.into_iter().rev() {
+            let i = index[&GCObject::of(&v).unwrap().addr()];
+            marked[i] = true;
+            gray.push(i);
+            self.tobefnz.push_back(v);
+        }
+        propagate(&objs, &index, &mut marked, gray);
+
+        let mut garbage = 0;
+        for (i, o) in objs.iter().enumerate() {
+            if !marked[i] {
+                o.clear();
+                garbage += 1;
+            }
+        }
+        drop(objs);         /* the cleared objects are freed here */
+
+        self.tables.retain(|w| w.strong_count() > 0);
+        self.closures.retain(|w| w.strong_count() > 0);
+        self.threads.retain(|w| w.strong_count() > 0);
+        self.userdata.retain(|w| w.strong_count() > 0);
+        let estimate = self.tracked() * self.pause.max(0) as usize / 100;
+        self.threshold = estimate.max(GCMINOBJS);
+        garbage
+    }
+}
+
+// Marks everything reachable from the objects in `gray`.
+fn propagate(objs: &[GCObject], index: &HashMap<usize, usize>, marked: &mut [bool], mut gray: Vec<usize>) {
+    while let Some(i) = gray.pop() {
+        objs[i].traverse(&mut |child| {
+            if let Some(&j) = index.get(&child.addr()) {
+                if !marked[j] {
+                    marked[j] = true;
+                    gray.push(j);
+                }
+            }
+        });
+    }
+}
+
+fn visit(val: &LuaValue, f: &mut dyn FnMut(GCObject)) {
+    if let Some(o) = GCObject::of(val) {
+        f(o);
+    }
+}
+
+impl GCObject {
+    fn of(val: &LuaValue) -> Option<Self> {
+        match val {
+            LuaValue::Table(t) => Some(GCObject::Table(Rc::clone(t))),
+            LuaValue::Function(c) => Some(GCObject::Closure(Rc::clone(c))),
+            LuaValue::Thread(co) => Some(GCObject::Thread(Rc::clone(co))),
+            LuaValue::UserData(u) => Some(GCObject::UserData(Rc::clone(u))),
+            _ => None,
+        }
+    }
+
+    fn addr(&self) -> usize {
+        match self {
+            GCObject::Table(t) => Rc::as_ptr(t) as *const u8 as usize,
+            GCObject::Closure(c) => Rc::as_ptr(c) as *const u8 as usize,
+            GCObject::Thread(co) => Rc::as_ptr(co) as *const u8 as usize,
+            GCObject::UserData(u) => Rc::as_ptr(u) as *const u8 as usize,
+            GCObject::UpVal(uv) => Rc::as_ptr(uv) as *const u8 as usize,
+        }
+    }
+
+    fn strongCount(&self) -> usize {
+        match self {
+            GCObject::Table(t) => Rc::strong_count(t),
+            GCObject::Closure(c) => Rc::strong_count(c),
+            GCObject::Thread(co) => Rc::strong_count(co),
+            GCObject::UserData(u) => Rc::strong_count(u),
+            GCObject::UpVal(uv) => Rc::strong_count(uv),
+        }
+    }
+
+    // Calls `f` once for every reference this object holds. Returns false
+    // if the object is borrowed and could not be looked into.
+    fn traverse(&self, f: &mut dyn FnMut(GCObject)) -> bool {
+        match self {
+            GCObject::Table(t) => match t.try_borrow() {
+                Ok(t) => t.traverse(&mut |v| visit(v, f)),
+                Err(_) => return false,
+            },
+            GCObject::Closure(c) => match c.upvals.try_borrow() {
+                Ok(uvs) => uvs.iter().for_each(|uv| f(GCObject::UpVal(Rc::clone(uv)))),
+                Err(_) => return false,
+            },
+            GCObject::Thread(co) => match co.try_borrow() {
+                Ok(co) => {
+                    visit(&co.errfunc, f);
+                    for frame in co.frames.iter() {
+                        frame.slots.iter().chain(frame.varargs.iter()).for_each(|v| visit(v, f));
+                        visit(&frame.registry, f);
+                        f(GCObject::Closure(Rc::clone(&frame.closure)));
+                        frame.openuvs.values().for_each(|uv| f(GCObject::UpVal(Rc::clone(uv))));
+                        if let Some(errfunc) = frame.kcont.as_ref().and_then(|k| k.errfunc.as_ref()) {
+                            visit(errfunc, f);
+                        }
+                    }
+                },
+                Err(_) => return false,
+            },
+            GCObject::UserData(u) => match u.metatable.try_borrow() {
+                Ok(mt) => if let Some(mt) = mt.as_ref() {
+                    f(GCObject::Table(Rc::clone(mt)));
+                },
+                Err(_) => return false,
+            },
+            GCObject::UpVal(uv) => match uv.try_borrow() {
+                Ok(v) => visit(&v, f),
+                Err(_) => return false,
+            },
+        }
+        true
+    }
+
+    // Drops all references of an unreachable object.
+    fn clear(&self) {
+        match self {
+            GCObject::Table(t) => if let Ok(mut t) = t.try_borrow_mut() {
+                t.clear();
+            },
+            GCObject::Closure(c) => if let Ok(mut uvs) = c.upvals.try_borrow_mut() {
+                uvs.clear();
+            },
+            GCObject::Thread(co) => if let Ok(mut co) = co.try_borrow_mut() {
+                co.frames.clear();
+                co.errfunc = LuaValue::Nil;
+            },
+            GCObject::UserData(u) => if let Ok(mut mt) = u.metatable.try_borrow_mut() {
+                *mt = None;
+            },
+            GCObject::UpVal(uv) => if let Ok(mut v) = uv.try_borrow_mut() {
+                *v = LuaValue::Nil;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::state::{closure::{newUpVal, Closure}, lua_table::newLuaTable, lua_value::LuaValue};
+    use crate::binchunk::binary_chunk::Prototype;
+    use super::GCState;
+
+    fn put(t: &LuaValue, k: &str, v: LuaValue) {
+        if let LuaValue::Table(t) = t {
+            t.borrow_mut().Put(LuaValue::Str(k.into()), v);
+        }
+    }
+
+    #[test]
+    fn test_collect_cycles() {
+        let mut gc = GCState::new();
+        let t = newLuaTable(0, 0);
+        let f = Closure::new(Rc::new(Prototype::FakeProto()));
+        f.upvals.borrow_mut().push(newUpVal(t.clone()));
+        let f = LuaValue::Function(Rc::new(f));
+        put(&t, "self", t.clone());
+        put(&t, "f", f.clone());
+        gc.track(&t);
+        gc.track(&f);
+        let weak = match &t {
+            LuaValue::Table(t) => Rc::downgrade(t),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(gc.collect(), 0);        /* still referenced from here */
+        drop(f);
+        assert_eq!(gc.collect(), 0);
+        drop(t);
+        assert!(weak.upgrade().is_some());
+        assert_eq!(gc.collect(), 3);        /* table, closure and upvalue */
+        assert!(weak.upgrade().is_none());
+        assert_eq!(gc.count(), 0);
+    }
+
+    #[test]
+    fn test_keep_objects_reachable_from_roots() {
+        let mut gc = GCState::new();
+        let root = newLuaTable(0, 0);
+        let t = newLuaTable(0, 0);
+        put(&t, "self", t.clone());
+        put(&root, "t", t.clone());
+        gc.track(&t);
+        drop(t);
+        assert_eq!(gc.collect(), 0);
+        if let LuaValue::Table(root) = &root {
+            let t = root.borrow().Get(&LuaValue::Str("t".into()));
+            if let LuaValue::Table(tbl) = &t {
+                assert!(tbl.borrow().Get(&LuaValue::Str("self".into())) == t);
+            }
+        }
+    }
+}
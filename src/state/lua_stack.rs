@@ -1,8 +1,17 @@
-use crate::api::{consts::LUA_REGISTRYINDEX, lua_state::LuaUpValueIndex};
+use crate::api::{consts::LUA_REGISTRYINDEX, lua_state::{KFunction, LuaUpValueIndex}};
 
-use super::{closure::Closure, lua_value::LuaValue};
+use super::{closure::{Closure, UpVal}, lua_value::LuaValue};
 use std::{collections::HashMap, rc::Rc, cell::RefCell};
 
+// Continuation of a Rust function that called `CallK` or `PCallK`.
+pub struct KCont {
+    pub k: KFunction,
+    pub ctx: i64,
+    pub status: i32,                    // status passed to `k`
+    pub errfunc: Option<LuaValue>,      // message handler to restore, for protected calls
+    pub base: i32,                      // stack top to restore on error
+}
+
 pub struct LuaStack {
     pub slots: Vec<LuaValue>,
     pub top: i32,
@@ -10,7 +19,10 @@ pub struct LuaStack {
     pub varargs: Vec<LuaValue>,
     pub pc: i32,
     pub registry: LuaValue,
-    pub openuvs: HashMap<i32, LuaValue>,        // local register, upvalues
+    pub openuvs: HashMap<i32, UpVal>,           // local register, open upvalues
+    pub nresults: i32,                          // number of results expected by the caller
+    pub fresh: bool,                            // started by a call from Rust
+    pub kcont: Option<KCont>,
 }
 
 impl LuaStack {
@@ -28,6 +40,9 @@ impl LuaStack {
             pc: 0,
             registry: registry,
             openuvs: HashMap::new(),
+            nresults: 0,
+            fresh: false,
+            kcont: None,
         }
     }
 
@@ -83,7 +98,7 @@ impl LuaStack {
             if c.is_fake || uvIdx >= c.upvals.borrow().len() as i32 {
                 return LuaValue::Nil;
             }
-            return c.upvals.borrow().get(uvIdx as usize).unwrap().clone();
+            return c.upvals.borrow()[uvIdx as usize].borrow().clone();
         }
 
         if idx == LUA_REGISTRYINDEX as i32 {
@@ -92,6 +107,9 @@ impl LuaStack {
 
         let absIdx = self.absIndex(idx);
         if (absIdx > 0) && (absIdx <= self.top) {
+            if let Some(uv) = self.openUpVal(absIdx - 1) {
+                return uv.borrow().clone();
+            }
             return self.slots[absIdx as usize - 1].clone();
         }
         LuaValue::Nil
@@ -104,8 +122,8 @@ impl LuaStack {
             let _is_fake = c.is_fake;
             let _up_len = c.upvals.borrow().len() as i32;
             if (!_is_fake) || uvIdx < _up_len {
-                if let Some(r_val) = c.upvals.borrow_mut().get_mut(uvIdx as usize) {
-                    *r_val = val;
+                if let Some(uv) = c.upvals.borrow().get(uvIdx as usize) {
+                    *uv.borrow_mut() = val;
                 }
             }
             return;
@@ -118,12 +136,24 @@ impl LuaStack {
 
         let absIdx = self.absIndex(idx);
         if (absIdx > 0) && (absIdx <= self.top) {
+            if let Some(uv) = self.openUpVal(absIdx - 1) {
+                *uv.borrow_mut() = val;
+                return;
+            }
             self.slots[absIdx as usize - 1] = val;
             return;
         }
         panic!("invalid index!");
     }
 
+    // The cell of a captured register that is still open.
+    fn openUpVal(&self, reg: i32) -> Option<&UpVal> {
+        if self.openuvs.is_empty() {
+            return None;
+        }
+        self.openuvs.get(&reg)
+    }
+
     pub fn reverse(&mut self, mut from: i32, mut to: i32) {
         while from < to {
             self.slots.swap(from as usize, to as usize);
@@ -1,13 +1,31 @@
-use std::{cell::RefCell, rc::Rc, result};
+use std::{any::Any, cell::RefCell, ffi::c_void, panic::{catch_unwind, resume_unwind, AssertUnwindSafe}, rc::Rc};
 
-use crate::{api::{consts::*, lua_state::LuaAPI, lua_vm::LuaVM}, binchunk::{self, binary_chunk::Prototype}, state::{api_arith::OPERATORS, lua_value::{callMetamethod, getMetafield}}, vm::{inst_operators::le, instruction::Instruction, opcodes::{OPCODES, OP_RETURN}}};
+use crate::{api::{consts::*, lua_state::{KFunction, LuaAPI, LuaDebug, LuaError, LuaHook, RustClosure, RustClosureMut}, lua_vm::LuaVM}, binchunk::{self, binary_chunk::Prototype}, number::{format::FloatToString, parser::{ParseFloat, ParseInteger}}, state::{api_arith::OPERATORS, lua_value::{callMetamethod, getMetafield}}, vm::{inst_operators::le, instruction::Instruction, opcodes::{OPCODES, OP_RETURN}}};
 use crate::binchunk::binary_chunk::LUA_SIGNATURE;
 use crate::compiler::codegen::compile;
-use super::{api_arith, api_compare::{self, eq}, closure::Closure, lua_stack::LuaStack, lua_table::{newLuaTable, newTable, LuaTable}, lua_value::{getMetatable, setMetatable, LuaValue}};
+use super::{api_arith, api_compare::{self, eq}, closure::{newUpVal, Closure, RustFunc, UpVal}, lua_debug::{currentLine, funcNameFromCode, localName, operandName, shortSrc, typeErrorOperands, Operand}, lua_gc::GCState, lua_stack::{KCont, LuaStack}, lua_table::{newLuaTable, newTable, LuaTable}, lua_thread::{HookState, LuaThread}, lua_userdata::{LuaUserData, UserDataRef}, lua_value::{getMetatable, setMetatable, LuaValue}};
+
+// Unwinding payload of a yield that has to leave Rust functions, it is
+// caught by `Resume`.
+struct YieldSignal;
+
+// Unwinding payload of a Lua error, caught by `PCall` and `Resume`. The
+// error itself waits in `LuaState::error`, Lua values cannot leave the
+// thread they belong to.
+struct ErrorSignal;
 
 pub struct LuaState {
-    pub registry: LuaValue,
+    pub(crate) registry: LuaValue,
     frames: Vec<LuaStack>,
+    errfunc: LuaValue,      // message handler of the innermost protected call
+    thread: Rc<RefCell<LuaThread>>,     // the running thread, its frames are `frames`
+    status: i32,            // LUA_YIELD while the running thread is yielding
+    nny: usize,             // number of non-yieldable calls in the running thread
+    nCcalls: usize,         // number of nested calls through the Rust stack
+    overflowed: bool,       // a stack overflow is being handled
+    hook: HookState,        // debug hook of the running thread
+    error: Option<LuaError>,    // the error being raised
+    gc: GCState,
 }
 
 impl LuaState {
@@ -16,50 +34,50 @@ impl LuaState {
         let fake_closure = Rc::new(Closure::new(fake_proto));
 
         let registry = newTable(0, 0);
+        let main_thread = Rc::new(RefCell::new(LuaThread::new(vec![])));
+        registry.borrow_mut().Put(LuaValue::Integer(LUA_RIDX_MAINTHREAD), LuaValue::Thread(Rc::clone(&main_thread)));
         registry.borrow_mut().Put(LuaValue::Integer(LUA_RIDX_GLOBALS), newLuaTable(0, 0));
         let fake_frame = LuaStack::new(20, fake_closure, LuaValue::Table(Rc::clone(&registry)));
         LuaState {
             registry: LuaValue::Table(registry),
             frames: vec![fake_frame],
+            errfunc: LuaValue::Nil,
+            thread: main_thread,
+            status: LUA_OK,
+            nny: 1,         // main thread is not yieldable
+            nCcalls: 0,
+            overflowed: false,
+            hook: HookState::default(),
+            error: None,
+            gc: GCState::new(),
         }
     }
 
-    pub fn printStack(&self, opname: &'static str) {
-        print!("  {} ", opname);
-        let top = self.GetTop();
-        for i in 1..=top {
-            let t = self.Type(i);
-            match t {
-                LUA_TBOOLEAN => {
-                    print!("[{}]", self.ToBoolean(i));
-                },
-                LUA_TNUMBER => {
-                    print!("[{}]", self.ToNumber(i));
-                },
-                LUA_TSTRING => {
-                    print!("[\"{}\"]", self.ToString(i));
-                },
-                _ => {
-                    print!("[{}]", self.TypeName(t));
-                }
-            }
-        }
-        println!();
-    }
-
-    pub fn stack_mut(&mut self) -> &mut LuaStack {
+    pub(crate) fn stack_mut(&mut self) -> &mut LuaStack {
         self.frames.last_mut().unwrap()
     }
 
-    pub fn stack(&self) -> &LuaStack {
+    pub(crate) fn stack(&self) -> &LuaStack {
         self.frames.last().unwrap()
     }
 
-    pub fn pushFrame(&mut self, frame: LuaStack) {
+    pub(crate) fn pushFrame(&mut self, frame: LuaStack) {
+        // the overflow is raised once, then a few extra frames are left for
+        // the message handler, until the stack is unwound below the limit
+        let n = self.frames.len();
+        if n < LUAI_MAXFRAMES {
+            self.overflowed = false;
+        } else if n >= LUAI_MAXFRAMES + 20 {
+            let err = LuaValue::Str("error while handling stack overflow".into());
+            self.throw(LuaError { status: LUA_ERRERR, value: err });
+        } else if !self.overflowed {
+            self.overflowed = true;
+            self.runtimeError(String::from("stack overflow"));
+        }
         self.frames.push(frame);
     }
 
-    pub fn popFrame(&mut self) -> LuaStack {
+    pub(crate) fn popFrame(&mut self) -> LuaStack {
         self.frames.pop().unwrap()
     }
 }
@@ -74,7 +92,11 @@ impl LuaAPI for LuaState {
     }
 
     fn CheckStack(&mut self, n: i32) -> bool {
-        self.stack_mut().check(n);
+        let stack = self.stack_mut();
+        if stack.top as i64 > LUAI_MAXSTACK - n as i64 {
+            return false;       /* would grow over the limit */
+        }
+        stack.check(n);
         true
     }
 
@@ -157,7 +179,11 @@ impl LuaAPI for LuaState {
     }
 
     fn PushString(&mut self, s: String) {
-        self.stack_mut().push(super::lua_value::LuaValue::Str(s));
+        self.stack_mut().push(super::lua_value::LuaValue::Str(s.into_bytes()));
+    }
+
+    fn PushBytes(&mut self, b: Vec<u8>) {
+        self.stack_mut().push(super::lua_value::LuaValue::Str(b));
     }
 
     // access information from stack
@@ -227,12 +253,21 @@ impl LuaAPI for LuaState {
         self.Type(idx) == LUA_TTHREAD
     }
 
+    fn IsUserData(&self, idx: i32) -> bool {
+        let t = self.Type(idx);
+        t == LUA_TUSERDATA || t == LUA_TLIGHTUSERDATA
+    }
+
+    fn IsLightUserData(&self, idx: i32) -> bool {
+        self.Type(idx) == LUA_TLIGHTUSERDATA
+    }
+
     fn ToBoolean(&self, idx: i32) -> bool {
         self.stack().get(idx).ToBoolean()
     }
 
     fn ToNumber(&self, idx: i32) -> f64 {
-        self.ToNumberX(idx).unwrap()
+        self.ToNumberX(idx).unwrap_or(0.0)
     }
 
     fn ToNumberX(&self, idx: i32) -> Option<f64> {
@@ -240,7 +275,7 @@ impl LuaAPI for LuaState {
     }
 
     fn ToInteger(&self, idx: i32) -> i64 {
-        self.ToIntegerX(idx).unwrap()
+        self.ToIntegerX(idx).unwrap_or(0)
     }
 
     fn ToIntegerX(&self, idx: i32) -> Option<i64> {
@@ -251,12 +286,22 @@ impl LuaAPI for LuaState {
         self.ToStringX(idx).unwrap()
     }
 
+    // The string as text: bytes that are not UTF-8 are replaced, so it is
+    // for names and messages, `ToBytesX` gives the string itself.
     fn ToStringX(&self, idx: i32) -> Option<String> {
+        self.ToBytesX(idx).map(|b| String::from_utf8_lossy(&b).into_owned())
+    }
+
+    fn ToBytes(&self, idx: i32) -> Vec<u8> {
+        self.ToBytesX(idx).unwrap()
+    }
+
+    fn ToBytesX(&self, idx: i32) -> Option<Vec<u8>> {
         match self.stack().get(idx) {
             LuaValue::Str(s) => Some(s),
-            LuaValue::Number(n) => Some(n.to_string()),
-            LuaValue::Integer(i) => Some(i.to_string()),
-            LuaValue::Bool(b) => Some(b.to_string()),
+            LuaValue::Number(n) => Some(FloatToString(n).into_bytes()),
+            LuaValue::Integer(i) => Some(i.to_string().into_bytes()),
+            LuaValue::Bool(b) => Some(b.to_string().into_bytes()),
             _ => None,
         }
     }
@@ -267,6 +312,13 @@ impl LuaAPI for LuaState {
         if op != LUA_OPUNM && op != LUA_OPBNOT {
             b = self.stack_mut().pop();
             a = self.stack_mut().pop();
+            if let (LuaValue::Integer(_), LuaValue::Integer(0)) = (&a, &b) {
+                match op {
+                    LUA_OPMOD => self.runtimeError(String::from("attempt to perform 'n%0'")),
+                    LUA_OPIDIV => self.runtimeError(String::from("attempt to perform 'n//0'")),
+                    _ => {},
+                }
+            }
             if let Some(val) = api_arith::arith(&a, &b, op) {
                 self.stack_mut().push(val);
                 return;
@@ -280,12 +332,12 @@ impl LuaAPI for LuaState {
             }
         }
         let _mm_ = OPERATORS[op as usize].0;
-        if let Some(res) = callMetamethod(a, b, _mm_, self) {
+        if let Some(res) = callMetamethod(a.clone(), b.clone(), _mm_, self) {
             self.stack_mut().push(res);
             return;
         }
 
-        panic!("arithmetic error!");
+        self.arithError(&a, &b, op);
     }
 
     fn Compare(&mut self, idx1: i32, idx2: i32, op: u8) -> bool {
@@ -300,7 +352,12 @@ impl LuaAPI for LuaState {
             if let Some(res) = api_compare::compare(&a, &b, op) {
                 return res;
             }
-            panic!("comparison error!");
+            let (t1, t2) = (self.TypeName(a.typeOf()), self.TypeName(b.typeOf()));
+            if t1 == t2 {
+                self.runtimeError(format!("attempt to compare two {} values", t1));
+            } else {
+                self.runtimeError(format!("attempt to compare {} with {}", t1, t2));
+            }
         }
     }
 
@@ -321,44 +378,66 @@ impl LuaAPI for LuaState {
                 self.stack_mut().push(LuaValue::Integer(t.borrow().Len() as i64));
             }
             _ => {
-                panic!("length error!");
+                let tn = self.TypeName(val.typeOf());
+                let info = self.varInfo(&val);
+                self.runtimeError(format!("attempt to get length of a {} value{}", tn, info));
             }
         }
     }
 
     fn Concat(&mut self, n: i32) {
         if n == 0 {
-            self.stack_mut().push(LuaValue::Str(String::from("")));
+            self.stack_mut().push(LuaValue::Str(vec![]));
         } else if n >= 2 {
             for _ in 1..n {
                 if self.IsString(-1) && self.IsString(-2) {
-                    let s2 = self.ToString(-1);
-                    let mut s1 = self.ToString(-2);
+                    let s2 = self.ToBytes(-1);
+                    let mut s1 = self.ToBytes(-2);
                     let _ = self.stack_mut().pop();
                     let _ = self.stack_mut().pop();
-                    s1.push_str(&s2);
+                    s1.extend_from_slice(&s2);
                     self.stack_mut().push(LuaValue::Str(s1));
                 } else {
                     let b = self.stack_mut().pop();
                     let a = self.stack_mut().pop();
-                    let _res_ = callMetamethod(a, b, "__concat", self);
+                    let _res_ = callMetamethod(a.clone(), b.clone(), "__concat", self);
                     if let Some(res) = _res_ {
                         self.stack_mut().push(res);
                         continue;
                     }
-                    panic!("concatenation error!");
+                    let bad = match a {
+                        LuaValue::Str(_) | LuaValue::Integer(_) | LuaValue::Number(_) => b,
+                        _ => a,
+                    };
+                    let tn = self.TypeName(bad.typeOf());
+                    let info = self.varInfo(&bad);
+                    self.runtimeError(format!("attempt to concatenate a {} value{}", tn, info));
                 }
             }
         }
     }
 
+    // Converts a numeral to an integer or a float and pushes it, returns
+    // false (pushing nothing) if `s` is not a valid numeral.
+    fn StringToNumber(&mut self, s: &str) -> bool {
+        if let (n, true) = ParseInteger(s) {
+            self.PushInteger(n);
+        } else if let (n, true) = ParseFloat(s) {
+            self.PushNumber(n);
+        } else {
+            return false;
+        }
+        true
+    }
+
     fn NewTable(&mut self) {
         self.CreateTable(0, 0);
     }
 
     fn CreateTable(&mut self, nArr: i32, nRec: i32) {
-        let t = LuaTable::new(nArr, nRec);
-        self.stack_mut().push(LuaValue::Table(Rc::new(RefCell::new(t))));
+        let t = LuaValue::Table(Rc::new(RefCell::new(LuaTable::new(nArr, nRec))));
+        self.gc.track(&t);
+        self.stack_mut().push(t);
     }
 
     fn GetTable(&mut self, idx: i32) -> i8 {
@@ -369,7 +448,7 @@ impl LuaAPI for LuaState {
 
     fn GetField(&mut self, idx: i32, k: &'static str) -> i8 {
         let t = self.stack().get(idx);
-        self.getTable(&t, &LuaValue::Str(String::from(k)), false)
+        self.getTable(&t, &LuaValue::Str(k.into()), false)
     }
 
     fn GetI(&mut self, idx: i32, i: i64) -> i8 {
@@ -387,7 +466,7 @@ impl LuaAPI for LuaState {
     fn SetField(&mut self, idx: i32, k: &'static str) {
         let t = self.stack().get(idx);
         let v = self.stack_mut().pop();
-        self.setTable(&t, &LuaValue::Str(String::from(k)), &v, false);     // warning.
+        self.setTable(&t, &LuaValue::Str(k.into()), &v, false);     // warning.
     }
 
     fn SetI(&mut self, idx: i32, n: i64) {
@@ -397,11 +476,32 @@ impl LuaAPI for LuaState {
     }
 
     fn Load(&mut self, chunk: Vec<u8>, chunk_name: &str, mode: &str) -> i32 {
-        let proto: Prototype = if is_binary_chunk(&chunk) {
-            binchunk::undump(chunk)
+        let binary = is_binary_chunk(&chunk);
+        if !mode.contains(if binary { 'b' } else { 't' }) {
+            let x = if binary { "binary" } else { "text" };
+            let msg = format!("attempt to load a {} chunk (mode is '{}')", x, mode);
+            self.stack_mut().push(LuaValue::Str(msg.into_bytes()));
+            return LUA_ERRSYNTAX;
+        }
+        let result = if binary {
+            binchunk::undump(chunk).map_err(|msg| {
+                /* named like in lundump.c */
+                let name = match chunk_name.strip_prefix(['@', '=']) {
+                    Some(name) => name,
+                    None if chunk_name.as_bytes().starts_with(&LUA_SIGNATURE[..1]) => "binary string",
+                    None => chunk_name,
+                };
+                format!("{}: {}", name, msg)
+            })
         } else {
-            let s_chunk: String = chunk.iter().map(|s|{*s as char}).collect();
-            compile(s_chunk, chunk_name.to_owned())
+            compile(chunk, chunk_name.to_owned()).map_err(|err| err.to_string())
+        };
+        let proto: Prototype = match result {
+            Ok(proto) => proto,
+            Err(msg) => {
+                self.stack_mut().push(LuaValue::Str(msg.into_bytes()));
+                return LUA_ERRSYNTAX;
+            },
         };
         
         let r_len = proto.upvalues.len();
@@ -410,34 +510,319 @@ impl LuaAPI for LuaState {
             let _env_ = &self.registry;
             if let LuaValue::Table(tbl) = _env_ {
                 let __env__ = tbl.borrow().Get(&LuaValue::Integer(LUA_RIDX_GLOBALS));
-                if let Some(r_val) = c.upvals.borrow_mut().get_mut(0) {
-                    *r_val = __env__;
+                if let Some(uv) = c.upvals.borrow().get(0) {
+                    *uv.borrow_mut() = __env__;
                 }
             }
         }
-        self.stack_mut().push(LuaValue::Function(Rc::new(c)));
+        let f = LuaValue::Function(Rc::new(c));
+        self.gc.track(&f);
+        self.stack_mut().push(f);
         0
     }
 
-    fn Call(&mut self, mut nArgs: i32, nResults: i32) {
-        let mut val = self.stack().get(-(nArgs + 1));
-        if let LuaValue::Function(_) = val {} else {
-            let _mf_ = getMetafield(val.clone(), "__call", self);
-            if let LuaValue::Function(_) = _mf_ {
-                self.stack_mut().push(val.clone());
-                self.Insert(-(nArgs + 2));
-                nArgs += 1;
-                val = _mf_;
-            }
+    // Dumps the Lua function on top of the stack as a binary chunk,
+    // `None` if it is not a Lua function.
+    fn Dump(&mut self, strip: bool) -> Option<Vec<u8>> {
+        match self.stack().get(-1) {
+            LuaValue::Function(c) if c.rustFunc.is_none() => Some(binchunk::dump_strip(&c.proto, strip)),
+            _ => None,
         }
+    }
 
-        if let LuaValue::Function(c) = val {
-            match c.rustFunc {
-                None => self.callLuaClosure(nArgs, nResults, Rc::clone(&c)),
-                Some(_) => self.callRustClosure(nArgs, nResults, Rc::clone(&c)),
-            };
+    fn Call(&mut self, nArgs: i32, nResults: i32) {
+        if self.nCcalls >= LUAI_MAXCCALLS {
+            self.runtimeError(String::from("C stack overflow"));
+        }
+        self.nCcalls += 1;
+        self.nny += 1;
+        if !self.preCall(nArgs, nResults) {
+            self.stack_mut().fresh = true;
+            self.runLuaClosure();
+        }
+        self.nny -= 1;
+        self.nCcalls -= 1;
+    }
+
+    fn CallK(&mut self, nArgs: i32, nResults: i32, ctx: i64, k: KFunction) {
+        if self.nny > 0 {       // no continuation needed outside coroutines
+            self.Call(nArgs, nResults);
+            return;
+        }
+        self.stack_mut().kcont = Some(KCont { k, ctx, status: LUA_YIELD, errfunc: None, base: 0 });
+        self.callK(nArgs, nResults);
+        self.stack_mut().kcont = None;
+    }
+
+    fn PCallK(&mut self, nArgs: i32, nResults: i32, msgh: i32, ctx: i64, k: KFunction) -> i32 {
+        if self.nny > 0 {
+            return self.PCall(nArgs, nResults, msgh);
+        }
+        // errors are caught by `Resume`, which then recovers this frame
+        let base = self.GetTop() - nArgs - 1;
+        let handler = if msgh != 0 { self.stack().get(msgh) } else { LuaValue::Nil };
+        let old_errfunc = std::mem::replace(&mut self.errfunc, handler);
+        let kcont = KCont { k, ctx, status: LUA_YIELD, errfunc: Some(old_errfunc), base };
+        self.stack_mut().kcont = Some(kcont);
+        self.callK(nArgs, nResults);
+        let kcont = self.stack_mut().kcont.take().unwrap();
+        self.errfunc = kcont.errfunc.unwrap();
+        LUA_OK
+    }
+
+    fn PCall(&mut self, nArgs: i32, nResults: i32, msgh: i32) -> i32 {
+        let depth = self.frames.len();
+        let (nny, nCcalls, hooking) = (self.nny, self.nCcalls, self.hook.running);
+        let base = self.GetTop() - nArgs - 1;
+        let handler = if msgh != 0 { self.stack().get(msgh) } else { LuaValue::Nil };
+        let old_errfunc = std::mem::replace(&mut self.errfunc, handler);
+
+        let result = catch_unwind(AssertUnwindSafe(|| self.Call(nArgs, nResults)));
+        self.errfunc = old_errfunc;
+        match result {
+            Ok(_) => LUA_OK,
+            Err(payload) => {
+                // unwind the frame stack back to the protected call
+                self.frames.truncate(depth);
+                self.nny = nny;
+                self.nCcalls = nCcalls;
+                self.hook.running = hooking;
+                if !payload.is::<ErrorSignal>() {
+                    resume_unwind(payload);
+                }
+                let err = self.error.take().unwrap();
+                self.SetTop(base);
+                self.stack_mut().check(1);
+                self.stack_mut().push(err.value);
+                err.status
+            },
+        }
+    }
+
+    fn Error(&mut self) -> i32 {
+        let err = self.stack_mut().pop();
+        self.raiseError(err);
+    }
+
+    fn NewThread(&mut self) {
+        let fake_closure = Rc::new(Closure::new(Rc::new(Prototype::FakeProto())));
+        let frame = LuaStack::new(LUA_MINSTACK as usize, fake_closure, self.registry.clone());
+        let mut thread = LuaThread::new(vec![frame]);
+        let HookState { hook, mask, basecount, .. } = self.hook;    /* the hook is inherited */
+        thread.hook = HookState { hook, mask, basecount, count: basecount, ..HookState::default() };
+        let co = LuaValue::Thread(Rc::new(RefCell::new(thread)));
+        self.gc.track(&co);
+        self.stack_mut().push(co);
+    }
+
+    fn XMove(&mut self, idx: i32, n: i32) {
+        let co = match self.stack().get(idx) {
+            LuaValue::Thread(co) => co,
+            _ => self.runtimeError(String::from("thread expected")),
+        };
+        let vals = self.stack_mut().popN(n);
+        if Rc::ptr_eq(&co, &self.thread) {
+            self.stack_mut().pushN(vals, n);
+            return;
+        }
+        let mut co = co.borrow_mut();
+        let stack = co.frames.last_mut().unwrap();
+        stack.check(n);
+        stack.pushN(vals, n);
+    }
+
+    fn Resume(&mut self, nArgs: i32) -> i32 {
+        let args = self.stack_mut().popN(nArgs);
+        let co = match self.stack_mut().pop() {
+            LuaValue::Thread(co) => co,
+            _ => self.runtimeError(String::from("thread expected")),
+        };
+        let err = if Rc::ptr_eq(&co, &self.thread) {
+            Some("cannot resume non-suspended coroutine")
         } else {
-            panic!("not function!");
+            let c = co.borrow();
+            if c.status == LUA_YIELD {
+                None
+            } else if c.status != LUA_OK || c.frames.is_empty() || c.frames.len() > 1 {
+                if c.status == LUA_OK && !c.frames.is_empty() {
+                    Some("cannot resume non-suspended coroutine")
+                } else {
+                    Some("cannot resume dead coroutine")
+                }
+            } else if c.frames[0].top == 0 {
+                Some("cannot resume dead coroutine")
+            } else {
+                None
+            }
+        };
+        if let Some(msg) = err {
+            self.stack_mut().check(1);
+            self.stack_mut().push(LuaValue::Str(msg.into()));
+            return LUA_ERRRUN;
+        }
+        if self.nCcalls >= LUAI_MAXCCALLS {
+            self.stack_mut().check(1);
+            self.stack_mut().push(LuaValue::Str("C stack overflow".into()));
+            return LUA_ERRRUN;
+        }
+
+        let nCcalls = self.nCcalls;
+        self.nCcalls += 1;
+        let prev = self.switchThread(co);
+        let starting = self.status == LUA_OK;
+        self.status = LUA_OK;
+        let mut result = catch_unwind(AssertUnwindSafe(|| {
+            self.stack_mut().check(nArgs);
+            self.stack_mut().pushN(args, nArgs);
+            if starting {
+                if !self.preCall(nArgs, -1) && self.status != LUA_YIELD {
+                    self.stack_mut().fresh = true;
+                    self.runLuaClosure();
+                }
+            } else {
+                // the arguments are the results of the pending yield
+                self.postCall(nArgs);
+                self.unroll();
+            }
+        }));
+
+        let results = loop {
+            self.nCcalls = nCcalls + 1;
+            match result {
+                Ok(_) => {
+                    let n = self.GetTop();
+                    break self.stack_mut().popN(n);
+                },
+                Err(payload) if payload.is::<YieldSignal>() => {
+                    let n = self.GetTop();
+                    break self.stack_mut().popN(n);
+                },
+                Err(payload) => match payload.downcast::<ErrorSignal>() {
+                    Ok(_) => {
+                        let err = self.error.take().unwrap();
+                        if self.recover(&err) {
+                            result = catch_unwind(AssertUnwindSafe(|| self.unroll()));
+                            continue;
+                        }
+                        self.frames.truncate(1);
+                        self.SetTop(0);
+                        self.status = err.status;
+                        break vec![err.value];
+                    },
+                    Err(payload) => {
+                        self.frames.truncate(1);
+                        self.SetTop(0);
+                        self.status = LUA_ERRRUN;
+                        self.nCcalls = nCcalls;
+                        self.switchThread(prev);
+                        resume_unwind(payload);
+                    },
+                },
+            }
+        };
+        self.nCcalls = nCcalls;
+        let status = self.status;
+        self.switchThread(prev);
+        self.stack_mut().check(results.len() as i32);
+        self.stack_mut().pushN(results, -1);
+        status
+    }
+
+    fn Yield(&mut self, nResults: i32) -> i32 {
+        if self.nny > 0 {
+            if self.isMainThread() {
+                self.runtimeError(String::from("attempt to yield from outside a coroutine"));
+            }
+            self.runtimeError(String::from("attempt to yield across a C-call boundary"));
+        }
+        // only the yielded values are left in the frame
+        let vals = self.stack_mut().popN(nResults);
+        self.SetTop(0);
+        self.stack_mut().pushN(vals, nResults);
+        self.status = LUA_YIELD;
+        nResults
+    }
+
+    fn IsYieldable(&self) -> bool {
+        self.nny == 0
+    }
+
+    fn PushThread(&mut self) -> bool {
+        let co = LuaValue::Thread(Rc::clone(&self.thread));
+        self.stack_mut().push(co);
+        self.isMainThread()
+    }
+
+    fn ToThread(&self, idx: i32) -> Option<Rc<RefCell<LuaThread>>> {
+        match self.stack().get(idx) {
+            LuaValue::Thread(co) => Some(co),
+            _ => None,
+        }
+    }
+
+    fn NewUserData<T: Any>(&mut self, data: T) {
+        let u = LuaValue::UserData(Rc::new(LuaUserData::new(Box::new(data))));
+        self.gc.track(&u);
+        self.stack_mut().push(u);
+    }
+
+    // The full userdata at `idx`, if it holds a `T`.
+    fn ToUserData<T: Any>(&self, idx: i32) -> Option<UserDataRef<T>> {
+        match self.stack().get(idx) {
+            LuaValue::UserData(u) => UserDataRef::new(u),
+            _ => None,
+        }
+    }
+
+    fn PushLightUserData(&mut self, p: *mut c_void) {
+        self.stack_mut().push(LuaValue::LightUserData(p));
+    }
+
+    fn ToLightUserData(&self, idx: i32) -> Option<*mut c_void> {
+        match self.stack().get(idx) {
+            LuaValue::LightUserData(p) => Some(p),
+            _ => None,
+        }
+    }
+
+    // Address of a table, function, thread or userdata, only good to
+    // tell values apart; null for other values.
+    fn ToPointer(&self, idx: i32) -> *const c_void {
+        match self.stack().get(idx) {
+            LuaValue::Table(t) => Rc::as_ptr(&t) as *const c_void,
+            LuaValue::Function(c) => Rc::as_ptr(&c) as *const c_void,
+            LuaValue::Thread(co) => Rc::as_ptr(&co) as *const c_void,
+            LuaValue::UserData(u) => Rc::as_ptr(&u) as *const c_void,
+            LuaValue::LightUserData(p) => p,
+            _ => std::ptr::null(),
+        }
+    }
+
+    fn GC(&mut self, what: i32, data: i32) -> i32 {
+        match what {
+            LUA_GCSTOP => {
+                self.gc.running = false;
+                0
+            },
+            LUA_GCRESTART => {
+                self.gc.running = true;
+                0
+            },
+            LUA_GCCOLLECT => {
+                self.fullGC();
+                0
+            },
+            LUA_GCCOUNT => (self.gc.count() >> 10) as i32,
+            LUA_GCCOUNTB => (self.gc.count() & 0x3ff) as i32,
+            LUA_GCSTEP => {
+                // there are no partial steps, a step is a whole cycle
+                self.fullGC();
+                1
+            },
+            LUA_GCSETPAUSE => std::mem::replace(&mut self.gc.pause, data),
+            LUA_GCSETSTEPMUL => std::mem::replace(&mut self.gc.stepmul, data),
+            LUA_GCISRUNNING => self.gc.running as i32,
+            _ => -1,        /* invalid option */
         }
     }
 
@@ -448,7 +833,7 @@ impl LuaAPI for LuaState {
     fn IsRustFunction(&self, idx: i32) -> bool {
         let val = self.stack().get(idx);
         if let LuaValue::Function(f) = val {
-            if let Some(_) = f.rustFunc {
+            if f.rustFunc.is_some() {
                 return true;
             }
         }
@@ -458,11 +843,23 @@ impl LuaAPI for LuaState {
     fn ToGoFunction(&self, idx: i32) -> Option<crate::api::lua_state::RustFn> {
         let val = self.stack().get(idx);
         if let LuaValue::Function(f) = val {
-            return f.rustFunc;
+            if let Some(RustFunc::Fn(f)) = f.rustFunc {
+                return Some(f);
+            }
         }
         None
     }
 
+    // Pushes a closure of the host, with `n` upvalues taken from the stack
+    // like `PushGoClosure`.
+    fn PushRustClosure(&mut self, f: RustClosure, n: i32) {
+        self.pushRustFunc(RustFunc::Closure(f), n);
+    }
+
+    fn PushRustClosureMut(&mut self, f: RustClosureMut, n: i32) {
+        self.pushRustFunc(RustFunc::ClosureMut(RefCell::new(f)), n);
+    }
+
     fn PushGlobalTable(&mut self) {
         let _global_ = &self.registry;
         if let LuaValue::Table(tbl) = _global_ {
@@ -475,7 +872,7 @@ impl LuaAPI for LuaState {
         let rf_t = &self.registry;
         if let LuaValue::Table(tbl) = rf_t {
             let t = tbl.borrow().Get(&LuaValue::Integer(LUA_RIDX_GLOBALS));
-            return self.getTable(&t, &LuaValue::Str(String::from(name)), false);
+            return self.getTable(&t, &LuaValue::Str(name.into()), false);
         }
         -1
     }
@@ -485,7 +882,7 @@ impl LuaAPI for LuaState {
         if let LuaValue::Table(tbl) = rf_t {
             let t = tbl.borrow().Get(&LuaValue::Integer(LUA_RIDX_GLOBALS));
             let v = self.stack_mut().pop();
-            self.setTable(&t, &LuaValue::Str(String::from(name)), &v, false);
+            self.setTable(&t, &LuaValue::Str(name.into()), &v, false);
         }
     }
 
@@ -494,16 +891,18 @@ impl LuaAPI for LuaState {
         self.SetGlobal(name);
     }
 
+    fn RegisterClosure(&mut self, name: &'static str, f: RustClosure) {
+        self.PushRustClosure(f, 0);
+        self.SetGlobal(name);
+    }
+
+    fn RegisterClosureMut(&mut self, name: &'static str, f: RustClosureMut) {
+        self.PushRustClosureMut(f, 0);
+        self.SetGlobal(name);
+    }
+
     fn PushGoClosure(&mut self, f: crate::api::lua_state::RustFn, n: i32) {
-        let mut _closure_ = Closure::newRustClosure(f, n);
-        let closure = &mut _closure_;
-        for _ in (1..=n).rev() {
-            let val = self.stack_mut().pop();
-            if let Some(r_val) = closure.upvals.borrow_mut().get_mut(n as usize - 1) {
-                *r_val = val;
-            }
-        }
-        self.stack_mut().push(LuaValue::Function(Rc::new(_closure_)));
+        self.pushRustFunc(RustFunc::Fn(f), n);
     }
 
     fn GetMetatable(&mut self, idx: i32) -> bool {
@@ -525,10 +924,11 @@ impl LuaAPI for LuaState {
                 setMetatable(val, None, self);
             },
             LuaValue::Table(tbl) => {
+                self.gc.checkFinalizer(&val, Some(&tbl));
                 setMetatable(val, Some(tbl), self);
             },
             _ => {
-                panic!("table expected!");
+                self.runtimeError(String::from("table expected"));
             },
         }
     }
@@ -584,18 +984,235 @@ impl LuaAPI for LuaState {
         if let LuaValue::Table(t) = val {
             let key = self.stack_mut().pop();
             let next_key = t.borrow_mut().nextKey(&key);
-            if !next_key.IsNil() {
-                self.stack_mut().push(next_key.clone());
-                self.stack_mut().push(t.borrow().Get(&next_key));
-                return true;
+            match next_key {
+                Some(next_key) if !next_key.IsNil() => {
+                    self.stack_mut().push(next_key.clone());
+                    self.stack_mut().push(t.borrow().Get(&next_key));
+                    return true;
+                },
+                Some(_) => return false,
+                None => self.runtimeError(String::from("invalid key to 'next'")),
             }
-            return false;
         }
-        panic!("table expected!");
+        self.runtimeError(String::from("table expected"));
+    }
+
+    // The call at `level` of thread `co` (the running one if `None`), level 0
+    // being the running function.
+    fn GetStack(&self, co: Option<&Rc<RefCell<LuaThread>>>, level: i32) -> Option<LuaDebug> {
+        let thread = co.filter(|co| !Rc::ptr_eq(co, &self.thread)).cloned();
+        let n = self.withFrames(&thread, |frames| frames.len()) as i32;
+        let ci = n - 1 - level;
+        /* the first frame only holds the values of the host */
+        if level < 0 || ci < 1 {
+            return None;
+        }
+        Some(LuaDebug { thread, ci: ci as usize, ..LuaDebug::default() })
+    }
+
+    // Fills the fields of `ar` selected by `what`: 'S' source, 'l' current
+    // line, 'u' upvalues and parameters, 'n' name, 't' tail call, and 'f'
+    // pushes the function. With a leading '>' the function is popped from
+    // the stack instead of being the one of a call.
+    fn GetInfo(&mut self, what: &str, ar: &mut LuaDebug) -> bool {
+        let (c, pc, name) = match what.strip_prefix('>') {
+            Some(_) => match self.stack_mut().pop() {
+                LuaValue::Function(c) => (c, None, None),
+                _ => return false,
+            },
+            None => {
+                let ci = ar.ci;
+                let call = self.withFrames(&ar.thread, |frames| {
+                    let frame = frames.get(ci)?;
+                    Some((Rc::clone(&frame.closure), frame.pc, callerName(frames, ci)))
+                });
+                match call {
+                    Some((c, pc, name)) => (c, Some(pc), name),
+                    None => return false,
+                }
+            },
+        };
+        let proto = &c.proto;
+        let isLua = c.rustFunc.is_none();
+        let mut ok = true;
+        for option in what.trim_start_matches('>').chars() {
+            match option {
+                'S' => {
+                    if isLua {
+                        ar.source = proto.source.clone().unwrap_or(String::from("=?"));
+                        ar.short_src = shortSrc(proto);
+                        ar.linedefined = proto.lineDefined as i32;
+                        ar.lastlinedefined = proto.lastLineDefined as i32;
+                        ar.what = if proto.lineDefined == 0 { "main" } else { "Lua" };
+                    } else {
+                        ar.source = String::from("=[C]");
+                        ar.short_src = String::from("[C]");
+                        ar.linedefined = -1;
+                        ar.lastlinedefined = -1;
+                        ar.what = "C";
+                    }
+                },
+                'l' => {
+                    ar.currentline = match pc {
+                        Some(pc) if isLua => currentLine(proto, pc),
+                        _ => -1,
+                    };
+                },
+                'u' => {
+                    ar.nups = c.upvals.borrow().len() as u8;
+                    ar.nparams = if isLua { proto.numParams } else { 0 };
+                    ar.isvararg = !isLua || proto.isVararg == 1;
+                },
+                'n' => {
+                    let (namewhat, name) = name.clone().map_or(("", None), |(w, n)| (w, Some(n)));
+                    ar.namewhat = namewhat;
+                    ar.name = name;
+                },
+                't' => ar.istailcall = false,
+                'f' | 'L' => {},        /* handled below */
+                _ => ok = false,        /* invalid option */
+            }
+        }
+        if what.contains('f') {
+            self.stack_mut().check(1);
+            self.stack_mut().push(LuaValue::Function(Rc::clone(&c)));
+        }
+        if what.contains('L') {        /* lines with code, as keys of a table */
+            if isLua {
+                self.CreateTable(0, 0);
+                for line in &c.proto.lineInfo {
+                    self.PushBoolean(true);
+                    self.RawSetI(-2, *line as i64);
+                }
+            } else {
+                self.PushNil();
+            }
+        }
+        ok
+    }
+
+    fn GetUpvalue(&mut self, funcIdx: i32, n: i32) -> Option<String> {
+        let (name, uv) = self.upvalueAt(funcIdx, n)?;
+        let val = uv.borrow().clone();
+        self.stack_mut().push(val);
+        Some(name)
+    }
+
+    fn SetUpvalue(&mut self, funcIdx: i32, n: i32) -> Option<String> {
+        let (name, uv) = self.upvalueAt(funcIdx, n)?;
+        let val = self.stack_mut().pop();
+        *uv.borrow_mut() = val;
+        Some(name)
+    }
+
+    // An identity of the `n`th upvalue of the function at `funcIdx`, shared
+    // by the closures that share the upvalue.
+    fn UpvalueId(&self, funcIdx: i32, n: i32) -> Option<*mut c_void> {
+        let (_, uv) = self.upvalueAt(funcIdx, n)?;
+        Some(Rc::as_ptr(&uv) as *mut c_void)
+    }
+
+    // Makes the `n1`th upvalue of the closure at `funcIdx1` refer to the
+    // `n2`th upvalue of the closure at `funcIdx2`.
+    fn UpvalueJoin(&mut self, funcIdx1: i32, n1: i32, funcIdx2: i32, n2: i32) {
+        let uv = match self.upvalueAt(funcIdx2, n2) {
+            Some((_, uv)) => uv,
+            None => return,
+        };
+        if let (LuaValue::Function(c), Ok(i)) = (self.stack().get(funcIdx1), usize::try_from(n1 - 1)) {
+            if let Some(slot) = c.upvals.borrow_mut().get_mut(i) {
+                *slot = uv;
+            }
+        }
+    }
+
+    // Pushes the value of local `n` of the call `ar` and returns its name.
+    // Without `ar`, gives the name of the `n`th parameter of the Lua function
+    // on the top of the stack, and pushes nothing.
+    fn GetLocal(&mut self, ar: Option<&LuaDebug>, n: i32) -> Option<String> {
+        let ar = match ar {
+            Some(ar) => ar,
+            None => {                   /* information about non-active function? */
+                return match self.stack().get(-1) {
+                    LuaValue::Function(c) if c.rustFunc.is_none() => localName(&c.proto, n, 0).map(String::from),
+                    _ => None,          /* not a Lua function */
+                };
+            },
+        };
+        let (name, val) = self.withFrames(&ar.thread, |frames| {
+            let frame = frames.get(ar.ci)?;
+            let (name, slot) = findLocal(frame, n)?;
+            let val = match slot {
+                LocalSlot::Register(idx) => frame.get(idx),
+                LocalSlot::Vararg(i) => frame.varargs[i].clone(),
+            };
+            Some((name, val))
+        })?;
+        self.stack_mut().check(1);
+        self.stack_mut().push(val);
+        Some(name)
+    }
+
+    // Pops a value into local `n` of the call `ar` and returns its name.
+    // Nothing is popped if there is no such local.
+    fn SetLocal(&mut self, ar: &LuaDebug, n: i32) -> Option<String> {
+        let val = self.stack().get(-1);
+        let name = self.withFramesMut(&ar.thread, |frames| {
+            let frame = frames.get_mut(ar.ci)?;
+            let (name, slot) = findLocal(frame, n)?;
+            match slot {
+                LocalSlot::Register(idx) => frame.set(idx, val),
+                LocalSlot::Vararg(i) => frame.varargs[i] = val,
+            }
+            Some(name)
+        })?;
+        let _ = self.stack_mut().pop();
+        Some(name)
+    }
+
+    // Sets the hook of thread `co` (the running one if `None`) for the events
+    // in `mask`, LUA_MASKCOUNT being every `count` instructions. No hook or
+    // an empty mask turns it off.
+    fn SetHook(&mut self, co: Option<&Rc<RefCell<LuaThread>>>, f: Option<LuaHook>, mut mask: i32, count: i32) {
+        let mut f = f;
+        if f.is_none() || mask == 0 {  /* turn off hooks? */
+            mask = 0;
+            f = None;
+        }
+        let set = |hook: &mut HookState| *hook = HookState { hook: f, mask, basecount: count, count, ..*hook };
+        match co.filter(|co| !Rc::ptr_eq(co, &self.thread)) {
+            Some(co) => set(&mut co.borrow_mut().hook),
+            None => set(&mut self.hook),
+        }
+    }
+
+    // The hook of thread `co` (the running one if `None`), with its mask and count.
+    fn GetHook(&self, co: Option<&Rc<RefCell<LuaThread>>>) -> (Option<LuaHook>, i32, i32) {
+        let hook = match co.filter(|co| !Rc::ptr_eq(co, &self.thread)) {
+            Some(co) => co.borrow().hook,
+            None => self.hook,
+        };
+        (hook.hook, hook.mask, hook.basecount)
     }
 }
 
 impl LuaState {
+    // The name and the cell of the `n`th upvalue of the function at
+    // `funcIdx`. Rust functions have unnamed upvalues.
+    fn upvalueAt(&self, funcIdx: i32, n: i32) -> Option<(String, UpVal)> {
+        if let LuaValue::Function(c) = self.stack().get(funcIdx) {
+            let i = usize::try_from(n - 1).ok()?;
+            let uv = Rc::clone(c.upvals.borrow().get(i)?);
+            let name = if c.rustFunc.is_some() {
+                String::new()
+            } else {
+                c.proto.upvalueNames.get(i).cloned().unwrap_or(String::from("(*no name)"))
+            };
+            return Some((name, uv));
+        }
+        None
+    }
+
     fn getTable(&mut self, t: &LuaValue, k: &LuaValue, raw: bool) -> i8 {
         if let LuaValue::Table(tbl) = t {
             let v = tbl.borrow().Get(k);
@@ -604,33 +1221,38 @@ impl LuaState {
                 self.stack_mut().push(v.clone());
                 return v.typeOf();
             }
-        } else {
-            print!("{t:?}");
         }
 
         if !raw {
             let mf = getMetafield(t.clone(), "__index", self);
             match mf {
-                LuaValue::Table(_) => {
-                    return self.getTable(&mf, k, false);
-                },
+                LuaValue::Nil => {},
                 LuaValue::Function(_) => {
                     self.stack_mut().push(mf);
                     self.stack_mut().push(t.clone());
                     self.stack_mut().push(k.clone());
-                    self.Call(2, 1);
+                    self.callTM(2, 1);
                     let v = self.stack().get(-1);
                     return v.typeOf();
                 },
-                _ => {},
+                _ => {
+                    return self.getTable(&mf, k, false);    /* repeat with the metamethod */
+                },
             };
         }
-        panic!("index error!");
+        let tn = self.TypeName(t.typeOf());
+        let info = self.varInfo(t);
+        self.runtimeError(format!("attempt to index a {} value{}", tn, info));
     }
 
     fn setTable(&mut self, t: &LuaValue, k: &LuaValue, v: &LuaValue, raw: bool) {
         if let LuaValue::Table(tbl) = t {
             if raw || !tbl.borrow().Get(k).IsNil() || !tbl.borrow().hasMetafield("__newindex") {
+                match k {
+                    LuaValue::Nil => self.runtimeError(String::from("table index is nil")),
+                    LuaValue::Number(n) if n.is_nan() => self.runtimeError(String::from("table index is NaN")),
+                    _ => {},
+                }
                 tbl.borrow_mut().Put(k.clone(), v.clone());
                 return;
             }
@@ -639,23 +1261,339 @@ impl LuaState {
         if !raw {
             let mf = getMetafield(t.clone(), "__newindex", self);
             match mf {
-                LuaValue::Table(_) => {
-                    self.setTable(&mf, k, v, false);
-                    return;
-                },
+                LuaValue::Nil => {},
                 LuaValue::Function(_) => {
                     self.stack_mut().push(mf);
                     self.stack_mut().push(t.clone());
                     self.stack_mut().push(k.clone());
                     self.stack_mut().push(v.clone());
-                    self.Call(3, 0);
+                    self.callTM(3, 0);
+                    return;
+                },
+                _ => {
+                    self.setTable(&mf, k, v, false);    /* repeat with the metamethod */
                     return;
                 },
-                _ => {},
             };
         }
 
-        panic!("not a table!");
+        let tn = self.TypeName(t.typeOf());
+        let info = self.varInfo(t);
+        self.runtimeError(format!("attempt to index a {} value{}", tn, info));
+    }
+
+    fn pushRustFunc(&mut self, f: RustFunc, n: i32) {
+        let mut _closure_ = Closure::newRustClosure(f, n);
+        let closure = &mut _closure_;
+        for i in (1..=n).rev() {
+            let val = self.stack_mut().pop();
+            if let Some(uv) = closure.upvals.borrow().get(i as usize - 1) {
+                *uv.borrow_mut() = val;
+            }
+        }
+        let f = LuaValue::Function(Rc::new(_closure_));
+        self.gc.track(&f);
+        self.stack_mut().push(f);
+    }
+
+    // A whole collection cycle, then the finalizers of the objects it
+    // found unreachable.
+    fn fullGC(&mut self) {
+        self.gc.collect();
+        self.callPendingFinalizers(true);
+    }
+
+    // Calls the `__gc` metamethods of the objects to be finalized. An
+    // error in one is raised again if `propagate`, else it is ignored.
+    fn callPendingFinalizers(&mut self, propagate: bool) {
+        while let Some(obj) = self.gc.nextToFinalize() {
+            let tm = getMetafield(obj.clone(), "__gc", self);
+            if let LuaValue::Function(_) = tm {
+                let running = std::mem::replace(&mut self.gc.running, false);  /* avoid GC steps */
+                self.stack_mut().check(2);
+                self.stack_mut().push(tm);
+                self.stack_mut().push(obj);
+                let status = self.PCall(1, 0, 0);
+                self.gc.running = running;
+                if status != LUA_OK {
+                    let err = self.stack_mut().pop();
+                    if propagate {
+                        let msg = match &err {
+                            LuaValue::Str(s) => String::from_utf8_lossy(s).into_owned(),
+                            _ => String::from("no message"),
+                        };
+                        let err = LuaValue::Str(format!("error in __gc metamethod ({})", msg).into_bytes());
+                        self.throw(LuaError { status: LUA_ERRGCMM, value: err });
+                    }
+                }
+            }
+        }
+    }
+
+    // Unwinds to the innermost protected call with `err`.
+    fn throw(&mut self, err: LuaError) -> ! {
+        self.error = Some(err);
+        resume_unwind(Box::new(ErrorSignal));
+    }
+
+    // Raises `err` as a Lua error. The message handler of the innermost
+    // protected call (if any) runs here, before the frames are unwound.
+    pub(crate) fn raiseError(&mut self, err: LuaValue) -> ! {
+        let handler = std::mem::replace(&mut self.errfunc, LuaValue::Nil);
+        if let LuaValue::Nil = handler {
+            self.throw(LuaError { status: LUA_ERRRUN, value: err });
+        }
+
+        let depth = self.frames.len();
+        let top = self.GetTop();
+        self.stack_mut().check(2);
+        self.stack_mut().push(handler.clone());
+        self.stack_mut().push(err);
+        let result = catch_unwind(AssertUnwindSafe(|| self.Call(1, 1)));
+        self.errfunc = handler;
+        match result {
+            Ok(_) => {
+                let err = self.stack_mut().pop();
+                self.throw(LuaError { status: LUA_ERRRUN, value: err });
+            },
+            Err(payload) => {
+                if payload.is::<ErrorSignal>() {
+                    self.error = None;
+                    self.frames.truncate(depth);
+                    self.SetTop(top);
+                    let err = LuaValue::Str("error in error handling".into());
+                    self.throw(LuaError { status: LUA_ERRERR, value: err });
+                }
+                resume_unwind(payload);
+            },
+        }
+    }
+
+    // Raises an error with message `msg`, which is prefixed with the position
+    // of the running function if it is a Lua function.
+    pub(crate) fn runtimeError(&mut self, msg: String) -> ! {
+        let frame = self.stack();
+        let msg = if self.frames.len() > 1 && frame.closure.rustFunc.is_none() {
+            let proto = &frame.closure.proto;
+            format!("{}:{}: {}", shortSrc(proto), currentLine(proto, frame.pc), msg)
+        } else {
+            msg
+        };
+        self.raiseError(LuaValue::Str(msg.into_bytes()));
+    }
+
+    // Where the value `v` that made the running instruction fail comes from,
+    // e.g. " (global 'foo')", or "" if it is not a named operand.
+    fn varInfo(&self, v: &LuaValue) -> String {
+        let frame = self.stack();
+        if self.frames.len() < 2 || frame.closure.rustFunc.is_some() || frame.pc < 1 {
+            return String::new();
+        }
+        let proto = &frame.closure.proto;
+        let pc = frame.pc - 1;
+        let value = |operand: Operand| match operand {
+            Operand::Register(reg) => frame.get(reg + 1),
+            Operand::Upvalue(uv) => frame.closure.upvals.borrow().get(uv as usize).map_or(LuaValue::Nil, |uv| uv.borrow().clone()),
+        };
+        typeErrorOperands(proto, pc, |reg| frame.get(reg + 1)).into_iter()
+            .find(|operand| eq(v, &value(*operand)))
+            .and_then(|operand| operandName(proto, pc, operand))
+            .map_or(String::new(), |(kind, name)| format!(" ({} '{}')", kind, name))
+    }
+
+    // Runs `f` on the frames of `thread`, the running thread if `None`.
+    fn withFrames<R>(&self, thread: &Option<Rc<RefCell<LuaThread>>>, f: impl FnOnce(&[LuaStack]) -> R) -> R {
+        match thread {
+            Some(co) => f(&co.borrow().frames),
+            None => f(&self.frames),
+        }
+    }
+
+    fn withFramesMut<R>(&mut self, thread: &Option<Rc<RefCell<LuaThread>>>, f: impl FnOnce(&mut [LuaStack]) -> R) -> R {
+        match thread {
+            Some(co) => f(&mut co.borrow_mut().frames),
+            None => f(&mut self.frames),
+        }
+    }
+
+    fn arithError(&mut self, a: &LuaValue, b: &LuaValue, op: u8) -> ! {
+        let bitwise = matches!(op, LUA_OPBAND | LUA_OPBOR | LUA_OPBXOR | LUA_OPSHL | LUA_OPSHR | LUA_OPBNOT);
+        if bitwise && a.ToFloat().is_some() && b.ToFloat().is_some() {
+            let bad = if a.ToInteger().is_none() { a } else { b };
+            let info = self.varInfo(bad);
+            self.runtimeError(format!("number{} has no integer representation", info));
+        }
+        let bad = if a.ToFloat().is_some() { b } else { a };
+        let tn = self.TypeName(bad.typeOf());
+        let info = self.varInfo(bad);
+        if bitwise {
+            self.runtimeError(format!("attempt to perform bitwise operation on a {} value{}", tn, info));
+        } else {
+            self.runtimeError(format!("attempt to perform arithmetic on a {} value{}", tn, info));
+        }
+    }
+
+    fn isMainThread(&self) -> bool {
+        let main = match &self.registry {
+            LuaValue::Table(tbl) => tbl.borrow().Get(&LuaValue::Integer(LUA_RIDX_MAINTHREAD)),
+            _ => LuaValue::Nil,
+        };
+        matches!(main, LuaValue::Thread(t) if Rc::ptr_eq(&t, &self.thread))
+    }
+
+    // Makes `co` the running thread and returns the thread that was running.
+    fn switchThread(&mut self, co: Rc<RefCell<LuaThread>>) -> Rc<RefCell<LuaThread>> {
+        {
+            let mut cur = self.thread.borrow_mut();
+            cur.frames = std::mem::take(&mut self.frames);
+            cur.status = self.status;
+            cur.nny = self.nny;
+            cur.errfunc = std::mem::replace(&mut self.errfunc, LuaValue::Nil);
+            cur.hook = self.hook;
+        }
+        {
+            let mut next = co.borrow_mut();
+            self.frames = std::mem::take(&mut next.frames);
+            self.status = next.status;
+            self.nny = next.nny;
+            self.errfunc = std::mem::replace(&mut next.errfunc, LuaValue::Nil);
+            self.hook = next.hook;
+        }
+        std::mem::replace(&mut self.thread, co)
+    }
+
+    // Starts a call of the value below the `nArgs` arguments on top of the
+    // stack. Rust functions run right away and true is returned once they
+    // are done; a Lua function only gets its frame, which is then run by
+    // `runLuaClosure`.
+    fn preCall(&mut self, mut nArgs: i32, nResults: i32) -> bool {
+        let mut val = self.stack().get(-(nArgs + 1));
+        if let LuaValue::Function(_) = val {} else {
+            let _mf_ = getMetafield(val.clone(), "__call", self);
+            if let LuaValue::Function(_) = _mf_ {
+                self.stack_mut().push(val.clone());
+                self.Insert(-(nArgs + 2));
+                nArgs += 1;
+                val = _mf_;
+            }
+        }
+
+        if let LuaValue::Function(c) = val {
+            match c.rustFunc {
+                None => {
+                    self.callLuaClosure(nArgs, nResults, Rc::clone(&c));
+                    false
+                },
+                Some(_) => self.callRustClosure(nArgs, nResults, Rc::clone(&c)),
+            }
+        } else {
+            let tn = self.TypeName(val.typeOf());
+            let info = self.varInfo(&val);
+            self.runtimeError(format!("attempt to call a {} value{}", tn, info));
+        }
+    }
+
+    // Pops the running frame and passes its top `nrets` values to the caller.
+    fn postCall(&mut self, nrets: i32) {
+        if self.hook.mask & (LUA_MASKRET | LUA_MASKLINE) != 0 {
+            if self.hook.mask & LUA_MASKRET != 0 {
+                self.callHook(LUA_HOOKRET, -1);
+            }
+            let caller = &self.frames[self.frames.len() - 2];
+            self.hook.oldpc = caller.pc - 1;    /* 'oldpc' for caller function */
+        }
+        let mut frame = self.popFrame();
+        let nResults = frame.nresults;
+        if nResults != 0 {
+            let results = frame.popN(nrets);
+            self.stack_mut().check(nrets.max(nResults));
+            self.stack_mut().pushN(results, nResults);
+        }
+    }
+
+    // A call of a Rust function with a continuation, the callee may yield.
+    fn callK(&mut self, nArgs: i32, nResults: i32) {
+        if self.nCcalls >= LUAI_MAXCCALLS {
+            self.runtimeError(String::from("C stack overflow"));
+        }
+        self.nCcalls += 1;
+        if !self.preCall(nArgs, nResults) {
+            if self.status != LUA_YIELD {
+                self.stack_mut().fresh = true;
+                self.runLuaClosure();
+            }
+            if self.status == LUA_YIELD {
+                // leave the Rust function, its continuation runs on resume
+                resume_unwind(Box::new(YieldSignal));
+            }
+        }
+        self.nCcalls -= 1;
+    }
+
+    // Calls a metamethod. Called by an instruction of a Lua function, the
+    // metamethod may yield like a callee of OP_CALL: the instruction is then
+    // completed by `finishOp` when the thread is resumed.
+    pub(crate) fn callTM(&mut self, nArgs: i32, nResults: i32) {
+        if self.nny > 0 || self.frames.len() < 2 || self.stack().closure.rustFunc.is_some() {
+            self.Call(nArgs, nResults);
+        } else {
+            self.callK(nArgs, nResults);
+        }
+    }
+
+    // Finishes the interrupted frames of a resumed coroutine.
+    fn unroll(&mut self) {
+        while self.frames.len() > 1 && self.status != LUA_YIELD {
+            if self.stack().closure.rustFunc.is_some() {
+                self.finishCcall();
+            } else {
+                self.finishOp();
+                self.runLuaClosure();
+            }
+        }
+    }
+
+    // Calls the continuation of the running Rust function and returns its results.
+    fn finishCcall(&mut self) {
+        let kcont = self.stack_mut().kcont.take().unwrap();
+        if let Some(errfunc) = kcont.errfunc {
+            self.errfunc = errfunc;
+        }
+        let r = (kcont.k)(self, kcont.status, kcont.ctx);
+        if self.status != LUA_YIELD {
+            self.postCall(r);
+        }
+    }
+
+    // Makes the innermost yieldable protected call the running frame again,
+    // with the error object on top. Returns false if there is none.
+    fn recover(&mut self, err: &LuaError) -> bool {
+        let idx = self.frames.iter().rposition(|f| {
+            matches!(&f.kcont, Some(kcont) if kcont.errfunc.is_some())
+        });
+        match idx {
+            Some(idx) => {
+                self.frames.truncate(idx + 1);
+                let kcont = self.stack_mut().kcont.as_mut().unwrap();
+                kcont.status = err.status;
+                let base = kcont.base;
+                self.SetTop(base);
+                self.stack_mut().check(1);
+                self.stack_mut().push(err.value.clone());
+                self.nny = 0;
+                self.hook.running = false;  /* hooks are not yieldable */
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Completes the interrupted instruction of the running frame after its
+    // callee or metamethod returned.
+    fn finishOp(&mut self) {
+        let pc = self.stack().pc as usize;
+        let i = Instruction::new(self.stack().closure.proto.code[pc - 1]);
+        i.Finish(self);
     }
 
     fn callLuaClosure(&mut self, nArgs: i32, nResults: i32, c: Rc<Closure>) {
@@ -664,6 +1602,7 @@ impl LuaState {
         let isVararg = c.proto.isVararg == 1;
 
         let mut newStack = LuaStack::new(nRegs as usize + 20, Rc::clone(&c), self.registry.clone());
+        newStack.nresults = nResults;
         // pass args, pop func
         let mut args = self.stack_mut().popN(nArgs);
         self.stack_mut().pop(); // pop func
@@ -680,55 +1619,125 @@ impl LuaState {
         }
         newStack.pushN(args, nParams as i32);
         newStack.SetTop(nRegs as i32);
-
-        // run closure
         self.pushFrame(newStack);
-        self.runLuaClosure();
-        newStack = self.popFrame();
+        if self.hook.mask & LUA_MASKCALL != 0 {
+            self.callHook(LUA_HOOKCALL, -1);
+        }
+    }
 
-        // return results
-        if nResults != 0 {
-            let nrets = newStack.top - nRegs;
-            let results = newStack.popN(nrets);
-            self.stack_mut().check(nrets);
-            self.stack_mut().pushN(results, nResults);
+    // Calls the hook for `event` in the running frame. The hook runs with
+    // hooks off and cannot yield.
+    fn callHook(&mut self, event: i32, line: i32) {
+        let hook = match self.hook.hook {
+            Some(hook) if !self.hook.running => hook,
+            _ => return,
+        };
+        let mut ar = LuaDebug { event, currentline: line, ci: self.frames.len() - 1, ..LuaDebug::default() };
+        let top = self.GetTop();
+        self.CheckStack(LUA_MINSTACK as i32);   /* ensure minimum stack size */
+        self.hook.running = true;       /* cannot call hooks inside a hook */
+        self.nny += 1;
+        hook(self, &mut ar);
+        self.nny -= 1;
+        self.hook.running = false;
+        self.SetTop(top);
+    }
+
+    // Fires the count and line events due before the next instruction of
+    // the running frame, like `luaG_traceexec`.
+    fn traceExec(&mut self) {
+        if self.hook.mask & LUA_MASKCOUNT != 0 {
+            self.hook.count -= 1;
+            if self.hook.count == 0 {
+                self.hook.count = self.hook.basecount;  /* reset count */
+                self.callHook(LUA_HOOKCOUNT, -1);
+            }
         }
+        let npc = self.stack().pc;
+        if self.hook.mask & LUA_MASKLINE != 0 {
+            let proto = Rc::clone(&self.stack().closure.proto);
+            let oldpc = self.hook.oldpc;
+            let newline = currentLine(&proto, npc + 1);
+            /* call line hook when entering a new function, when jumping back
+               (loop), or when entering a new line */
+            if npc == 0 || npc <= oldpc || newline != currentLine(&proto, oldpc + 1) {
+                self.callHook(LUA_HOOKLINE, newline);
+            }
+        }
+        self.hook.oldpc = npc;
     }
 
+    // Runs Lua frames until a fresh frame returns or the thread yields.
     fn runLuaClosure(&mut self) {
         loop {
-            let mut inst = Instruction::new(self.Fetch());
+            if self.hook.mask & (LUA_MASKLINE | LUA_MASKCOUNT) != 0 && !self.hook.running {
+                self.traceExec();
+            }
+            let inst = Instruction::new(self.Fetch());
             inst.Execute(self);
-            if inst.Opcode() == OP_RETURN as i32{
-                break;
+            if self.status == LUA_YIELD {
+                return;
+            }
+            if self.gc.needsCollect() {
+                self.fullGC();
             }
+            if inst.Opcode() == OP_RETURN as i32 {
+                let fresh = self.stack().fresh;
+                let nrets = self.GetTop() - self.RegisterCount();
+                self.postCall(nrets);
+                if fresh {
+                    return;
+                }
+                self.finishOp();
+            }
+        }
+    }
+
+    // Raises the error returned by a Rust closure.
+    fn checkResult(&mut self, res: Result<i32, LuaError>) -> i32 {
+        match res {
+            Ok(n) => n,
+            Err(e) if e.status == LUA_ERRRUN => self.raiseError(e.value),
+            Err(e) => self.throw(e),
         }
     }
 
-    fn callRustClosure(&mut self, nArgs: i32, nResults: i32, c: Rc<Closure>) {
+    // Returns false if the function yielded, its frame is then kept until
+    // the thread is resumed.
+    fn callRustClosure(&mut self, nArgs: i32, nResults: i32, c: Rc<Closure>) -> bool {
         let mut newStack = LuaStack::new(nArgs as usize + 20, Rc::clone(&c), self.registry.clone());
+        newStack.nresults = nResults;
         let args = self.stack_mut().popN(nArgs);
         newStack.pushN(args, nArgs);
         let _ = self.stack_mut().pop();
-        let rustFunction = c.rustFunc.unwrap();
 
         self.pushFrame(newStack);
-        let r = rustFunction(self);
-        newStack = self.popFrame();
-
-        if nResults != 0 {
-            let results = newStack.popN(r);
-            self.stack_mut().check(results.len() as i32);
-            self.stack_mut().pushN(results, nResults);
+        if self.hook.mask & LUA_MASKCALL != 0 {
+            self.callHook(LUA_HOOKCALL, -1);
         }
+        let r = match c.rustFunc.as_ref().unwrap() {
+            RustFunc::Fn(f) => f(self),
+            RustFunc::Closure(f) => {
+                let res = f(self);
+                self.checkResult(res)
+            },
+            RustFunc::ClosureMut(f) => match f.try_borrow_mut() {
+                Ok(mut f) => {
+                    let res = f(self);
+                    self.checkResult(res)
+                },
+                Err(_) => self.runtimeError(String::from("Rust closure called again while running")),
+            },
+        };
+        if self.status == LUA_YIELD {
+            return false;
+        }
+        self.postCall(r);
+        true
     }
 }
 
 impl LuaVM for LuaState {
-    fn PC(&self) -> i32 {
-        self.stack().pc
-    }
-
     fn AddPC(&mut self, n: i32) {
         self.stack_mut().pc += n
     }
@@ -781,27 +1790,30 @@ impl LuaVM for LuaState {
             let uvInfo = &closure.proto.upvalues[i];
             let uvIdx = uvInfo.idx as i32;
             if uvInfo.instack == 1 {
-                let _openuv_ = self.stack().openuvs.get(&uvIdx);
+                let _openuv_ = self.stack().openuvs.get(&uvIdx).cloned();
                 match _openuv_ {
                     Some(openuv) => {
-                        if let Some(r_val) = closure.upvals.borrow_mut().get_mut(i) {
-                            *r_val = openuv.clone();
-                        }
+                        closure.upvals.borrow_mut()[i] = openuv;
                     },
                     None => {
-                        if let Some(r_val) = closure.upvals.borrow_mut().get_mut(i) {
-                            *r_val = self.stack().slots[uvIdx as usize].clone();
-                        }
-                        self.stack_mut().openuvs.insert(uvIdx, closure.upvals.borrow().get(i).unwrap().clone());
+                        // the register lives in the new cell from now on
+                        let openuv = newUpVal(self.stack().slots[uvIdx as usize].clone());
+                        closure.upvals.borrow_mut()[i] = Rc::clone(&openuv);
+                        self.stack_mut().openuvs.insert(uvIdx, openuv);
                     }
                 }
             } else {
-                if let Some(r_val) = closure.upvals.borrow_mut().get_mut(i) {
-                    *r_val = self.stack().closure.upvals.borrow().get(uvIdx as usize).unwrap().clone();
-                }
+                let uv = Rc::clone(&self.stack().closure.upvals.borrow()[uvIdx as usize]);
+                closure.upvals.borrow_mut()[i] = uv;
             }
         }
-        self.stack_mut().push(LuaValue::Function(Rc::new(_closure_)));
+        let f = LuaValue::Function(Rc::new(_closure_));
+        self.gc.track(&f);
+        self.stack_mut().push(f);
+    }
+
+    fn PreCall(&mut self, nArgs: i32, nResults: i32) -> bool {
+        self.preCall(nArgs, nResults)
     }
 
     fn CloseUpvalues(&mut self, a: i32) {
@@ -812,9 +1824,67 @@ impl LuaVM for LuaState {
             }
         }
         for k in to_del {
-            let _ = self.stack_mut().openuvs.remove(&k);
+            // the cell keeps the value, the register gets it back
+            let openuv = self.stack_mut().openuvs.remove(&k).unwrap();
+            self.stack_mut().slots[k as usize] = openuv.borrow().clone();
+        }
+    }
+}
+
+// Like `lua_close`, all pending finalizers run when the state goes away.
+impl Drop for LuaState {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            return;             /* no Lua code while unwinding */
         }
+        self.gc.separateAll();
+        self.callPendingFinalizers(false);
+        /* break the cycles, the frames go to the thread to be cleared with it */
+        self.thread.borrow_mut().frames = std::mem::take(&mut self.frames);
+        let roots = [std::mem::replace(&mut self.registry, LuaValue::Nil), LuaValue::Thread(Rc::clone(&self.thread)),
+            std::mem::replace(&mut self.errfunc, LuaValue::Nil)];
+        self.gc.clearAll(&roots);
+    }
+}
+
+// Where a local of a frame is kept.
+enum LocalSlot {
+    Register(i32),              // stack index in the frame
+    Vararg(usize),
+}
+
+// The name and the place of local `n` of `frame`, like `findlocal` in
+// ldebug.c. Negative `n` are the extra arguments of vararg functions.
+fn findLocal(frame: &LuaStack, n: i32) -> Option<(String, LocalSlot)> {
+    let isLua = frame.closure.rustFunc.is_none();
+    if isLua && n < 0 {                 /* access to vararg values? */
+        let i = (-n - 1) as usize;
+        return (i < frame.varargs.len()).then(|| (String::from("(*vararg)"), LocalSlot::Vararg(i)));
+    }
+    let name = if isLua {
+        localName(&frame.closure.proto, n, (frame.pc - 1).max(0)).map(String::from)
+    } else {
+        None
+    };
+    match name {
+        Some(name) => Some((name, LocalSlot::Register(n))),
+        /* no 'standard' name, but 'n' is inside the frame? */
+        None if n > 0 && n <= frame.top => {
+            let name = if isLua { "(*temporary)" } else { "(*C temporary)" };
+            Some((String::from(name), LocalSlot::Register(n)))
+        },
+        None => None,                   /* no name */
+    }
+}
+
+// How the function of frame `ci` was called, if a Lua function called it.
+fn callerName(frames: &[LuaStack], ci: usize) -> Option<(&'static str, String)> {
+    /* the first frame is not a call of any function */
+    let caller = frames.get(ci - 1).filter(|_| ci >= 2)?;
+    if caller.closure.rustFunc.is_some() || caller.pc < 1 {
+        return None;
     }
+    funcNameFromCode(&caller.closure.proto, caller.pc - 1)
 }
 
 pub fn is_binary_chunk(data: &Vec<u8>) -> bool {
@@ -828,10 +1898,473 @@ pub fn is_binary_chunk(data: &Vec<u8>) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::{LuaAPI, LuaUpValueIndex}};
+    use super::LuaState;
+
     #[test]
     fn it_works() {
         let a = [1, 2, 4];
         let b = [1, 2, 5];
         assert_eq!(a == b, false);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_pcall_catches_runtime_error() {
+        let mut ls = LuaState::new();
+        ls.Load(b"local t = nil; return t.x".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "test:1: attempt to index a nil value (local 't')");
+        assert_eq!(ls.GetTop(), 1);
+
+        ls.Load(b"return 1 + 2".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 1, 0), LUA_OK);
+        assert_eq!(ls.ToInteger(-1), 3);
+    }
+
+    #[test]
+    fn test_integer_division_by_zero() {
+        let mut ls = LuaState::new();
+        ls.Load(b"local a, b = 1, 0\nreturn a // b".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "test:2: attempt to perform 'n//0'");
+        ls.Load(b"local a, b = 1, 0\nreturn a % b".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "test:2: attempt to perform 'n%0'");
+
+        // floats divide by zero as usual
+        ls.Load(b"return 1 // 0.0, 1 % 0.0".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 2, 0), LUA_OK);
+        assert_eq!(ls.ToNumber(-2), f64::INFINITY);
+        assert!(ls.ToNumber(-1).is_nan());
+    }
+
+    #[test]
+    fn test_stack_overflow_in_xpcall() {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        let src = b"local function f() return 1 + f() end\n\
+            local ok, msg = xpcall(f, function (m) return 'handled: ' .. m end)\n\
+            local ok2, msg2 = xpcall(f, debug.traceback)\n\
+            return ok, msg, ok2 or msg2:match('^[^\\n]*')";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 3, 0), LUA_OK);
+        assert!(!ls.ToBoolean(-3));
+        assert_eq!(ls.ToString(-2), "handled: test:1: stack overflow");
+        assert_eq!(ls.ToString(-1), "test:1: stack overflow");
+        ls.pop(3);
+
+        // the margin is given again once the stack has been unwound
+        ls.Load(b"local function f() return 1 + f() end\n\
+            return select(2, xpcall(f, function (m) return 'again: ' .. m end))".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 1, 0), LUA_OK);
+        assert_eq!(ls.ToString(-1), "again: test:1: stack overflow");
+
+        // a handler that overflows too
+        ls.Load(b"local function f() return 1 + f() end\n\
+            return xpcall(f, function (m) return f() end)".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 2, 0), LUA_OK);
+        assert_eq!(ls.ToString(-1), "error in error handling");
+    }
+
+    #[test]
+    fn test_runtime_error_positions() {
+        let mut ls = LuaState::new();
+        let src = b"local function f(t)\n  return t.x\nend\n\nreturn f(1)";
+        ls.Load(src.to_vec(), "@script.lua", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "script.lua:2: attempt to index a number value (local 't')");
+
+        // errors of Rust functions have no position
+        ls.PushRustFunction(|ls| { ls.ArithOp(LUA_OPADD); 1 });
+        ls.PushBoolean(true);
+        ls.PushInteger(1);
+        assert_eq!(ls.PCall(2, 0, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "attempt to perform arithmetic on a boolean value");
+    }
+
+    #[test]
+    fn test_get_stack_and_info() {
+        let mut ls = LuaState::new();
+        ls.PushRustFunction(|ls| {
+            let mut ar = ls.GetStack(None, 1).unwrap();
+            assert!(ls.GetInfo("Slnu", &mut ar));
+            let info = format!("{} {} {} {}-{} {} {:?} {} {} {}", ar.what, ar.short_src, ar.currentline,
+                ar.linedefined, ar.lastlinedefined, ar.namewhat, ar.name, ar.nups, ar.nparams, ar.isvararg);
+            let mut ar = ls.GetStack(None, 0).unwrap();
+            assert!(ls.GetInfo("Sl", &mut ar));
+            assert_eq!((ar.what, ar.short_src.as_str(), ar.currentline), ("C", "[C]", -1));
+            assert!(ls.GetStack(None, 3).is_none());
+            assert!(!ls.GetInfo("x", &mut ar));
+            ls.PushString(info);
+            1
+        });
+        ls.SetGlobal("info");
+        let src = b"local t = {}\nfunction t.get(a, b)\n  local v =\n    info()\n  return v\nend\nreturn (t.get())";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 1, 0), LUA_OK);
+        assert_eq!(ls.ToString(-1), "Lua test 4 2-6 field Some(\"get\") 1 2 false");
+    }
+
+    #[test]
+    fn test_closures_share_upvalues() {
+        let mut ls = LuaState::new();
+        let src = b"
+            local n = 0
+            local function inc() n = n + 1 end
+            local function get() return n end
+            inc() inc()
+            n = n * 10
+            local fs = {}
+            for i = 1, 2 do fs[i] = function() return i end end
+            return get(), fs[1]() + fs[2]()";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 2, 0), LUA_OK);
+        assert_eq!((ls.ToInteger(-2), ls.ToInteger(-1)), (20, 3));
+    }
+
+    #[test]
+    fn test_goto_continue_and_backward_jump() {
+        let mut ls = LuaState::new();
+        let src = b"
+            local s = 0
+            for i = 1, 4 do
+                if i % 2 == 0 then goto continue end
+                s = s + i
+                ::continue::
+            end
+            local fs, i = {}, 1
+            ::top::
+            local x = i * 10
+            fs[i] = function() return x end
+            i = i + 1
+            if i <= 2 then goto top end
+            return s, fs[1]() + fs[2]()";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 2, 0), LUA_OK);
+        assert_eq!((ls.ToInteger(-2), ls.ToInteger(-1)), (4, 30));
+    }
+
+    fn yield_twice(ls: &mut LuaState) -> i32 {
+        ls.Yield(ls.GetTop())
+    }
+
+    #[test]
+    fn test_resume_thread_from_host() {
+        let mut ls = LuaState::new();
+        ls.Register("y", yield_twice);
+        ls.NewThread();
+        ls.Load(b"local a = y(1, 2) return a * 10".to_vec(), "=test", "bt");
+        ls.XMove(1, 1);
+
+        ls.PushValue(1);
+        assert_eq!(ls.Resume(0), LUA_YIELD);
+        assert_eq!(ls.GetTop(), 3);
+        assert_eq!((ls.ToInteger(2), ls.ToInteger(3)), (1, 2));
+
+        ls.SetTop(1);
+        ls.PushValue(1);
+        ls.PushInteger(5);
+        assert_eq!(ls.Resume(1), LUA_OK);
+        assert_eq!(ls.ToInteger(-1), 50);
+
+        ls.PushValue(1);
+        assert_eq!(ls.Resume(0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "cannot resume dead coroutine");
+    }
+
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    fn newPoint(ls: &mut LuaState) -> i32 {
+        let (x, y) = (ls.CheckInteger(1), ls.CheckInteger(2));
+        ls.NewUserData(Point { x, y });
+        ls.SetMetatableByName("Point");
+        1
+    }
+
+    fn pointIndex(ls: &mut LuaState) -> i32 {
+        let p = ls.CheckUData::<Point>(1, "Point");
+        let v = match ls.CheckString(2).as_str() {
+            "x" => p.borrow().x,
+            "y" => p.borrow().y,
+            _ => return 0,
+        };
+        ls.PushInteger(v);
+        1
+    }
+
+    fn pointNewIndex(ls: &mut LuaState) -> i32 {
+        let p = ls.CheckUData::<Point>(1, "Point");
+        let v = ls.CheckInteger(3);
+        match ls.CheckString(2).as_str() {
+            "x" => p.borrow_mut().x = v,
+            "y" => p.borrow_mut().y = v,
+            _ => return ls.Error2(String::from("no such field")),
+        }
+        0
+    }
+
+    fn pointAdd(ls: &mut LuaState) -> i32 {
+        let a = ls.CheckUData::<Point>(1, "Point");
+        let b = ls.CheckUData::<Point>(2, "Point");
+        let (x, y) = (a.borrow().x + b.borrow().x, a.borrow().y + b.borrow().y);
+        ls.NewUserData(Point { x, y });
+        ls.SetMetatableByName("Point");
+        1
+    }
+
+    fn pointEq(ls: &mut LuaState) -> i32 {
+        let a = ls.CheckUData::<Point>(1, "Point");
+        let b = ls.CheckUData::<Point>(2, "Point");
+        let eq = (a.borrow().x, a.borrow().y) == (b.borrow().x, b.borrow().y);
+        ls.PushBoolean(eq);
+        1
+    }
+
+    fn pointLen(ls: &mut LuaState) -> i32 {
+        let p = ls.CheckUData::<Point>(1, "Point");
+        let len = p.borrow().x.abs() + p.borrow().y.abs();
+        ls.PushInteger(len);
+        1
+    }
+
+    fn pointToString(ls: &mut LuaState) -> i32 {
+        let p = ls.CheckUData::<Point>(1, "Point");
+        let s = format!("({}, {})", p.borrow().x, p.borrow().y);
+        ls.PushString(s);
+        1
+    }
+
+    fn openPoint(ls: &mut LuaState) {
+        ls.OpenLibs();
+        ls.NewMetatable("Point");
+        ls.SetFuncs(&[
+            ("__index", pointIndex),
+            ("__newindex", pointNewIndex),
+            ("__add", pointAdd),
+            ("__eq", pointEq),
+            ("__len", pointLen),
+            ("__tostring", pointToString),
+        ], 0);
+        ls.pop(1);
+        ls.Register("Point", newPoint);
+    }
+
+    #[test]
+    fn test_userdata_with_metamethods() {
+        let mut ls = LuaState::new();
+        openPoint(&mut ls);
+        let src = b"
+            local p = Point(1, 2) + Point(10, 20)
+            p.y = -p.y
+            return p, p.x, #p, tostring(p), p == Point(11, -22), p == Point(0, 0), type(p)";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 7, 0), LUA_OK);
+        assert_eq!(ls.ToInteger(2), 11);
+        assert_eq!(ls.ToInteger(3), 33);
+        assert_eq!(ls.ToString(4), "(11, -22)");
+        assert!(ls.ToBoolean(5) && !ls.ToBoolean(6));
+        assert_eq!(ls.ToString(7), "userdata");
+        let p = ls.ToUserData::<Point>(1).unwrap();
+        assert_eq!((p.borrow().x, p.borrow().y), (11, -22));
+        assert!(ls.ToUserData::<String>(1).is_none());
+        assert!(ls.ToUserData::<Point>(2).is_none());
+
+        ls.Load(b"return Point(1, 2).z".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 1, 0), LUA_OK);
+        assert!(ls.IsNil(-1));
+        ls.Load(b"local p = Point(1, 2) p.z = 1".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "test:1: no such field");
+        ls.Load(b"return Point(1, 2) + {}".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        assert!(ls.ToString(-1).ends_with("(Point expected, got table)"));
+    }
+
+    #[test]
+    fn test_light_userdata() {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        let mut x = 42;
+        let p = &mut x as *mut i32 as *mut std::ffi::c_void;
+        ls.PushLightUserData(p);
+        ls.SetGlobal("p");
+        ls.PushLightUserData(p);
+        ls.SetGlobal("q");
+        let src = b"local t = {[p] = 1} return type(p), p == q, t[q], tostring(p):match('^userdata: 0x')";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 4, 0), LUA_OK);
+        assert_eq!(ls.ToString(1), "userdata");
+        assert!(ls.ToBoolean(2));
+        assert_eq!(ls.ToInteger(3), 1);
+        assert_eq!(ls.ToString(4), "userdata: 0x");
+        ls.GetGlobal("p");
+        assert!(ls.IsUserData(-1) && ls.IsLightUserData(-1));
+        assert_eq!(ls.ToLightUserData(-1), Some(p));
+        assert_eq!(ls.ToPointer(-1), p as *const _);
+        assert!(ls.ToUserData::<i32>(-1).is_none());
+    }
+
+    // Finalizers record the name of their object in the global `log`.
+    const FINALIZERS: &[u8] = b"
+        log = {}
+        mt = {__gc = function(o) log[#log + 1] = o.name end}
+        function new(name) return setmetatable({name = name}, mt) end";
+
+    fn run(ls: &mut LuaState, src: &[u8]) -> i32 {
+        ls.Load(src.to_vec(), "=test", "bt");
+        ls.PCall(0, 1, 0)
+    }
+
+    #[test]
+    fn test_gc_metamethod() {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        assert_eq!(run(&mut ls, FINALIZERS), LUA_OK);
+        let src = b"
+            local a, b = new('a'), new('b')
+            local c = new('c')
+            c.self = c
+            keep = new('keep')
+            local late = setmetatable({name = 'late'}, {})
+            getmetatable(late).__gc = mt.__gc   -- no __gc when set: never finalized
+            a, b, c, late = nil
+            collectgarbage()
+            local first = table.concat(log, ' ')
+            collectgarbage()
+            return first .. '|' .. table.concat(log, ' ')";
+        assert_eq!(run(&mut ls, src), LUA_OK);
+        assert_eq!(ls.ToString(-1), "c b a|c b a");
+
+        // an object is alive while its finalizer runs, and may be kept
+        let src = b"
+            local r = setmetatable({n = 1}, {__gc = function(o) saved = o end})
+            r = nil
+            collectgarbage()
+            return saved.n";
+        assert_eq!(run(&mut ls, src), LUA_OK);
+        assert_eq!(ls.ToInteger(-1), 1);
+
+        let src = b"
+            setmetatable({}, {__gc = function() error('oops') end})
+            collectgarbage()";
+        assert_eq!(run(&mut ls, src), LUA_ERRGCMM);
+        assert_eq!(ls.ToString(-1), "error in __gc metamethod (test:2: oops)");
+    }
+
+    #[test]
+    fn test_gc_metamethod_on_close() {
+        use std::{cell::Cell, rc::Rc};
+
+        struct Resource(Rc<Cell<i32>>);
+
+        fn release(ls: &mut LuaState) -> i32 {
+            let r = ls.CheckUData::<Resource>(1, "Resource");
+            let count = &r.borrow().0;
+            count.set(count.get() + 1);
+            0
+        }
+
+        let released = Rc::new(Cell::new(0));
+        let mut ls = LuaState::new();
+        ls.NewMetatable("Resource");
+        ls.PushRustFunction(release);
+        ls.SetField(-2, "__gc");
+        ls.pop(1);
+        for _ in 0..3 {
+            ls.NewUserData(Resource(released.clone()));
+            ls.SetMetatableByName("Resource");
+        }
+        ls.pop(1);
+        ls.GC(LUA_GCCOLLECT, 0);
+        assert_eq!(released.get(), 1);
+        drop(ls);
+        assert_eq!(released.get(), 3);
+        assert_eq!(Rc::strong_count(&released), 1);    /* the userdata were freed */
+    }
+
+    #[test]
+    fn test_close_frees_the_heap() {
+        use std::rc::Rc;
+        use crate::state::lua_value::LuaValue;
+
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        ls.Load(b"local t = {} t.self = t keep = t co = coroutine.create(function() coroutine.yield(t) end) coroutine.resume(co)".to_vec(), "=test", "t");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_OK);
+        let globals = match ls.registry.clone() {
+            LuaValue::Table(reg) => match reg.borrow().Get(&LuaValue::Integer(LUA_RIDX_GLOBALS)) {
+                LuaValue::Table(g) => Rc::downgrade(&g),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        drop(ls);
+        assert!(globals.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_rust_closures() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        let users = Rc::new(RefCell::new(vec![String::from("ann")]));
+        let db = users.clone();
+        ls.RegisterClosure("adduser", Box::new(move |ls: &mut LuaState| {
+            let name = ls.CheckString(1);
+            if db.borrow().contains(&name) {
+                return Err(format!("user '{}' exists", name).into());
+            }
+            db.borrow_mut().push(name);
+            ls.PushInteger(db.borrow().len() as i64);
+            Ok(1)
+        }));
+        let mut calls = 0;
+        ls.PushString(String::from("call "));
+        ls.PushRustClosureMut(Box::new(move |ls: &mut LuaState| {
+            calls += 1;
+            ls.PushValue(LuaUpValueIndex(1));
+            ls.PushInteger(calls);
+            ls.Concat(2);
+            Ok(1)
+        }), 1);
+        ls.SetGlobal("counter");
+
+        let src = b"
+            local n = adduser('bob')
+            local ok, err = pcall(adduser, 'ann')
+            return n, err, counter(), counter()";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 4, 0), LUA_OK);
+        assert_eq!(ls.ToInteger(1), 2);
+        assert_eq!(ls.ToString(2), "user 'ann' exists");
+        assert_eq!((ls.ToString(3), ls.ToString(4)), (String::from("call 1"), String::from("call 2")));
+        assert_eq!(*users.borrow(), ["ann", "bob"]);
+        ls.GetGlobal("adduser");
+        assert!(ls.IsRustFunction(-1) && ls.ToGoFunction(-1).is_none());
+        ls.SetTop(0);
+
+        // a closure may run again from Lua code it calls, unless it is FnMut
+        ls.RegisterClosure("apply", Box::new(|ls: &mut LuaState| {
+            ls.CheckType(1, LUA_TFUNCTION);
+            let n = ls.GetTop();
+            ls.Call(n - 1, 1);
+            Ok(1)
+        }));
+        ls.RegisterClosureMut("applymut", Box::new(|ls: &mut LuaState| {
+            let n = ls.GetTop();
+            ls.Call(n - 1, 1);
+            Ok(1)
+        }));
+        let src = b"
+            local r = apply(apply, function(x) return x * 2 end, 21)
+            return r, select(2, pcall(applymut, applymut, print))";
+        ls.Load(src.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 2, 0), LUA_OK);
+        assert_eq!(ls.ToInteger(1), 42);
+        assert_eq!(ls.ToString(2), "Rust closure called again while running");
+    }
+}
@@ -70,12 +70,13 @@ impl LuaTable {
         }
     }
 
+    // A border of the table: t[n] is not nil and t[n + 1] is nil (or n is 0).
     pub fn Len(&self) -> usize {
-        if self.arr.len() > self._map.len() {
-            self.arr.len()
-        } else {
-            self._map.len()
+        let mut n = self.arr.len();     /* the array part never ends with nil */
+        while self._map.contains_key(&LuaValue::Integer(n as i64 + 1)) {
+            n += 1;
         }
+        n
     }
 
     pub fn Get(&self, key: &LuaValue) -> LuaValue {
@@ -156,12 +157,13 @@ impl LuaTable {
         match &self.metatable {
             None => false,
             Some(_tbl_) => {
-                !_tbl_.borrow().Get(&LuaValue::Str(String::from(fieldName))).IsNil()
+                !_tbl_.borrow().Get(&LuaValue::Str(fieldName.into())).IsNil()
             },
         }
     }
 
-    pub fn nextKey(&mut self, key: &LuaValue) -> LuaValue {
+    // Returns `None` if `key` is not a key of this table.
+    pub fn nextKey(&mut self, key: &LuaValue) -> Option<LuaValue> {
         if self.keys.is_none() || (key.IsNil() && self.changed) {
             self.initKeys();
             self.changed = false;
@@ -169,14 +171,67 @@ impl LuaTable {
 
         let next_key = self.keys.as_ref().unwrap().get(key);
         if next_key.is_none() && !key.IsNil() && *key != *self.lastKey.as_ref().unwrap() {
-            panic!("invalid key to 'next'");
+            return None;
         }
 
         if let Some(val) = next_key {
-            return val.clone();
+            return Some(val.clone());
         } else {
-            return LuaValue::Nil;
+            return Some(LuaValue::Nil);
+        }
+    }
+
+    // Non-nil entries of the table, leaves the state of `nextKey` untouched.
+    pub fn entries(&self) -> Vec<(LuaValue, LuaValue)> {
+        let mut entries = vec![];
+        for (i, v) in self.arr.iter().enumerate() {
+            if !v.IsNil() {
+                entries.push((LuaValue::Integer(i as i64 + 1), v.clone()));
+            }
+        }
+        for (k, v) in self._map.iter() {
+            if !v.IsNil() {
+                entries.push((k.clone(), v.clone()));
+            }
         }
+        entries
+    }
+
+    // Calls `f` on every value the table refers to, for the collector.
+    pub fn traverse(&self, f: &mut dyn FnMut(&LuaValue)) {
+        self.arr.iter().for_each(&mut *f);
+        for (k, v) in self._map.iter() {
+            f(k);
+            f(v);
+        }
+        if let Some(mt) = &self.metatable {
+            f(&LuaValue::Table(Rc::clone(mt)));
+        }
+        if let Some(keys) = &self.keys {
+            for (k, v) in keys.iter() {
+                f(k);
+                f(v);
+            }
+        }
+        if let Some(key) = &self.lastKey {
+            f(key);
+        }
+    }
+
+    // Drops all contents, used on unreachable tables.
+    pub fn clear(&mut self) {
+        self.arr = Vec::new();
+        self._map = HashMap::new();
+        self.metatable = None;
+        self.keys = None;
+        self.lastKey = None;
+    }
+
+    // Approximate memory used by the table, in bytes.
+    pub fn memSize(&self) -> usize {
+        let val_size = std::mem::size_of::<LuaValue>();
+        std::mem::size_of::<Self>() + self.arr.capacity() * val_size
+            + self._map.capacity() * 2 * val_size
     }
 
     pub fn initKeys(&mut self) {
@@ -0,0 +1,35 @@
+use crate::api::{consts::LUA_OK, lua_state::LuaHook};
+use super::{lua_stack::LuaStack, lua_value::LuaValue};
+
+// A coroutine. While it runs, its frames are moved into the `LuaState`;
+// otherwise they are kept here until the next resume.
+pub struct LuaThread {
+    pub frames: Vec<LuaStack>,
+    pub status: i32,
+    pub nny: usize,             // number of non-yieldable calls in the frames
+    pub errfunc: LuaValue,
+    pub hook: HookState,
+}
+
+// The debug hook of a thread and what it needs to fire its events.
+#[derive(Clone, Copy, Default)]
+pub struct HookState {
+    pub hook: Option<LuaHook>,
+    pub mask: i32,
+    pub basecount: i32,         // instructions between count events
+    pub count: i32,             // instructions left before the next count event
+    pub oldpc: i32,             // last traced instruction, for line events
+    pub running: bool,          // a hook is running, others are off
+}
+
+impl LuaThread {
+    pub fn new(frames: Vec<LuaStack>) -> Self {
+        LuaThread {
+            frames,
+            status: LUA_OK,
+            nny: 0,
+            errfunc: LuaValue::Nil,
+            hook: HookState::default(),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::{any::Any, cell::{Ref, RefCell, RefMut}, marker::PhantomData, rc::Rc};
+use super::lua_table::LuaTable;
+
+// A full userdata: a block of Rust data owned by Lua, with its own
+// metatable. The data is dropped with the userdata, which is how
+// resources like files are released.
+pub struct LuaUserData {
+    pub data: RefCell<Box<dyn Any>>,
+    pub metatable: RefCell<Option<Rc<RefCell<LuaTable>>>>,
+}
+
+impl LuaUserData {
+    pub fn new(data: Box<dyn Any>) -> Self {
+        LuaUserData {
+            data: RefCell::new(data),
+            metatable: RefCell::new(None),
+        }
+    }
+}
+
+// A userdata known to hold a `T`, as given by `LuaAPI::ToUserData`. It
+// keeps the userdata alive, the value is borrowed like in a `RefCell`.
+pub struct UserDataRef<T> {
+    u: Rc<LuaUserData>,
+    _t: PhantomData<T>,
+}
+
+impl<T: Any> UserDataRef<T> {
+    // None if the userdata holds something else.
+    pub fn new(u: Rc<LuaUserData>) -> Option<Self> {
+        if u.data.borrow().is::<T>() {
+            Some(UserDataRef { u, _t: PhantomData })
+        } else {
+            None
+        }
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref::map(self.u.data.borrow(), |d| d.downcast_ref::<T>().unwrap())
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        RefMut::map(self.u.data.borrow_mut(), |d| d.downcast_mut::<T>().unwrap())
+    }
+}
@@ -1,7 +1,7 @@
-use std::{fmt, cell::RefCell, rc::Rc, hash::{Hash, Hasher}};
+use std::{fmt, cell::RefCell, ffi::c_void, rc::Rc, hash::{Hash, Hasher}};
 
-use crate::{api::{consts, lua_state::{LuaAPI, RustFn}}, number::parser::ParseInteger};
-use super::{closure::Closure, lua_state::LuaState, lua_table::LuaTable};
+use crate::{api::{consts, lua_state::{LuaAPI, RustFn}}, number::{math::FloatToInteger, parser::{ParseFloat, ParseInteger}}};
+use super::{closure::{Closure, RustFunc}, lua_state::LuaState, lua_table::LuaTable, lua_thread::LuaThread, lua_userdata::LuaUserData};
 
 #[derive(Clone)]
 pub enum LuaValue {
@@ -9,9 +9,12 @@ pub enum LuaValue {
     Bool(bool),
     Integer(i64),
     Number(f64),
-    Str(String),
+    Str(Vec<u8>),           // any bytes, not necessarily UTF-8
     Table(Rc<RefCell<LuaTable>>),
     Function(Rc<Closure>),
+    Thread(Rc<RefCell<LuaThread>>),
+    UserData(Rc<LuaUserData>),
+    LightUserData(*mut c_void),     // a pointer of the host, never dereferenced
 }
 
 impl fmt::Debug for LuaValue {
@@ -21,9 +24,12 @@ impl fmt::Debug for LuaValue {
             LuaValue::Bool(b) => write!(f, "({})", b),
             LuaValue::Integer(i) => write!(f, "({})", i),
             LuaValue::Number(n) => write!(f, "({})", n),
-            LuaValue::Str(s) => write!(f, "({})", s),
+            LuaValue::Str(s) => write!(f, "({})", String::from_utf8_lossy(s)),
             LuaValue::Table(tbl) => write!(f, "({:?})", tbl),
             LuaValue::Function(_) => write!(f, "(closure)"),
+            LuaValue::Thread(_) => write!(f, "(thread)"),
+            LuaValue::UserData(_) => write!(f, "(userdata)"),
+            LuaValue::LightUserData(p) => write!(f, "({:p})", p),
         }
     }
 }
@@ -44,7 +50,13 @@ impl PartialEq for LuaValue {
             Rc::ptr_eq(x, y)
         } else if let (LuaValue::Function(x), LuaValue::Function(y)) = (self, other) {
             Rc::ptr_eq(x, y)
-        }else {
+        } else if let (LuaValue::Thread(x), LuaValue::Thread(y)) = (self, other) {
+            Rc::ptr_eq(x, y)
+        } else if let (LuaValue::UserData(x), LuaValue::UserData(y)) = (self, other) {
+            Rc::ptr_eq(x, y)
+        } else if let (LuaValue::LightUserData(x), LuaValue::LightUserData(y)) = (self, other) {
+            x == y
+        } else {
             false
         }
     }
@@ -62,8 +74,11 @@ impl Hash for LuaValue {
             LuaValue::Integer(i) => i.hash(state),
             LuaValue::Number(n) => n.to_bits().hash(state),
             LuaValue::Str(s) => s.hash(state),
-            LuaValue::Table(t) => t.borrow().hash(state),
+            LuaValue::Table(t) => Rc::as_ptr(t).hash(state),
             LuaValue::Function(f) => f.hash(state),
+            LuaValue::Thread(t) => Rc::as_ptr(t).hash(state),
+            LuaValue::UserData(u) => Rc::as_ptr(u).hash(state),
+            LuaValue::LightUserData(p) => p.hash(state),
         }
     }
 }
@@ -78,9 +93,9 @@ impl LuaValue {
             Self::Str(_) => consts::LUA_TSTRING,
             Self::Table(_) => consts::LUA_TTABLE,
             Self::Function(_) => consts::LUA_TFUNCTION,
-            _ => {
-                panic!("todo!");
-            }
+            Self::Thread(_) => consts::LUA_TTHREAD,
+            Self::UserData(_) => consts::LUA_TUSERDATA,
+            Self::LightUserData(_) => consts::LUA_TLIGHTUSERDATA,
         }
     }
 
@@ -97,10 +112,8 @@ impl LuaValue {
             LuaValue::Number(n) => Some(*n),
             LuaValue::Integer(i) => Some(*i as f64),
             LuaValue::Str(s) => {
-                match s.parse::<f64>() {
-                    Ok(val) => Some(val),
-                    _ => None,
-                }
+                let (val, ok) = ParseFloat(std::str::from_utf8(s).ok()?);
+                if ok { Some(val) } else { None }
             }
             _ => None,
         }
@@ -109,13 +122,17 @@ impl LuaValue {
     pub fn ToInteger(&self) -> Option<i64> {
         match self {
             LuaValue::Integer(i) => Some(*i),
-            LuaValue::Number(n) => Some(*n as i64),
+            LuaValue::Number(n) => {
+                let (i, ok) = FloatToInteger(*n);
+                if ok { Some(i) } else { None }
+            },
             LuaValue::Str(s) => {
+                let s = std::str::from_utf8(s).ok()?;
                 let (val, b) = ParseInteger(s);
                 if b {
                     Some(val)
                 } else {
-                    None
+                    LuaValue::Number(ParseFloat(s).0).ToInteger().filter(|_| ParseFloat(s).1)
                 }
             },
             _ => None,
@@ -130,7 +147,7 @@ impl LuaValue {
     }
 
     pub fn newRustClosure(f: RustFn, n_upvals: i32) -> Self {
-        Self::Function(Rc::new(Closure::newRustClosure(f, n_upvals)))
+        Self::Function(Rc::new(Closure::newRustClosure(RustFunc::Fn(f), n_upvals)))
     }
 }
 
@@ -139,9 +156,14 @@ pub fn setMetatable(val: LuaValue, mt: Option<Rc<RefCell<LuaTable>>>, ls: &mut L
         tbl.borrow_mut().metatable = mt;
         return;
     }
+    if let LuaValue::UserData(u) = &val {
+        *u.metatable.borrow_mut() = mt;
+        return;
+    }
     let _key_ = format!("_MT{}", val.typeOf());
     if let LuaValue::Table(tbl) = &ls.registry {
-        tbl.borrow_mut().Put(LuaValue::Str(_key_), val);
+        let mt = mt.map_or(LuaValue::Nil, LuaValue::Table);
+        tbl.borrow_mut().Put(LuaValue::Str(_key_.into_bytes()), mt);
     }
 }
 
@@ -151,7 +173,10 @@ pub fn getMetatable(val: LuaValue, ls: &mut LuaState) -> LuaValue {
             return LuaValue::Table(Rc::clone(r_meta));
         }
     }
-    let _key_ = LuaValue::Str(format!("_MT{}", val.typeOf()));
+    if let LuaValue::UserData(u) = &val {
+        return u.metatable.borrow().clone().map_or(LuaValue::Nil, LuaValue::Table);
+    }
+    let _key_ = LuaValue::Str(format!("_MT{}", val.typeOf()).into_bytes());
     if let LuaValue::Table(tbl) = &ls.registry {
         return tbl.borrow().Get(&_key_);
     }
@@ -160,7 +185,7 @@ pub fn getMetatable(val: LuaValue, ls: &mut LuaState) -> LuaValue {
 
 pub fn getMetafield(val: LuaValue, fieldName: &str, ls: &mut LuaState) -> LuaValue {
     if let LuaValue::Table(tbl) = getMetatable(val, ls) {
-        return tbl.borrow().Get(&LuaValue::Str(String::from(fieldName)));
+        return tbl.borrow().Get(&LuaValue::Str(fieldName.into()));
     }
     LuaValue::Nil
 }
@@ -179,7 +204,7 @@ pub fn callMetamethod(a: LuaValue, b: LuaValue, mmName: &str, ls: &mut LuaState)
     ls.stack_mut().push(_mm_);
     ls.stack_mut().push(a);
     ls.stack_mut().push(b);
-    ls.Call(2, 1);
+    ls.callTM(2, 1);
     Some(ls.stack_mut().pop())
 }
 
@@ -189,8 +214,8 @@ mod tests {
 
     #[test]
     fn test_partial_eq() {
-        let a = LuaValue::Str("10".to_string());
-        let b = LuaValue::Str("10".to_string());
+        let a = LuaValue::Str(b"10".to_vec());
+        let b = LuaValue::Str(b"10".to_vec());
         assert_eq!(&a, &b);
     }
 }
\ No newline at end of file
@@ -3,5 +3,10 @@ pub mod lua_stack;
 pub mod lua_state;
 mod api_arith;
 mod api_compare;
+pub mod auxlib;
 pub mod lua_table;
-pub mod closure;
\ No newline at end of file
+pub mod closure;
+pub mod lua_thread;
+pub mod lua_userdata;
+pub mod lua_gc;
+mod lua_debug;
\ No newline at end of file
@@ -0,0 +1,269 @@
+use std::{env, fs::{self, File, OpenOptions}, io::{self, Read, Seek, SeekFrom, Write}, process};
+
+// A file as seen by the io library, with the buffering of a C `FILE*`:
+// bytes can be looked at before being consumed (to read numbers and
+// lines), and writes are kept until the buffer is full or flushed.
+
+const BUFSIZ: usize = 8192;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BufMode {
+    No,
+    Full,
+    Line,
+}
+
+enum Handle {
+    File(File),
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+pub struct LStream {
+    handle: Option<Handle>,         // None when closed
+    rbuf: Vec<u8>,                  // bytes read ahead
+    rpos: usize,                    // first byte of `rbuf` not consumed yet
+    wbuf: Vec<u8>,                  // bytes not written yet
+    mode: BufMode,
+    bufsize: usize,
+}
+
+impl LStream {
+    fn new(handle: Handle, mode: BufMode) -> Self {
+        LStream {
+            handle: Some(handle),
+            rbuf: vec![],
+            rpos: 0,
+            wbuf: vec![],
+            mode,
+            bufsize: BUFSIZ,
+        }
+    }
+
+    // Opens a file with a mode of C's `fopen`, which must have been checked.
+    pub fn open(filename: &str, mode: &str) -> io::Result<Self> {
+        let mut opts = OpenOptions::new();
+        let plus = mode.contains('+');
+        match mode.as_bytes()[0] {
+            b'r' => opts.read(true).write(plus),
+            b'w' => opts.write(true).create(true).truncate(true).read(plus),
+            _ => opts.append(true).create(true).read(plus),
+        };
+        Ok(Self::new(Handle::File(opts.open(filename)?), BufMode::Full))
+    }
+
+    // A temporary file, removed as soon as it is created so that it goes
+    // away when closed.
+    pub fn tmpfile() -> io::Result<Self> {
+        for i in 0.. {
+            let path = env::temp_dir().join(format!("lua_{}_{}", process::id(), i));
+            match OpenOptions::new().read(true).write(true).create_new(true).open(&path) {
+                Ok(f) => {
+                    let _ = fs::remove_file(&path);
+                    return Ok(Self::new(Handle::File(f), BufMode::Full));
+                },
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!()
+    }
+
+    pub fn stdin() -> Self {
+        Self::new(Handle::Stdin, BufMode::Line)
+    }
+
+    // Writes to the standard output and error go straight to Rust's
+    // handles, so they stay in order with `print`.
+    pub fn stdout() -> Self {
+        Self::new(Handle::Stdout, BufMode::No)
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(Handle::Stderr, BufMode::No)
+    }
+
+    pub fn isClosed(&self) -> bool {
+        self.handle.is_none()
+    }
+
+    pub fn isStd(&self) -> bool {
+        !matches!(self.handle, Some(Handle::File(_)))
+    }
+
+    pub fn close(&mut self) -> io::Result<()> {
+        let res = self.flush();
+        self.handle = None;
+        self.rbuf.clear();
+        self.rpos = 0;
+        res
+    }
+
+    fn handle(&mut self) -> io::Result<&mut Handle> {
+        match self.handle.as_mut() {
+            Some(h) => Ok(h),
+            None => Err(io::Error::other("file is closed")),
+        }
+    }
+
+    // Gets more input, returns false at the end of the file.
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.rpos < self.rbuf.len() {
+            return Ok(true);
+        }
+        self.flushWrites()?;
+        let mut buf = vec![0; self.bufsize.max(1)];
+        let n = match self.handle()? {
+            Handle::File(f) => f.read(&mut buf)?,
+            Handle::Stdin => io::stdin().read(&mut buf)?,
+            _ => return Err(io::Error::from_raw_os_error(9)),   /* EBADF */
+        };
+        buf.truncate(n);
+        self.rbuf = buf;
+        self.rpos = 0;
+        Ok(n > 0)
+    }
+
+    // The next byte, without consuming it.
+    pub fn peek(&mut self) -> io::Result<Option<u8>> {
+        if self.fill()? {
+            Ok(Some(self.rbuf[self.rpos]))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn getc(&mut self) -> io::Result<Option<u8>> {
+        let c = self.peek()?;
+        if c.is_some() {
+            self.rpos += 1;
+        }
+        Ok(c)
+    }
+
+    // Reads a line, with its end of line if `keep_nl`. Returns the bytes
+    // and whether the line ended with a newline.
+    pub fn readLine(&mut self, keep_nl: bool) -> io::Result<(Vec<u8>, bool)> {
+        let mut line = vec![];
+        while self.fill()? {
+            let avail = &self.rbuf[self.rpos..];
+            match avail.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    line.extend_from_slice(&avail[..i + usize::from(keep_nl)]);
+                    self.rpos += i + 1;
+                    return Ok((line, true));
+                },
+                None => {
+                    line.extend_from_slice(avail);
+                    self.rpos = self.rbuf.len();
+                },
+            }
+        }
+        Ok((line, false))
+    }
+
+    // Reads up to `n` bytes, less only at the end of the file.
+    pub fn readChars(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        let mut chars = vec![];
+        while chars.len() < n && self.fill()? {
+            let k = (n - chars.len()).min(self.rbuf.len() - self.rpos);
+            chars.extend_from_slice(&self.rbuf[self.rpos..self.rpos + k]);
+            self.rpos += k;
+        }
+        Ok(chars)
+    }
+
+    pub fn readAll(&mut self) -> io::Result<Vec<u8>> {
+        let mut all = self.rbuf.split_off(self.rpos);
+        self.rbuf.clear();
+        self.rpos = 0;
+        self.flushWrites()?;
+        match self.handle()? {
+            Handle::File(f) => f.read_to_end(&mut all)?,
+            Handle::Stdin => io::stdin().read_to_end(&mut all)?,
+            _ => return Err(io::Error::from_raw_os_error(9)),   /* EBADF */
+        };
+        Ok(all)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.discardReads()?;
+        self.wbuf.extend_from_slice(bytes);
+        let full = match self.mode {
+            BufMode::No => true,
+            BufMode::Line => bytes.contains(&b'\n'),
+            BufMode::Full => self.wbuf.len() >= self.bufsize,
+        };
+        if full || self.isStd() {
+            self.flushWrites()?;
+        }
+        if self.mode == BufMode::No {
+            self.flushHandle()?;
+        }
+        Ok(())
+    }
+
+    fn flushWrites(&mut self) -> io::Result<()> {
+        if self.wbuf.is_empty() {
+            return Ok(());
+        }
+        let wbuf = std::mem::take(&mut self.wbuf);
+        match self.handle()? {
+            Handle::File(f) => f.write_all(&wbuf),
+            Handle::Stdout => io::stdout().write_all(&wbuf),
+            Handle::Stderr => io::stderr().write_all(&wbuf),
+            Handle::Stdin => Err(io::Error::from_raw_os_error(9)),  /* EBADF */
+        }
+    }
+
+    fn flushHandle(&mut self) -> io::Result<()> {
+        match self.handle()? {
+            Handle::File(f) => f.flush(),
+            Handle::Stdout => io::stdout().flush(),
+            Handle::Stderr => io::stderr().flush(),
+            Handle::Stdin => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flushWrites()?;
+        self.flushHandle()
+    }
+
+    // Gives back the bytes read ahead, so that the position of the file is
+    // the one seen by the reader.
+    fn discardReads(&mut self) -> io::Result<()> {
+        let ahead = (self.rbuf.len() - self.rpos) as i64;
+        self.rbuf.clear();
+        self.rpos = 0;
+        if ahead > 0 {
+            if let Handle::File(f) = self.handle()? {
+                f.seek(SeekFrom::Current(-ahead))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.flushWrites()?;
+        self.discardReads()?;
+        match self.handle()? {
+            Handle::File(f) => f.seek(pos),
+            _ => Err(io::Error::from_raw_os_error(29)),     /* ESPIPE */
+        }
+    }
+
+    pub fn setvbuf(&mut self, mode: BufMode, size: usize) -> io::Result<()> {
+        self.flushWrites()?;
+        self.mode = mode;
+        self.bufsize = size;
+        Ok(())
+    }
+}
+
+impl Drop for LStream {
+    fn drop(&mut self) {
+        let _ = self.flush();       /* like C, pending output is written on close */
+    }
+}
@@ -0,0 +1,540 @@
+use std::io::{self, Write};
+
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::LuaAPI};
+use crate::state::lua_state::LuaState;
+use super::lib_table::tabUnpack;
+
+const BASE_FUNCS: &[FuncReg] = &[
+    ("assert", baseAssert),
+    ("collectgarbage", baseCollectGarbage),
+    ("dofile", baseDoFile),
+    ("error", baseError),
+    ("getmetatable", baseGetMetatable),
+    ("ipairs", baseIPairs),
+    ("loadfile", baseLoadFile),
+    ("load", baseLoad),
+    ("next", baseNext),
+    ("pairs", basePairs),
+    ("pcall", basePCall),
+    ("print", basePrint),
+    ("rawequal", baseRawEqual),
+    ("rawlen", baseRawLen),
+    ("rawget", baseRawGet),
+    ("rawset", baseRawSet),
+    ("select", baseSelect),
+    ("setmetatable", baseSetMetatable),
+    ("tonumber", baseToNumber),
+    ("tostring", baseToString),
+    ("type", baseType),
+    ("unpack", tabUnpack),
+    ("xpcall", baseXPCall),
+];
+
+pub fn open_base(ls: &mut LuaState) -> i32 {
+    /* open lib into global table */
+    ls.PushGlobalTable();
+    ls.SetFuncs(BASE_FUNCS, 0);
+    /* set global _G */
+    ls.PushValue(-1);
+    ls.SetField(-2, "_G");
+    /* set global _VERSION */
+    ls.PushString(String::from("Lua 5.3"));
+    ls.SetField(-2, "_VERSION");
+    1
+}
+
+fn basePrint(ls: &mut LuaState) -> i32 {
+    let n = ls.GetTop();        /* number of arguments */
+    let mut out = vec![];
+    for i in 1..=n {
+        if i > 1 {
+            out.push(b'\t');
+        }
+        ls.ToString2(i);
+        out.extend(ls.ToBytes(-1));
+        ls.pop(1);              /* pop result */
+    }
+    out.push(b'\n');
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(&out);
+    let _ = stdout.flush();
+    0
+}
+
+// Converts the digits of `s` in base `base`, like "ff" in base 16. The
+// result wraps around on overflow.
+fn strToInt(s: &str, base: i64) -> Option<i64> {
+    let s = s.trim_matches(|c: char| c == ' ' || ('\t'..='\r').contains(&c));
+    let (neg, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    if digits.is_empty() {      /* no digit? */
+        return None;
+    }
+    let mut n: i64 = 0;
+    for c in digits.chars() {
+        let digit = c.to_digit(36)? as i64;
+        if digit >= base {
+            return None;        /* invalid numeral */
+        }
+        n = n.wrapping_mul(base).wrapping_add(digit);
+    }
+    Some(if neg { n.wrapping_neg() } else { n })
+}
+
+fn baseToNumber(ls: &mut LuaState) -> i32 {
+    if ls.IsNoneOrNil(2) {      /* standard conversion? */
+        if ls.Type(1) == LUA_TNUMBER {  /* already a number? */
+            ls.SetTop(1);       /* yes; return it */
+            return 1;
+        }
+        if ls.Type(1) == LUA_TSTRING {
+            let s = ls.ToString(1);
+            if ls.StringToNumber(&s) {
+                return 1;       /* successful conversion to number */
+            }
+            /* else not a number */
+        }
+        ls.CheckAny(1);         /* (but there must be some parameter) */
+    } else {
+        let base = ls.CheckInteger(2);
+        ls.CheckType(1, LUA_TSTRING);   /* no numbers as strings */
+        let s = ls.ToString(1);
+        ls.ArgCheck((2..=36).contains(&base), 2, "base out of range");
+        if let Some(n) = strToInt(&s, base) {
+            ls.PushInteger(n);
+            return 1;
+        }   /* else not a number */
+    }
+    ls.PushNil();               /* not a number */
+    1
+}
+
+fn baseError(ls: &mut LuaState) -> i32 {
+    let level = ls.OptInteger(2, 1);
+    ls.SetTop(1);
+    if ls.Type(1) == LUA_TSTRING && level > 0 {
+        ls.Where(level as i32);     /* add extra information */
+        ls.PushValue(1);
+        ls.Concat(2);
+    }
+    ls.Error()
+}
+
+fn baseGetMetatable(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    if !ls.GetMetatable(1) {
+        ls.PushNil();
+        return 1;               /* no metatable */
+    }
+    ls.GetMetafield(1, "__metatable");
+    1                           /* returns either __metatable field (if present) or metatable */
+}
+
+fn baseSetMetatable(ls: &mut LuaState) -> i32 {
+    let t = ls.Type(2);
+    ls.CheckType(1, LUA_TTABLE);
+    ls.ArgCheck(t == LUA_TNIL || t == LUA_TTABLE, 2, "nil or table expected");
+    if ls.GetMetafield(1, "__metatable") != LUA_TNIL {
+        return ls.Error2(String::from("cannot change a protected metatable"));
+    }
+    ls.SetTop(2);
+    ls.SetMetatable(1);
+    1
+}
+
+fn baseRawEqual(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    ls.CheckAny(2);
+    let b = ls.RawEqual(1, 2);
+    ls.PushBoolean(b);
+    1
+}
+
+fn baseRawLen(ls: &mut LuaState) -> i32 {
+    let t = ls.Type(1);
+    ls.ArgCheck(t == LUA_TTABLE || t == LUA_TSTRING, 1, "table or string expected");
+    let n = ls.RawLen(1);
+    ls.PushInteger(n as i64);
+    1
+}
+
+fn baseRawGet(ls: &mut LuaState) -> i32 {
+    ls.CheckType(1, LUA_TTABLE);
+    ls.CheckAny(2);
+    ls.SetTop(2);
+    ls.RawGet(1);
+    1
+}
+
+fn baseRawSet(ls: &mut LuaState) -> i32 {
+    ls.CheckType(1, LUA_TTABLE);
+    ls.CheckAny(2);
+    ls.CheckAny(3);
+    ls.SetTop(3);
+    ls.RawSet(1);
+    1
+}
+
+fn baseCollectGarbage(ls: &mut LuaState) -> i32 {
+    const OPTS: &[&str] = &["stop", "restart", "collect", "count", "step", "setpause", "setstepmul", "isrunning"];
+    const OPTSNUM: &[i32] = &[LUA_GCSTOP, LUA_GCRESTART, LUA_GCCOLLECT, LUA_GCCOUNT, LUA_GCSTEP,
+        LUA_GCSETPAUSE, LUA_GCSETSTEPMUL, LUA_GCISRUNNING];
+    let o = OPTSNUM[ls.CheckOption(1, Some("collect"), OPTS)];
+    let ex = ls.OptInteger(2, 0) as i32;
+    let res = ls.GC(o, ex);
+    match o {
+        LUA_GCCOUNT => {
+            let b = ls.GC(LUA_GCCOUNTB, 0);
+            ls.PushNumber(res as f64 + b as f64 / 1024.0);
+        },
+        LUA_GCSTEP | LUA_GCISRUNNING => ls.PushBoolean(res != 0),
+        _ => ls.PushInteger(res as i64),
+    }
+    1
+}
+
+fn baseType(ls: &mut LuaState) -> i32 {
+    let t = ls.Type(1);
+    ls.ArgCheck(t != LUA_TNONE, 1, "value expected");
+    ls.PushString(String::from(ls.TypeName(t)));
+    1
+}
+
+fn loadAux(ls: &mut LuaState, status: i32, envidx: i32) -> i32 {
+    if status == LUA_OK {
+        if envidx != 0 {        /* 'env' parameter? */
+            ls.PushValue(envidx);   /* environment for loaded function */
+            if ls.SetUpvalue(-2, 1).is_none() { /* set it as 1st upvalue */
+                ls.pop(1);      /* remove 'env' if not used by previous call */
+            }
+        }
+        1
+    } else {                    /* error (message is on top of the stack) */
+        ls.PushNil();
+        ls.Insert(-2);          /* put before error message */
+        2                       /* return nil plus error message */
+    }
+}
+
+fn optFileName(ls: &mut LuaState, arg: i32) -> Option<String> {
+    if ls.IsNoneOrNil(arg) {
+        None
+    } else {
+        Some(ls.CheckString(arg))
+    }
+}
+
+fn baseLoadFile(ls: &mut LuaState) -> i32 {
+    let fname = optFileName(ls, 1);
+    let mode = ls.OptString(2, "bt");
+    let env = if !ls.IsNone(3) { 3 } else { 0 };    /* 'env' index or 0 if no 'env' */
+    let status = ls.LoadFileX(fname.as_deref(), &mode);
+    loadAux(ls, status, env)
+}
+
+// Calls the reader function at index 1 until it returns nil or an empty
+// string, and joins the pieces. On errors, the message is left on the
+// stack and the status is returned.
+fn readChunk(ls: &mut LuaState) -> Result<Vec<u8>, i32> {
+    let mut chunk = Vec::new();
+    loop {
+        ls.PushValue(1);        /* get function */
+        let status = ls.PCall(0, 1, 0);     /* call it */
+        if status != LUA_OK {
+            return Err(status);
+        }
+        if ls.IsNil(-1) {
+            ls.pop(1);          /* pop result */
+            return Ok(chunk);   /* no more pieces */
+        } else if !ls.IsString(-1) {
+            ls.pop(1);
+            ls.PushString(String::from("reader function must return a string"));
+            return Err(LUA_ERRSYNTAX);
+        }
+        let piece = ls.CheckBytes(-1);
+        ls.pop(1);
+        if piece.is_empty() {
+            return Ok(chunk);
+        }
+        chunk.extend(piece);
+    }
+}
+
+fn baseLoad(ls: &mut LuaState) -> i32 {
+    let mode = ls.OptString(3, "bt");
+    let env = if !ls.IsNone(4) { 4 } else { 0 };    /* 'env' index or 0 if no 'env' */
+    let status = if ls.IsString(1) {    /* loading a string? */
+        let s = ls.ToString(1);
+        let chunkname = ls.OptString(2, &s);
+        let chunk = ls.CheckBytes(1);
+        ls.Load(chunk, &chunkname, &mode)
+    } else {                    /* loading from a reader function */
+        let chunkname = ls.OptString(2, "=(load)");
+        ls.CheckType(1, LUA_TFUNCTION);
+        match readChunk(ls) {
+            Ok(chunk) => ls.Load(chunk, &chunkname, &mode),
+            Err(status) => status,
+        }
+    };
+    loadAux(ls, status, env)
+}
+
+fn doFileCont(ls: &mut LuaState, _: i32, _: i64) -> i32 {
+    ls.GetTop() - 1
+}
+
+fn baseDoFile(ls: &mut LuaState) -> i32 {
+    let fname = optFileName(ls, 1);
+    ls.SetTop(1);
+    if ls.LoadFileX(fname.as_deref(), "bt") != LUA_OK {
+        return ls.Error();
+    }
+    ls.CallK(0, -1, 0, doFileCont);
+    doFileCont(ls, 0, 0)
+}
+
+fn baseNext(ls: &mut LuaState) -> i32 {
+    ls.CheckType(1, LUA_TTABLE);
+    ls.SetTop(2);               /* create a 2nd argument if there isn't one */
+    if ls.Next(1) {
+        2
+    } else {
+        ls.PushNil();
+        1
+    }
+}
+
+// 'pairs' returns a generator, the state and the initial value, or
+// whatever '__pairs' returns.
+fn basePairs(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    if ls.GetMetafield(1, "__pairs") == LUA_TNIL {  /* no metamethod? */
+        ls.PushRustFunction(baseNext);  /* will return generator, */
+        ls.PushValue(1);                /* state, */
+        ls.PushNil();                   /* and initial value */
+    } else {
+        ls.PushValue(1);                /* argument 'self' to metamethod */
+        ls.Call(1, 3);                  /* get 3 values from metamethod */
+    }
+    3
+}
+
+// Traversal function for 'ipairs'
+fn iPairsAux(ls: &mut LuaState) -> i32 {
+    let i = ls.CheckInteger(2).wrapping_add(1);
+    ls.PushInteger(i);
+    if ls.GetI(1, i) == LUA_TNIL { 1 } else { 2 }
+}
+
+// 'ipairs' function. Returns 'ipairsaux', given "table", 0.
+// (The given "table" may not be a table.)
+fn baseIPairs(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    ls.PushRustFunction(iPairsAux);     /* iteration function */
+    ls.PushValue(1);                    /* state */
+    ls.PushInteger(0);                  /* initial value */
+    3
+}
+
+fn baseAssert(ls: &mut LuaState) -> i32 {
+    if ls.ToBoolean(1) {        /* condition is true? */
+        ls.GetTop()             /* return all arguments */
+    } else {                    /* error */
+        ls.CheckAny(1);         /* there must be a condition */
+        ls.Remove(1);           /* remove it */
+        ls.PushString(String::from("assertion failed!"));   /* default message */
+        ls.SetTop(1);           /* leave only message (default if no other one) */
+        baseError(ls)           /* call 'error' */
+    }
+}
+
+fn baseSelect(ls: &mut LuaState) -> i32 {
+    let n = ls.GetTop() as i64;
+    if ls.Type(1) == LUA_TSTRING && ls.ToString(1).starts_with('#') {
+        ls.PushInteger(n - 1);
+        1
+    } else {
+        let mut i = ls.CheckInteger(1);
+        if i < 0 {
+            i += n;
+        } else if i > n {
+            i = n;
+        }
+        ls.ArgCheck(1 <= i, 1, "index out of range");
+        (n - i) as i32
+    }
+}
+
+// Continuation function for 'pcall' and 'xpcall'. Both functions
+// already pushed a 'true' before doing the call, so in case of success
+// 'finishPCall' only has to return everything in the stack minus
+// 'extra' values (where 'extra' is exactly the number of items to be
+// ignored).
+fn finishPCall(ls: &mut LuaState, status: i32, extra: i64) -> i32 {
+    if status != LUA_OK && status != LUA_YIELD {    /* error? */
+        ls.PushBoolean(false);          /* first result (false) */
+        ls.PushValue(-2);               /* error message */
+        return 2;                       /* return false, msg */
+    }
+    ls.GetTop() - extra as i32          /* return all results */
+}
+
+fn basePCall(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    ls.PushBoolean(true);               /* first result if no errors */
+    ls.Insert(1);                       /* put it in place */
+    let status = ls.PCallK(ls.GetTop() - 2, -1, 0, 0, finishPCall);
+    finishPCall(ls, status, 0)
+}
+
+// Do a protected call with error handling. After 'Rotate', the stack
+// will have <f, err, true, f, [args...]>; so, the function passes
+// 2 to 'finishPCall' to skip the 2 first values when returning results.
+fn baseXPCall(ls: &mut LuaState) -> i32 {
+    let n = ls.GetTop();
+    ls.CheckType(2, LUA_TFUNCTION);     /* check error function */
+    ls.PushBoolean(true);               /* first result */
+    ls.PushValue(1);                    /* function */
+    ls.Rotate(3, 2);                    /* move them below function's arguments */
+    let status = ls.PCallK(n - 2, -1, 2, 2, finishPCall);
+    finishPCall(ls, status, 2)
+}
+
+fn baseToString(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    ls.ToString2(1);
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::test_util::{eval, run};
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(eval("type(nil) .. type(1) .. type('') .. type({}) .. type(print)"), "nilnumberstringtablefunction");
+        assert_eq!(eval("tostring(nil) .. tostring(true) .. tostring(12) .. tostring(1.5)"), "niltrue121.5");
+        assert_eq!(eval("type(tostring(12)) .. type(tostring(1.5))"), "stringstring");
+        assert_eq!(eval("tostring(12) == '12' and tostring(1.5) == '1.5' and tostring(-0.0) == '-0.0' and tostring(1e100) == '1e+100'"), "true");
+        assert_eq!(eval("math.type(tostring(3)) == nil and #tostring(2^63) == 19"), "true");
+        assert_eq!(eval("tostring(setmetatable({}, {__tostring = function () return 'obj' end}))"), "obj");
+        assert_eq!(eval("tostring(setmetatable({}, {__name = 'MyType'})):match('^MyType: 0x%x+$') ~= nil"), "true");
+        assert_eq!(eval("tostring({}):match('^table: 0x%x+$') ~= nil"), "true");
+        assert_eq!(eval("tonumber('0x10') + tonumber('  12  ') + tonumber('1e1')"), "38.0");
+        assert_eq!(eval("math.type(tonumber('10')) .. math.type(tonumber('10.0'))"), "integerfloat");
+        assert_eq!(eval("tonumber('ff', 16) .. tonumber('-zz', 36) .. tonumber('777', 8) .. tonumber('1010', 2)"), "255-129551110");
+        assert_eq!(eval("tonumber('8', 8) == nil and tonumber('') == nil and tonumber('1e') == nil and tonumber({}) == nil"), "true");
+        assert_eq!(run("return tonumber()"), Err(String::from("test:1: bad argument #1 to 'tonumber' (value expected)")));
+        assert_eq!(run("return tonumber('1', 99)"), Err(String::from("test:1: bad argument #2 to 'tonumber' (base out of range)")));
+        assert_eq!(run("return tostring(setmetatable({}, {__tostring = function () return {} end}))"),
+            Err(String::from("test:1: '__tostring' must return a string")));
+    }
+
+    #[test]
+    fn test_select_and_raw_access() {
+        assert_eq!(eval("select('#') .. select('#', nil, nil) .. select(2, 'a', 'b', 'c') .. select(-1, 'a', 'b', 'c')"), "02bc");
+        assert_eq!(run("return select(-3, 1, 2)"), Err(String::from("test:1: bad argument #1 to 'select' (index out of range)")));
+        let out = run(r#"
+            local t = setmetatable({}, {
+                __index = function () return "meta" end,
+                __newindex = function () error("no") end,
+                __len = function () return 42 end,
+                __eq = function () return true end,
+            })
+            rawset(t, "x", 1)
+            return rawget(t, "x") .. tostring(rawget(t, "y")) .. t.y .. rawlen(t) .. #t .. rawlen("abc")
+                .. tostring(rawequal(t, setmetatable({}, getmetatable(t))))
+        "#);
+        assert_eq!(out, Ok(String::from("1nilmeta0423false")));
+        assert_eq!(eval("select('#', unpack({1, 2, 3})) .. unpack({1, 2, 3}, 3)"), "33");
+    }
+
+    #[test]
+    fn test_metatables_and_iteration() {
+        let out = run(r#"
+            local t = setmetatable({}, {__metatable = "locked"})
+            local ok, err = pcall(setmetatable, t, {})
+            local s = ""
+            for i, v in ipairs({"a", "b", nil, "d"}) do s = s .. i .. v end
+            local proxy = setmetatable({}, {__pairs = function (t) return function (_, k)
+                if not k then return 1, "one" end
+            end, t, nil end})
+            for k, v in pairs(proxy) do s = s .. k .. v end
+            local n = 0
+            for _ in pairs({1, 2, x = 3}) do n = n + 1 end
+            return getmetatable(t) .. tostring(ok) .. err .. s .. n .. _VERSION .. tostring(_G == _G._G)
+        "#);
+        assert_eq!(out, Ok(String::from("lockedfalsecannot change a protected metatable1a2b1one3Lua 5.3true")));
+    }
+
+    #[test]
+    fn test_load() {
+        assert_eq!(eval("load('return 1 + 2')()"), "3");
+        assert_eq!(eval("load('x = ...; return x', 'chunk', 't')(5) + x"), "10");
+        let out = run(r#"
+            local parts, i = {"return ", "'read", "er'"}, 0
+            local f = load(function () i = i + 1 return parts[i] end)
+            local env = {y = 7}
+            local g = load("y = y * 2 return y", "=env", "t", env)
+            return f() .. g() .. env.y .. tostring(y)
+        "#);
+        assert_eq!(out, Ok(String::from("reader1414nil")));
+        let out = run(r#"
+            local bin = string.dump(function () return "bin" end)
+            local f1, e1 = load(bin, "b", "t")
+            local f2, e2 = load("return 1", "t", "b")
+            return tostring(f1) .. e1 .. "|" .. tostring(f2) .. e2 .. "|" .. load(bin, "b", "b")()
+        "#);
+        assert_eq!(out, Ok(String::from("nilattempt to load a binary chunk (mode is 't')|\
+            nilattempt to load a text chunk (mode is 'b')|bin")));
+        assert_eq!(eval("select(2, load(function () return {} end))"), "reader function must return a string");
+        assert_eq!(eval("select(2, load(function () error('oops', 0) end))"), "oops");
+        assert_eq!(eval("load('x = = 1') == nil"), "true");
+        assert_eq!(eval("select(2, load('\\27Lua garbage'))"), "binary string: version mismatch in precompiled chunk");
+        assert_eq!(eval("select(2, load(string.dump(function () end):sub(1, 20), '=bin'))"), "bin: truncated precompiled chunk");
+    }
+
+    #[test]
+    fn test_loadfile_and_dofile() {
+        let path = std::env::temp_dir().join("lua_complier_test_dofile.lua");
+        std::fs::write(&path, "#!/usr/bin/lua\nlocal a = ... or 1\nreturn a, a * 2\n").unwrap();
+        let name = path.to_str().unwrap().replace('\\', "/");
+        let out = run(&format!(r#"
+            local x, y = dofile("{0}")
+            local f = loadfile("{0}", "t", {{}})
+            local a, b = f(10)
+            return x .. y .. a .. b
+        "#, name));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(out, Ok(String::from("121020")));
+        let out = run("return select(2, loadfile('/nonexistent/file.lua'))");
+        assert_eq!(out, Ok(String::from("cannot open /nonexistent/file.lua: No such file or directory")));
+        assert_eq!(run("dofile('/nonexistent/file.lua')"),
+            Err(String::from("cannot open /nonexistent/file.lua: No such file or directory")));
+    }
+
+    #[test]
+    fn test_assert_and_errors() {
+        assert_eq!(eval("select('#', assert(1, 2, 3))"), "3");
+        assert_eq!(run("assert(false)"), Err(String::from("test:1: assertion failed!")));
+        assert_eq!(run("assert(nil, 'custom')"), Err(String::from("test:1: custom")));
+        assert_eq!(run("assert()"), Err(String::from("test:1: bad argument #1 to 'assert' (value expected)")));
+        assert_eq!(eval("select(2, pcall(error, {code = 1})).code"), "1");
+        assert_eq!(eval("select(2, xpcall(error, function (m) return 'handled ' .. m end, 'oops'))"), "handled oops");
+        assert_eq!(run("return rawlen(1)"), Err(String::from("test:1: bad argument #1 to 'rawlen' (table or string expected)")));
+        assert_eq!(run("return setmetatable(1, {})"),
+            Err(String::from("test:1: bad argument #1 to 'setmetatable' (table expected, got number)")));
+    }
+
+    #[test]
+    fn test_error_levels() {
+        let src = "local function check(x)\n  if not x then error('no x', LEVEL) end\nend\ncheck()";
+        assert_eq!(run(&src.replace("LEVEL", "1")), Err(String::from("test:2: no x")));
+        assert_eq!(run(&src.replace("LEVEL", "2")), Err(String::from("test:4: no x")));
+        assert_eq!(run(&src.replace("LEVEL", "0")), Err(String::from("no x")));
+        assert_eq!(run(&src.replace("LEVEL", "9")), Err(String::from("no x")));
+        assert_eq!(run("error(42)").unwrap_err(), "42");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::{LuaAPI, LuaUpValueIndex}};
+use crate::state::lua_state::LuaState;
+
+const CO_FUNCS: &[FuncReg] = &[
+    ("create", coCreate),
+    ("resume", coResume),
+    ("yield", coYield),
+    ("status", coStatus),
+    ("isyieldable", coYieldable),
+    ("running", coRunning),
+    ("wrap", coWrap),
+];
+
+pub fn open_coroutine(ls: &mut LuaState) -> i32 {
+    ls.NewLib(CO_FUNCS);
+    1
+}
+
+fn getCo(ls: &mut LuaState) {
+    let is_co = ls.IsThread(1);
+    ls.ArgCheck(is_co, 1, "coroutine expected");
+}
+
+// Resumes the coroutine below the `nArg` arguments on top of the stack.
+// Returns the number of results, or -1 with the error object on top.
+fn auxResume(ls: &mut LuaState, nArg: i32) -> i32 {
+    let base = ls.GetTop() - nArg - 1;
+    let status = ls.Resume(nArg);
+    if status == LUA_OK || status == LUA_YIELD {
+        ls.GetTop() - base
+    } else {
+        -1      /* error flag */
+    }
+}
+
+fn coCreate(ls: &mut LuaState) -> i32 {
+    ls.CheckType(1, LUA_TFUNCTION);
+    ls.NewThread();
+    ls.PushValue(1);        /* move function to top */
+    ls.XMove(-2, 1);        /* move function from ls to the new thread */
+    1
+}
+
+fn coResume(ls: &mut LuaState) -> i32 {
+    getCo(ls);
+    let r = auxResume(ls, ls.GetTop() - 1);
+    if r < 0 {
+        ls.PushBoolean(false);
+        ls.Insert(-2);
+        2                   /* return false + error message */
+    } else {
+        ls.PushBoolean(true);
+        ls.Insert(-(r + 1));
+        r + 1               /* return true + 'resume' returns */
+    }
+}
+
+fn coWrap(ls: &mut LuaState) -> i32 {
+    coCreate(ls);
+    ls.PushGoClosure(auxWrap, 1);
+    1
+}
+
+fn auxWrap(ls: &mut LuaState) -> i32 {
+    let n = ls.GetTop();
+    ls.PushValue(LuaUpValueIndex(1));
+    ls.Insert(1);
+    let r = auxResume(ls, n);
+    if r < 0 {
+        ls.Error();         /* propagate error */
+    }
+    r
+}
+
+fn coYield(ls: &mut LuaState) -> i32 {
+    ls.Yield(ls.GetTop())
+}
+
+fn coStatus(ls: &mut LuaState) -> i32 {
+    getCo(ls);
+    let status = auxStatus(ls);
+    ls.PushString(String::from(status));
+    1
+}
+
+fn auxStatus(ls: &mut LuaState) -> &'static str {
+    ls.PushThread();
+    let running = ls.RawEqual(1, -1);
+    ls.pop(1);
+    if running {
+        return "running";
+    }
+    let co = ls.ToThread(1).unwrap();
+    let co = co.borrow();
+    match co.status {
+        LUA_YIELD => "suspended",
+        LUA_OK if co.frames.len() > 1 => "normal",      /* it is running */
+        LUA_OK if co.frames[0].top > 0 => "suspended",  /* initial state */
+        _ => "dead",                /* it ended or stopped with an error */
+    }
+}
+
+fn coYieldable(ls: &mut LuaState) -> i32 {
+    let yieldable = ls.IsYieldable();
+    ls.PushBoolean(yieldable);
+    1
+}
+
+fn coRunning(ls: &mut LuaState) -> i32 {
+    let is_main = ls.PushThread();
+    ls.PushBoolean(is_main);
+    2
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+    use crate::state::lua_state::LuaState;
+    use super::open_coroutine;
+    use crate::stdlib::test_util::run;
+
+    #[test]
+    fn test_resume_and_yield() {
+        let out = run(r#"
+            local co = coroutine.create(function (a, b)
+                local c = coroutine.yield(a + b)
+                local d, e = coroutine.yield(c * 2)
+                return d + e
+            end)
+            local s = ""
+            local ok, v = coroutine.resume(co, 1, 2)
+            s = s .. v .. coroutine.status(co)
+            ok, v = coroutine.resume(co, 10)
+            s = s .. "," .. v
+            ok, v = coroutine.resume(co, 3, 4)
+            s = s .. "," .. v .. coroutine.status(co)
+            ok, v = coroutine.resume(co)
+            return s .. "," .. (ok and "true" or "false") .. ":" .. v
+        "#).unwrap();
+        assert_eq!(out, "3suspended,20,7dead,false:cannot resume dead coroutine");
+    }
+
+    #[test]
+    fn test_wrap_generator() {
+        let out = run(r#"
+            local function gen(n)
+                return coroutine.wrap(function ()
+                    for i = 1, n do coroutine.yield(i) end
+                end)
+            end
+            local s = ""
+            for i in gen(4) do s = s .. i end
+            return s
+        "#).unwrap();
+        assert_eq!(out, "1234");
+    }
+
+    #[test]
+    fn test_yield_from_metamethods() {
+        // every metamethod yields its event and returns what it is resumed with
+        let out = run(r#"
+            local mt = {}
+            for _, e in ipairs({"index", "lt", "le", "eq", "add", "unm", "len", "concat", "call"}) do
+                mt["__" .. e] = function () return coroutine.yield(e) end
+            end
+            mt.__newindex = function (t, k, v) rawset(t, k, coroutine.yield("newindex") .. v) end
+            local a, b = setmetatable({}, mt), setmetatable({}, mt)
+            local co = coroutine.wrap(function ()
+                local r = {a.x}
+                a.y = 1
+                r[#r + 1] = a.y
+                r[#r + 1] = a < b and "yes" or "no"
+                r[#r + 1] = a <= b and "yes" or "no"
+                r[#r + 1] = a == b and "yes" or "no"
+                r[#r + 1] = a + 1
+                r[#r + 1] = -a
+                r[#r + 1] = #a
+                r[#r + 1] = "<" .. a .. "|" .. b .. ">"
+                r[#r + 1] = a(5)
+                return "done: " .. table.concat(r, " ")
+            end)
+            local replies = {"X", 2, true, false, 1, 3, 4, 5, "C", "D", "called"}
+            local log, v = "", co()
+            for _, reply in ipairs(replies) do
+                log = log .. v .. ";"
+                v = co(reply)
+            end
+            return log .. v
+        "#).unwrap();
+        assert_eq!(out, "index;newindex;lt;le;eq;add;unm;len;concat;concat;call;done: X 21 yes no yes 3 4 5 <D called");
+    }
+
+    #[test]
+    fn test_yield_from_metamethods_in_other_contexts() {
+        let out = run(r#"
+            local obj = setmetatable({}, {__index = coroutine.yield})
+            local env = setmetatable({}, {__index = function (_, k) return coroutine.yield(k) end})
+            local f = load("return missing", "=f", "t", env)
+            local co = coroutine.wrap(function ()
+                local ok, v = pcall(function () return obj.field .. f() end)
+                local lt = setmetatable({}, {__lt = function () return coroutine.yield("lt") end})
+                local sorted = pcall(table.sort, {lt, lt, lt})
+                return tostring(ok) .. " " .. v .. " " .. tostring(sorted)
+            end)
+            local _, k = co()           -- the table and the key are yielded
+            return k .. "," .. co("A") .. "," .. co("B")
+        "#);
+        assert_eq!(out, Ok(String::from("field,missing,true AB false")));
+    }
+
+    #[test]
+    fn test_yield_outside_coroutine() {
+        let mut ls = LuaState::new();
+        ls.RequireF("coroutine", open_coroutine, true);
+        ls.pop(1);
+        ls.Load(b"coroutine.yield(1)".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 0, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "attempt to yield from outside a coroutine");
+    }
+}
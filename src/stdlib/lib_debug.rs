@@ -0,0 +1,508 @@
+use std::{cell::RefCell, ptr, rc::Rc};
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::{LuaAPI, LuaDebug, LuaHook}};
+use crate::state::{lua_state::LuaState, lua_thread::LuaThread};
+
+/* key, in the registry, of the table with the Lua hook of each thread */
+const HOOKKEY: &str = "_HOOKKEY";
+
+const DB_FUNCS: &[FuncReg] = &[
+    ("gethook", dbGetHook),
+    ("getinfo", dbGetInfo),
+    ("getlocal", dbGetLocal),
+    ("getregistry", dbGetRegistry),
+    ("getmetatable", dbGetMetatable),
+    ("getupvalue", dbGetUpvalue),
+    ("upvaluejoin", dbUpvalueJoin),
+    ("upvalueid", dbUpvalueId),
+    ("sethook", dbSetHook),
+    ("setlocal", dbSetLocal),
+    ("setmetatable", dbSetMetatable),
+    ("setupvalue", dbSetUpvalue),
+    ("traceback", dbTraceback),
+];
+
+pub fn open_debug(ls: &mut LuaState) -> i32 {
+    ls.NewLib(DB_FUNCS);
+    1
+}
+
+fn dbGetRegistry(ls: &mut LuaState) -> i32 {
+    ls.PushValue(LUA_REGISTRYINDEX as i32);
+    1
+}
+
+fn dbGetMetatable(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    if !ls.GetMetatable(1) {
+        ls.PushNil();           /* no metatable */
+    }
+    1
+}
+
+fn dbSetMetatable(ls: &mut LuaState) -> i32 {
+    let t = ls.Type(2);
+    ls.ArgCheck(t == LUA_TNIL || t == LUA_TTABLE, 2, "nil or table expected");
+    ls.SetTop(2);
+    ls.SetMetatable(1);
+    1                           /* return 1st argument */
+}
+
+// debug.getupvalue (f, up) and debug.setupvalue (f, up, value)
+fn auxUpvalue(ls: &mut LuaState, get: bool) -> i32 {
+    let n = ls.CheckInteger(2) as i32;  /* upvalue index */
+    ls.CheckType(1, LUA_TFUNCTION);     /* closure */
+    let name = if get { ls.GetUpvalue(1, n) } else { ls.SetUpvalue(1, n) };
+    match name {
+        Some(name) => {
+            ls.PushString(name);
+            if get {
+                ls.Insert(-2);  /* name before the value */
+            }
+            get as i32 + 1
+        },
+        None => 0,
+    }
+}
+
+fn dbGetUpvalue(ls: &mut LuaState) -> i32 {
+    auxUpvalue(ls, true)
+}
+
+fn dbSetUpvalue(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(3);
+    auxUpvalue(ls, false)
+}
+
+// Checks that the function at `argf` has an upvalue `argnup`, and
+// returns its index.
+fn checkUpval(ls: &mut LuaState, argf: i32, argnup: i32) -> i32 {
+    let nup = ls.CheckInteger(argnup) as i32;   /* upvalue index */
+    ls.CheckType(argf, LUA_TFUNCTION);  /* closure */
+    let valid = ls.UpvalueId(argf, nup).is_some();
+    ls.ArgCheck(valid, argnup, "invalid upvalue index");
+    nup
+}
+
+fn dbUpvalueId(ls: &mut LuaState) -> i32 {
+    let n = checkUpval(ls, 1, 2);
+    let id = ls.UpvalueId(1, n).unwrap();
+    ls.PushLightUserData(id);
+    1
+}
+
+fn dbUpvalueJoin(ls: &mut LuaState) -> i32 {
+    let n1 = checkUpval(ls, 1, 2);
+    let n2 = checkUpval(ls, 3, 4);
+    let (c1, c2) = (ls.IsRustFunction(1), ls.IsRustFunction(3));
+    ls.ArgCheck(!c1, 1, "Lua function expected");
+    ls.ArgCheck(!c2, 3, "Lua function expected");
+    ls.UpvalueJoin(1, n1, 3, n2);
+    0
+}
+
+// The thread given as the optional first argument, with the index of the
+// arguments after it.
+fn getThread(ls: &mut LuaState) -> (Option<Rc<RefCell<LuaThread>>>, i32) {
+    if ls.IsThread(1) {
+        (ls.ToThread(1), 1)
+    } else {
+        (None, 0)               /* function will operate over the running thread */
+    }
+}
+
+// Moves the value on the top into field `fname` of the table below it.
+fn treatStackOption(ls: &mut LuaState, fname: &'static str) {
+    ls.Rotate(-2, 1);           /* exchange object and table */
+    ls.SetField(-2, fname);
+}
+
+// debug.getinfo ([thread,] f, [what])
+fn dbGetInfo(ls: &mut LuaState) -> i32 {
+    let (co, arg) = getThread(ls);
+    let mut options = ls.OptString(arg + 2, "flnStu");
+    let mut ar = if ls.IsFunction(arg + 1) {    /* info about a function? */
+        options = format!(">{}", options);      /* add '>' to 'options' */
+        ls.PushValue(arg + 1);  /* push the function for 'GetInfo' */
+        LuaDebug::default()
+    } else {                    /* stack level */
+        let level = ls.CheckInteger(arg + 1) as i32;
+        match ls.GetStack(co.as_ref(), level) {
+            Some(ar) => ar,
+            None => {
+                ls.PushNil();   /* level out of range */
+                return 1;
+            },
+        }
+    };
+    if !ls.GetInfo(&options, &mut ar) {
+        return ls.ArgError(arg + 2, "invalid option");
+    }
+    ls.NewTable();              /* table to collect results */
+    if options.contains('S') {
+        ls.PushString(ar.source);
+        ls.SetField(-2, "source");
+        ls.PushString(ar.short_src);
+        ls.SetField(-2, "short_src");
+        ls.PushInteger(ar.linedefined as i64);
+        ls.SetField(-2, "linedefined");
+        ls.PushInteger(ar.lastlinedefined as i64);
+        ls.SetField(-2, "lastlinedefined");
+        ls.PushString(String::from(ar.what));
+        ls.SetField(-2, "what");
+    }
+    if options.contains('l') {
+        ls.PushInteger(ar.currentline as i64);
+        ls.SetField(-2, "currentline");
+    }
+    if options.contains('u') {
+        ls.PushInteger(ar.nups as i64);
+        ls.SetField(-2, "nups");
+        ls.PushInteger(ar.nparams as i64);
+        ls.SetField(-2, "nparams");
+        ls.PushBoolean(ar.isvararg);
+        ls.SetField(-2, "isvararg");
+    }
+    if options.contains('n') {
+        match ar.name {
+            Some(name) => ls.PushString(name),
+            None => ls.PushNil(),
+        }
+        ls.SetField(-2, "name");
+        ls.PushString(String::from(ar.namewhat));
+        ls.SetField(-2, "namewhat");
+    }
+    if options.contains('t') {
+        ls.PushBoolean(ar.istailcall);
+        ls.SetField(-2, "istailcall");
+    }
+    if options.contains('L') {
+        treatStackOption(ls, "activelines");
+    }
+    if options.contains('f') {
+        treatStackOption(ls, "func");
+    }
+    1                           /* return table */
+}
+
+// debug.getlocal ([thread,] f, local)
+fn dbGetLocal(ls: &mut LuaState) -> i32 {
+    let (co, arg) = getThread(ls);
+    let nvar = ls.CheckInteger(arg + 2) as i32;     /* local-variable index */
+    if ls.IsFunction(arg + 1) { /* function argument? */
+        ls.PushValue(arg + 1);  /* push function */
+        match ls.GetLocal(None, nvar) {     /* push local name */
+            Some(name) => ls.PushString(name),
+            None => ls.PushNil(),
+        }
+        return 1;               /* return only name (there is no value) */
+    }
+    let level = ls.CheckInteger(arg + 1) as i32;
+    let ar = match ls.GetStack(co.as_ref(), level) {
+        Some(ar) => ar,
+        None => return ls.ArgError(arg + 1, "level out of range"),     /* out of range? */
+    };
+    match ls.GetLocal(Some(&ar), nvar) {
+        Some(name) => {
+            ls.PushString(name);    /* push name */
+            ls.Rotate(-2, 1);       /* re-order */
+            2
+        },
+        None => {
+            ls.PushNil();           /* no name (nor value) */
+            1
+        },
+    }
+}
+
+// debug.setlocal ([thread,] level, local, value)
+fn dbSetLocal(ls: &mut LuaState) -> i32 {
+    let (co, arg) = getThread(ls);
+    let level = ls.CheckInteger(arg + 1) as i32;
+    let nvar = ls.CheckInteger(arg + 2) as i32;
+    let ar = match ls.GetStack(co.as_ref(), level) {
+        Some(ar) => ar,
+        None => return ls.ArgError(arg + 1, "level out of range"),     /* out of range? */
+    };
+    ls.CheckAny(arg + 3);
+    ls.SetTop(arg + 3);
+    match ls.SetLocal(&ar, nvar) {
+        Some(name) => ls.PushString(name),
+        None => {
+            ls.pop(1);          /* pop value (if not popped by 'SetLocal') */
+            ls.PushNil();
+        },
+    }
+    1
+}
+
+// Pushes the key of thread `co` (the running one if `None`) in the hook table.
+fn pushThread(ls: &mut LuaState, co: &Option<Rc<RefCell<LuaThread>>>) {
+    match co {
+        Some(_) => ls.PushValue(1),
+        None => {
+            ls.PushThread();
+        },
+    }
+}
+
+// The hook set by `debug.sethook`: calls the Lua function of the thread
+// with the event name and the new line, if any.
+fn hookF(ls: &mut LuaState, ar: &mut LuaDebug) {
+    const HOOKNAMES: [&str; 5] = ["call", "return", "line", "count", "tail call"];
+    ls.GetField(LUA_REGISTRYINDEX as i32, HOOKKEY);
+    ls.PushThread();
+    if ls.RawGet(-2) == LUA_TFUNCTION {    /* is there a hook function? */
+        ls.PushString(String::from(HOOKNAMES[ar.event as usize]));     /* push event name */
+        if ar.currentline >= 0 {
+            ls.PushInteger(ar.currentline as i64);  /* push current line */
+        } else {
+            ls.PushNil();
+        }
+        ls.Call(2, 0);          /* call hook function */
+    }
+}
+
+// The hook mask for the events in `smask` and the `count` of instructions.
+fn makeMask(smask: &str, count: i32) -> i32 {
+    let mut mask = 0;
+    if smask.contains('c') {
+        mask |= LUA_MASKCALL;
+    }
+    if smask.contains('r') {
+        mask |= LUA_MASKRET;
+    }
+    if smask.contains('l') {
+        mask |= LUA_MASKLINE;
+    }
+    if count > 0 {
+        mask |= LUA_MASKCOUNT;
+    }
+    mask
+}
+
+// The events of a hook mask, e.g. "cl".
+fn unmakeMask(mask: i32) -> String {
+    let mut smask = String::new();
+    if mask & LUA_MASKCALL != 0 {
+        smask.push('c');
+    }
+    if mask & LUA_MASKRET != 0 {
+        smask.push('r');
+    }
+    if mask & LUA_MASKLINE != 0 {
+        smask.push('l');
+    }
+    smask
+}
+
+// debug.sethook ([thread,] hook, mask [, count])
+fn dbSetHook(ls: &mut LuaState) -> i32 {
+    let (co, arg) = getThread(ls);
+    let (func, mask, count): (Option<LuaHook>, i32, i32) = if ls.IsNoneOrNil(arg + 1) {  /* no hook? */
+        ls.SetTop(arg + 1);
+        (None, 0, 0)            /* turn off hooks */
+    } else {
+        let smask = ls.CheckString(arg + 2);
+        ls.CheckType(arg + 1, LUA_TFUNCTION);
+        let count = ls.OptInteger(arg + 3, 0) as i32;
+        (Some(hookF), makeMask(&smask, count), count)
+    };
+    ls.GetSubTable(LUA_REGISTRYINDEX as i32, HOOKKEY);     /* hook table */
+    pushThread(ls, &co);        /* key (thread) */
+    ls.PushValue(arg + 1);      /* value (hook function) */
+    ls.RawSet(-3);              /* hooktable[thread] = new Lua hook */
+    ls.SetHook(co.as_ref(), func, mask, count);
+    0
+}
+
+// debug.gethook ([thread])
+fn dbGetHook(ls: &mut LuaState) -> i32 {
+    let (co, _) = getThread(ls);
+    let (hook, mask, count) = ls.GetHook(co.as_ref());
+    match hook {
+        None => ls.PushNil(),   /* no hook? */
+        Some(hook) if !ptr::fn_addr_eq(hook, hookF as LuaHook) => {
+            ls.PushString(String::from("external hook"));  /* external hook? */
+        },
+        Some(_) => {            /* hook table must exist */
+            ls.GetField(LUA_REGISTRYINDEX as i32, HOOKKEY);
+            pushThread(ls, &co);
+            ls.RawGet(-2);      /* 1st result = hooktable[thread] */
+            ls.Remove(-2);      /* remove hook table */
+        },
+    }
+    ls.PushString(unmakeMask(mask));    /* 2nd result = mask */
+    ls.PushInteger(count as i64);       /* 3rd result = count */
+    3
+}
+
+// debug.traceback ([thread,] [message [, level]])
+fn dbTraceback(ls: &mut LuaState) -> i32 {
+    let (co, arg) = getThread(ls);
+    let msg = ls.ToStringX(arg + 1);
+    if msg.is_none() && !ls.IsNoneOrNil(arg + 1) {   /* non-string 'msg'? */
+        ls.PushValue(arg + 1);  /* return it untouched */
+    } else {
+        let level = ls.OptInteger(arg + 2, if co.is_none() { 1 } else { 0 });
+        ls.Traceback(co, msg.as_deref(), level as i32);
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::{LuaAPI, LuaDebug}};
+    use crate::state::lua_state::LuaState;
+    use crate::stdlib::test_util::run_in;
+
+    // chunks are files here, for `source` and `short_src`
+    fn run(src: &str) -> Result<String, String> {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        run_in(&mut ls, src, "@test.lua")
+    }
+
+    #[test]
+    fn test_traceback() {
+        let src = "local function inner()\n  return debug.traceback('msg')\nend\nfunction outer()\n  \
+            local tb = inner()\n  return tb\nend\nreturn outer()";
+        assert_eq!(run(src), Ok(String::from("msg\nstack traceback:\n\
+            \ttest.lua:2: in upvalue 'inner'\n\
+            \ttest.lua:5: in function 'outer'\n\
+            \ttest.lua:8: in main chunk")));
+        assert_eq!(run("return debug.traceback(nil, 2)"), Ok(String::from("stack traceback:")));
+        assert_eq!(run("return (debug.traceback('x', 0):gsub('\\n.*', ''))"), Ok(String::from("x")));
+        assert_eq!(run("return debug.traceback('m', 0)"),
+            Ok(String::from("m\nstack traceback:\n\t[C]: in function 'debug.traceback'\n\ttest.lua:1: in main chunk")));
+        assert_eq!(run("local t = {} return tostring(debug.traceback(t) == t)"), Ok(String::from("true")));
+    }
+
+    #[test]
+    fn test_traceback_of_coroutine_and_deep_stack() {
+        let src = "local co = coroutine.create(function () coroutine.yield() end)\n\
+            coroutine.resume(co)\nreturn debug.traceback(co, 'co')";
+        assert_eq!(run(src), Ok(String::from("co\nstack traceback:\n\
+            \t[C]: in function 'coroutine.yield'\n\ttest.lua:1: in function <test.lua:1>")));
+        let src = "local function f(n) if n == 0 then return debug.traceback() end local s = f(n - 1) return s end\n\
+            return f(30)";
+        let tb = run(src).unwrap();
+        assert_eq!(tb.lines().count(), 1 + 10 + 1 + 11);
+        assert!(tb.contains("\n\t...\n"));
+        assert!(tb.ends_with("in local 'f'\n\ttest.lua:2: in main chunk"));
+    }
+
+    #[test]
+    fn test_getinfo() {
+        let src = "local function f(a, b, ...)\n  return debug.getinfo(1)\nend\nlocal i = f()\n\
+            return table.concat({i.source, i.short_src, i.what, i.currentline, i.linedefined, i.lastlinedefined,\
+            i.name, i.namewhat, i.nups, i.nparams, tostring(i.isvararg), tostring(i.func == f)}, ' ')";
+        assert_eq!(run(src), Ok(String::from("@test.lua test.lua Lua 2 1 3 f local 1 2 true true")));
+        let src = "local i = debug.getinfo(print, 'Su')\n\
+            return table.concat({i.what, i.short_src, i.linedefined, i.nups, tostring(i.isvararg), tostring(i.currentline)}, ' ')";
+        assert_eq!(run(src), Ok(String::from("C [C] -1 0 true nil")));
+        let src = "local function f()\n  local x = 1\n\n  return x\nend\n\
+            local lines = {}\nfor l in pairs(debug.getinfo(f, 'L').activelines) do lines[#lines + 1] = l end\n\
+            table.sort(lines)\nreturn table.concat(lines, ',') .. ' ' .. debug.getinfo(1, 'S').what";
+        assert_eq!(run(src), Ok(String::from("2,4,5 main")));
+        assert_eq!(run("return debug.getinfo(10)"), Ok(String::new()));
+        assert_eq!(run("return debug.getinfo(1, '?')"), Err(String::from("test.lua:1: bad argument #2 to 'getinfo' (invalid option)")));
+    }
+
+    #[test]
+    fn test_getlocal_and_setlocal() {
+        let src = "local function f(a, ...)\n  local b = a * 2\n  local names = {}\n\
+            for i = 1, 3 do names[#names + 1] = select(1, debug.getlocal(1, i)) end\n\
+            local va, v = debug.getlocal(1, -2)\n\
+            debug.setlocal(1, 2, 50)\n\
+            return table.concat(names, ',') .. ' ' .. va .. '=' .. v .. ' ' .. b .. ' ' .. tostring(debug.getlocal(1, -3))\n\
+            end\nreturn f(1, 'x', 'y')";
+        assert_eq!(run(src), Ok(String::from("a,b,names (*vararg)=y 50 nil")));
+        let src = "local function f(p, q) local r end\n\
+            return tostring(debug.getlocal(f, 1)) .. tostring(debug.getlocal(f, 2)) .. tostring(debug.getlocal(f, 3))";
+        assert_eq!(run(src), Ok(String::from("pqnil")));
+        let src = "local co = coroutine.create(function (x) local y = x + 1 coroutine.yield() end)\n\
+            coroutine.resume(co, 41)\n\
+            debug.setlocal(co, 1, 1, 0)\n\
+            return select(2, debug.getlocal(co, 1, 2)) .. ' ' .. select(2, debug.getlocal(co, 1, 1))";
+        assert_eq!(run(src), Ok(String::from("42 0")));
+        assert_eq!(run("return debug.getlocal(5, 1)"), Err(String::from("test.lua:1: bad argument #1 to 'getlocal' (level out of range)")));
+    }
+
+    #[test]
+    fn test_upvalues() {
+        let src = "local a, b = 1, 2\nlocal function f() return a end\nlocal function g() return b end\n\
+            local n1, v1 = debug.getupvalue(f, 1)\n\
+            local n2 = debug.setupvalue(f, 1, 10)\n\
+            local same = debug.upvalueid(f, 1) == debug.upvalueid(g, 1)\n\
+            debug.upvaluejoin(f, 1, g, 1)\n\
+            return table.concat({n1, v1, n2, a, tostring(same), f(), tostring(debug.upvalueid(f, 1) == debug.upvalueid(g, 1)),\
+            select('#', debug.getupvalue(f, 2))}, ' ')";
+        assert_eq!(run(src), Ok(String::from("a 1 a 10 false 2 true 0")));
+        assert_eq!(run("return debug.upvalueid(print, 1)"), Err(String::from("test.lua:1: bad argument #2 to 'upvalueid' (invalid upvalue index)")));
+        assert_eq!(run("local x\nlocal function f() return x end\ndebug.upvaluejoin(f, 1, string.gmatch('', ''), 1)"),
+            Err(String::from("test.lua:3: bad argument #3 to 'upvaluejoin' (Lua function expected)")));
+    }
+
+    #[test]
+    fn test_metatables_and_registry() {
+        let src = "local mt = {__metatable = 'locked'}\nlocal t = setmetatable({}, mt)\n\
+            debug.setmetatable(10, {__index = {twice = function (n) return n * 2 end}})\n\
+            local r = (5):twice()\ndebug.setmetatable(10, nil)\n\
+            return tostring(debug.getmetatable(t) == mt) .. ' ' .. getmetatable(t) .. ' ' .. r .. ' ' ..\
+            tostring(debug.getregistry()._LOADED.debug == debug) .. ' ' .. tostring(debug.getmetatable(1))";
+        assert_eq!(run(src), Ok(String::from("true locked 10 true nil")));
+    }
+
+    #[test]
+    fn test_hooks() {
+        let src = "local events = {}\n\
+            local function f() return 1 end\n\
+            debug.sethook(function (ev, line) events[#events + 1] = ev .. (line or '') end, 'crl')\n\
+            f()\n\
+            debug.sethook()\n\
+            return table.concat(events, ' ')";
+        assert_eq!(run(src), Ok(String::from("return line4 call line2 return line5 call")));
+        let src = "local n = 0\ndebug.sethook(function () n = n + 1 end, '', 1)\n\
+            local h, mask, count = debug.gethook()\nfor i = 1, 10 do end\ndebug.sethook()\n\
+            return tostring(n > 10) .. ' ' .. mask .. ' ' .. count .. ' ' .. tostring(debug.gethook())";
+        assert_eq!(run(src), Ok(String::from("true  1 nil")));
+        let src = "local lines = {}\nlocal co = coroutine.create(function ()\n  local x = 1\n  coroutine.yield()\nend)\n\
+            debug.sethook(co, function (_, l) lines[#lines + 1] = l end, 'l')\n\
+            coroutine.resume(co)\nlocal _, mask = debug.gethook(co)\n\
+            return table.concat(lines, ',') .. ' ' .. mask .. ' ' .. tostring(debug.gethook())";
+        assert_eq!(run(src), Ok(String::from("3,4 l nil")));
+        // an error in a hook is caught like any other, and hooks go on
+        let src = "local n = 0\n\
+            debug.sethook(function (_, l) n = n + 1 if l == 4 then error('in hook') end end, 'l')\n\
+            local ok, err = pcall(function ()\n  return 1\nend)\n\
+            local x = 1\ndebug.sethook()\nreturn tostring(ok) .. ' ' .. err .. ' ' .. n";
+        assert_eq!(run(src), Ok(String::from("false test.lua:2: in hook 6")));
+    }
+
+    #[test]
+    fn test_external_hook() {
+        fn countCalls(ls: &mut LuaState, ar: &mut LuaDebug) {
+            assert_eq!(ar.event, LUA_HOOKCALL);
+            let mut caller = ls.GetStack(None, 0).unwrap();
+            ls.GetInfo("n", &mut caller);
+            if caller.name.as_deref() == Some("f") {
+                ls.GetGlobal("calls");
+                let n = ls.ToInteger(-1);
+                ls.PushInteger(n + 1);
+                ls.SetGlobal("calls");
+            }
+        }
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        ls.PushInteger(0);
+        ls.SetGlobal("calls");
+        ls.SetHook(None, Some(countCalls), LUA_MASKCALL, 0);
+        ls.Load(b"function f() end\nf() f() f()\nreturn debug.gethook()".to_vec(), "=test", "t");
+        assert_eq!(ls.PCall(0, 1, 0), LUA_OK);
+        assert_eq!(ls.ToString(-1), "external hook");
+        ls.SetHook(None, None, LUA_MASKCALL, 0);
+        assert!(ls.GetHook(None).0.is_none());
+        ls.GetGlobal("calls");
+        assert_eq!(ls.ToInteger(-1), 3);
+    }
+}
@@ -0,0 +1,676 @@
+use std::{cell::RefMut, io::{self, SeekFrom}};
+
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::{LuaAPI, LuaUpValueIndex}};
+use crate::number::format::FormatFloat;
+use crate::state::{auxlib::strError, lua_state::LuaState, lua_userdata::UserDataRef};
+use super::io_file::{BufMode, LStream};
+use super::lib_string::pushBytes;
+
+const LUA_FILEHANDLE: &str = "FILE*";
+
+const IO_PREFIX: &str = "_IO_";
+const IO_INPUT: &str = "_IO_input";
+const IO_OUTPUT: &str = "_IO_output";
+
+const MAXARGLINE: i32 = 250;        // maximum number of formats of 'lines'
+const L_MAXLENNUM: usize = 200;     // maximum length of a numeral read by 'read'
+const LUAL_BUFFERSIZE: i64 = 8192;
+
+const IO_FUNCS: &[FuncReg] = &[
+    ("close", ioClose),
+    ("flush", ioFlush),
+    ("input", ioInput),
+    ("lines", ioLines),
+    ("open", ioOpen),
+    ("output", ioOutput),
+    ("popen", ioPopen),
+    ("read", ioRead),
+    ("tmpfile", ioTmpfile),
+    ("type", ioType),
+    ("write", ioWrite),
+];
+
+// Methods for file handles.
+const F_FUNCS: &[FuncReg] = &[
+    ("close", ioClose),
+    ("flush", fFlush),
+    ("lines", fLines),
+    ("read", fRead),
+    ("seek", fSeek),
+    ("setvbuf", fSetvbuf),
+    ("write", fWrite),
+    ("__tostring", fToString),
+];
+
+pub fn open_io(ls: &mut LuaState) -> i32 {
+    ls.NewLib(IO_FUNCS);        /* new module */
+    createMeta(ls);
+    /* create (and set) default files */
+    createStdFile(ls, LStream::stdin(), Some(IO_INPUT), "stdin");
+    createStdFile(ls, LStream::stdout(), Some(IO_OUTPUT), "stdout");
+    createStdFile(ls, LStream::stderr(), None, "stderr");
+    1
+}
+
+fn createMeta(ls: &mut LuaState) {
+    ls.NewMetatable(LUA_FILEHANDLE);    /* create metatable for file handles */
+    ls.PushValue(-1);           /* push metatable */
+    ls.SetField(-2, "__index"); /* metatable.__index = metatable */
+    ls.SetFuncs(F_FUNCS, 0);    /* add file methods to new metatable */
+    ls.pop(1);                  /* pop new metatable */
+}
+
+fn createStdFile(ls: &mut LuaState, f: LStream, k: Option<&'static str>, fname: &'static str) {
+    newFile(ls, f);
+    if let Some(k) = k {
+        ls.PushValue(-1);
+        ls.SetField(LUA_REGISTRYINDEX as i32, k);   /* add file to registry */
+    }
+    ls.SetField(-2, fname);     /* add file to module */
+}
+
+// A file handle, its metatable has been checked.
+type LFile = UserDataRef<LStream>;
+
+fn stream(u: &LFile) -> RefMut<'_, LStream> {
+    u.borrow_mut()
+}
+
+fn isClosed(u: &LFile) -> bool {
+    stream(u).isClosed()
+}
+
+fn newFile(ls: &mut LuaState, f: LStream) {
+    ls.NewUserData(f);
+    ls.SetMetatableByName(LUA_FILEHANDLE);
+}
+
+fn toFile(ls: &mut LuaState) -> LFile {
+    let u = ls.CheckUData(1, LUA_FILEHANDLE);
+    if isClosed(&u) {
+        ls.Error2(String::from("attempt to use a closed file"));
+    }
+    u
+}
+
+// Pushes the results of a file operation: true, or nil plus an error
+// message and code.
+pub(super) fn fileResult(ls: &mut LuaState, res: io::Result<()>, fname: Option<&str>) -> i32 {
+    match res {
+        Ok(()) => {
+            ls.PushBoolean(true);
+            1
+        },
+        Err(e) => {
+            ls.PushNil();
+            let msg = match fname {
+                Some(fname) => format!("{}: {}", fname, strError(&e)),
+                None => strError(&e),
+            };
+            ls.PushString(msg);
+            ls.PushInteger(e.raw_os_error().unwrap_or(0) as i64);
+            3
+        },
+    }
+}
+
+// Checks a mode of C's `fopen`: [rwa]%+?b*
+fn checkMode(mode: &str) -> bool {
+    let mut rest = match mode.strip_prefix(['r', 'w', 'a']) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    rest = rest.strip_prefix('+').unwrap_or(rest);
+    rest.bytes().all(|b| b == b'b')
+}
+
+fn openCheckFile(ls: &mut LuaState, fname: &str, mode: &str) {
+    match LStream::open(fname, mode) {
+        Ok(f) => newFile(ls, f),
+        Err(e) => {
+            ls.Error2(format!("cannot open file '{}' ({})", fname, strError(&e)));
+        },
+    }
+}
+
+// The default file `findex`, which must not be closed.
+fn getIOFile(ls: &mut LuaState, findex: &'static str) -> LFile {
+    ls.GetField(LUA_REGISTRYINDEX as i32, findex);
+    let u = ls.ToUserData(-1).unwrap();
+    if isClosed(&u) {
+        ls.Error2(format!("standard {} file is closed", &findex[IO_PREFIX.len()..]));
+    }
+    u
+}
+
+fn auxClose(ls: &mut LuaState) -> i32 {
+    let u = ls.ToUserData(1).unwrap();
+    if stream(&u).isStd() {     /* standard files are never closed */
+        ls.PushNil();
+        ls.PushString(String::from("cannot close standard file"));
+        return 2;
+    }
+    let res = stream(&u).close();
+    fileResult(ls, res, None)
+}
+
+// io.close([file]) and file:close()
+fn ioClose(ls: &mut LuaState) -> i32 {
+    if ls.IsNone(1) {           /* no argument? */
+        ls.GetField(LUA_REGISTRYINDEX as i32, IO_OUTPUT);   /* use standard output */
+    }
+    toFile(ls);                 /* make sure argument is an open stream */
+    auxClose(ls)
+}
+
+fn fToString(ls: &mut LuaState) -> i32 {
+    let u = ls.CheckUData(1, LUA_FILEHANDLE);
+    let s = if isClosed(&u) {
+        String::from("file (closed)")
+    } else {
+        format!("file ({:p})", ls.ToPointer(1))
+    };
+    ls.PushString(s);
+    1
+}
+
+// io.open(filename [, mode])
+fn ioOpen(ls: &mut LuaState) -> i32 {
+    let filename = ls.CheckString(1);
+    let mode = ls.OptString(2, "r");
+    ls.ArgCheck(checkMode(&mode), 2, "invalid mode");
+    match LStream::open(&filename, &mode) {
+        Ok(f) => {
+            newFile(ls, f);
+            1
+        },
+        Err(e) => fileResult(ls, Err(e), Some(&filename)),
+    }
+}
+
+fn ioPopen(ls: &mut LuaState) -> i32 {
+    ls.Error2(String::from("'popen' not supported"))
+}
+
+fn ioTmpfile(ls: &mut LuaState) -> i32 {
+    match LStream::tmpfile() {
+        Ok(f) => {
+            newFile(ls, f);
+            1
+        },
+        Err(e) => fileResult(ls, Err(e), None),
+    }
+}
+
+// io.type(obj)
+fn ioType(ls: &mut LuaState) -> i32 {
+    ls.CheckAny(1);
+    match ls.TestUData(1, LUA_FILEHANDLE) {
+        None => ls.PushNil(),   /* not a file */
+        Some(u) if isClosed(&u) => ls.PushString(String::from("closed file")),
+        Some(_) => ls.PushString(String::from("file")),
+    }
+    1
+}
+
+fn gIOFile(ls: &mut LuaState, f: &'static str, mode: &str) -> i32 {
+    if !ls.IsNoneOrNil(1) {
+        match ls.ToStringX(1) {
+            Some(filename) => {
+                openCheckFile(ls, &filename, mode);
+            },
+            _ => {
+                toFile(ls);     /* check that it's a valid file handle */
+                ls.PushValue(1);
+            },
+        }
+        ls.SetField(LUA_REGISTRYINDEX as i32, f);
+    }
+    /* return current value */
+    ls.GetField(LUA_REGISTRYINDEX as i32, f);
+    1
+}
+
+// io.input([file])
+fn ioInput(ls: &mut LuaState) -> i32 {
+    gIOFile(ls, IO_INPUT, "r")
+}
+
+// io.output([file])
+fn ioOutput(ls: &mut LuaState) -> i32 {
+    gIOFile(ls, IO_OUTPUT, "w")
+}
+
+/*
+** {======================================================
+** lines
+** =======================================================
+*/
+
+// Creates the iteration function of 'lines', with the file, the number
+// of formats, whether to close the file at the end and the formats as
+// its upvalues.
+fn auxLines(ls: &mut LuaState, toclose: bool) {
+    let n = ls.GetTop() - 1;    /* number of arguments to read */
+    ls.ArgCheck(n <= MAXARGLINE, MAXARGLINE + 2, "too many arguments");
+    ls.PushInteger(n as i64);   /* number of arguments to read */
+    ls.PushBoolean(toclose);    /* close/not close file when finished */
+    ls.Rotate(2, 2);            /* move 'n' and 'toclose' to their positions */
+    ls.PushGoClosure(ioReadline, 3 + n);
+}
+
+// file:lines(...)
+fn fLines(ls: &mut LuaState) -> i32 {
+    toFile(ls);                 /* check that it's a valid file handle */
+    auxLines(ls, false);
+    1
+}
+
+// io.lines([filename, ...])
+fn ioLines(ls: &mut LuaState) -> i32 {
+    if ls.IsNone(1) {
+        ls.PushNil();           /* at least one argument */
+    }
+    let toclose = if ls.IsNil(1) {  /* no file name? */
+        ls.GetField(LUA_REGISTRYINDEX as i32, IO_INPUT);    /* get default input */
+        ls.Replace(1);          /* put it at index 1 */
+        toFile(ls);             /* check that it's a valid file handle */
+        false                   /* do not close it after iteration */
+    } else {                    /* open a new file */
+        let filename = ls.CheckString(1);
+        openCheckFile(ls, &filename, "r");
+        ls.Replace(1);          /* put file at index 1 */
+        true                    /* close it after iteration */
+    };
+    auxLines(ls, toclose);      /* push iteration function */
+    1
+}
+
+fn ioReadline(ls: &mut LuaState) -> i32 {
+    let u = ls.ToUserData(LuaUpValueIndex(1)).unwrap();
+    let n = ls.ToInteger(LuaUpValueIndex(2)) as i32;
+    if isClosed(&u) {           /* file is already closed? */
+        return ls.Error2(String::from("file is already closed"));
+    }
+    ls.SetTop(1);
+    ls.CheckStack(n);
+    for i in 1..=n {            /* push arguments to 'g_read' */
+        ls.PushValue(LuaUpValueIndex(3 + i));
+    }
+    let n = gRead(ls, &u, 2);   /* 'n' is number of results */
+    if ls.ToBoolean(-n) {       /* read at least one value? */
+        return n;               /* return them */
+    }
+    /* first result is nil: EOF or error */
+    if n > 1 {                  /* is there error information? */
+        /* 2nd result is error message */
+        let msg = ls.ToString(-n + 1);
+        return ls.Error2(msg);
+    }
+    if ls.ToBoolean(LuaUpValueIndex(3)) {   /* generator created file? */
+        ls.SetTop(0);
+        ls.PushValue(LuaUpValueIndex(1));
+        auxClose(ls);           /* close it */
+    }
+    0
+}
+
+/* }====================================================== */
+
+/*
+** {======================================================
+** READ
+** =======================================================
+*/
+
+// Reading state of a numeral.
+struct RN<'a> {
+    f: &'a mut LStream,
+    c: Option<u8>,              // current character (look ahead)
+    buff: Vec<u8>,              // up to L_MAXLENNUM characters
+    overflow: bool,
+}
+
+impl RN<'_> {
+    // Adds the current char to the buffer (if not out of space) and
+    // reads the next one.
+    fn nextc(&mut self) -> io::Result<bool> {
+        if self.buff.len() >= L_MAXLENNUM {     /* buffer overflow? */
+            self.overflow = true;               /* invalidate result */
+            return Ok(false);   /* fail */
+        }
+        self.buff.push(self.c.unwrap());    /* save current char */
+        self.f.getc()?;
+        self.c = self.f.peek()?;            /* read next one */
+        Ok(true)
+    }
+
+    // Accepts the current char if it is in `set` (of size 2).
+    fn test2(&mut self, set: &[u8; 2]) -> io::Result<bool> {
+        match self.c {
+            Some(c) if c == set[0] || c == set[1] => self.nextc(),
+            _ => Ok(false),
+        }
+    }
+
+    // Reads a sequence of (hex)digits, returns how many.
+    fn readDigits(&mut self, hex: bool) -> io::Result<usize> {
+        let mut count = 0;
+        while let Some(c) = self.c {
+            let digit = if hex { c.is_ascii_hexdigit() } else { c.is_ascii_digit() };
+            if !(digit && self.nextc()?) {
+                break;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+// Reads a numeral as long as it can look like one, then lets Lua convert
+// it; pushes nil if it is not a valid number.
+fn readNumber(ls: &mut LuaState, f: &mut LStream) -> io::Result<bool> {
+    while let Some(c) = f.peek()? {     /* skip spaces */
+        if !c.is_ascii_whitespace() {
+            break;
+        }
+        f.getc()?;
+    }
+    let c = f.peek()?;
+    let mut rn = RN { f, c, buff: vec![], overflow: false };
+    let mut count = 0;
+    let mut hex = false;
+    rn.test2(b"-+")?;           /* optional signal */
+    if rn.test2(b"00")? {
+        if rn.test2(b"xX")? {
+            hex = true;         /* numeral is hexadecimal */
+        } else {
+            count = 1;          /* count initial '0' as a valid digit */
+        }
+    }
+    count += rn.readDigits(hex)?;   /* integral part */
+    if rn.test2(b"..")? {       /* decimal point? */
+        count += rn.readDigits(hex)?;   /* fractional part */
+    }
+    if count > 0 && rn.test2(if hex { b"pP" } else { b"eE" })? {   /* exponent mark? */
+        rn.test2(b"-+")?;       /* exponent signal */
+        rn.readDigits(false)?;  /* exponent digits */
+    }
+    let s: String = rn.buff.iter().map(|b| *b as char).collect();
+    if !rn.overflow && ls.StringToNumber(&s) {
+        Ok(true)                /* ok */
+    } else {                    /* invalid format */
+        ls.PushNil();           /* "result" to be removed */
+        Ok(false)               /* read fails */
+    }
+}
+
+fn testEof(ls: &mut LuaState, f: &mut LStream) -> io::Result<bool> {
+    let c = f.peek()?;
+    ls.PushString(String::new());
+    Ok(c.is_some())
+}
+
+fn readLine(ls: &mut LuaState, f: &mut LStream, chop: bool) -> io::Result<bool> {
+    let (line, nl) = f.readLine(!chop)?;
+    pushBytes(ls, &line);
+    /* return ok if read something (either a newline or something else) */
+    Ok(nl || !line.is_empty())
+}
+
+fn readAll(ls: &mut LuaState, f: &mut LStream) -> io::Result<bool> {
+    let all = f.readAll()?;
+    pushBytes(ls, &all);
+    Ok(true)
+}
+
+fn readChars(ls: &mut LuaState, f: &mut LStream, n: usize) -> io::Result<bool> {
+    let chars = f.readChars(n)?;
+    pushBytes(ls, &chars);
+    Ok(!chars.is_empty())       /* true iff read something */
+}
+
+fn gRead(ls: &mut LuaState, u: &LFile, first: i32) -> i32 {
+    let mut f = stream(u);
+    let nargs = ls.GetTop() - 1;
+    let mut n = first;
+    let res = (|| -> io::Result<bool> {
+        if nargs == 0 {         /* no arguments? */
+            n = first + 1;      /* to return 1 result */
+            return readLine(ls, &mut f, true);
+        }
+        /* ensure stack space for all results and for auxlib's buffer */
+        ls.CheckStack(nargs + LUA_MINSTACK as i32);
+        let mut success = true;
+        while n < first + nargs && success {
+            if ls.Type(n) == LUA_TNUMBER {
+                let l = ls.CheckInteger(n) as usize;
+                success = if l == 0 { testEof(ls, &mut f)? } else { readChars(ls, &mut f, l)? };
+            } else {
+                let p = ls.CheckString(n);
+                let p = p.strip_prefix('*').unwrap_or(&p);  /* skip optional '*' (for compatibility) */
+                success = match p.as_bytes().first() {
+                    Some(b'n') => readNumber(ls, &mut f)?,  /* number */
+                    Some(b'l') => readLine(ls, &mut f, true)?,  /* line */
+                    Some(b'L') => readLine(ls, &mut f, false)?, /* line with end-of-line */
+                    Some(b'a') => readAll(ls, &mut f)?,     /* read entire file */
+                    _ => {
+                        ls.ArgError(n, "invalid format");
+                        unreachable!()
+                    },
+                };
+            }
+            n += 1;
+        }
+        Ok(success)
+    })();
+    drop(f);
+    match res {
+        Ok(true) => n - first,
+        Ok(false) => {
+            ls.pop(1);          /* remove last result */
+            ls.PushNil();       /* push nil instead */
+            n - first
+        },
+        Err(e) => fileResult(ls, Err(e), None),
+    }
+}
+
+// io.read(...)
+fn ioRead(ls: &mut LuaState) -> i32 {
+    let u = getIOFile(ls, IO_INPUT);
+    gRead(ls, &u, 1)
+}
+
+// file:read(...)
+fn fRead(ls: &mut LuaState) -> i32 {
+    let u = toFile(ls);
+    gRead(ls, &u, 2)
+}
+
+/* }====================================================== */
+
+// Writes the arguments from `arg` on, numbers in C's formats; the file
+// handle must be on the top of the stack to be returned.
+fn gWrite(ls: &mut LuaState, u: &LFile, arg: i32) -> i32 {
+    let nargs = ls.GetTop() - arg;
+    let mut res = Ok(());
+    for arg in arg..arg + nargs {
+        let bytes = if ls.Type(arg) == LUA_TNUMBER {
+            /* optimization: could be done exactly as for strings */
+            let s = if ls.IsInteger(arg) {
+                ls.ToInteger(arg).to_string()
+            } else {
+                FormatFloat(ls.ToNumber(arg), 'g', Some(14), false)
+            };
+            s.into_bytes()
+        } else {
+            ls.CheckBytes(arg)
+        };
+        if res.is_ok() {
+            res = stream(u).write(&bytes);
+        }
+    }
+    match res {
+        Ok(()) => 1,            /* file handle already on stack top */
+        Err(e) => fileResult(ls, Err(e), None),
+    }
+}
+
+// io.write(...)
+fn ioWrite(ls: &mut LuaState) -> i32 {
+    let u = getIOFile(ls, IO_OUTPUT);
+    gWrite(ls, &u, 1)
+}
+
+// file:write(...)
+fn fWrite(ls: &mut LuaState) -> i32 {
+    let u = toFile(ls);
+    ls.PushValue(1);            /* push file at the stack top (to be returned) */
+    gWrite(ls, &u, 2)
+}
+
+// file:seek([whence [, offset]])
+fn fSeek(ls: &mut LuaState) -> i32 {
+    let u = toFile(ls);
+    let op = ls.CheckOption(2, Some("cur"), &["set", "cur", "end"]);
+    let offset = ls.OptInteger(3, 0);
+    let pos = match op {
+        0 if offset < 0 => Err(io::Error::from_raw_os_error(22)),  /* EINVAL */
+        0 => Ok(SeekFrom::Start(offset as u64)),
+        1 => Ok(SeekFrom::Current(offset)),
+        _ => Ok(SeekFrom::End(offset)),
+    };
+    match pos.and_then(|pos| stream(&u).seek(pos)) {
+        Ok(pos) => {
+            ls.PushInteger(pos as i64);
+            1
+        },
+        Err(e) => fileResult(ls, Err(e), None),     /* error */
+    }
+}
+
+// file:setvbuf(mode [, size])
+fn fSetvbuf(ls: &mut LuaState) -> i32 {
+    const MODES: [BufMode; 3] = [BufMode::No, BufMode::Full, BufMode::Line];
+    let u = toFile(ls);
+    let op = ls.CheckOption(2, None, &["no", "full", "line"]);
+    let sz = ls.OptInteger(3, LUAL_BUFFERSIZE);
+    let res = stream(&u).setvbuf(MODES[op], sz.max(0) as usize);
+    fileResult(ls, res, None)
+}
+
+// io.flush()
+fn ioFlush(ls: &mut LuaState) -> i32 {
+    let u = getIOFile(ls, IO_OUTPUT);
+    let res = stream(&u).flush();
+    fileResult(ls, res, None)
+}
+
+// file:flush()
+fn fFlush(ls: &mut LuaState) -> i32 {
+    let u = toFile(ls);
+    let res = stream(&u).flush();
+    fileResult(ls, res, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+    use crate::stdlib::test_util::run;
+
+
+    // A fresh file name in the temporary directory, as a Lua string.
+    fn tmpName(name: &str) -> String {
+        let path = env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        format!("{:?}", path.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_write_read() {
+        let name = tmpName("lua_io_write_read.txt");
+        let src = format!(r#"
+            local f = assert(io.open({0}, "w"))
+            assert(f:write("line 1\n", 42, " ", 1.5, "\n", "0x10 -3e2 .5 abc\n") == f)
+            f:write("last")
+            f:close()
+            f = assert(io.open({0}))
+            local t = {{}}
+            t[#t+1] = f:read("l")
+            t[#t+1] = f:read("L")
+            local a, b, c = f:read("n", "n", "n")
+            t[#t+1], t[#t+2], t[#t+3] = a, b, c
+            t[#t+1] = tostring(f:read("n"))
+            t[#t+1] = f:read(2)
+            t[#t+1] = f:read("a")
+            t[#t+1] = tostring(f:read("l"))
+            t[#t+1] = f:read("a")
+            t[#t+1] = tostring(f:read(0))
+            f:close()
+            return table.concat(t, "|")
+        "#, name);
+        assert_eq!(run(&src), Ok(String::from("line 1|42 1.5\n|16|-300.0|0.5|nil|ab|c\nlast|nil||nil")));
+    }
+
+    #[test]
+    fn test_lines_and_seek() {
+        let name = tmpName("lua_io_lines.txt");
+        let src = format!(r#"
+            local f = assert(io.open({0}, "w+"))
+            f:write("a\nbb\n\nccc")
+            assert(f:seek("set") == 0)
+            local t = {{}}
+            for l in f:lines() do t[#t+1] = "[" .. l .. "]" end
+            assert(f:seek("end") == 9 and f:seek("cur", -3) == 6)
+            t[#t+1] = f:read("a")
+            f:setvbuf("no")
+            f:close()
+            for a, b in io.lines({0}, 1, "l") do t[#t+1] = a .. b end
+            return table.concat(t)
+        "#, name);
+        assert_eq!(run(&src), Ok(String::from("[a][bb][][ccc]cccabb\nccc")));
+    }
+
+    #[test]
+    fn test_default_files() {
+        let name = tmpName("lua_io_default.txt");
+        let src = format!(r#"
+            local out = io.output()
+            assert(io.output({0}) ~= out)
+            io.write("hello ", 1, "\n")
+            io.close()
+            io.output(out)
+            io.input({0})
+            local s = io.read()
+            assert(io.read() == nil)
+            io.input():close()
+            local ok, msg = pcall(io.read)
+            return s .. "|" .. msg .. "|" .. io.type(io.stdout) .. "|" .. io.type(io.input()) .. "|" .. tostring(io.type(42))
+        "#, name);
+        assert_eq!(run(&src), Ok(String::from("hello 1|standard input file is closed|file|closed file|nil")));
+    }
+
+    #[test]
+    fn test_tmpfile_and_errors() {
+        let src = r#"
+            local f = io.tmpfile()
+            f:write("abc")
+            f:seek("set", 1)
+            local s = f:read("a")
+            f:close()
+            local t = {s, tostring(f)}
+            t[#t+1] = select(2, pcall(f.read, f))
+            t[#t+1] = select(2, io.open("/nonexistent/dir/file"))
+            t[#t+1] = select(2, pcall(io.open, "x", "rw"))
+            t[#t+1] = select(2, io.stdout:close())
+            t[#t+1] = select(2, pcall(io.lines, "/nonexistent/file"))
+            t[#t+1] = select(2, pcall(io.read, "x"))
+            return table.concat(t, "|")
+        "#;
+        assert_eq!(run(src), Ok(String::from("bc|file (closed)|attempt to use a closed file|\
+            /nonexistent/dir/file: No such file or directory|bad argument #2 to 'io.open' (invalid mode)|\
+            cannot close standard file|cannot open file '/nonexistent/file' (No such file or directory)|\
+            bad argument #1 to 'io.read' (invalid format)")));
+    }
+}
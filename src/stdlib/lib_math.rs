@@ -0,0 +1,422 @@
+use std::f64::consts::PI;
+
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::{LuaAPI, LuaUpValueIndex}};
+use crate::number::math::FloatToInteger;
+use crate::state::lua_state::LuaState;
+
+const MATH_FUNCS: &[FuncReg] = &[
+    ("abs", mathAbs),
+    ("ceil", mathCeil),
+    ("floor", mathFloor),
+    ("sqrt", mathSqrt),
+    ("sin", mathSin),
+    ("cos", mathCos),
+    ("tan", mathTan),
+    ("asin", mathAsin),
+    ("acos", mathAcos),
+    ("atan", mathAtan),
+    ("exp", mathExp),
+    ("log", mathLog),
+    ("fmod", mathFmod),
+    ("modf", mathModf),
+    ("tointeger", mathToInt),
+    ("type", mathType),
+    ("ult", mathUlt),
+    ("max", mathMax),
+    ("min", mathMin),
+];
+
+// Functions sharing the state of the generator as their upvalue.
+const RAND_FUNCS: &[FuncReg] = &[
+    ("random", mathRandom),
+    ("randomseed", mathRandomSeed),
+];
+
+pub fn open_math(ls: &mut LuaState) -> i32 {
+    ls.NewLib(MATH_FUNCS);
+    ls.PushNumber(PI);
+    ls.SetField(-2, "pi");
+    ls.PushNumber(f64::INFINITY);
+    ls.SetField(-2, "huge");
+    ls.PushInteger(i64::MAX);
+    ls.SetField(-2, "maxinteger");
+    ls.PushInteger(i64::MIN);
+    ls.SetField(-2, "mininteger");
+    setRandFuncs(ls);
+    1
+}
+
+// Pushes a float as an integer when it has an exact representation.
+fn pushNumInt(ls: &mut LuaState, d: f64) {
+    match FloatToInteger(d) {
+        (n, true) => ls.PushInteger(n),
+        _ => ls.PushNumber(d),
+    }
+}
+
+fn mathAbs(ls: &mut LuaState) -> i32 {
+    if ls.IsInteger(1) {
+        let n = ls.ToInteger(1);
+        ls.PushInteger(n.wrapping_abs());
+    } else {
+        let n = ls.CheckNumber(1);
+        ls.PushNumber(n.abs());
+    }
+    1
+}
+
+fn mathFloor(ls: &mut LuaState) -> i32 {
+    if ls.IsInteger(1) {
+        ls.SetTop(1);           /* integer is its own floor */
+    } else {
+        let d = ls.CheckNumber(1).floor();
+        pushNumInt(ls, d);
+    }
+    1
+}
+
+fn mathCeil(ls: &mut LuaState) -> i32 {
+    if ls.IsInteger(1) {
+        ls.SetTop(1);           /* integer is its own ceil */
+    } else {
+        let d = ls.CheckNumber(1).ceil();
+        pushNumInt(ls, d);
+    }
+    1
+}
+
+fn mathSqrt(ls: &mut LuaState) -> i32 {
+    let n = ls.CheckNumber(1);
+    ls.PushNumber(n.sqrt());
+    1
+}
+
+fn mathSin(ls: &mut LuaState) -> i32 {
+    let n = ls.CheckNumber(1);
+    ls.PushNumber(n.sin());
+    1
+}
+
+fn mathCos(ls: &mut LuaState) -> i32 {
+    let n = ls.CheckNumber(1);
+    ls.PushNumber(n.cos());
+    1
+}
+
+fn mathTan(ls: &mut LuaState) -> i32 {
+    let n = ls.CheckNumber(1);
+    ls.PushNumber(n.tan());
+    1
+}
+
+fn mathAsin(ls: &mut LuaState) -> i32 {
+    let n = ls.CheckNumber(1);
+    ls.PushNumber(n.asin());
+    1
+}
+
+fn mathAcos(ls: &mut LuaState) -> i32 {
+    let n = ls.CheckNumber(1);
+    ls.PushNumber(n.acos());
+    1
+}
+
+fn mathAtan(ls: &mut LuaState) -> i32 {
+    let y = ls.CheckNumber(1);
+    let x = ls.OptNumber(2, 1.0);
+    ls.PushNumber(y.atan2(x));
+    1
+}
+
+fn mathExp(ls: &mut LuaState) -> i32 {
+    let n = ls.CheckNumber(1);
+    ls.PushNumber(n.exp());
+    1
+}
+
+fn mathLog(ls: &mut LuaState) -> i32 {
+    let x = ls.CheckNumber(1);
+    let res = if ls.IsNoneOrNil(2) {
+        x.ln()
+    } else {
+        match ls.CheckNumber(2) {
+            2.0 => x.log2(),
+            10.0 => x.log10(),
+            b => x.ln() / b.ln(),
+        }
+    };
+    ls.PushNumber(res);
+    1
+}
+
+fn mathFmod(ls: &mut LuaState) -> i32 {
+    if ls.IsInteger(1) && ls.IsInteger(2) {
+        let d = ls.ToInteger(2);
+        if (d as u64).wrapping_add(1) <= 1 {    /* special cases: -1 or 0 */
+            ls.ArgCheck(d != 0, 2, "zero");
+            ls.PushInteger(0);  /* avoid overflow with 0x80000... / -1 */
+        } else {
+            let m = ls.ToInteger(1);
+            ls.PushInteger(m % d);  /* truncated like C's '%' */
+        }
+    } else {
+        let a = ls.CheckNumber(1);
+        let b = ls.CheckNumber(2);
+        ls.PushNumber(a % b);
+    }
+    1
+}
+
+// next function does not use 'modf', avoiding problems with 'double*'
+// (which is not compatible with 'float*') when lua_Number is not
+// 'double'.
+fn mathModf(ls: &mut LuaState) -> i32 {
+    if ls.IsInteger(1) {
+        ls.SetTop(1);           /* number is its own integer part */
+        ls.PushNumber(0.0);     /* no fractional part */
+    } else {
+        let n = ls.CheckNumber(1);
+        /* integer part (rounds toward zero) */
+        let ip = if n < 0.0 { n.ceil() } else { n.floor() };
+        ls.PushNumber(ip);
+        /* fractional part (test needed for inf/-inf) */
+        ls.PushNumber(if n == ip { 0.0 } else { n - ip });
+    }
+    2
+}
+
+fn mathToInt(ls: &mut LuaState) -> i32 {
+    match ls.ToIntegerX(1) {
+        Some(n) => ls.PushInteger(n),
+        None => {
+            ls.CheckAny(1);
+            ls.PushNil();       /* value is not convertible to integer */
+        },
+    }
+    1
+}
+
+fn mathType(ls: &mut LuaState) -> i32 {
+    if ls.Type(1) == LUA_TNUMBER {
+        let t = if ls.IsInteger(1) { "integer" } else { "float" };
+        ls.PushString(String::from(t));
+    } else {
+        ls.CheckAny(1);
+        ls.PushNil();
+    }
+    1
+}
+
+fn mathUlt(ls: &mut LuaState) -> i32 {
+    let a = ls.CheckInteger(1);
+    let b = ls.CheckInteger(2);
+    ls.PushBoolean((a as u64) < (b as u64));
+    1
+}
+
+// Returns the index of the argument that wins against all the others
+// according to `op`.
+fn auxMinMax(ls: &mut LuaState, op: u8, swap: bool) -> i32 {
+    let n = ls.GetTop();        /* number of arguments */
+    ls.ArgCheck(n >= 1, 1, "value expected");
+    let mut best = 1;           /* index of current best value */
+    ls.CheckNumber(1);
+    for i in 2..=n {
+        ls.CheckNumber(i);
+        let better = if swap { ls.Compare(best, i, op) } else { ls.Compare(i, best, op) };
+        if better {
+            best = i;
+        }
+    }
+    best
+}
+
+fn mathMin(ls: &mut LuaState) -> i32 {
+    let imin = auxMinMax(ls, LUA_OPLT, false);
+    ls.PushValue(imin);
+    1
+}
+
+fn mathMax(ls: &mut LuaState) -> i32 {
+    let imax = auxMinMax(ls, LUA_OPLT, true);
+    ls.PushValue(imax);
+    1
+}
+
+/*
+** {==================================================================
+** Pseudo-Random Number Generator based on 'xoshiro256**'.
+** The state lives in a table shared by 'random' and 'randomseed' as
+** their first upvalue. Unlike the reference implementation, it always
+** starts from the same seed, so runs are reproducible.
+** ===================================================================
+*/
+
+// Reads the state of the generator from the table at `idx`.
+fn getState(ls: &mut LuaState, idx: i32) -> [u64; 4] {
+    let mut state = [0u64; 4];
+    for (i, s) in state.iter_mut().enumerate() {
+        ls.RawGetI(idx, i as i64 + 1);
+        *s = ls.ToInteger(-1) as u64;
+        ls.pop(1);
+    }
+    state
+}
+
+fn putState(ls: &mut LuaState, idx: i32, state: &[u64; 4]) {
+    let idx = ls.AbsIndex(idx);
+    for (i, s) in state.iter().enumerate() {
+        ls.PushInteger(*s as i64);
+        ls.RawSetI(idx, i as i64 + 1);
+    }
+}
+
+fn nextRand(state: &mut [u64; 4]) -> u64 {
+    let res = state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+    let t = state[1] << 17;
+    state[2] ^= state[0];
+    state[3] ^= state[1];
+    state[1] ^= state[2];
+    state[0] ^= state[3];
+    state[2] ^= t;
+    state[3] = state[3].rotate_left(45);
+    res
+}
+
+// Converts a random value to a float in the interval [0, 1), using its
+// 53 higher bits.
+fn randToFloat(rv: u64) -> f64 {
+    (rv >> 11) as f64 * (0.5 / (1u64 << 52) as f64)
+}
+
+// Projects a random value into the interval [0, n], rejecting values
+// outside the smallest power of two that contains the interval, so all
+// results are equally likely.
+fn project(mut ran: u64, n: u64, state: &mut [u64; 4]) -> u64 {
+    if n & n.wrapping_add(1) == 0 {     /* is 'n + 1' a power of 2? */
+        return ran & n;
+    }
+    let mut lim = n;
+    /* compute the smallest (2^b - 1) not smaller than n */
+    lim |= lim >> 1;
+    lim |= lim >> 2;
+    lim |= lim >> 4;
+    lim |= lim >> 8;
+    lim |= lim >> 16;
+    lim |= lim >> 32;
+    loop {
+        ran &= lim;             /* project 'ran' into [0, lim] */
+        if ran <= n {
+            return ran;
+        }
+        ran = nextRand(state);  /* not inside [0, n]? Try again */
+    }
+}
+
+fn seedState(n1: u64, n2: u64) -> [u64; 4] {
+    let mut state = [n1, 0xff, n2, 0];  /* avoid a zero state */
+    for _ in 0..16 {
+        nextRand(&mut state);   /* discard initial values to "spread" seed */
+    }
+    state
+}
+
+fn mathRandom(ls: &mut LuaState) -> i32 {
+    let mut state = getState(ls, LuaUpValueIndex(1));
+    let rv = nextRand(&mut state);
+    let (low, up) = match ls.GetTop() {
+        0 => {                  /* no arguments */
+            putState(ls, LuaUpValueIndex(1), &state);
+            ls.PushNumber(randToFloat(rv));     /* Number between 0 and 1 */
+            return 1;
+        },
+        1 => (1, ls.CheckInteger(1)),   /* only upper limit */
+        2 => (ls.CheckInteger(1), ls.CheckInteger(2)),  /* lower and upper limits */
+        _ => return ls.Error2(String::from("wrong number of arguments")),
+    };
+    /* random integer in the interval [low, up] */
+    ls.ArgCheck(low <= up, 1, "interval is empty");
+    let r = project(rv, (up as u64).wrapping_sub(low as u64), &mut state);
+    putState(ls, LuaUpValueIndex(1), &state);
+    ls.PushInteger(r.wrapping_add(low as u64) as i64);
+    1
+}
+
+fn mathRandomSeed(ls: &mut LuaState) -> i32 {
+    let n1 = if ls.IsInteger(1) {
+        ls.ToInteger(1) as u64
+    } else {
+        ls.CheckNumber(1).to_bits()
+    };
+    let n2 = ls.OptInteger(2, 0) as u64;
+    putState(ls, LuaUpValueIndex(1), &seedState(n1, n2));
+    0
+}
+
+fn setRandFuncs(ls: &mut LuaState) {
+    ls.CreateTable(4, 0);       /* state of the generator */
+    putState(ls, -1, &seedState(0, 0));
+    ls.SetFuncs(RAND_FUNCS, 1);
+}
+
+/* }================================================================== */
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::test_util::{eval, run};
+
+    #[test]
+    fn test_integer_and_float_results() {
+        assert_eq!(eval("math.floor(3.7)"), "3");
+        assert_eq!(eval("math.type(math.floor(3.7))"), "integer");
+        assert_eq!(eval("math.ceil(-3.7)"), "-3");
+        assert_eq!(eval("math.floor(1e100)"), "1e+100");
+        assert_eq!(eval("math.floor(-0.0)"), "0");
+        assert_eq!(eval("math.abs(-3)"), "3");
+        assert_eq!(eval("math.abs(-3.5)"), "3.5");
+        assert_eq!(eval("math.abs(math.mininteger)"), "-9223372036854775808");
+        assert_eq!(eval("math.fmod(7, 3)"), "1");
+        assert_eq!(eval("math.fmod(-7, 3)"), "-1");
+        assert_eq!(eval("math.fmod(7.5, 2)"), "1.5");
+        assert_eq!(eval("math.fmod(math.mininteger, -1)"), "0");
+        assert_eq!(run("local i, f = math.modf(3.7) return i .. (f > 0.69 and f < 0.71 and '' or '?')"),
+            Ok(String::from("3.0")));
+        assert_eq!(eval("math.modf(-3.7)"), "-3.0");
+        assert_eq!(eval("math.modf(5)"), "5");
+        assert_eq!(eval("math.tointeger(3.0)"), "3");
+        assert_eq!(eval("math.tointeger(3.5) == nil and math.tointeger(2^63) == nil"), "true");
+        assert_eq!(eval("math.type(1) .. math.type(1.0) .. (math.type('1') or 'nil')"), "integerfloatnil");
+        assert_eq!(eval("math.ult(1, -1) and not math.ult(-1, 1)"), "true");
+        assert_eq!(eval("math.max(1, 5.5, 3) .. math.min(4, 2, 8) .. math.type(math.max(2, 2.0))"), "5.52integer");
+        assert_eq!(eval("math.maxinteger + 1 == math.mininteger"), "true");
+        assert_eq!(eval("math.huge > 1e308 and -math.huge < -1e308"), "true");
+        assert_eq!(eval("math.sqrt(16) .. math.log(8, 2) .. math.log(100, 10) .. math.exp(0)"), "4.03.02.01.0");
+        assert_eq!(eval("math.sin(0) .. math.cos(0)"), "0.01.0");
+        assert_eq!(eval("math.atan(1, 1) * 4 == math.pi"), "true");
+        assert_eq!(run("return math.fmod(1, 0)"), Err(String::from("test:1: bad argument #2 to 'fmod' (zero)")));
+        assert_eq!(run("return math.max()"), Err(String::from("test:1: bad argument #1 to 'max' (value expected)")));
+        assert_eq!(run("return math.floor('x')"),
+            Err(String::from("test:1: bad argument #1 to 'floor' (number expected, got string)")));
+    }
+
+    #[test]
+    fn test_random() {
+        let seq = "return math.random(100) .. ',' .. math.random(100) .. ',' .. math.random()";
+        assert_eq!(run(seq), run(seq));     /* same seed in every state */
+        let out = run(r#"
+            math.randomseed(42)
+            local a, b = math.random(1, 1000000), math.random()
+            math.randomseed(42)
+            local ok = a == math.random(1, 1000000) and b == math.random()
+            for _ = 1, 1000 do
+                local r, i, j = math.random(), math.random(3), math.random(-2, 2)
+                ok = ok and r >= 0 and r < 1 and i >= 1 and i <= 3 and j >= -2 and j <= 2
+                    and math.type(i) == "integer"
+            end
+            return tostring(ok and math.random(math.mininteger, math.maxinteger) ~= nil)
+        "#);
+        assert_eq!(out, Ok(String::from("true")));
+        assert_eq!(run("return math.random(0)"), Err(String::from("test:1: bad argument #1 to 'random' (interval is empty)")));
+        assert_eq!(run("return math.random(1, 2, 3)"), Err(String::from("test:1: wrong number of arguments")));
+    }
+}
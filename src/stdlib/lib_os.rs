@@ -0,0 +1,514 @@
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::LuaAPI};
+use crate::state::{auxlib::strError, lua_state::LuaState};
+use super::lib_io::fileResult;
+use super::os_host::{OsHost, StdHost, OS_HOST};
+
+const OS_FUNCS: &[FuncReg] = &[
+    ("clock", osClock),
+    ("date", osDate),
+    ("difftime", osDifftime),
+    ("exit", osExit),
+    ("getenv", osGetenv),
+    ("remove", osRemove),
+    ("rename", osRename),
+    ("setlocale", osSetlocale),
+    ("time", osTime),
+    ("tmpname", osTmpname),
+];
+
+// Options of 'strftime' that take no modifier, and those that take 'E'
+// or 'O' (C99).
+const STRFTIME_OPTIONS: &str = "aAbBcCdDeFgGhHIjmMnprRStTuUVwWxXyYzZ%";
+const STRFTIME_E_OPTIONS: &str = "cCxXyY";
+const STRFTIME_O_OPTIONS: &str = "deHImMSuUVwWy";
+
+const L_MAXDATEFIELD: i64 = i32::MAX as i64 / 2;
+
+const DAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December"];
+
+pub fn open_os(ls: &mut LuaState) -> i32 {
+    ls.NewLib(OS_FUNCS);
+    1
+}
+
+// Calls `f` with the host set by the embedder, or the standard one.
+fn withHost<R>(ls: &mut LuaState, f: impl FnOnce(&dyn OsHost) -> R) -> R {
+    ls.GetField(LUA_REGISTRYINDEX as i32, OS_HOST);
+    let u = ls.ToUserData::<Box<dyn OsHost>>(-1);
+    ls.pop(1);
+    match u {
+        Some(u) => f(u.borrow().as_ref()),
+        None => f(&StdHost),
+    }
+}
+
+fn osExit(ls: &mut LuaState) -> i32 {
+    let status = if ls.IsBoolean(1) {
+        if ls.ToBoolean(1) { 0 } else { 1 }     /* EXIT_SUCCESS or EXIT_FAILURE */
+    } else {
+        ls.OptInteger(1, 0) as i32
+    };
+    match withHost(ls, |h| h.exit(status)) {
+        Ok(()) => 0,
+        Err(e) => ls.Error2(strError(&e)),
+    }
+}
+
+fn osGetenv(ls: &mut LuaState) -> i32 {
+    let name = ls.CheckString(1);
+    match withHost(ls, |h| h.getenv(&name)) {
+        Some(v) => ls.PushString(v),
+        None => ls.PushNil(),
+    }
+    1
+}
+
+fn osRemove(ls: &mut LuaState) -> i32 {
+    let filename = ls.CheckString(1);
+    let res = withHost(ls, |h| h.remove(&filename));
+    fileResult(ls, res, Some(&filename))
+}
+
+fn osRename(ls: &mut LuaState) -> i32 {
+    let fromname = ls.CheckString(1);
+    let toname = ls.CheckString(2);
+    let res = withHost(ls, |h| h.rename(&fromname, &toname));
+    fileResult(ls, res, None)
+}
+
+fn osTmpname(ls: &mut LuaState) -> i32 {
+    match withHost(ls, |h| h.tmpname()) {
+        Ok(name) => {
+            ls.PushString(name);
+            1
+        },
+        Err(_) => ls.Error2(String::from("unable to generate a unique filename")),
+    }
+}
+
+fn osClock(ls: &mut LuaState) -> i32 {
+    let clock = withHost(ls, |h| h.clock());
+    ls.PushNumber(clock);
+    1
+}
+
+// Only the "C" locale is available.
+fn osSetlocale(ls: &mut LuaState) -> i32 {
+    let l = ls.OptString(1, "");
+    ls.CheckOption(2, Some("all"), &["all", "collate", "ctype", "monetary", "numeric", "time"]);
+    if ls.IsNoneOrNil(1) || l.is_empty() || l == "C" || l == "POSIX" {
+        ls.PushString(String::from("C"));
+    } else {
+        ls.PushNil();
+    }
+    1
+}
+
+/*
+** {======================================================
+** Time/Date operations
+** { year=%Y, month=%m, day=%d, hour=%H, min=%M, sec=%S,
+**   wday=%w+1, yday=%j, isdst=? }
+** =======================================================
+*/
+
+// A broken-down time, like C's `struct tm` (but with the full year and
+// a month from 1).
+struct Tm {
+    year: i64,
+    month: i64,             // 1..12
+    day: i64,               // 1..31
+    hour: i64,
+    min: i64,
+    sec: i64,
+    wday: i64,              // days since Sunday, 0..6
+    yday: i64,              // days since January 1st, 0..365
+    isdst: bool,
+    gmtoff: i64,            // seconds east of UTC
+    zone: String,
+}
+
+// Days since the epoch of a date of the proleptic Gregorian calendar.
+pub(super) fn daysFromCivil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;                /* [0, 399] */
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;   /* [0, 365] */
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;    /* [0, 146096] */
+    era * 146097 + doe - 719468
+}
+
+pub(super) fn civilFromDays(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;             /* [0, 146096] */
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;    /* [0, 399] */
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);  /* [0, 365] */
+    let mp = (5 * doy + 2) / 153;           /* [0, 11], from March */
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(m <= 2), m, d)
+}
+
+fn breakTime(t: i64, gmtoff: i64, isdst: bool, zone: String) -> Tm {
+    let local = t + gmtoff;
+    let days = local.div_euclid(86400);
+    let secs = local.rem_euclid(86400);
+    let (year, month, day) = civilFromDays(days);
+    Tm {
+        year, month, day,
+        hour: secs / 3600,
+        min: secs / 60 % 60,
+        sec: secs % 60,
+        wday: (days + 4).rem_euclid(7),     /* 1970-01-01 was a Thursday */
+        yday: days - daysFromCivil(year, 1, 1),
+        isdst, gmtoff, zone,
+    }
+}
+
+fn localTime(ls: &mut LuaState, t: i64) -> Tm {
+    let (gmtoff, isdst, zone) = withHost(ls, |h| h.timezone(t));
+    breakTime(t, gmtoff, isdst, zone)
+}
+
+fn gmTime(t: i64) -> Tm {
+    breakTime(t, 0, false, String::from("GMT"))
+}
+
+// Like C's `mktime`: the time of a local date, whose fields may be out
+// of their ranges.
+fn mkTime(ls: &mut LuaState, year: i64, month: i64, day: i64, hour: i64, min: i64, sec: i64) -> i64 {
+    let (year, month) = (year + (month - 1).div_euclid(12), (month - 1).rem_euclid(12) + 1);
+    let local = (daysFromCivil(year, month, 1) + day - 1) * 86400 + hour * 3600 + min * 60 + sec;
+    let off = withHost(ls, |h| h.timezone(local).0);
+    let t = local - off;
+    let off2 = withHost(ls, |h| h.timezone(t).0);   /* offset may change around 't' */
+    if off2 != off { local - off2 } else { t }
+}
+
+fn setField(ls: &mut LuaState, key: &'static str, value: i64) {
+    ls.PushInteger(value);
+    ls.SetField(-2, key);
+}
+
+fn setBoolField(ls: &mut LuaState, key: &'static str, value: bool) {
+    ls.PushBoolean(value);
+    ls.SetField(-2, key);
+}
+
+// Sets all fields of the table on the top with the values of `tm`.
+fn setAllFields(ls: &mut LuaState, tm: &Tm) {
+    setField(ls, "sec", tm.sec);
+    setField(ls, "min", tm.min);
+    setField(ls, "hour", tm.hour);
+    setField(ls, "day", tm.day);
+    setField(ls, "month", tm.month);
+    setField(ls, "year", tm.year);
+    setField(ls, "wday", tm.wday + 1);
+    setField(ls, "yday", tm.yday + 1);
+    setBoolField(ls, "isdst", tm.isdst);
+}
+
+// A field of the date table at index 1, `d` is its default (or
+// negative if it is required).
+fn getField(ls: &mut LuaState, key: &'static str, d: i64) -> i64 {
+    let t = ls.GetField(-1, key);
+    let res = match ls.ToIntegerX(-1) {
+        Some(res) => {
+            if !(-L_MAXDATEFIELD..=L_MAXDATEFIELD).contains(&res) {
+                return ls.Error2(format!("field '{}' is out-of-bound", key)) as i64;
+            }
+            res
+        },
+        None if t != LUA_TNIL => {  /* field is not an integer? */
+            return ls.Error2(format!("field '{}' is not an integer", key)) as i64;
+        },
+        None if d < 0 => {          /* absent field; no default? */
+            return ls.Error2(format!("field '{}' missing in date table", key)) as i64;
+        },
+        None => d,
+    };
+    ls.pop(1);
+    res
+}
+
+// The ISO 8601 week-based year and week number of a date.
+fn isoWeek(tm: &Tm) -> (i64, i64) {
+    /* number of weeks of a year: 53 if it starts on a Thursday, or on a Wednesday in a leap year */
+    let weeks = |y: i64| {
+        let p = |y: i64| (y + y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400)).rem_euclid(7);
+        if p(y) == 4 || p(y - 1) == 3 { 53 } else { 52 }
+    };
+    let wday = (tm.wday + 6) % 7 + 1;       /* 1 (Monday) to 7 */
+    let week = (tm.yday + 1 - wday + 10) / 7;
+    if week < 1 {
+        (tm.year - 1, weeks(tm.year - 1))
+    } else if week > weeks(tm.year) {
+        (tm.year + 1, 1)
+    } else {
+        (tm.year, week)
+    }
+}
+
+// Appends conversion `c` of 'strftime' (in the "C" locale) for `tm`.
+fn strftime(out: &mut String, c: char, tm: &Tm) {
+    let hour12 = if tm.hour % 12 == 0 { 12 } else { tm.hour % 12 };
+    let s = match c {
+        'a' => DAYS[tm.wday as usize][..3].to_string(),
+        'A' => DAYS[tm.wday as usize].to_string(),
+        'b' | 'h' => MONTHS[tm.month as usize - 1][..3].to_string(),
+        'B' => MONTHS[tm.month as usize - 1].to_string(),
+        'c' => return strftimeAll(out, "%a %b %e %H:%M:%S %Y", tm),
+        'C' => format!("{:02}", tm.year.div_euclid(100)),
+        'd' => format!("{:02}", tm.day),
+        'D' | 'x' => return strftimeAll(out, "%m/%d/%y", tm),
+        'e' => format!("{:2}", tm.day),
+        'F' => return strftimeAll(out, "%Y-%m-%d", tm),
+        'g' => format!("{:02}", isoWeek(tm).0.rem_euclid(100)),
+        'G' => isoWeek(tm).0.to_string(),
+        'H' => format!("{:02}", tm.hour),
+        'I' => format!("{:02}", hour12),
+        'j' => format!("{:03}", tm.yday + 1),
+        'm' => format!("{:02}", tm.month),
+        'M' => format!("{:02}", tm.min),
+        'n' => String::from("\n"),
+        'p' => String::from(if tm.hour < 12 { "AM" } else { "PM" }),
+        'r' => return strftimeAll(out, "%I:%M:%S %p", tm),
+        'R' => return strftimeAll(out, "%H:%M", tm),
+        'S' => format!("{:02}", tm.sec),
+        't' => String::from("\t"),
+        'T' | 'X' => return strftimeAll(out, "%H:%M:%S", tm),
+        'u' => ((tm.wday + 6) % 7 + 1).to_string(),
+        'U' => format!("{:02}", (tm.yday + 7 - tm.wday) / 7),
+        'V' => format!("{:02}", isoWeek(tm).1),
+        'w' => tm.wday.to_string(),
+        'W' => format!("{:02}", (tm.yday + 7 - (tm.wday + 6) % 7) / 7),
+        'y' => format!("{:02}", tm.year.rem_euclid(100)),
+        'Y' => tm.year.to_string(),
+        'z' => {
+            let sign = if tm.gmtoff < 0 { '-' } else { '+' };
+            let off = tm.gmtoff.abs() / 60;
+            format!("{}{:02}{:02}", sign, off / 60, off % 60)
+        },
+        'Z' => tm.zone.clone(),
+        _ => String::from("%"),
+    };
+    out.push_str(&s);
+}
+
+// Formats `tm` with a format that has only valid conversions.
+fn strftimeAll(out: &mut String, format: &str, tm: &Tm) {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            strftime(out, chars.next().unwrap(), tm);
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+// Checks the conversion at the start of `conv`, returns its letter and
+// its length (with a modifier).
+fn checkOption(ls: &mut LuaState, conv: &str) -> (char, usize) {
+    let mut chars = conv.chars();
+    let c = chars.next();
+    let valid = match (c, chars.next()) {
+        (Some('E'), Some(c)) if STRFTIME_E_OPTIONS.contains(c) => Some((c, 2)),
+        (Some('O'), Some(c)) if STRFTIME_O_OPTIONS.contains(c) => Some((c, 2)),
+        (Some(c), _) if STRFTIME_OPTIONS.contains(c) => Some((c, 1)),
+        _ => None,
+    };
+    match valid {
+        Some(opt) => opt,
+        None => {
+            ls.ArgError(1, &format!("invalid conversion specifier '%{}'", conv));
+            unreachable!()
+        },
+    }
+}
+
+fn checkTime(ls: &mut LuaState, arg: i32) -> i64 {
+    ls.CheckInteger(arg)
+}
+
+fn osDate(ls: &mut LuaState) -> i32 {
+    let s = ls.OptString(1, "%c");
+    let t = if ls.IsNoneOrNil(2) {
+        withHost(ls, |h| h.time())
+    } else {
+        checkTime(ls, 2)
+    };
+    let tm = match s.strip_prefix('!') {    /* UTC? */
+        Some(_) => gmTime(t),
+        None => localTime(ls, t),
+    };
+    let s = s.strip_prefix('!').unwrap_or(&s);
+    if s == "*t" {
+        ls.CreateTable(0, 9);       /* 9 = number of fields */
+        setAllFields(ls, &tm);
+    } else {
+        let mut out = String::new();
+        let mut rest = s;
+        while let Some(i) = rest.find('%') {
+            out.push_str(&rest[..i]);
+            let (c, len) = checkOption(ls, &rest[i + 1..]);
+            strftime(&mut out, c, &tm);
+            rest = &rest[i + 1 + len..];
+        }
+        out.push_str(rest);
+        ls.PushString(out);
+    }
+    1
+}
+
+fn osTime(ls: &mut LuaState) -> i32 {
+    let t = if ls.IsNoneOrNil(1) {      /* called without args? */
+        withHost(ls, |h| h.time())      /* get current time */
+    } else {
+        ls.CheckType(1, LUA_TTABLE);
+        ls.SetTop(1);               /* make sure table is at the top */
+        let sec = getField(ls, "sec", 0);
+        let min = getField(ls, "min", 0);
+        let hour = getField(ls, "hour", 12);
+        let day = getField(ls, "day", -1);
+        let month = getField(ls, "month", -1);
+        let year = getField(ls, "year", -1);
+        let t = mkTime(ls, year, month, day, hour, min, sec);
+        let tm = localTime(ls, t);
+        setAllFields(ls, &tm);      /* update fields with normalized values */
+        t
+    };
+    ls.PushInteger(t);
+    1
+}
+
+fn osDifftime(ls: &mut LuaState) -> i32 {
+    let t1 = checkTime(ls, 1);
+    let t2 = if ls.IsNoneOrNil(2) { 0 } else { checkTime(ls, 2) };
+    ls.PushNumber(t1 as f64 - t2 as f64);
+    1
+}
+
+/* }====================================================== */
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, env, fs, io, rc::Rc};
+    use crate::stdlib::test_util::run_in;
+
+    use crate::api::lua_auxlib::LuaAuxLib;
+    use crate::state::lua_state::LuaState;
+    use crate::stdlib::os_host::OsHost;
+
+    // Frozen at 2023-11-14 22:13:20 UTC, one hour east of UTC.
+    struct FrozenHost;
+
+    impl OsHost for FrozenHost {
+        fn time(&self) -> i64 {
+            1700000000
+        }
+
+        fn clock(&self) -> f64 {
+            1.5
+        }
+
+        fn timezone(&self, _t: i64) -> (i64, bool, String) {
+            (3600, false, String::from("CET"))
+        }
+
+        fn getenv(&self, name: &str) -> Option<String> {
+            if name == "HOME" { Some(String::from("/home/lua")) } else { None }
+        }
+    }
+
+    // Denies removing files and exiting, counts the attempts.
+    struct SandboxHost(Rc<Cell<i32>>);
+
+    impl OsHost for SandboxHost {
+        fn remove(&self, _filename: &str) -> io::Result<()> {
+            self.0.set(self.0.get() + 1);
+            Err(io::Error::from_raw_os_error(1))    /* EPERM */
+        }
+
+        fn exit(&self, _code: i32) -> io::Result<()> {
+            self.0.set(self.0.get() + 1);
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "exit is not allowed"))
+        }
+    }
+
+    fn run(src: &str, host: Box<dyn OsHost>) -> Result<String, String> {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        ls.SetOsHost(host);
+        run_in(&mut ls, src, "=test")
+    }
+
+    #[test]
+    fn test_frozen_time() {
+        let src = r#"
+            local t = os.date("*t")
+            assert(t.year == 2023 and t.month == 11 and t.day == 14 and t.hour == 23 and t.min == 13)
+            assert(t.sec == 20 and t.wday == 3 and t.yday == 318 and t.isdst == false)
+            assert(os.time(t) == os.time() and os.time() == 1700000000)
+            assert(os.date("!*t").hour == 22)
+            assert(os.clock() == 1.5 and os.getenv("HOME") == "/home/lua" and os.getenv("X") == nil)
+            local tmp = os.tmpname()
+            assert(io.open(tmp)):close()
+            assert(os.rename(tmp, tmp .. ".2") and select(2, os.rename(tmp, tmp)))
+            assert(os.remove(tmp .. ".2") and not os.remove(tmp .. ".2"))
+            return os.date("%c|%x %X|%Y-%j %a %A %b %B %p %I|%U %W %V %G %u %w|%z %Z|%Ey%%")
+        "#;
+        assert_eq!(run(src, Box::new(FrozenHost)),
+            Ok(String::from("Tue Nov 14 23:13:20 2023|11/14/23 23:13:20|2023-318 Tue Tuesday Nov November PM 11|46 46 46 2023 2 2|+0100 CET|23%")));
+    }
+
+    #[test]
+    fn test_time_normalization() {
+        let src = r#"
+            local t = {year = 2020, month = 14, day = 31, hour = 25, min = -1}
+            local n = os.time(t)
+            assert(t.year == 2021 and t.month == 3 and t.day == 4 and t.hour == 0 and t.min == 59)
+            assert(os.difftime(n, os.time{year = 2021, month = 3, day = 3}) == 46740.0)
+            assert(os.date("!%Y-%m-%d %H:%M:%S", 0) == "1970-01-01 00:00:00")
+            assert(os.date("!%F %T", -86401) == "1969-12-30 23:59:59")
+            assert(os.date("!%G-W%V-%u", os.time{year = 2021, month = 1, day = 3}) == "2020-W53-7")
+            local msgs = {}
+            for _, f in ipairs{
+                function() return os.time{year = 2020, month = 1} end,
+                function() return os.time{year = 2020, month = 1, day = 1.5} end,
+                function() return os.time{year = 2020, month = 1, day = 2^40} end,
+                function() return os.date("%Ez") end,
+                function() return os.date("%") end,
+            } do
+                msgs[#msgs+1] = select(2, pcall(f))
+            end
+            return table.concat(msgs, "|")
+        "#;
+        assert_eq!(run(src, Box::new(FrozenHost)), Ok(String::from("test:11: field 'day' missing in date table|\
+            test:12: field 'day' is not an integer|test:13: field 'day' is out-of-bound|\
+            test:14: bad argument #1 to 'date' (invalid conversion specifier '%Ez')|\
+            test:15: bad argument #1 to 'date' (invalid conversion specifier '%')")));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let name = env::temp_dir().join("lua_os_sandbox.txt");
+        fs::write(&name, "x").unwrap();
+        let count = Rc::new(Cell::new(0));
+        let src = format!(r#"
+            local ok, msg, code = os.remove({0:?})
+            assert(ok == nil and code == 1)
+            local ok2, msg2 = pcall(os.exit, 0)
+            assert(not ok2)
+            return msg .. "|" .. msg2
+        "#, name.to_str().unwrap());
+        let res = run(&src, Box::new(SandboxHost(Rc::clone(&count))));
+        assert!(name.exists());
+        assert_eq!(count.get(), 2);
+        assert_eq!(res, Ok(format!("{}: Operation not permitted|exit is not allowed", name.to_str().unwrap())));
+        fs::remove_file(name).unwrap();
+    }
+}
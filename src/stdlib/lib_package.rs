@@ -0,0 +1,360 @@
+use std::{env, fs::File, path::{MAIN_SEPARATOR, MAIN_SEPARATOR_STR}};
+
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::{LuaAPI, LuaUpValueIndex}};
+use crate::state::lua_state::LuaState;
+
+const LUA_PATH_VAR: &str = "LUA_PATH";
+const LUA_PATH_DEFAULT: &str = "/usr/local/share/lua/5.3/?.lua;/usr/local/share/lua/5.3/?/init.lua;\
+    /usr/local/lib/lua/5.3/?.lua;/usr/local/lib/lua/5.3/?/init.lua;./?.lua;./?/init.lua";
+
+const LUA_PATH_SEP: &str = ";";     // separates templates in a path
+const LUA_PATH_MARK: &str = "?";    // the mark substituted by the module name
+const LUA_LSUBSEP: &str = ".";      // replaced by the directory separator in module names
+
+const PK_FUNCS: &[FuncReg] = &[
+    ("loadlib", pkLoadLib),
+    ("searchpath", pkSearchPath),
+];
+
+const LL_FUNCS: &[FuncReg] = &[
+    ("require", llRequire),
+];
+
+const SEARCHERS: &[fn(&mut LuaState) -> i32] = &[searcherPreload, searcherLua, searcherRust];
+
+pub fn open_package(ls: &mut LuaState) -> i32 {
+    ls.NewLib(PK_FUNCS);        /* create 'package' table */
+    createSearchersTable(ls);
+    /* set field 'path' */
+    setPath(ls, "path", LUA_PATH_VAR, LUA_PATH_DEFAULT);
+    /* store config information */
+    let config = format!("{}\n{}\n{}\n!\n-\n", MAIN_SEPARATOR, LUA_PATH_SEP, LUA_PATH_MARK);
+    ls.PushString(config);
+    ls.SetField(-2, "config");
+    /* set field 'loaded' */
+    ls.GetSubTable(LUA_REGISTRYINDEX as i32, "_LOADED");
+    ls.SetField(-2, "loaded");
+    /* set field 'preload' */
+    ls.GetSubTable(LUA_REGISTRYINDEX as i32, "_PRELOAD");
+    ls.SetField(-2, "preload");
+    ls.PushGlobalTable();
+    ls.PushValue(-2);           /* set 'package' as upvalue for next lib */
+    ls.SetFuncs(LL_FUNCS, 1);   /* open lib into global table */
+    ls.pop(1);                  /* pop global table */
+    1                           /* return 'package' table */
+}
+
+// There are no dynamic libraries, native modules are registered from Rust
+// (see `RegisterModule`), so 'loadlib' always fails like in a Lua built
+// without support for them.
+fn pkLoadLib(ls: &mut LuaState) -> i32 {
+    ls.PushNil();
+    ls.PushString(String::from("dynamic libraries not enabled; check your Lua installation"));
+    ls.PushString(String::from("absent"));
+    3                           /* return nil, error message, and where */
+}
+
+/*
+** {======================================================
+** 'require' function
+** =======================================================
+*/
+
+fn readable(filename: &str) -> bool {
+    File::open(filename).is_ok()
+}
+
+// Looks for `name` in the templates of `path`, returns the first file that
+// can be opened, or the list of the files tried.
+fn searchPath(name: &str, path: &str, sep: &str, dirsep: &str) -> Result<String, String> {
+    let name = if !sep.is_empty() {     /* non-empty separator? */
+        name.replace(sep, dirsep)   /* replace it by 'dirsep' */
+    } else {
+        String::from(name)
+    };
+    let mut msg = String::new();    /* to build error message */
+    for template in path.split(LUA_PATH_SEP).filter(|t| !t.is_empty()) {
+        let filename = template.replace(LUA_PATH_MARK, &name);
+        if readable(&filename) {    /* does file exist and is readable? */
+            return Ok(filename);    /* return that file name */
+        }
+        msg.push_str(&format!("\n\tno file '{}'", filename));   /* file is not readable */
+    }
+    Err(msg)                    /* not found */
+}
+
+fn pkSearchPath(ls: &mut LuaState) -> i32 {
+    let name = ls.CheckString(1);
+    let path = ls.CheckString(2);
+    let sep = ls.OptString(3, LUA_LSUBSEP);
+    let dirsep = ls.OptString(4, MAIN_SEPARATOR_STR);
+    match searchPath(&name, &path, &sep, &dirsep) {
+        Ok(filename) => {
+            ls.PushString(filename);
+            1
+        },
+        Err(msg) => {           /* error message is on top of the stack */
+            ls.PushNil();
+            ls.PushString(msg);
+            2                   /* return nil + error message */
+        },
+    }
+}
+
+fn findFile(ls: &mut LuaState, name: &str, pname: &'static str, dirsep: &str) -> Result<String, String> {
+    ls.GetField(LuaUpValueIndex(1), pname);
+    if !ls.IsString(-1) {
+        let msg = format!("'package.{}' must be a string", pname);
+        ls.Error2(msg);
+    }
+    let path = ls.ToString(-1);
+    ls.pop(1);
+    searchPath(name, &path, LUA_LSUBSEP, dirsep)
+}
+
+fn checkLoad(ls: &mut LuaState, stat: bool, filename: String) -> i32 {
+    if stat {                   /* module loaded successfully? */
+        ls.PushString(filename);    /* will be 2nd argument to module */
+        2                       /* return open function and file name */
+    } else {
+        let msg = format!("error loading module '{}' from file '{}':\n\t{}",
+            ls.ToString(1), filename, ls.ToString(-1));
+        ls.Error2(msg)
+    }
+}
+
+fn searcherLua(ls: &mut LuaState) -> i32 {
+    let name = ls.CheckString(1);
+    match findFile(ls, &name, "path", MAIN_SEPARATOR_STR) {
+        Ok(filename) => {
+            let stat = ls.LoadFileX(Some(&filename), "bt") == LUA_OK;
+            checkLoad(ls, stat, filename)
+        },
+        Err(msg) => {
+            ls.PushString(msg);
+            1                   /* module not found in this path */
+        },
+    }
+}
+
+// Looks for a module registered from Rust with `RegisterModule`.
+fn searcherRust(ls: &mut LuaState) -> i32 {
+    let name = ls.CheckString(1);
+    ls.GetSubTable(LUA_REGISTRYINDEX as i32, "_NATIVE");
+    ls.PushString(name.clone());
+    if ls.GetTable(-2) == LUA_TNIL {    /* not found? */
+        ls.PushString(format!("\n\tno native module '{}'", name));
+    }
+    1
+}
+
+fn searcherPreload(ls: &mut LuaState) -> i32 {
+    let name = ls.CheckString(1);
+    ls.GetField(LUA_REGISTRYINDEX as i32, "_PRELOAD");
+    ls.PushString(name.clone());
+    if ls.GetTable(-2) == LUA_TNIL {    /* not found? */
+        ls.PushString(format!("\n\tno field package.preload['{}']", name));
+    }
+    1
+}
+
+fn findLoader(ls: &mut LuaState, name: &str) {
+    /* push 'package.searchers' to index 3 in the stack */
+    if ls.GetField(LuaUpValueIndex(1), "searchers") != LUA_TTABLE {
+        ls.Error2(String::from("'package.searchers' must be a table"));
+    }
+    let mut msg = String::new();    /* to build error message */
+    /*  iterate over available searchers to find a loader */
+    for i in 1.. {
+        if ls.RawGetI(3, i) == LUA_TNIL {   /* no more searchers? */
+            ls.pop(1);          /* remove nil */
+            ls.Error2(format!("module '{}' not found:{}", name, msg));
+        }
+        ls.PushString(String::from(name));
+        ls.Call(1, 2);          /* call it */
+        if ls.IsFunction(-2) {  /* did it find a loader? */
+            return;             /* module loader found */
+        } else if ls.IsString(-2) {     /* searcher returned error message? */
+            ls.pop(1);          /* remove extra return */
+            msg.push_str(&ls.ToString(-1));     /* concatenate error message */
+            ls.pop(1);
+        } else {
+            ls.pop(2);          /* remove both returns */
+        }
+    }
+}
+
+// Runs the loader below the name and the loader data on top of the stack.
+// Modules being loaded are marked in the registry, so that a module that
+// (indirectly) requires itself gets an error instead of recursing forever.
+fn callLoader(ls: &mut LuaState, name: &str) {
+    ls.GetSubTable(LUA_REGISTRYINDEX as i32, "_LOADING");
+    ls.PushString(String::from(name));
+    if ls.RawGet(-2) != LUA_TNIL {
+        ls.Error2(format!("loop or previous error loading module '{}'", name));
+    }
+    ls.pop(1);
+    ls.PushString(String::from(name));
+    ls.PushBoolean(true);
+    ls.RawSet(-3);              /* LOADING[name] = true */
+    ls.Insert(-4);              /* put LOADING below the loader */
+    let status = ls.PCall(2, 1, 0);
+    ls.PushString(String::from(name));
+    ls.PushNil();
+    ls.RawSet(-4);              /* LOADING[name] = nil */
+    ls.Remove(-2);              /* remove LOADING */
+    if status != LUA_OK {
+        ls.Error();             /* propagate the error of the loader */
+    }
+}
+
+fn llRequire(ls: &mut LuaState) -> i32 {
+    let name = ls.CheckString(1);
+    ls.SetTop(1);               /* LOADED table will be at index 2 */
+    ls.GetField(LUA_REGISTRYINDEX as i32, "_LOADED");
+    ls.PushString(name.clone());
+    ls.GetTable(2);             /* LOADED[name] */
+    if ls.ToBoolean(-1) {       /* is it there? */
+        return 1;               /* package is already loaded */
+    }
+    /* else must load package */
+    ls.pop(1);                  /* remove 'getfield' result */
+    findLoader(ls, &name);
+    ls.PushString(name.clone());    /* pass name as argument to module loader */
+    ls.Insert(-2);              /* name is 1st argument (before search data) */
+    callLoader(ls, &name);      /* run loader to load module */
+    if !ls.IsNil(-1) {          /* non-nil return? */
+        ls.PushString(name.clone());
+        ls.Insert(-2);
+        ls.SetTable(2);         /* LOADED[name] = returned value */
+    }
+    ls.PushString(name.clone());
+    if ls.GetTable(2) == LUA_TNIL {     /* module set no value? */
+        ls.pop(1);
+        ls.PushString(name);
+        ls.PushBoolean(true);   /* use true as result */
+        ls.SetTable(2);         /* LOADED[name] = true */
+        ls.PushBoolean(true);
+    }
+    1
+}
+
+/* }====================================================== */
+
+// Sets the path `fieldname` from the environment variable `envname`
+// (or its versioned variant), using `def` when it is not set. A ";;" in
+// the variable is replaced by the default path.
+fn setPath(ls: &mut LuaState, fieldname: &'static str, envname: &str, def: &str) {
+    let path = env::var(format!("{}_5_3", envname)).or_else(|_| env::var(envname));
+    let path = match path {
+        Err(_) => String::from(def),    /* use default */
+        Ok(path) => {
+            /* path contains a ";;": insert default path in its place */
+            let path = path.replacen(";;", &format!(";{};", def), 1);
+            String::from(path.trim_matches(';'))
+        },
+    };
+    ls.PushString(path);
+    ls.SetField(-2, fieldname);
+}
+
+fn createSearchersTable(ls: &mut LuaState) {
+    /* create 'searchers' table */
+    ls.CreateTable(SEARCHERS.len() as i32, 0);
+    /* fill it with predefined searchers */
+    for (i, searcher) in SEARCHERS.iter().enumerate() {
+        ls.PushValue(-2);       /* set 'package' as upvalue for all searchers */
+        ls.PushGoClosure(*searcher, 1);
+        ls.RawSetI(-2, i as i64 + 1);
+    }
+    ls.SetField(-2, "searchers");   /* put it in field 'searchers' */
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+    use crate::stdlib::test_util::run_in;
+
+    use crate::api::{lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+    use crate::compiler::error::chunk_id;
+    use crate::state::lua_state::LuaState;
+
+    fn run(src: &str) -> Result<String, String> {
+        let mut ls = LuaState::new();
+        ls.OpenLibs();
+        ls.RegisterModule("native", |ls| {
+            ls.NewTable();
+            ls.PushValue(1);
+            ls.SetField(-2, "name");
+            1
+        });
+        run_in(&mut ls, src, "=test")
+    }
+
+    // Writes the modules to a fresh directory, returns a 'package.path'
+    // for it.
+    fn writeModules(dir: &str, modules: &[(&str, &str)]) -> (PathBuf, String) {
+        let root = env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&root);
+        for (file, src) in modules {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, src).unwrap();
+        }
+        let path = format!("{0}/?.lua;{0}/?/init.lua", root.to_str().unwrap().replace('\\', "/"));
+        (root, path)
+    }
+
+    #[test]
+    fn test_require_lua_files() {
+        let (root, path) = writeModules("lua_complier_test_require", &[
+            ("counter.lua", "loads = (loads or 0) + 1 return {name = ..., file = select(2, ...)}"),
+            ("pkg/init.lua", "return {sub = require('pkg.sub')}"),
+            ("pkg/sub.lua", "return 'sub of ' .. ..."),
+            ("noreturn.lua", "x = 1"),
+        ]);
+        let out = run(&format!(r#"
+            package.path = "{}"
+            local a, b = require("counter"), require("counter")
+            local file = a.file:match("counter%.lua$")
+            return tostring(a == b) .. loads .. a.name .. file .. require("pkg").sub
+                .. tostring(require("noreturn")) .. tostring(package.loaded.noreturn)
+        "#, path));
+        fs::remove_dir_all(root).unwrap();
+        assert_eq!(out, Ok(String::from("true1countercounter.luasub of pkg.subtruetrue")));
+    }
+
+    #[test]
+    fn test_preload_native_and_searchpath() {
+        let out = run(r#"
+            package.preload.pre = function (name, extra) return name .. tostring(extra) end
+            local n = require("native")
+            local f, err = package.searchpath("a.b", "./?.x;/nonexistent/?.y")
+            return require("pre") .. n.name .. tostring(f) .. err .. tostring(require("string") == string)
+        "#);
+        assert_eq!(out, Ok(String::from("prenilnativenil\n\tno file './a/b.x'\n\tno file '/nonexistent/a/b.y'true")));
+    }
+
+    #[test]
+    fn test_require_errors() {
+        let (root, path) = writeModules("lua_complier_test_require_errors", &[
+            ("a.lua", "return require('b')"),
+            ("b.lua", "return require('a')"),
+            ("bad.lua", "error('failed')"),
+        ]);
+        let out = run(&format!(r#"
+            package.path = "{}"
+            local ok1, e1 = pcall(require, "a")
+            local ok2, e2 = pcall(require, "bad")
+            local ok3, e3 = pcall(require, "bad")
+            return e1 .. "|" .. e2 .. "|" .. e3 .. tostring(package.loaded.a)
+        "#, path));
+        let src = |file: &str| chunk_id(&format!("@{}", path.split(';').next().unwrap().replace("?.lua", file)));
+        fs::remove_dir_all(root).unwrap();
+        assert_eq!(out, Ok(format!("{}:1: loop or previous error loading module 'a'|{}:1: failed|{1}:1: failednil",
+            src("b.lua"), src("bad.lua"))));
+        let out = run("package.path = './?.none' return select(2, pcall(require, 'missing'))");
+        assert_eq!(out, Ok(String::from("module 'missing' not found:\n\tno field package.preload['missing']\
+            \n\tno file './missing.none'\n\tno native module 'missing'")));
+    }
+}
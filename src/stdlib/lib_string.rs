@@ -0,0 +1,761 @@
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::{LuaAPI, LuaUpValueIndex}};
+use crate::number::format::FormatFloat;
+use crate::state::lua_state::LuaState;
+use super::str_pack::{strPack, strPackSize, strUnpack};
+use super::str_pattern::{findPlain, noSpecials, Capture, MatchResult, MatchState};
+
+const STR_FUNCS: &[FuncReg] = &[
+    ("byte", strByte),
+    ("char", strChar),
+    ("dump", strDump),
+    ("find", strFind),
+    ("format", strFormat),
+    ("gmatch", strGmatch),
+    ("gsub", strGsub),
+    ("len", strLen),
+    ("lower", strLower),
+    ("match", strMatch),
+    ("rep", strRep),
+    ("reverse", strReverse),
+    ("sub", strSub),
+    ("upper", strUpper),
+    ("pack", strPack),
+    ("packsize", strPackSize),
+    ("unpack", strUnpack),
+];
+
+const MAXSIZE: usize = i32::MAX as usize;   // limit for the size of a result
+const L_FMTFLAGS: &[u8] = b"-+ #0";         // valid flags in a format specification
+
+pub fn open_string(ls: &mut LuaState) -> i32 {
+    ls.NewLib(STR_FUNCS);
+    createMetatable(ls);
+    1
+}
+
+// Makes the library the `__index` of all strings, so that `s:upper()` works.
+fn createMetatable(ls: &mut LuaState) {
+    ls.CreateTable(0, 1);       /* table to be metatable for strings */
+    ls.PushString(String::new());   /* dummy string */
+    ls.PushValue(-2);           /* copy table */
+    ls.SetMetatable(-2);        /* set table as metatable for strings */
+    ls.pop(1);                  /* pop dummy string */
+    ls.PushValue(-2);           /* get string library */
+    ls.SetField(-2, "__index"); /* metatable.__index = string */
+    ls.pop(1);                  /* pop metatable */
+}
+
+pub(super) fn pushBytes(ls: &mut LuaState, bytes: &[u8]) {
+    ls.PushBytes(bytes.to_vec());
+}
+
+// Translates a relative string position: negative means back from end.
+pub(super) fn posRelat(pos: i64, len: usize) -> i64 {
+    if pos >= 0 {
+        pos
+    } else if pos.unsigned_abs() > len as u64 {
+        0
+    } else {
+        len as i64 + pos + 1
+    }
+}
+
+// Raises the error of a failed pattern operation.
+fn checkMatch<T>(ls: &mut LuaState, res: MatchResult<T>) -> T {
+    match res {
+        Ok(v) => v,
+        Err(msg) => {
+            ls.Error2(msg);
+            unreachable!()
+        },
+    }
+}
+
+// string.len (s)
+fn strLen(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    ls.PushInteger(s.len() as i64);
+    1
+}
+
+// string.sub (s, i [, j])
+fn strSub(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    let l = s.len() as i64;
+    let start = posRelat(ls.CheckInteger(2), s.len()).max(1);
+    let end = posRelat(ls.OptInteger(3, -1), s.len()).min(l);
+    if start <= end {
+        pushBytes(ls, &s[start as usize - 1..end as usize]);
+    } else {
+        ls.PushString(String::new());
+    }
+    1
+}
+
+// string.reverse (s)
+fn strReverse(ls: &mut LuaState) -> i32 {
+    let mut s = ls.CheckBytes(1);
+    s.reverse();
+    pushBytes(ls, &s);
+    1
+}
+
+// string.lower (s)
+fn strLower(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    pushBytes(ls, &s.to_ascii_lowercase());
+    1
+}
+
+// string.upper (s)
+fn strUpper(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    pushBytes(ls, &s.to_ascii_uppercase());
+    1
+}
+
+// string.rep (s, n [, sep])
+fn strRep(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    let n = ls.CheckInteger(2);
+    let sep = if ls.IsNoneOrNil(3) { vec![] } else { ls.CheckBytes(3) };
+    if n <= 0 {
+        ls.PushString(String::new());
+        return 1;
+    }
+    let n = n as usize;
+    if (s.len() + sep.len()).saturating_mul(n) > MAXSIZE {
+        return ls.Error2(String::from("resulting string too large"));
+    }
+    let mut buf = Vec::with_capacity((s.len() + sep.len()) * n);
+    for i in 0..n {
+        if i > 0 {
+            buf.extend_from_slice(&sep);
+        }
+        buf.extend_from_slice(&s);
+    }
+    pushBytes(ls, &buf);
+    1
+}
+
+// string.byte (s [, i [, j]])
+fn strByte(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    let posi = posRelat(ls.OptInteger(2, 1), s.len());
+    let pose = posRelat(ls.OptInteger(3, posi), s.len());
+    let posi = posi.max(1);
+    let pose = pose.min(s.len() as i64);
+    if posi > pose {
+        return 0;               /* empty interval; return no values */
+    }
+    let n = (pose - posi + 1) as i32;
+    ls.CheckStack(n);
+    for b in &s[posi as usize - 1..pose as usize] {
+        ls.PushInteger(*b as i64);
+    }
+    n
+}
+
+// string.char (...)
+fn strChar(ls: &mut LuaState) -> i32 {
+    let n = ls.GetTop();        /* number of arguments */
+    let mut buf = Vec::with_capacity(n as usize);
+    for i in 1..=n {
+        let c = ls.CheckInteger(i);
+        ls.ArgCheck((c as u64) <= 0xFF, i, "value out of range");
+        buf.push(c as u8);
+    }
+    pushBytes(ls, &buf);
+    1
+}
+
+// string.dump (function [, strip])
+fn strDump(ls: &mut LuaState) -> i32 {
+    let strip = ls.ToBoolean(2);
+    ls.CheckType(1, LUA_TFUNCTION);
+    ls.SetTop(1);
+    match ls.Dump(strip) {
+        Some(chunk) => {
+            pushBytes(ls, &chunk);
+            1
+        },
+        None => ls.Error2(String::from("unable to dump given function")),
+    }
+}
+
+/*
+** {======================================================
+** PATTERN MATCHING
+** =======================================================
+*/
+
+fn pushCapture(ls: &mut LuaState, ms: &MatchState, src: &[u8], i: usize, s: usize, e: usize) {
+    match checkMatch(ls, ms.getCapture(i, s, e)) {
+        Capture::Str(b, e) => pushBytes(ls, &src[b..e]),
+        Capture::Position(n) => ls.PushInteger(n),
+    }
+}
+
+// Pushes the captures of a match of `s..e`, or the whole match if the
+// pattern has no captures and `whole` is set.
+fn pushCaptures(ls: &mut LuaState, ms: &MatchState, src: &[u8], s: usize, e: usize, whole: bool) -> i32 {
+    let nlevels = if ms.level() == 0 && whole { 1 } else { ms.level() };
+    ls.CheckStack(nlevels as i32);
+    for i in 0..nlevels {
+        pushCapture(ls, ms, src, i, s, e);
+    }
+    nlevels as i32              /* number of strings pushed */
+}
+
+fn strFindAux(ls: &mut LuaState, find: bool) -> i32 {
+    let s = ls.CheckBytes(1);
+    let p = ls.CheckBytes(2);
+    let init = posRelat(ls.OptInteger(3, 1), s.len()).max(1);
+    if init > s.len() as i64 + 1 {     /* start after string's end? */
+        ls.PushNil();           /* cannot find anything */
+        return 1;
+    }
+    let init = init as usize - 1;
+    /* explicit request or no special characters? */
+    if find && (ls.ToBoolean(4) || noSpecials(&p)) {
+        /* do a plain search */
+        if let Some(i) = findPlain(&s[init..], &p) {
+            ls.PushInteger((init + i) as i64 + 1);
+            ls.PushInteger((init + i + p.len()) as i64);
+            return 2;
+        }
+    } else {
+        let anchor = p.first() == Some(&b'^');
+        let pat = if anchor { &p[1..] } else { &p[..] };    /* skip anchor character */
+        let mut ms = MatchState::new(&s, pat);
+        let mut s1 = init;
+        loop {
+            ms.reprep();
+            if let Some(e) = checkMatch(ls, ms.doMatch(s1, 0)) {
+                if find {
+                    ls.PushInteger(s1 as i64 + 1);  /* start */
+                    ls.PushInteger(e as i64);       /* end */
+                    return pushCaptures(ls, &ms, &s, 0, 0, false) + 2;
+                } else {
+                    return pushCaptures(ls, &ms, &s, s1, e, true);
+                }
+            }
+            s1 += 1;
+            if s1 > s.len() || anchor {
+                break;
+            }
+        }
+    }
+    ls.PushNil();               /* not found */
+    1
+}
+
+// string.find (s, pattern [, init [, plain]])
+fn strFind(ls: &mut LuaState) -> i32 {
+    strFindAux(ls, true)
+}
+
+// string.match (s, pattern [, init])
+fn strMatch(ls: &mut LuaState) -> i32 {
+    strFindAux(ls, false)
+}
+
+// string.gmatch (s, pattern)
+fn strGmatch(ls: &mut LuaState) -> i32 {
+    ls.CheckString(1);
+    ls.CheckString(2);
+    ls.SetTop(2);
+    ls.PushInteger(0);          /* where the next search starts */
+    ls.PushNil();               /* end of last match */
+    ls.PushGoClosure(gmatchAux, 4);
+    1
+}
+
+fn gmatchAux(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(LuaUpValueIndex(1));
+    let p = ls.CheckBytes(LuaUpValueIndex(2));
+    let start = ls.ToInteger(LuaUpValueIndex(3)) as usize;
+    let lastmatch = ls.ToIntegerX(LuaUpValueIndex(4)).map(|e| e as usize);
+    let mut ms = MatchState::new(&s, &p);
+    for src in start..=s.len() {
+        ms.reprep();
+        match checkMatch(ls, ms.doMatch(src, 0)) {
+            Some(e) if Some(e) != lastmatch => {
+                ls.PushInteger(e as i64);
+                ls.PushValue(-1);
+                ls.Replace(LuaUpValueIndex(3));
+                ls.Replace(LuaUpValueIndex(4));
+                return pushCaptures(ls, &ms, &s, src, e, true);
+            },
+            _ => {},
+        }
+    }
+    0                           /* not found */
+}
+
+// Appends the replacement string of `gsub` for the match `s..e`.
+fn addS(ls: &mut LuaState, ms: &MatchState, src: &[u8], buf: &mut Vec<u8>, s: usize, e: usize) {
+    let news = ls.CheckBytes(3);
+    let mut i = 0;
+    while i < news.len() {
+        if news[i] != b'%' {
+            buf.push(news[i]);
+        } else {
+            i += 1;             /* skip ESC */
+            let c = news.get(i).copied().unwrap_or(0);
+            if !c.is_ascii_digit() {
+                if c != b'%' {
+                    ls.Error2(String::from("invalid use of '%' in replacement string"));
+                }
+                buf.push(c);
+            } else if c == b'0' {
+                buf.extend_from_slice(&src[s..e]);
+            } else {
+                pushCapture(ls, ms, src, (c - b'1') as usize, s, e);
+                ls.ToString2(-1);       /* if number, convert it to string */
+                buf.extend(ls.CheckBytes(-1));
+                ls.pop(2);      /* remove the value and its string */
+            }
+        }
+        i += 1;
+    }
+}
+
+// Appends the replacement of `gsub` for the match `s..e`.
+fn addValue(ls: &mut LuaState, ms: &MatchState, src: &[u8], buf: &mut Vec<u8>, s: usize, e: usize, tr: i8) {
+    match tr {
+        LUA_TFUNCTION => {
+            ls.PushValue(3);
+            let n = pushCaptures(ls, ms, src, s, e, true);
+            ls.Call(n, 1);      /* call it */
+        },
+        LUA_TTABLE => {
+            pushCapture(ls, ms, src, 0, s, e);
+            ls.GetTable(3);
+        },
+        _ => {                  /* LUA_TNUMBER or LUA_TSTRING */
+            addS(ls, ms, src, buf, s, e);
+            return;
+        },
+    }
+    if !ls.ToBoolean(-1) {      /* nil or false? */
+        buf.extend_from_slice(&src[s..e]);      /* keep original text */
+    } else if !ls.IsString(-1) {
+        let msg = format!("invalid replacement value (a {})", ls.TypeName2(-1));
+        ls.Error2(msg);
+    } else {
+        buf.extend(ls.CheckBytes(-1));
+    }
+    ls.pop(1);
+}
+
+// string.gsub (s, pattern, repl [, n])
+fn strGsub(ls: &mut LuaState) -> i32 {
+    let src = ls.CheckBytes(1);
+    let p = ls.CheckBytes(2);
+    let tr = ls.Type(3);        /* replacement type */
+    let max_s = ls.OptInteger(4, src.len() as i64 + 1);    /* max replacements */
+    ls.ArgCheck(tr == LUA_TNUMBER || tr == LUA_TSTRING || tr == LUA_TFUNCTION || tr == LUA_TTABLE,
+        3, "string/function/table expected");
+    let anchor = p.first() == Some(&b'^');
+    let pat = if anchor { &p[1..] } else { &p[..] };    /* skip anchor character */
+    let mut ms = MatchState::new(&src, pat);
+    let mut buf = vec![];
+    let mut s = 0;
+    let mut lastmatch = None;   /* end of last match */
+    let mut n = 0;              /* replacement count */
+    while n < max_s {
+        ms.reprep();            /* (re)prepare state for new match */
+        match checkMatch(ls, ms.doMatch(s, 0)) {
+            Some(e) if Some(e) != lastmatch => {    /* match? */
+                n += 1;
+                addValue(ls, &ms, &src, &mut buf, s, e, tr);    /* add replacement to buffer */
+                s = e;
+                lastmatch = Some(e);
+            },
+            _ if s < src.len() => {     /* otherwise, skip one character */
+                buf.push(src[s]);
+                s += 1;
+            },
+            _ => break,         /* end of subject */
+        }
+        if anchor {
+            break;
+        }
+    }
+    buf.extend_from_slice(&src[s..]);
+    pushBytes(ls, &buf);
+    ls.PushInteger(n);          /* number of substitutions */
+    2
+}
+
+/* }====================================================== */
+
+/*
+** {======================================================
+** STRING FORMAT
+** =======================================================
+*/
+
+// A conversion specification like "%-5.2f", without its conversion.
+struct FormatSpec {
+    flags: Vec<u8>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl FormatSpec {
+    fn has(&self, flag: u8) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    fn isPlain(&self) -> bool {
+        self.flags.is_empty() && self.width == 0 && self.precision.is_none()
+    }
+
+    // Pads `body` to the width; `zeros` are put between the sign or prefix
+    // and the digits when the '0' flag allows it.
+    fn pad(&self, prefix: &str, body: &[u8], zeros: bool) -> Vec<u8> {
+        let len = prefix.len() + body.len();
+        let fill = self.width.saturating_sub(len);
+        let mut out = Vec::with_capacity(len + fill);
+        if self.has(b'-') {
+            out.extend_from_slice(prefix.as_bytes());
+            out.extend_from_slice(body);
+            out.resize(out.len() + fill, b' ');
+        } else if zeros && self.has(b'0') {
+            out.extend_from_slice(prefix.as_bytes());
+            out.resize(out.len() + fill, b'0');
+            out.extend_from_slice(body);
+        } else {
+            out.resize(fill, b' ');
+            out.extend_from_slice(prefix.as_bytes());
+            out.extend_from_slice(body);
+        }
+        out
+    }
+
+    fn sign(&self, neg: bool) -> &'static str {
+        if neg {
+            "-"
+        } else if self.has(b'+') {
+            "+"
+        } else if self.has(b' ') {
+            " "
+        } else {
+            ""
+        }
+    }
+}
+
+fn scanFormat(ls: &mut LuaState, strfrmt: &[u8], i: &mut usize) -> FormatSpec {
+    let at = |i: usize| strfrmt.get(i).copied().unwrap_or(0);
+    let start = *i;
+    while L_FMTFLAGS.contains(&at(*i)) {
+        *i += 1;                /* skip flags */
+    }
+    if *i - start > L_FMTFLAGS.len() {
+        ls.Error2(String::from("invalid format (repeated flags)"));
+    }
+    let flags = strfrmt[start..*i].to_vec();
+    let mut width = 0;
+    for _ in 0..2 {             /* (2 digits at most) */
+        if at(*i).is_ascii_digit() {
+            width = width * 10 + (at(*i) - b'0') as usize;
+            *i += 1;
+        }
+    }
+    let mut precision = None;
+    if at(*i) == b'.' {
+        *i += 1;
+        let mut p = 0;
+        for _ in 0..2 {         /* (2 digits at most) */
+            if at(*i).is_ascii_digit() {
+                p = p * 10 + (at(*i) - b'0') as usize;
+                *i += 1;
+            }
+        }
+        precision = Some(p);
+    }
+    if at(*i).is_ascii_digit() {
+        ls.Error2(String::from("invalid format (width or precision too long)"));
+    }
+    FormatSpec { flags, width, precision }
+}
+
+fn formatInteger(spec: &FormatSpec, n: i64, conv: u8) -> Vec<u8> {
+    let u = n as u64;
+    let (prefix, mut digits) = match conv {
+        b'd' | b'i' => (spec.sign(n < 0), n.unsigned_abs().to_string()),
+        b'o' => ("", format!("{:o}", u)),
+        b'u' => ("", u.to_string()),
+        b'x' => (if spec.has(b'#') && u != 0 { "0x" } else { "" }, format!("{:x}", u)),
+        _ => (if spec.has(b'#') && u != 0 { "0X" } else { "" }, format!("{:X}", u)),
+    };
+    if let Some(p) = spec.precision {
+        if p == 0 && n == 0 {
+            digits.clear();
+        }
+        while digits.len() < p {
+            digits.insert(0, '0');
+        }
+    }
+    if conv == b'o' && spec.has(b'#') && !digits.starts_with('0') {
+        digits.insert(0, '0');
+    }
+    spec.pad(prefix, digits.as_bytes(), spec.precision.is_none())
+}
+
+fn formatNumber(spec: &FormatSpec, n: f64, conv: u8) -> Vec<u8> {
+    let body = FormatFloat(n, conv as char, spec.precision, spec.has(b'#'));
+    let sign = spec.sign(body.starts_with('-'));
+    let body = body.trim_start_matches('-');
+    if n.is_finite() && (conv == b'a' || conv == b'A') {
+        let prefix = format!("{}{}", sign, &body[..2]);     /* keep the zeros after "0x" */
+        spec.pad(&prefix, &body.as_bytes()[2..], true)
+    } else {
+        spec.pad(sign, body.as_bytes(), n.is_finite())
+    }
+}
+
+// Appends `s` as a string literal that can be read back by Lua.
+fn addQuoted(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.push(b'"');
+    for (i, c) in s.iter().enumerate() {
+        if *c == b'"' || *c == b'\\' || *c == b'\n' {
+            buf.push(b'\\');
+            buf.push(*c);
+        } else if c.is_ascii_control() {
+            let next_is_digit = s.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+            if next_is_digit {
+                buf.extend(format!("\\{:03}", c).bytes());
+            } else {
+                buf.extend(format!("\\{}", c).bytes());
+            }
+        } else {
+            buf.push(*c);
+        }
+    }
+    buf.push(b'"');
+}
+
+fn addLiteral(ls: &mut LuaState, buf: &mut Vec<u8>, arg: i32) {
+    match ls.Type(arg) {
+        LUA_TSTRING => {
+            let s = ls.CheckBytes(arg);
+            addQuoted(buf, &s);
+        },
+        LUA_TNUMBER => {
+            if !ls.IsInteger(arg) {     /* float? */
+                let n = ls.ToNumber(arg);       /* write as hexa ('%a') */
+                buf.extend(FormatFloat(n, 'a', None, false).bytes());
+            } else {            /* integers */
+                let n = ls.ToInteger(arg);
+                if n == i64::MIN {      /* corner case? */
+                    buf.extend(format!("0x{:x}", n).bytes());   /* use hexa */
+                } else {
+                    buf.extend(n.to_string().bytes());
+                }
+            }
+        },
+        LUA_TNIL | LUA_TBOOLEAN => {
+            ls.ToString2(arg);
+            buf.extend(ls.CheckBytes(-1));
+            ls.pop(1);
+        },
+        _ => {
+            ls.ArgError(arg, "value has no literal form");
+        },
+    }
+}
+
+// string.format (formatstring, ...)
+fn strFormat(ls: &mut LuaState) -> i32 {
+    let top = ls.GetTop();
+    let mut arg = 1;
+    let strfrmt = ls.CheckBytes(arg);
+    let mut buf: Vec<u8> = vec![];
+    let mut i = 0;
+    while i < strfrmt.len() {
+        if strfrmt[i] != b'%' {
+            buf.push(strfrmt[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+        if strfrmt.get(i) == Some(&b'%') {
+            buf.push(b'%');     /* %% */
+            i += 1;
+            continue;
+        }
+        /* format item */
+        arg += 1;
+        if arg > top {
+            ls.ArgError(arg, "no value");
+        }
+        let spec = scanFormat(ls, &strfrmt, &mut i);
+        let conv = strfrmt.get(i).copied().unwrap_or(0);
+        i += 1;
+        match conv {
+            b'c' => {
+                let c = ls.CheckInteger(arg) as u8;
+                buf.extend(spec.pad("", &[c], false));
+            },
+            b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+                let n = ls.CheckInteger(arg);
+                buf.extend(formatInteger(&spec, n, conv));
+            },
+            b'a' | b'A' | b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+                let n = ls.CheckNumber(arg);
+                buf.extend(formatNumber(&spec, n, conv));
+            },
+            b'q' => addLiteral(ls, &mut buf, arg),
+            b's' => {
+                ls.ToString2(arg);
+                let s = ls.CheckBytes(-1);
+                ls.pop(1);
+                if spec.isPlain() {
+                    buf.extend(s);      /* keep entire string */
+                } else {
+                    ls.ArgCheck(!s.contains(&0), arg, "string contains zeros");
+                    if spec.precision.is_none() && s.len() >= 100 {
+                        /* no precision and string is too long to be formatted */
+                        buf.extend(s);  /* keep entire string */
+                    } else {
+                        let len = spec.precision.map_or(s.len(), |p| p.min(s.len()));
+                        buf.extend(spec.pad("", &s[..len], false));
+                    }
+                }
+            },
+            _ => {              /* also treat cases 'pnLlh' */
+                let msg = format!("invalid option '%{}' to 'format'", conv as char);
+                return ls.Error2(msg);
+            },
+        }
+    }
+    pushBytes(ls, &buf);
+    1
+}
+
+/* }====================================================== */
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+    use crate::state::lua_state::LuaState;
+    use super::open_string;
+    use crate::stdlib::test_util::run;
+
+    #[test]
+    fn test_dump_and_load() {
+        let mut ls = LuaState::new();
+        ls.RequireF("string", open_string, true);
+        ls.pop(1);
+        ls.Load(b"return string.dump(function (a) return a * 2 end)".to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 1, 0), LUA_OK);
+        let chunk = ls.ToBytes(-1);
+        ls.Load(chunk, "=dumped", "b");
+        ls.PushInteger(21);
+        assert_eq!(ls.PCall(1, 1, 0), LUA_OK);
+        assert_eq!(ls.ToInteger(-1), 42);
+
+        ls.Load(b"return string.dump(print)".to_vec(), "=test", "bt");
+        ls.Register("print", |_| 0);
+        assert_eq!(ls.PCall(0, 1, 0), LUA_ERRRUN);
+        assert_eq!(ls.ToString(-1), "test:1: unable to dump given function");
+    }
+
+    #[test]
+    fn test_basic_functions() {
+        let out = run(r#"
+            local s = "Hello"
+            local b1, b2, b3 = s:byte(1, -4)
+            return s:len() .. s:upper() .. s:lower() .. s:sub(2, -2) .. s:sub(-3) .. s:sub(4, 2)
+                .. s:rep(2, "-") .. s:reverse() .. b1 .. b2 .. (b3 or "nil")
+                .. string.char(72, 105) .. ("x"):rep(0)
+        "#);
+        assert_eq!(out, Ok(String::from("5HELLOhelloellllo\
+            Hello-HelloolleH72101nilHi")));
+        assert_eq!(run("return string.char(256)"),
+            Err(String::from("test:1: bad argument #1 to 'char' (value out of range)")));
+        assert_eq!(run("return ('x'):bad()"), Err(String::from("test:1: attempt to call a nil value (method 'bad')")));
+    }
+
+    #[test]
+    fn test_find_and_match() {
+        let out = run(r#"
+            local a, b = string.find("hello world", "o w")
+            local c, d = string.find("a.b", ".", 1, true)
+            local e, f, g = string.find("key=val", "(%w+)=")
+            local k, v = string.match("  name = lua ", "(%w+)%s*=%s*(%w+)")
+            return a .. b .. c .. d .. e .. f .. g .. k .. v
+                .. (string.find("abc", "^b") or "nil") .. string.match("hello", "()ll()")
+        "#);
+        assert_eq!(out, Ok(String::from("572214keynameluanil3")));
+    }
+
+    #[test]
+    fn test_gmatch_and_gsub() {
+        let out = run(r#"
+            local words = ""
+            for w in string.gmatch("one two  three", "%a+") do words = words .. w .. "," end
+            for k, v in ("a=1, b=2"):gmatch("(%w+)=(%w+)") do words = words .. k .. v end
+            local s1, n1 = string.gsub("hello world", "o", "0")
+            local s2 = string.gsub("hello world", "(%w+)", "<%1>")
+            local s3 = string.gsub("$name is $age", "%$(%w+)", {name = "lua", age = 30})
+            local s4 = string.gsub("abc", "%w", function (c) return c:upper() .. "." end)
+            local s5, n5 = string.gsub("abc", "", "-")
+            local s6 = string.gsub("hello world", "^h", "H", 1)
+            return words .. "|" .. s1 .. n1 .. "|" .. s2 .. "|" .. s3 .. "|" .. s4 .. "|" .. s5 .. n5 .. "|" .. s6
+        "#);
+        assert_eq!(out, Ok(String::from("one,two,three,a1b2|hell0 w0rld2|<hello> <world>|lua is 30|A.B.C.|-a-b-c-4|Hello world")));
+        assert_eq!(run(r#"return string.gsub("abc", "(a)", "%2")"#), Err(String::from("test:1: invalid capture index %2")));
+        assert_eq!(run(r#"return string.gsub("abc", "a", "%x")"#),
+            Err(String::from("test:1: invalid use of '%' in replacement string")));
+        assert_eq!(run(r#"return string.gsub("abc", "a", {a = {}})"#),
+            Err(String::from("test:1: invalid replacement value (a table)")));
+        assert_eq!(run(r#"return string.find("a", "[a")"#), Err(String::from("test:1: malformed pattern (missing ']')")));
+        assert_eq!(run(r#"return string.rep("a", 300):find(string.rep("a?", 300) .. "b")"#),
+            Err(String::from("test:1: pattern too complex")));
+    }
+
+    #[test]
+    fn test_format() {
+        let out = run(r#"
+            return string.format("%d|%5d|%-5d|%05d|%+d|%x|%X|%#x|%o|%.3d", 42, 42, 42, 42, 42, 255, 255, 255, 8, 7)
+                .. string.format("|%5.2f|%e|%g|%g|%.3g|%10.4f|%-8.1f|", 3.14159, 12345.678, 0.1, 1e20, 2/3, -1.5, 2.25)
+                .. string.format("%s|%10s|%-4s|%.2s|%c|%%|%a", "str", "right", "l", "abc", 65, 1.0)
+                .. string.format("|%q|%q|%q", 'a "q"\n\0', 1/4, 10)
+                .. string.format("|%s|%s|%s", 1.5, 10, 2^63)
+        "#);
+        assert_eq!(out, Ok(String::from("42|   42|42   |00042|+42|ff|FF|0xff|10|007\
+            | 3.14|1.234568e+04|0.1|1e+20|0.667|   -1.5000|2.2     |\
+            str|     right|l   |ab|A|%|0x1p+0\
+            |\"a \\\"q\\\"\\\n\\0\"|0x1p-2|10\
+            |1.5|10|9.2233720368548e+18")));
+        assert_eq!(run(r#"return string.format("%d", 1.5)"#),
+            Err(String::from("test:1: bad argument #2 to 'format' (number has no integer representation)")));
+        assert_eq!(run(r#"return string.format("%y", 1)"#), Err(String::from("test:1: invalid option '%y' to 'format'")));
+        assert_eq!(run(r#"return string.format("%d")"#), Err(String::from("test:1: bad argument #2 to 'format' (no value)")));
+        assert_eq!(run(r#"return string.format("%100d", 1)"#),
+            Err(String::from("test:1: invalid format (width or precision too long)")));
+    }
+
+    #[test]
+    fn test_pack_and_unpack() {
+        let out = run(r#"
+            local s = string.pack("i4", 100)
+            local t = {string.unpack("<i2 >I2 z s1", string.pack("<i2 >I2 z s1", -2, 258, "hi", "abc"))}
+            local f = string.unpack("d", string.pack("d", 0.5))
+            return #s .. string.packsize("i4i8") .. "|" .. t[1] .. t[2] .. t[3] .. t[4] .. t[5]
+                .. "|" .. f .. string.unpack("B", "\255") .. string.packsize("!8 i1 i8")
+        "#);
+        assert_eq!(out, Ok(String::from("412|-2258hiabc12|0.525516")));
+        assert_eq!(run(r#"return string.pack("i1", 200)"#),
+            Err(String::from("test:1: bad argument #2 to 'pack' (integer overflow)")));
+        assert_eq!(run(r#"return string.packsize("s")"#),
+            Err(String::from("test:1: bad argument #1 to 'packsize' (variable-length format)")));
+        assert_eq!(run(r#"return string.unpack("i4", "ab")"#),
+            Err(String::from("test:1: bad argument #2 to 'unpack' (data string too short)")));
+    }
+}
@@ -0,0 +1,443 @@
+use crate::api::{consts::*, lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::LuaAPI};
+use crate::state::lua_state::LuaState;
+
+const TAB_FUNCS: &[FuncReg] = &[
+    ("concat", tabConcat),
+    ("insert", tabInsert),
+    ("pack", tabPack),
+    ("unpack", tabUnpack),
+    ("remove", tabRemove),
+    ("move", tabMove),
+    ("sort", tabSort),
+];
+
+/*
+** Operations that an object must define to mimic a table
+** (some functions only need some of them)
+*/
+const TAB_R: i32 = 1;               // read
+const TAB_W: i32 = 2;               // write
+const TAB_L: i32 = 4;               // length
+const TAB_RW: i32 = TAB_R | TAB_W;  // read/write
+
+const RANLIMIT: i64 = 100;          // partitions smaller than this use the middle element as pivot
+
+pub fn open_table(ls: &mut LuaState) -> i32 {
+    ls.NewLib(TAB_FUNCS);
+    1
+}
+
+fn checkField(ls: &mut LuaState, key: &'static str, n: i32) -> bool {
+    ls.PushString(String::from(key));
+    ls.RawGet(-n) != LUA_TNIL
+}
+
+// Checks that `arg` is a table or behaves like one, with the metamethods
+// for the operations in `what`.
+fn checkTab(ls: &mut LuaState, arg: i32, what: i32) {
+    if ls.Type(arg) != LUA_TTABLE {     /* is it not a table? */
+        let mut n = 1;          /* number of elements to pop */
+        let ok = ls.GetMetatable(arg)   /* must have metatable */
+            && (what & TAB_R == 0 || { n += 1; checkField(ls, "__index", n) })
+            && (what & TAB_W == 0 || { n += 1; checkField(ls, "__newindex", n) })
+            && (what & TAB_L == 0 || { n += 1; checkField(ls, "__len", n) });
+        if ok {
+            ls.pop(n);          /* pop metatable and tested metamethods */
+        } else {
+            ls.CheckType(arg, LUA_TTABLE);  /* force an error */
+        }
+    }
+}
+
+fn auxGetN(ls: &mut LuaState, arg: i32, what: i32) -> i64 {
+    checkTab(ls, arg, what | TAB_L);
+    ls.Len2(arg)
+}
+
+// table.insert (list, [pos,] value)
+fn tabInsert(ls: &mut LuaState) -> i32 {
+    let e = auxGetN(ls, 1, TAB_RW).wrapping_add(1);     /* first empty element */
+    let pos = match ls.GetTop() {
+        2 => e,                 /* called with only 2 arguments, insert at the end */
+        3 => {
+            let pos = ls.CheckInteger(2);   /* 2nd argument is the position */
+            ls.ArgCheck(1 <= pos && pos <= e, 2, "position out of bounds");
+            for i in (pos + 1..=e).rev() {  /* move up elements */
+                ls.GetI(1, i - 1);
+                ls.SetI(1, i);  /* t[i] = t[i - 1] */
+            }
+            pos
+        },
+        _ => return ls.Error2(String::from("wrong number of arguments to 'insert'")),
+    };
+    ls.SetI(1, pos);            /* t[pos] = v */
+    0
+}
+
+// table.remove (list [, pos])
+fn tabRemove(ls: &mut LuaState) -> i32 {
+    let size = auxGetN(ls, 1, TAB_RW);
+    let mut pos = ls.OptInteger(2, size);
+    if pos != size {            /* validate 'pos' if given */
+        ls.ArgCheck(1 <= pos && pos <= size.wrapping_add(1), 1, "position out of bounds");
+    }
+    ls.GetI(1, pos);            /* result = t[pos] */
+    while pos < size {
+        ls.GetI(1, pos + 1);
+        ls.SetI(1, pos);        /* t[pos] = t[pos + 1] */
+        pos += 1;
+    }
+    ls.PushNil();
+    ls.SetI(1, pos);            /* t[pos] = nil */
+    1
+}
+
+// table.move (a1, f, e, t [,a2])
+fn tabMove(ls: &mut LuaState) -> i32 {
+    let f = ls.CheckInteger(2);
+    let e = ls.CheckInteger(3);
+    let t = ls.CheckInteger(4);
+    let tt = if !ls.IsNoneOrNil(5) { 5 } else { 1 };    /* destination table */
+    checkTab(ls, 1, TAB_R);
+    checkTab(ls, tt, TAB_W);
+    if e >= f {                 /* otherwise, nothing to move */
+        ls.ArgCheck(f > 0 || e < i64::MAX + f, 3, "too many elements to move");
+        let n = e - f + 1;      /* number of elements to move */
+        ls.ArgCheck(t <= i64::MAX - n + 1, 4, "destination wrap around");
+        if t > e || t <= f || (tt != 1 && !ls.Compare(1, tt, LUA_OPEQ)) {
+            for i in 0..n {
+                ls.GetI(1, f + i);
+                ls.SetI(tt, t + i);
+            }
+        } else {
+            for i in (0..n).rev() {
+                ls.GetI(1, f + i);
+                ls.SetI(tt, t + i);
+            }
+        }
+    }
+    ls.PushValue(tt);           /* return destination table */
+    1
+}
+
+fn addField(ls: &mut LuaState, buf: &mut Vec<u8>, i: i64) {
+    ls.GetI(1, i);
+    if !ls.IsString(-1) {
+        let msg = format!("invalid value (at index {}) in table for 'concat'", i);
+        ls.Error2(msg);
+    }
+    buf.extend(ls.ToBytes(-1));
+    ls.pop(1);
+}
+
+// table.concat (list [, sep [, i [, j]]])
+fn tabConcat(ls: &mut LuaState) -> i32 {
+    let last = auxGetN(ls, 1, TAB_R);
+    let sep = if ls.IsNoneOrNil(2) { vec![] } else { ls.CheckBytes(2) };
+    let mut i = ls.OptInteger(3, 1);
+    let last = ls.OptInteger(4, last);
+    let mut buf = vec![];
+    while i < last {
+        addField(ls, &mut buf, i);
+        buf.extend_from_slice(&sep);
+        i += 1;
+    }
+    if i == last {              /* add last value (if interval was not empty) */
+        addField(ls, &mut buf, i);
+    }
+    ls.PushBytes(buf);
+    1
+}
+
+/*
+** {======================================================
+** Pack/unpack
+** =======================================================
+*/
+
+// table.pack (...)
+fn tabPack(ls: &mut LuaState) -> i32 {
+    let n = ls.GetTop();        /* number of elements to pack */
+    ls.CreateTable(n, 1);       /* create result table */
+    ls.Insert(1);               /* put it at index 1 */
+    for i in (1..=n).rev() {    /* assign elements */
+        ls.SetI(1, i as i64);
+    }
+    ls.PushInteger(n as i64);
+    ls.SetField(1, "n");        /* t.n = number of elements */
+    1                           /* return table */
+}
+
+// table.unpack (list [, i [, j]])
+pub(super) fn tabUnpack(ls: &mut LuaState) -> i32 {
+    let i = ls.OptInteger(2, 1);
+    let e = if ls.IsNoneOrNil(3) { ls.Len2(1) } else { ls.CheckInteger(3) };
+    if i > e {
+        return 0;               /* empty range */
+    }
+    let n = (e as u64).wrapping_sub(i as u64);  /* number of elements minus 1 (avoid overflows) */
+    if n >= i32::MAX as u64 || !ls.CheckStack(n as i32 + 1) {
+        return ls.Error2(String::from("too many results to unpack"));
+    }
+    for k in i..e {             /* push arg[i..e - 1] (to avoid overflows) */
+        ls.GetI(1, k);
+    }
+    ls.GetI(1, e);              /* push last element */
+    n as i32 + 1
+}
+
+/* }====================================================== */
+
+/*
+** {======================================================
+** Quicksort
+** (based on 'Algorithms in MODULA-3', Robert Sedgewick;
+**  Addison-Wesley, 1993.)
+** =======================================================
+*/
+
+// Takes the two values on top of the stack and sets t[i] and t[j] to them.
+fn set2(ls: &mut LuaState, i: i64, j: i64) {
+    ls.SetI(1, i);
+    ls.SetI(1, j);
+}
+
+// Returns true if the value at `a` is smaller than the one at `b`, using
+// the comparator at index 2 if there is one.
+fn sortComp(ls: &mut LuaState, a: i32, b: i32) -> bool {
+    if ls.IsNil(2) {            /* no function? */
+        ls.Compare(a, b, LUA_OPLT)      /* a < b */
+    } else {                    /* function */
+        ls.PushValue(2);        /* push function */
+        ls.PushValue(a - 1);    /* -1 to compensate function */
+        ls.PushValue(b - 2);    /* -2 to compensate function and 'a' */
+        ls.Call(2, 1);          /* call function */
+        let res = ls.ToBoolean(-1);     /* get result */
+        ls.pop(1);              /* pop result */
+        res
+    }
+}
+
+// Does the partition: Pivot P is at the top of the stack.
+// precondition: a[lo] <= P == a[up-1] <= a[up],
+// so it only needs to do the partition from lo + 1 to up - 2.
+// Pos-condition: a[lo .. i - 1] <= a[i] == P <= a[i + 1 .. up]
+// returns 'i'.
+fn partition(ls: &mut LuaState, lo: i64, up: i64) -> i64 {
+    let mut i = lo;             /* will be incremented before first use */
+    let mut j = up - 1;         /* will be decremented before first use */
+    /* loop invariant: a[lo .. i] <= P <= a[j .. up], a[up - 1] == P */
+    loop {
+        /* next loop: repeat ++i while a[i] < P */
+        loop {
+            i += 1;
+            ls.GetI(1, i);
+            if !sortComp(ls, -1, -2) {
+                break;
+            }
+            if i == up - 1 {    /* a[i] < P  but a[up - 1] == P  ?? */
+                ls.Error2(String::from("invalid order function for sorting"));
+            }
+            ls.pop(1);          /* remove a[i] */
+        }
+        /* after the loop, a[i] >= P and a[lo .. i - 1] < P */
+        /* next loop: repeat --j while P < a[j] */
+        loop {
+            j -= 1;
+            ls.GetI(1, j);
+            if !sortComp(ls, -3, -1) {
+                break;
+            }
+            if j < i {          /* j < i  but  a[j] > P ?? */
+                ls.Error2(String::from("invalid order function for sorting"));
+            }
+            ls.pop(1);          /* remove a[j] */
+        }
+        /* after the loop, a[j] <= P and a[j + 1 .. up] >= P */
+        if j < i {              /* no elements to be exchanged? */
+            ls.pop(1);          /* pop a[j] */
+            /* swap pivot (a[up - 1]) with a[i] to satisfy pred.: a[up - 1] == P */
+            set2(ls, up - 1, i);
+            return i;
+        }
+        /* otherwise, swap a[i] - a[j] to restore invariant and repeat */
+        set2(ls, i, j);
+    }
+}
+
+// Chooses an element in the middle (2nd-3th quarters) of [lo,up]
+// "randomized" by 'rnd'.
+fn choosePivot(lo: i64, up: i64, rnd: u32) -> i64 {
+    let r4 = (up - lo) / 4;     /* range/4 */
+    rnd as i64 % (r4 * 2) + (lo + r4)
+}
+
+// A new seed for choosing pivots of an imbalanced partition. The original
+// uses the clock; this one depends on the interval so sorting stays
+// reproducible.
+fn randomizePivot(lo: i64, up: i64) -> u32 {
+    let h = (lo as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (up as u64);
+    (h ^ (h >> 29)) as u32 | 1
+}
+
+fn auxSort(ls: &mut LuaState, lo: i64, up: i64, rnd: u32) {
+    let (mut lo, mut up, mut rnd) = (lo, up, rnd);
+    while lo < up {             /* loop for tail recursion */
+        /* sort elements 'lo', 'p', and 'up' */
+        ls.GetI(1, lo);
+        ls.GetI(1, up);
+        if sortComp(ls, -1, -2) {   /* a[up] < a[lo]? */
+            set2(ls, lo, up);   /* swap a[lo] - a[up] */
+        } else {
+            ls.pop(2);          /* remove both values */
+        }
+        if up - lo == 1 {       /* only 2 elements? */
+            break;              /* already sorted */
+        }
+        let mut p = if up - lo < RANLIMIT || rnd == 0 {     /* small interval or no randomize? */
+            (lo + up) / 2       /* middle element is a good pivot */
+        } else {                /* for larger intervals, it is expensive to solve worst cases */
+            choosePivot(lo, up, rnd)
+        };
+        ls.GetI(1, p);
+        ls.GetI(1, lo);
+        if sortComp(ls, -2, -1) {   /* a[p] < a[lo]? */
+            set2(ls, p, lo);    /* swap a[p] - a[lo] */
+        } else {
+            ls.pop(1);          /* remove second element */
+            ls.GetI(1, up);
+            if sortComp(ls, -1, -2) {   /* a[up] < a[p]? */
+                set2(ls, p, up);    /* swap up - p */
+            } else {
+                ls.pop(2);      /* clean stack */
+            }
+        }
+        if up - lo == 2 {       /* only 3 elements? */
+            break;              /* already sorted */
+        }
+        ls.GetI(1, p);          /* get median (Pivot) */
+        ls.PushValue(-1);       /* push Pivot */
+        ls.GetI(1, up - 1);     /* push a[up - 1] */
+        set2(ls, p, up - 1);    /* a[p] = a[up - 1]; a[up - 1] = a[p] */
+        p = partition(ls, lo, up);
+        /* a[lo .. p - 1] <= a[p] == P <= a[p + 1 .. up] */
+        let n;                  /* size of smaller interval */
+        if p - lo < up - p {    /* lower interval is shorter? */
+            auxSort(ls, lo, p - 1, rnd);    /* call recursively for lower interval */
+            n = p - lo;
+            lo = p + 1;         /* tail call for [p + 1 .. up] (upper interval) */
+        } else {
+            auxSort(ls, p + 1, up, rnd);    /* call recursively for upper interval */
+            n = up - p;
+            up = p - 1;         /* tail call for [lo .. p - 1]  (lower interval) */
+        }
+        if (up - lo) / 128 > n {    /* partition too imbalanced? */
+            rnd = randomizePivot(lo, up);   /* try a new randomization */
+        }
+    }
+}
+
+// table.sort (list [, comp])
+fn tabSort(ls: &mut LuaState) -> i32 {
+    let n = auxGetN(ls, 1, TAB_RW);
+    if n > 1 {                  /* non-trivial interval? */
+        ls.ArgCheck(n < i32::MAX as i64, 1, "array too big");
+        if !ls.IsNoneOrNil(2) { /* is there a 2nd argument? */
+            ls.CheckType(2, LUA_TFUNCTION);     /* must be a function */
+        }
+        ls.SetTop(2);           /* make sure there are two arguments */
+        auxSort(ls, 1, n, 0);
+    }
+    0
+}
+
+/* }====================================================== */
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+    use crate::state::lua_state::LuaState;
+    use super::open_table;
+    use crate::stdlib::test_util::run;
+
+    #[test]
+    fn test_insert_remove_concat() {
+        let out = run(r#"
+            local t = {"b", "d"}
+            table.insert(t, "e")
+            table.insert(t, 1, "a")
+            table.insert(t, 3, "c")
+            local s = table.concat(t, ",")
+            local last = table.remove(t)
+            local first = table.remove(t, 1)
+            return s .. "|" .. last .. first .. "|" .. table.concat(t) .. #t
+                .. "|" .. table.concat({1, 2.5, "x"}, "-", 2, 3) .. table.concat({}, "?")
+        "#);
+        assert_eq!(out, Ok(String::from("a,b,c,d,e|ea|bcd3|2.5-x")));
+        assert_eq!(run("table.insert({}, 3, 1)"),
+            Err(String::from("test:1: bad argument #2 to 'insert' (position out of bounds)")));
+        assert_eq!(run("table.insert({}, 1, 2, 3)"), Err(String::from("test:1: wrong number of arguments to 'insert'")));
+        assert_eq!(run("return table.concat({1, {}, 3})"),
+            Err(String::from("test:1: invalid value (at index 2) in table for 'concat'")));
+    }
+
+    #[test]
+    fn test_pack_unpack_move() {
+        let out = run(r#"
+            local p = table.pack(1, nil, 3)
+            local a, b, c = table.unpack({10, 20, 30})
+            local x, y = table.unpack({1, 2, 3, 4}, 2, 3)
+            local m = table.move({1, 2, 3}, 1, 3, 2)
+            local d = table.move({1, 2}, 1, 2, 3, {7, 8})
+            return p.n .. (p[2] == nil and "nil" or "?") .. p[3] .. "|" .. a .. b .. c .. "|" .. x .. y
+                .. "|" .. table.concat(m, ",") .. "|" .. table.concat(d, ",")
+        "#);
+        assert_eq!(out, Ok(String::from("3nil3|102030|23|1,1,2,3|7,8,1,2")));
+        assert_eq!(run("return table.unpack({}, 1, 1e8)"), Err(String::from("test:1: too many results to unpack")));
+    }
+
+    #[test]
+    fn test_sort() {
+        let out = run(r#"
+            local t = {5, 2, 8, 1, 9, 3, 7, 4, 6, 0}
+            table.sort(t)
+            local s = table.concat(t)
+            table.sort(t, function (a, b) return a > b end)
+            s = s .. "|" .. table.concat(t)
+            local big = {}
+            for i = 1, 500 do big[i] = (i * 7919) % 1000 end
+            table.sort(big)
+            for i = 2, #big do if big[i - 1] > big[i] then return "unsorted" end end
+            local words = {"pear", "apple", "fig"}
+            table.sort(words)
+            return s .. "|" .. table.concat(words, " ")
+        "#);
+        assert_eq!(out, Ok(String::from("0123456789|9876543210|apple fig pear")));
+        assert_eq!(run("local t = {} for i = 1, 20 do t[i] = i % 3 end table.sort(t, function (a, b) return true end)"),
+            Err(String::from("test:1: invalid order function for sorting")));
+        assert_eq!(run("table.sort({1, 2, 'x'})"), Err(String::from("attempt to compare string with number")));
+    }
+
+    #[test]
+    fn test_honors_metamethods() {
+        let mut ls = LuaState::new();
+        ls.RequireF("table", open_table, true);
+        ls.pop(1);
+        ls.Register("setmetatable", |ls| {
+            ls.SetMetatable(1);
+            1
+        });
+        ls.Load(br#"
+            local log = {}
+            local proxy = setmetatable({}, {
+                __index = function (_, k) return k * 10 end,
+                __newindex = function (_, k, v) log[#log + 1] = k .. "=" .. v end,
+                __len = function () return 3 end,
+            })
+            table.insert(proxy, 99)
+            return table.concat(proxy, ",") .. "|" .. table.concat(log, ",")
+        "#.to_vec(), "=test", "bt");
+        assert_eq!(ls.PCall(0, 1, 0), LUA_OK);
+        assert_eq!(ls.ToString(-1), "10,20,30|4=99");
+    }
+}
@@ -0,0 +1,274 @@
+use crate::api::{lua_auxlib::{FuncReg, LuaAuxLib}, lua_state::LuaAPI};
+use crate::compiler::lexer::lexer::utf8_esc;
+use crate::state::lua_state::LuaState;
+use super::lib_string::posRelat;
+
+const MAXUNICODE: u32 = 0x10FFFF;
+
+/* pattern to match a single UTF-8 character */
+const UTF8PATT: &[u8] = b"[\0-\x7F\xC2-\xF4][\x80-\xBF]*";
+
+const UTF8_FUNCS: &[FuncReg] = &[
+    ("offset", byteOffset),
+    ("codepoint", codePoint),
+    ("char", utfChar),
+    ("len", utfLen),
+    ("codes", iterCodes),
+];
+
+pub fn open_utf8(ls: &mut LuaState) -> i32 {
+    ls.NewLib(UTF8_FUNCS);
+    ls.PushBytes(UTF8PATT.to_vec());
+    ls.SetField(-2, "charpattern");
+    1
+}
+
+// Whether the byte at `i` is a continuation byte; the end of the string
+// is not, like the '\0' ending a C string.
+fn isCont(s: &[u8], i: usize) -> bool {
+    s.get(i).is_some_and(|&c| c & 0xC0 == 0x80)
+}
+
+// Decodes one UTF-8 sequence at the start of `s`, returns the code point
+// and the length of the sequence. None if the sequence is invalid.
+fn utf8Decode(s: &[u8]) -> Option<(u32, usize)> {
+    const LIMITS: [u32; 4] = [0xFF, 0x7F, 0x7FF, 0xFFFF];
+    let mut c = *s.first().unwrap_or(&0) as u32;
+    let mut res = 0;
+    if c < 0x80 {               /* ascii? */
+        return Some((c, 1));
+    }
+    let mut count = 0;          /* to count number of continuation bytes */
+    while c & 0x40 != 0 {       /* still have continuation bytes? */
+        count += 1;
+        let cc = *s.get(count).unwrap_or(&0) as u32;    /* read next byte */
+        if cc & 0xC0 != 0x80 {  /* not a continuation byte? */
+            return None;        /* invalid byte sequence */
+        }
+        res = (res << 6) | (cc & 0x3F);     /* add lower 6 bits from cont. byte */
+        c <<= 1;                /* to test next bit */
+    }
+    res |= (c & 0x7F) << (count * 5);       /* add first byte */
+    if count > 3 || res > MAXUNICODE || res <= LIMITS[count] {
+        return None;            /* invalid byte sequence */
+    }
+    Some((res, count + 1))
+}
+
+// utf8len(s [, i [, j]]) --> number of characters that start in the
+// range [i,j], or nil + current position if 's' is not well formed in
+// that interval
+fn utfLen(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    let len = s.len() as i64;
+    let mut posi = posRelat(ls.OptInteger(2, 1), s.len());
+    let mut posj = posRelat(ls.OptInteger(3, -1), s.len());
+    ls.ArgCheck(1 <= posi && posi - 1 <= len, 2, "initial position out of string");
+    posi -= 1;
+    posj -= 1;
+    ls.ArgCheck(posj < len, 3, "final position out of string");
+    let mut n = 0;
+    while posi <= posj {
+        match utf8Decode(&s[posi as usize..]) {
+            Some((_, size)) => posi += size as i64,
+            None => {           /* conversion error? */
+                ls.PushNil();   /* return nil ... */
+                ls.PushInteger(posi + 1);   /* ... and current position */
+                return 2;
+            },
+        }
+        n += 1;
+    }
+    ls.PushInteger(n);
+    1
+}
+
+// codepoint(s, [i, [j]])  -> returns codepoints for all characters
+// that start in the range [i,j]
+fn codePoint(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    let posi = posRelat(ls.OptInteger(2, 1), s.len());
+    let pose = posRelat(ls.OptInteger(3, posi), s.len());
+    ls.ArgCheck(posi >= 1, 2, "out of range");
+    ls.ArgCheck(pose <= s.len() as i64, 3, "out of range");
+    if posi > pose {
+        return 0;               /* empty interval; return no values */
+    }
+    if pose - posi >= i32::MAX as i64 {     /* (lua_Integer -> int) overflow? */
+        return ls.Error2(String::from("string slice too long"));
+    }
+    if !ls.CheckStack((pose - posi) as i32 + 1) {
+        return ls.Error2(String::from("stack overflow (string slice too long)"));
+    }
+    let mut n = 0;
+    let mut i = posi as usize - 1;
+    while i < pose as usize {
+        match utf8Decode(&s[i..]) {
+            Some((code, size)) => {
+                ls.PushInteger(code as i64);
+                i += size;
+                n += 1;
+            },
+            None => return ls.Error2(String::from("invalid UTF-8 code")),
+        }
+    }
+    n
+}
+
+fn pushUtfChar(ls: &mut LuaState, arg: i32) -> Vec<u8> {
+    let code = ls.CheckInteger(arg);
+    ls.ArgCheck((0..=MAXUNICODE as i64).contains(&code), arg, "value out of range");
+    utf8_esc(code as u32)
+}
+
+// utfchar(n1, n2, ...)  -> char(n1)..char(n2)...
+fn utfChar(ls: &mut LuaState) -> i32 {
+    let n = ls.GetTop();        /* number of arguments */
+    let mut b = vec![];
+    for i in 1..=n {
+        b.extend(pushUtfChar(ls, i));
+    }
+    ls.PushBytes(b);
+    1
+}
+
+// offset(s, n, [i])  -> index where n-th character counting from
+//   position 'i' starts; 0 means character at 'i'.
+fn byteOffset(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    let len = s.len() as i64;
+    let mut n = ls.CheckInteger(2);
+    let posi = if n >= 0 { 1 } else { len + 1 };
+    let mut posi = posRelat(ls.OptInteger(3, posi), s.len());
+    ls.ArgCheck(1 <= posi && posi - 1 <= len, 3, "position out of range");
+    posi -= 1;
+    if n == 0 {
+        /* find beginning of current byte sequence */
+        while posi > 0 && isCont(&s, posi as usize) {
+            posi -= 1;
+        }
+    } else {
+        if isCont(&s, posi as usize) {
+            return ls.Error2(String::from("initial position is a continuation byte"));
+        }
+        if n < 0 {
+            while n < 0 && posi > 0 {   /* move back */
+                loop {          /* find beginning of previous character */
+                    posi -= 1;
+                    if !(posi > 0 && isCont(&s, posi as usize)) {
+                        break;
+                    }
+                }
+                n += 1;
+            }
+        } else {
+            n -= 1;             /* do not move for 1st character */
+            while n > 0 && posi < len {
+                loop {          /* find beginning of next character */
+                    posi += 1;
+                    if !isCont(&s, posi as usize) {
+                        break;  /* (cannot pass final '\0') */
+                    }
+                }
+                n -= 1;
+            }
+        }
+    }
+    if n == 0 {                 /* did it find given character? */
+        ls.PushInteger(posi + 1);
+    } else {                    /* no such character */
+        ls.PushNil();
+    }
+    1
+}
+
+fn iterAux(ls: &mut LuaState) -> i32 {
+    let s = ls.CheckBytes(1);
+    let len = s.len() as i64;
+    let mut n = ls.ToInteger(2) - 1;
+    if n < 0 {                  /* first iteration? */
+        n = 0;                  /* start from here */
+    } else if n < len {
+        n += 1;                 /* skip current byte */
+        while isCont(&s, n as usize) {
+            n += 1;             /* and its continuations */
+        }
+    }
+    if n >= len {
+        return 0;               /* no more codepoints */
+    }
+    match utf8Decode(&s[n as usize..]) {
+        Some((code, size)) if !isCont(&s, n as usize + size) => {
+            ls.PushInteger(n + 1);
+            ls.PushInteger(code as i64);
+            2
+        },
+        _ => ls.Error2(String::from("invalid UTF-8 code")),
+    }
+}
+
+fn iterCodes(ls: &mut LuaState) -> i32 {
+    ls.CheckBytes(1);
+    ls.PushRustFunction(iterAux);
+    ls.PushValue(1);
+    ls.PushInteger(0);
+    3
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::test_util::{eval, run};
+
+    #[test]
+    fn test_byte_strings() {
+        assert_eq!(eval("#'\\xff\\0\\x80'"), "3");
+        assert_eq!(eval("string.byte('\\xff')"), "255");
+        assert_eq!(eval("#'\\u{7FF}' .. #'\\u{FFFF}' .. #'\\u{10FFFF}'"), "234");
+        assert_eq!(eval("#'héllo'"), "6");
+        assert_eq!(eval("'héllo' == '\\104\\xc3\\xa9llo'"), "true");
+        assert_eq!(eval("string.char(0xe2, 0x82, 0xac) == '€'"), "true");
+        assert_eq!(eval("('\\xff\\xfe'):rep(3):byte(-1)"), "254");
+        assert_eq!(eval("table.concat({'\\xff', '\\x80'}, '\\0'):byte(1, -1)"), "255");
+        assert_eq!(eval("select('#', table.concat({'\\xff', '\\x80'}, '\\0'):byte(1, -1))"), "3");
+        assert_eq!(eval("load(string.dump(function() return '\\xff\\x00' end))():byte(1)"), "255");
+        assert_eq!(eval("#load(string.dump(function() return '\\xff\\x00' end))()"), "2");
+    }
+
+    #[test]
+    fn test_char_and_codepoint() {
+        assert_eq!(eval("utf8.char(72, 0xe9, 0x20ac, 0x10FFFF) == 'H\\u{e9}\\u{20ac}\\u{10FFFF}'"), "true");
+        assert_eq!(eval("utf8.char()"), "");
+        assert_eq!(run("return utf8.char(0x110000)"),
+            Err(String::from("test:1: bad argument #1 to 'char' (value out of range)")));
+        assert_eq!(eval("select('#', utf8.codepoint('h€llo', 1, -1))"), "5");
+        assert_eq!(eval("select(2, utf8.codepoint('h€llo', 1, -1))"), "8364");
+        assert_eq!(eval("utf8.codepoint('€', 1)"), "8364");
+        assert_eq!(run("return utf8.codepoint('\\xff')"), Err(String::from("test:1: invalid UTF-8 code")));
+        assert_eq!(run("return utf8.codepoint('abc', 4)"),
+            Err(String::from("test:1: bad argument #3 to 'codepoint' (out of range)")));
+        assert_eq!(run("return utf8.codepoint(string.rep('a', 1e6), 1, -1)"),
+            Err(String::from("test:1: stack overflow (string slice too long)")));
+        assert_eq!(eval("utf8.charpattern == '[\\0-\\x7F\\xC2-\\xF4][\\x80-\\xBF]*'"), "true");
+        assert_eq!(eval("select(2, ('h€llo'):gsub(utf8.charpattern, ''))"), "5");
+    }
+
+    #[test]
+    fn test_len_offset_codes() {
+        assert_eq!(eval("utf8.len('h€llo')"), "5");
+        assert_eq!(eval("tostring(utf8.len('h€llo', 3))"), "nil");
+        assert_eq!(eval("select(2, utf8.len('h€llo', 3))"), "3");
+        assert_eq!(eval("select(2, utf8.len('ab\\xffcd'))"), "3");
+        assert_eq!(eval("utf8.len('')"), "0");
+        assert_eq!(eval("utf8.len('\\xed\\xa0\\x80')"), "1");      /* surrogates are accepted in 5.3 */
+        assert_eq!(eval("tostring(utf8.len('\\xc0\\x80'))"), "nil");          /* overlong */
+        assert_eq!(eval("utf8.offset('h€llo', 3)"), "5");
+        assert_eq!(eval("utf8.offset('h€llo', -1)"), "7");
+        assert_eq!(eval("utf8.offset('h€llo', 0, 3)"), "2");
+        assert_eq!(eval("tostring(utf8.offset('h€llo', 10))"), "nil");
+        assert_eq!(run("return utf8.offset('h€llo', 1, 3)"),
+            Err(String::from("test:1: initial position is a continuation byte")));
+        assert_eq!(run("local t = {} for p, c in utf8.codes('a€b') do t[#t+1] = p .. ':' .. c end return table.concat(t, ' ')"),
+            Ok(String::from("1:97 2:8364 5:98")));
+        assert_eq!(run("for p, c in utf8.codes('a\\xffb') do end"), Err(String::from("test:1: invalid UTF-8 code")));
+    }
+}
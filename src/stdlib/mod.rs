@@ -0,0 +1,16 @@
+pub mod lib_base;
+pub mod io_file;
+pub mod lib_coroutine;
+pub mod lib_debug;
+pub mod lib_io;
+pub mod lib_math;
+pub mod lib_os;
+pub mod lib_package;
+pub mod lib_string;
+pub mod lib_table;
+pub mod lib_utf8;
+pub mod os_host;
+pub mod str_pattern;
+pub mod str_pack;
+#[cfg(test)]
+mod test_util;
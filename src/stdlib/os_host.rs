@@ -0,0 +1,418 @@
+use super::lib_os::{civilFromDays, daysFromCivil};
+use std::{env, ffi::c_long, fs::{self, OpenOptions}, io::{self, Write}, process, sync::{atomic::{AtomicUsize, Ordering}, OnceLock}, time::{SystemTime, UNIX_EPOCH}};
+
+// What the os library asks of the system. Every method has the usual
+// behavior as default, so an embedder replaces only what it needs, e.g.
+// a frozen clock for tests or a sandbox where files cannot be removed
+// (see `LuaAuxLib::SetOsHost`).
+pub trait OsHost {
+    // Current time, in seconds since the epoch.
+    fn time(&self) -> i64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    }
+
+    // Processor time used by the program, in seconds, like C's `clock`.
+    fn clock(&self) -> f64 {
+        processorTime()
+    }
+
+    // Offset from UTC in seconds east, whether it is daylight saving time
+    // and name of the local time zone at time `t`.
+    fn timezone(&self, t: i64) -> (i64, bool, String) {
+        match TZINFO.get_or_init(loadTzInfo) {
+            Some(tz) => tz.lookup(t),
+            None => (0, false, String::from("UTC")),
+        }
+    }
+
+    fn getenv(&self, name: &str) -> Option<String> {
+        env::var(name).ok()
+    }
+
+    fn remove(&self, filename: &str) -> io::Result<()> {
+        match fs::remove_file(filename) {
+            Err(e) if fs::metadata(filename).map(|m| m.is_dir()).unwrap_or(false) => {
+                fs::remove_dir(filename).map_err(|_| e)     /* like C's 'remove', also empty directories */
+            },
+            res => res,
+        }
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    // Creates a new empty file, returns its name.
+    fn tmpname(&self) -> io::Result<String> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let i = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let path = env::temp_dir().join(format!("lua_{}_{}", process::id(), i));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(path.to_string_lossy().into_owned()),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Terminates the program; an error makes 'os.exit' fail instead.
+    fn exit(&self, code: i32) -> io::Result<()> {
+        let _ = io::stdout().flush();
+        process::exit(code)
+    }
+}
+
+// Registry key of the host set by the embedder.
+pub const OS_HOST: &str = "_OS_HOST";
+
+// The host used when the embedder sets none.
+pub struct StdHost;
+
+impl OsHost for StdHost {}
+
+static TZINFO: OnceLock<Option<TzInfo>> = OnceLock::new();
+
+// The C runtime is linked anyway, its `clock` gives the processor time
+// (`clock_t` is a `long` there).
+#[cfg(any(unix, windows))]
+fn processorTime() -> f64 {
+    extern "C" {
+        fn clock() -> c_long;
+    }
+    #[cfg(unix)]
+    const CLOCKS_PER_SEC: f64 = 1_000_000.0;   /* required by XSI */
+    #[cfg(windows)]
+    const CLOCKS_PER_SEC: f64 = 1000.0;
+    unsafe { clock() as f64 / CLOCKS_PER_SEC }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn processorTime() -> f64 {
+    -1.0                        /* not available, like a failing 'clock' */
+}
+
+/*
+** {======================================================
+** Local time zone, from the TZif file of the system
+** =======================================================
+*/
+
+struct TzInfo {
+    transitions: Vec<(i64, usize)>,     // time of each change and the type it changes to
+    types: Vec<(i64, bool, String)>,    // offset, is DST, abbreviation
+    rule: Option<TzRule>,               // for the times after the last transition
+}
+
+impl TzInfo {
+    fn lookup(&self, t: i64) -> (i64, bool, String) {
+        if let Some(rule) = &self.rule {
+            if self.transitions.last().is_none_or(|&(at, _)| at <= t) {
+                return rule.lookup(t);
+            }
+        }
+        let i = match self.transitions.iter().rposition(|&(at, _)| at <= t) {
+            Some(i) => self.transitions[i].1,
+            /* before the first transition: first standard time type */
+            None => self.types.iter().position(|ty| !ty.1).unwrap_or(0),
+        };
+        self.types[i].clone()
+    }
+}
+
+fn loadTzInfo() -> Option<TzInfo> {
+    let path = match env::var("TZ") {
+        Ok(tz) if tz.is_empty() || tz == "UTC" || tz == "GMT" => return None,
+        Ok(tz) => {
+            let tz = tz.trim_start_matches(':').to_string();
+            if tz.starts_with('/') { tz } else { format!("/usr/share/zoneinfo/{}", tz) }
+        },
+        Err(_) => String::from("/etc/localtime"),
+    };
+    parseTzif(&fs::read(path).ok()?)
+}
+
+fn parseTzif(data: &[u8]) -> Option<TzInfo> {
+    let be = |p: usize, n: usize| -> Option<i64> {
+        let bytes = data.get(p..p + n)?;
+        let v = bytes.iter().fold(0u64, |v, b| v << 8 | *b as u64);
+        Some(if n == 4 { v as u32 as i32 as i64 } else { v as i64 })
+    };
+    if data.get(..4)? != b"TZif" {
+        return None;
+    }
+    let counts = |p: usize| -> Option<[usize; 6]> {
+        let mut c = [0; 6];
+        for (i, c) in c.iter_mut().enumerate() {
+            *c = be(p + 20 + 4 * i, 4)? as usize;
+        }
+        Some(c)     /* isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt */
+    };
+    let mut p = 0;
+    let mut tsize = 4;
+    let mut c = counts(p)?;
+    if *data.get(4)? >= b'2' {        /* skip the 32-bit data for the 64-bit one */
+        p += 44 + c[3] * 5 + c[4] * 6 + c[5] + c[2] * 8 + c[1] + c[0];
+        tsize = 8;
+        c = counts(p)?;
+    }
+    let [_, _, _, timecnt, typecnt, charcnt] = c;
+    let times = p + 44;
+    let idxs = times + timecnt * tsize;
+    let ttinfos = idxs + timecnt;
+    let chars = data.get(ttinfos + typecnt * 6..ttinfos + typecnt * 6 + charcnt)?;
+    let mut transitions = vec![];
+    for i in 0..timecnt {
+        transitions.push((be(times + i * tsize, tsize)?, *data.get(idxs + i)? as usize));
+    }
+    let mut types = vec![];
+    for i in 0..typecnt {
+        let ttinfo = ttinfos + i * 6;
+        let off = be(ttinfo, 4)?;
+        let isdst = *data.get(ttinfo + 4)? != 0;
+        let start = *data.get(ttinfo + 5)? as usize;
+        let abbr = chars.get(start..)?.iter().take_while(|&&b| b != 0).map(|&b| b as char).collect();
+        types.push((off, isdst, abbr));
+    }
+    if types.is_empty() || transitions.iter().any(|&(_, i)| i >= types.len()) {
+        return None;
+    }
+    /* the footer of version 2+, "\n<TZ rule>\n", follows the leap seconds
+       and the standard/wall and UT/local indicators */
+    let mut rule = None;
+    if tsize == 8 {
+        let footer = ttinfos + typecnt * 6 + charcnt + c[2] * 12 + c[1] + c[0];
+        if let Some(footer) = data.get(footer + 1..) {
+            let end = footer.iter().position(|&b| b == b'\n').unwrap_or(footer.len());
+            rule = parseTzRule(&String::from_utf8_lossy(&footer[..end]));
+        }
+    }
+    Some(TzInfo { transitions, types, rule })
+}
+
+// A day of the year in a POSIX TZ rule.
+enum TzDate {
+    Julian(i64),                // "Jn": 1..365, February 29 is never counted
+    Day(i64),                   // "n": 0..365
+    Month(i64, i64, i64),       // "Mm.w.d": day d (0 is Sunday) of week w (5 is the last one) of month m
+}
+
+impl TzDate {
+    // The day of year `y`, in days since the epoch.
+    fn day(&self, y: i64) -> i64 {
+        let jan1 = daysFromCivil(y, 1, 1);
+        match *self {
+            TzDate::Julian(n) => {
+                let leap = daysFromCivil(y + 1, 1, 1) - jan1 == 366;
+                jan1 + n - 1 + i64::from(leap && n >= 60)
+            },
+            TzDate::Day(n) => jan1 + n,
+            TzDate::Month(m, w, d) => {
+                let first = daysFromCivil(y, m, 1);
+                let next = if m == 12 { daysFromCivil(y + 1, 1, 1) } else { daysFromCivil(y, m + 1, 1) };
+                let mut day = first + (d - (first + 4)).rem_euclid(7) + (w - 1) * 7;
+                while day >= next {
+                    day -= 7;           /* no fifth such day in the month */
+                }
+                day
+            },
+        }
+    }
+}
+
+// A POSIX TZ rule like "EST5EDT,M3.2.0,M11.1.0": the standard time and
+// optionally a daylight saving time, with the local times of the changes.
+struct TzRule {
+    std: (i64, bool, String),
+    dst: Option<((i64, bool, String), (TzDate, i64), (TzDate, i64))>,
+}
+
+impl TzRule {
+    fn lookup(&self, t: i64) -> (i64, bool, String) {
+        let (dst, (start, start_time), (end, end_time)) = match &self.dst {
+            Some(dst) => dst,
+            None => return self.std.clone(),
+        };
+        let (year, _, _) = civilFromDays((t + self.std.0).div_euclid(86400));
+        /* the change to DST is given in standard time and back in DST */
+        let start = start.day(year) * 86400 + start_time - self.std.0;
+        let end = end.day(year) * 86400 + end_time - dst.0;
+        let isdst = if start <= end {
+            start <= t && t < end
+        } else {
+            !(end <= t && t < start)    /* southern hemisphere */
+        };
+        if isdst { dst.clone() } else { self.std.clone() }
+    }
+}
+
+fn parseTzRule(s: &str) -> Option<TzRule> {
+    let mut p = TzRuleParser { s: s.as_bytes(), pos: 0 };
+    let name = p.name()?;
+    let std = (-p.time()?, false, name);
+    if p.pos == p.s.len() {
+        return Some(TzRule { std, dst: None });
+    }
+    let name = p.name()?;
+    let off = match p.peek() {
+        Some(b',') | None => std.0 + 3600,  /* one hour ahead by default */
+        _ => -p.time()?,
+    };
+    let (start, end) = if p.eat(b',') {
+        let start = p.date()?;
+        if !p.eat(b',') {
+            return None;
+        }
+        (start, p.date()?)
+    } else {
+        /* no rule: the US one, like glibc */
+        ((TzDate::Month(3, 2, 0), 7200), (TzDate::Month(11, 1, 0), 7200))
+    };
+    if p.pos != p.s.len() {
+        return None;
+    }
+    Some(TzRule { std, dst: Some(((off, true, name), start, end)) })
+}
+
+struct TzRuleParser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl TzRuleParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let found = self.peek() == Some(b);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.pos]).ok()?.parse().ok()
+    }
+
+    // An abbreviation: at least three letters, or "<...>" for any of
+    // letters, digits, '+' and '-'.
+    fn name(&mut self) -> Option<String> {
+        let start = self.pos;
+        let name = if self.eat(b'<') {
+            while self.peek().is_some_and(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'-') {
+                self.pos += 1;
+            }
+            let name = &self.s[start + 1..self.pos];
+            if !self.eat(b'>') {
+                return None;
+            }
+            name
+        } else {
+            while self.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+                self.pos += 1;
+            }
+            &self.s[start..self.pos]
+        };
+        if name.len() < 3 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(name).into_owned())
+    }
+
+    // "[+-]hh[:mm[:ss]]", in seconds.
+    fn time(&mut self) -> Option<i64> {
+        let sign = if self.eat(b'-') { -1 } else { self.eat(b'+'); 1 };
+        let mut secs = self.number()? * 3600;
+        if self.eat(b':') {
+            secs += self.number()? * 60;
+            if self.eat(b':') {
+                secs += self.number()?;
+            }
+        }
+        Some(sign * secs)
+    }
+
+    // "Jn", "n" or "Mm.w.d", then the local time of the change, "/time"
+    // (2:00 by default).
+    fn date(&mut self) -> Option<(TzDate, i64)> {
+        let date = if self.eat(b'J') {
+            TzDate::Julian(self.number().filter(|n| (1..=365).contains(n))?)
+        } else if self.eat(b'M') {
+            let m = self.number().filter(|m| (1..=12).contains(m))?;
+            let w = if self.eat(b'.') { self.number().filter(|w| (1..=5).contains(w))? } else { return None };
+            let d = if self.eat(b'.') { self.number().filter(|d| (0..=6).contains(d))? } else { return None };
+            TzDate::Month(m, w, d)
+        } else {
+            TzDate::Day(self.number().filter(|n| (0..=365).contains(n))?)
+        };
+        let time = if self.eat(b'/') { self.time()? } else { 7200 };
+        Some((date, time))
+    }
+}
+
+/* }====================================================== */
+
+#[cfg(test)]
+mod tests {
+    use super::{parseTzRule, parseTzif};
+
+    // A TZif v2 file with a single type and no transitions: all the times
+    // are ruled by the footer.
+    fn tzif(footer: &str) -> Vec<u8> {
+        let mut data = vec![];
+        for (off, abbr) in [(0i32, "UTC"), (-18000, "EST")] {
+            data.extend_from_slice(b"TZif2");
+            data.extend_from_slice(&[0; 15]);
+            for count in [0u32, 0, 0, 0, 1, abbr.len() as u32 + 1] {
+                data.extend_from_slice(&count.to_be_bytes());
+            }
+            data.extend_from_slice(&off.to_be_bytes());
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(abbr.as_bytes());
+            data.push(0);
+        }
+        data.extend_from_slice(format!("\n{}\n", footer).as_bytes());
+        data
+    }
+
+    #[test]
+    fn test_tzif_footer() {
+        let tz = parseTzif(&tzif("EST5EDT,M3.2.0,M11.1.0")).unwrap();
+        assert_eq!(tz.lookup(2224828800), (-14400, true, String::from("EDT")));    /* 2040-07-02 */
+        assert_eq!(tz.lookup(2240000000), (-18000, false, String::from("EST")));
+        /* the changes happen at 2:00 local time */
+        assert_eq!(tz.lookup(1710054000 - 1).0, -18000);    /* 2024-03-10 07:00 UTC */
+        assert_eq!(tz.lookup(1710054000).0, -14400);
+        assert_eq!(tz.lookup(1730613600 - 1).0, -14400);    /* 2024-11-03 06:00 UTC */
+        assert_eq!(tz.lookup(1730613600).0, -18000);
+
+        let tz = parseTzif(&tzif("")).unwrap();
+        assert_eq!(tz.lookup(2224828800), (-18000, false, String::from("EST")));
+    }
+
+    #[test]
+    fn test_tz_rules() {
+        let sydney = parseTzRule("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.lookup(1704067200), (39600, true, String::from("AEDT")));   /* January */
+        assert_eq!(sydney.lookup(1719792000), (36000, false, String::from("AEST")));  /* July */
+        let santiago = parseTzRule("<-04>4<-03>,M9.1.6/24,M4.1.6/24").unwrap();
+        assert_eq!(santiago.lookup(1704067200), (-10800, true, String::from("-03")));
+        let kolkata = parseTzRule("IST-5:30").unwrap();
+        assert_eq!(kolkata.lookup(0), (19800, false, String::from("IST")));
+        let julian = parseTzRule("XST3XDT,J60/0,300").unwrap();   /* March 1st to October 27th/28th */
+        assert_eq!(julian.lookup(1709262000 - 1).1, false);      /* 2024-03-01 03:00 UTC */
+        assert_eq!(julian.lookup(1709262000).1, true);
+        for bad in ["", "E5", "EST", "EST5EDT,M3.2.0", "EST5EDT,M13.1.0,M11.1.0", "<EST5"] {
+            assert!(parseTzRule(bad).is_none(), "{}", bad);
+        }
+    }
+}
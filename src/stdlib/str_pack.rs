@@ -0,0 +1,334 @@
+use crate::api::{lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+use crate::state::lua_state::LuaState;
+use super::lib_string::{posRelat, pushBytes};
+
+// `string.pack`, `string.unpack` and `string.packsize`, ported from
+// `lstrlib.c` for a 64-bit little-endian host.
+
+const LUAL_PACKPADBYTE: u8 = 0x00;      // value used for padding
+const MAXINTSIZE: usize = 16;           // maximum size for the binary representation of an integer
+const NB: usize = 8;                    // number of bits in a byte
+const MC: u64 = (1 << NB) - 1;          // mask for one byte
+const SZINT: usize = 8;                 // size of a lua integer
+const MAXALIGN: usize = 8;
+const MAXSIZE: usize = i32::MAX as usize;   // limit for the size of a result
+
+#[derive(Clone, Copy, PartialEq)]
+enum KOption {
+    Kint,           // signed integers
+    Kuint,          // unsigned integers
+    Kfloat,         // floating-point numbers
+    Kchar,          // fixed-length strings
+    Kstring,        // strings with prefixed length
+    Kzstr,          // zero-terminated strings
+    Kpadding,       // padding
+    Kpaddalign,     // padding for alignment
+    Knop,           // no-op (configuration or spaces)
+}
+
+// Options read so far from a format string.
+struct Header {
+    fmt: Vec<u8>,
+    pos: usize,
+    islittle: bool,
+    maxalign: usize,
+}
+
+impl Header {
+    fn new(fmt: Vec<u8>) -> Self {
+        Header { fmt, pos: 0, islittle: true, maxalign: 1 }
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.fmt.len()
+    }
+
+    fn peek(&self) -> u8 {
+        self.fmt.get(self.pos).copied().unwrap_or(0)
+    }
+
+    fn getNum(&mut self, df: usize) -> usize {
+        if !self.peek().is_ascii_digit() {      /* no number? */
+            return df;          /* return default value */
+        }
+        let mut a = 0;
+        loop {
+            a = a * 10 + (self.peek() - b'0') as usize;
+            self.pos += 1;
+            if !(self.peek().is_ascii_digit() && a <= (MAXSIZE - 9) / 10) {
+                return a;
+            }
+        }
+    }
+
+    // Reads an integer size, checking that it is inside the limits.
+    fn getNumLimit(&mut self, ls: &mut LuaState, df: usize) -> usize {
+        let sz = self.getNum(df);
+        if sz > MAXINTSIZE || sz == 0 {
+            return ls.Error2(format!("integral size ({}) out of limits [1,{}]", sz, MAXINTSIZE)) as usize;
+        }
+        sz
+    }
+
+    // Reads the next option, returns it with its size.
+    fn getOption(&mut self, ls: &mut LuaState) -> (KOption, usize) {
+        let opt = self.peek();
+        self.pos += 1;
+        match opt {
+            b'b' => (KOption::Kint, 1),
+            b'B' => (KOption::Kuint, 1),
+            b'h' => (KOption::Kint, 2),
+            b'H' => (KOption::Kuint, 2),
+            b'l' | b'j' => (KOption::Kint, 8),
+            b'L' | b'J' | b'T' => (KOption::Kuint, 8),
+            b'f' => (KOption::Kfloat, 4),
+            b'd' | b'n' => (KOption::Kfloat, 8),
+            b'i' => (KOption::Kint, self.getNumLimit(ls, 4)),
+            b'I' => (KOption::Kuint, self.getNumLimit(ls, 4)),
+            b's' => (KOption::Kstring, self.getNumLimit(ls, 8)),
+            b'c' => {
+                if !self.peek().is_ascii_digit() {
+                    ls.Error2(String::from("missing size for format option 'c'"));
+                }
+                (KOption::Kchar, self.getNum(0))
+            },
+            b'z' => (KOption::Kzstr, 0),
+            b'x' => (KOption::Kpadding, 1),
+            b'X' => (KOption::Kpaddalign, 0),
+            b' ' => (KOption::Knop, 0),
+            b'<' | b'=' => {
+                self.islittle = true;
+                (KOption::Knop, 0)
+            },
+            b'>' => {
+                self.islittle = false;
+                (KOption::Knop, 0)
+            },
+            b'!' => {
+                self.maxalign = self.getNumLimit(ls, MAXALIGN);
+                (KOption::Knop, 0)
+            },
+            _ => {
+                ls.Error2(format!("invalid format option '{}'", opt as char));
+                (KOption::Knop, 0)
+            },
+        }
+    }
+
+    // Reads the next option and computes how many bytes of padding align
+    // it, given the `totalsize` used so far. Returns (option, size, padding).
+    fn getDetails(&mut self, ls: &mut LuaState, totalsize: usize) -> (KOption, usize, usize) {
+        let (opt, size) = self.getOption(ls);
+        let mut align = size;   /* usually, alignment follows size */
+        if opt == KOption::Kpaddalign {     /* 'X' gets alignment from following option */
+            if self.done() {
+                ls.ArgError(1, "invalid next option for option 'X'");
+            }
+            let (next, next_size) = self.getOption(ls);
+            align = next_size;
+            if next == KOption::Kchar || align == 0 {
+                ls.ArgError(1, "invalid next option for option 'X'");
+            }
+        }
+        if align <= 1 || opt == KOption::Kchar {    /* need no alignment? */
+            return (opt, size, 0);
+        }
+        if align > self.maxalign {      /* enforce maximum alignment */
+            align = self.maxalign;
+        }
+        if align & (align - 1) != 0 {   /* is 'align' not a power of 2? */
+            ls.ArgError(1, "format asks for alignment not power of 2");
+        }
+        (opt, size, (align - (totalsize & (align - 1))) & (align - 1))
+    }
+}
+
+fn packInt(buf: &mut Vec<u8>, n: u64, islittle: bool, size: usize, neg: bool) {
+    let mut bytes = vec![0u8; size];
+    let mut n = n;
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = if i < SZINT {
+            (n & MC) as u8
+        } else if neg {
+            MC as u8            /* negative numbers need sign extension */
+        } else {
+            0
+        };
+        n = if i + 1 < SZINT { n >> NB } else { 0 };
+    }
+    if !islittle {
+        bytes.reverse();
+    }
+    buf.extend_from_slice(&bytes);
+}
+
+fn unpackInt(ls: &mut LuaState, bytes: &[u8], islittle: bool, issigned: bool) -> i64 {
+    let size = bytes.len();
+    let byte = |i: usize| if islittle { bytes[i] } else { bytes[size - 1 - i] };
+    let limit = size.min(SZINT);
+    let mut res: u64 = 0;
+    for i in (0..limit).rev() {
+        res = (res << NB) | byte(i) as u64;
+    }
+    if size < SZINT {           /* real size smaller than lua integer? */
+        if issigned {           /* needs sign extension? */
+            let mask = 1u64 << (size * NB - 1);
+            res = (res ^ mask).wrapping_sub(mask);
+        }
+    } else if size > SZINT {    /* must check unread bytes */
+        let mask = if !issigned || (res as i64) >= 0 { 0 } else { MC as u8 };
+        for i in limit..size {
+            if byte(i) != mask {
+                ls.Error2(format!("{}-byte integer does not fit into Lua Integer", size));
+            }
+        }
+    }
+    res as i64
+}
+
+fn copyWithEndian(bytes: &[u8], islittle: bool) -> Vec<u8> {
+    let mut v = bytes.to_vec();
+    if !islittle {
+        v.reverse();
+    }
+    v
+}
+
+// string.pack (fmt, v1, v2, ...)
+pub fn strPack(ls: &mut LuaState) -> i32 {
+    let mut h = Header::new(ls.CheckBytes(1));
+    let mut buf: Vec<u8> = vec![];
+    let mut arg = 1;
+    let mut totalsize = 0;
+    while !h.done() {
+        let (opt, size, ntoalign) = h.getDetails(ls, totalsize);
+        totalsize += ntoalign + size;
+        buf.resize(buf.len() + ntoalign, LUAL_PACKPADBYTE);     /* fill alignment */
+        arg += 1;
+        match opt {
+            KOption::Kint => {
+                let n = ls.CheckInteger(arg);
+                if size < SZINT {       /* need overflow check? */
+                    let lim = 1i64 << (size * NB - 1);
+                    ls.ArgCheck(-lim <= n && n < lim, arg, "integer overflow");
+                }
+                packInt(&mut buf, n as u64, h.islittle, size, n < 0);
+            },
+            KOption::Kuint => {
+                let n = ls.CheckInteger(arg);
+                if size < SZINT {       /* need overflow check? */
+                    ls.ArgCheck((n as u64) < (1u64 << (size * NB)), arg, "unsigned overflow");
+                }
+                packInt(&mut buf, n as u64, h.islittle, size, false);
+            },
+            KOption::Kfloat => {
+                let n = ls.CheckNumber(arg);
+                if size == 4 {
+                    buf.extend(copyWithEndian(&(n as f32).to_le_bytes(), h.islittle));
+                } else {
+                    buf.extend(copyWithEndian(&n.to_le_bytes(), h.islittle));
+                }
+            },
+            KOption::Kchar => {         /* fixed-size string */
+                let s = ls.CheckBytes(arg);
+                ls.ArgCheck(s.len() <= size, arg, "string longer than given size");
+                buf.extend_from_slice(&s);
+                buf.resize(buf.len() + size - s.len(), LUAL_PACKPADBYTE);   /* pad extra space */
+            },
+            KOption::Kstring => {       /* strings with length count */
+                let s = ls.CheckBytes(arg);
+                ls.ArgCheck(size >= SZINT || (s.len() as u64) < (1u64 << (size * NB)),
+                    arg, "string length does not fit in given size");
+                packInt(&mut buf, s.len() as u64, h.islittle, size, false);    /* pack length */
+                buf.extend_from_slice(&s);
+                totalsize += s.len();
+            },
+            KOption::Kzstr => {         /* zero-terminated string */
+                let s = ls.CheckBytes(arg);
+                ls.ArgCheck(!s.contains(&0), arg, "string contains zeros");
+                buf.extend_from_slice(&s);
+                buf.push(0);            /* add zero at the end */
+                totalsize += s.len() + 1;
+            },
+            KOption::Kpadding => {
+                buf.push(LUAL_PACKPADBYTE);
+                arg -= 1;               /* undo increment */
+            },
+            KOption::Kpaddalign | KOption::Knop => arg -= 1,    /* undo increment */
+        }
+    }
+    pushBytes(ls, &buf);
+    1
+}
+
+// string.packsize (fmt)
+pub fn strPackSize(ls: &mut LuaState) -> i32 {
+    let mut h = Header::new(ls.CheckBytes(1));
+    let mut totalsize: usize = 0;       /* accumulate total size of result */
+    while !h.done() {
+        let (opt, size, ntoalign) = h.getDetails(ls, totalsize);
+        let size = size + ntoalign;     /* total space used by option */
+        ls.ArgCheck(totalsize <= MAXSIZE - size, 1, "format result too large");
+        totalsize += size;
+        if opt == KOption::Kstring || opt == KOption::Kzstr {
+            ls.ArgError(1, "variable-length format");
+        }
+    }
+    ls.PushInteger(totalsize as i64);
+    1
+}
+
+// string.unpack (fmt, s [, pos])
+pub fn strUnpack(ls: &mut LuaState) -> i32 {
+    let mut h = Header::new(ls.CheckBytes(1));
+    let data = ls.CheckBytes(2);
+    let ld = data.len();
+    let pos = posRelat(ls.OptInteger(3, 1), ld) - 1;
+    ls.ArgCheck(pos >= 0 && pos as usize <= ld, 3, "initial position out of string");
+    let mut pos = pos as usize;
+    let mut n = 0;              /* number of results */
+    while !h.done() {
+        let (opt, size, ntoalign) = h.getDetails(ls, pos);
+        if ntoalign + size > ld - pos {
+            ls.ArgError(2, "data string too short");
+        }
+        pos += ntoalign;        /* skip alignment */
+        ls.CheckStack(2);       /* stack space for item + next position */
+        n += 1;
+        match opt {
+            KOption::Kint | KOption::Kuint => {
+                let res = unpackInt(ls, &data[pos..pos + size], h.islittle, opt == KOption::Kint);
+                ls.PushInteger(res);
+            },
+            KOption::Kfloat => {
+                let bytes = copyWithEndian(&data[pos..pos + size], h.islittle);
+                let num = if size == 4 {
+                    f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+                } else {
+                    let mut b = [0u8; 8];
+                    b.copy_from_slice(&bytes);
+                    f64::from_le_bytes(b)
+                };
+                ls.PushNumber(num);
+            },
+            KOption::Kchar => pushBytes(ls, &data[pos..pos + size]),
+            KOption::Kstring => {
+                let len = unpackInt(ls, &data[pos..pos + size], h.islittle, false) as u64 as usize;
+                ls.ArgCheck(len <= ld - pos - size, 2, "data string too short");
+                pushBytes(ls, &data[pos + size..pos + size + len]);
+                pos += len;     /* skip string */
+            },
+            KOption::Kzstr => {
+                let len = data[pos..].iter().position(|b| *b == 0);
+                ls.ArgCheck(len.is_some(), 2, "unfinished string for format 'z'");
+                let len = len.unwrap_or(0);
+                pushBytes(ls, &data[pos..pos + len]);
+                pos += len + 1;         /* skip string plus final '\0' */
+            },
+            KOption::Kpaddalign | KOption::Kpadding | KOption::Knop => n -= 1,  /* undo increment */
+        }
+        pos += size;
+    }
+    ls.PushInteger(pos as i64 + 1);     /* next position */
+    n + 1
+}
@@ -0,0 +1,430 @@
+// Lua patterns, ported from `lstrlib.c`. They work on the bytes of the
+// subject and the pattern; errors are returned as the message to raise.
+
+pub const LUA_MAXCAPTURES: usize = 32;
+const CAP_UNFINISHED: isize = -1;
+const CAP_POSITION: isize = -2;
+const MAXCCALLS: usize = 200;       // maximum recursion depth of `doMatch`
+const L_ESC: u8 = b'%';
+const SPECIALS: &[u8] = b"^$*+?.([%-";
+
+pub type MatchResult<T> = Result<T, String>;
+
+// A capture of a successful match.
+pub enum Capture {
+    Str(usize, usize),      // the range of the subject it captured
+    Position(i64),          // position capture `()`, 1-based
+}
+
+pub struct MatchState<'a> {
+    src: &'a [u8],
+    pat: &'a [u8],
+    level: usize,           // total number of captures (finished or unfinished)
+    capture: [(usize, isize); LUA_MAXCAPTURES],     // (init, len) of each capture
+    matchdepth: usize,
+}
+
+// Checks whether a pattern has no special characters, so that it can be
+// searched for as a plain string.
+pub fn noSpecials(p: &[u8]) -> bool {
+    !p.iter().any(|c| SPECIALS.contains(c))
+}
+
+// Finds `p` in `s`, returns the offset where it starts.
+pub fn findPlain(s: &[u8], p: &[u8]) -> Option<usize> {
+    if p.is_empty() {
+        return Some(0);         /* empty strings are everywhere */
+    }
+    if p.len() > s.len() {
+        return None;
+    }
+    s.windows(p.len()).position(|w| w == p)
+}
+
+impl<'a> MatchState<'a> {
+    pub fn new(src: &'a [u8], pat: &'a [u8]) -> Self {
+        MatchState { src, pat, level: 0, capture: [(0, 0); LUA_MAXCAPTURES], matchdepth: MAXCCALLS }
+    }
+
+    // Prepares the state for a new match attempt.
+    pub fn reprep(&mut self) {
+        self.level = 0;
+        self.matchdepth = MAXCCALLS;
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    // Tries to match the pattern from `p` against the subject from `s`,
+    // returns where the match ends.
+    pub fn doMatch(&mut self, s: usize, p: usize) -> MatchResult<Option<usize>> {
+        if self.matchdepth == 0 {
+            return Err(String::from("pattern too complex"));
+        }
+        self.matchdepth -= 1;
+        let (mut s, mut p) = (s, p);
+        let res = loop {
+            if p == self.pat.len() {    /* end of pattern? */
+                break Some(s);
+            }
+            match self.pat[p] {
+                b'(' => {               /* start capture */
+                    break if self.patAt(p + 1) == b')' {    /* position capture? */
+                        self.startCapture(s, p + 2, CAP_POSITION)?
+                    } else {
+                        self.startCapture(s, p + 1, CAP_UNFINISHED)?
+                    };
+                },
+                b')' => break self.endCapture(s, p + 1)?,   /* end capture */
+                b'$' if p + 1 == self.pat.len() => {    /* is the '$' the last char in pattern? */
+                    break if s == self.src.len() { Some(s) } else { None };
+                },
+                L_ESC if self.patAt(p + 1) == b'b' => {     /* balanced string? */
+                    match self.matchBalance(s, p + 2)? {
+                        Some(e) => {
+                            s = e;
+                            p += 4;
+                            continue;
+                        },
+                        None => break None,
+                    }
+                },
+                L_ESC if self.patAt(p + 1) == b'f' => {     /* frontier? */
+                    p += 2;
+                    if self.patAt(p) != b'[' {
+                        return Err(String::from("missing '[' after '%f' in pattern"));
+                    }
+                    let ep = self.classEnd(p)?;     /* points to what is next */
+                    let previous = if s == 0 { 0 } else { self.src[s - 1] };
+                    if !self.matchBracketClass(previous, p, ep - 1)
+                        && self.matchBracketClass(self.srcAt(s), p, ep - 1) {
+                        p = ep;
+                        continue;
+                    }
+                    break None;         /* match failed */
+                },
+                L_ESC if self.patAt(p + 1).is_ascii_digit() => {  /* capture results (%0-%9)? */
+                    match self.matchCapture(s, self.patAt(p + 1))? {
+                        Some(e) => {
+                            s = e;
+                            p += 2;
+                            continue;
+                        },
+                        None => break None,
+                    }
+                },
+                _ => {                  /* pattern class plus optional suffix */
+                    let ep = self.classEnd(p)?;     /* points to optional suffix */
+                    let suffix = self.patAt(ep);
+                    if !self.singleMatch(s, p, ep) {    /* does not match at least once? */
+                        if suffix == b'*' || suffix == b'?' || suffix == b'-' {  /* accept empty? */
+                            p = ep + 1;
+                            continue;
+                        }
+                        break None;     /* '+' or no suffix */
+                    }
+                    match suffix {      /* matched once, handle optional suffix */
+                        b'?' => match self.doMatch(s + 1, ep + 1)? {
+                            Some(res) => break Some(res),
+                            None => {
+                                p = ep + 1;
+                                continue;
+                            },
+                        },
+                        b'+' => break self.maxExpand(s + 1, p, ep)?,     /* 1 match already done */
+                        b'*' => break self.maxExpand(s, p, ep)?,
+                        b'-' => break self.minExpand(s, p, ep)?,
+                        _ => {          /* no suffix */
+                            s += 1;
+                            p = ep;
+                            continue;
+                        },
+                    }
+                },
+            }
+        };
+        self.matchdepth += 1;
+        Ok(res)
+    }
+
+    // Returns capture `i` of a match of the subject range `s..e`; the
+    // whole match counts as capture 0 if the pattern has no captures.
+    pub fn getCapture(&self, i: usize, s: usize, e: usize) -> MatchResult<Capture> {
+        if i >= self.level {
+            if i == 0 {
+                Ok(Capture::Str(s, e))  /* add whole match */
+            } else {
+                Err(format!("invalid capture index %{}", i + 1))
+            }
+        } else {
+            let (init, len) = self.capture[i];
+            match len {
+                CAP_UNFINISHED => Err(String::from("unfinished capture")),
+                CAP_POSITION => Ok(Capture::Position(init as i64 + 1)),
+                _ => Ok(Capture::Str(init, init + len as usize)),
+            }
+        }
+    }
+
+    fn patAt(&self, p: usize) -> u8 {
+        self.pat.get(p).copied().unwrap_or(0)
+    }
+
+    fn srcAt(&self, s: usize) -> u8 {
+        self.src.get(s).copied().unwrap_or(0)
+    }
+
+    fn classEnd(&self, p: usize) -> MatchResult<usize> {
+        let mut p = p;
+        let c = self.pat[p];
+        p += 1;
+        if c == L_ESC {
+            if p >= self.pat.len() {
+                return Err(String::from("malformed pattern (ends with '%')"));
+            }
+            Ok(p + 1)
+        } else if c == b'[' {
+            if self.patAt(p) == b'^' {
+                p += 1;
+            }
+            loop {                      /* look for a ']' */
+                if p >= self.pat.len() {
+                    return Err(String::from("malformed pattern (missing ']')"));
+                }
+                let c = self.pat[p];
+                p += 1;
+                if c == L_ESC && p < self.pat.len() {
+                    p += 1;             /* skip escapes (e.g. '%]') */
+                }
+                if self.patAt(p) == b']' {
+                    break;
+                }
+            }
+            Ok(p + 1)
+        } else {
+            Ok(p)
+        }
+    }
+
+    // `p` is the '[' of the class and `ec` its ']'.
+    fn matchBracketClass(&self, c: u8, p: usize, ec: usize) -> bool {
+        let mut p = p;
+        let mut sig = true;
+        if self.patAt(p + 1) == b'^' {
+            sig = false;
+            p += 1;             /* skip the '^' */
+        }
+        loop {
+            p += 1;
+            if p >= ec {
+                break;
+            }
+            if self.pat[p] == L_ESC {
+                p += 1;
+                if matchClass(c, self.patAt(p)) {
+                    return sig;
+                }
+            } else if self.patAt(p + 1) == b'-' && p + 2 < ec {
+                p += 2;
+                if self.pat[p - 2] <= c && c <= self.pat[p] {
+                    return sig;
+                }
+            } else if self.pat[p] == c {
+                return sig;
+            }
+        }
+        !sig
+    }
+
+    fn singleMatch(&self, s: usize, p: usize, ep: usize) -> bool {
+        if s >= self.src.len() {
+            return false;
+        }
+        let c = self.src[s];
+        match self.pat[p] {
+            b'.' => true,       /* matches any char */
+            L_ESC => matchClass(c, self.patAt(p + 1)),
+            b'[' => self.matchBracketClass(c, p, ep - 1),
+            pc => pc == c,
+        }
+    }
+
+    fn matchBalance(&self, s: usize, p: usize) -> MatchResult<Option<usize>> {
+        if p + 1 >= self.pat.len() {
+            return Err(String::from("malformed pattern (missing arguments to '%b')"));
+        }
+        if s >= self.src.len() || self.src[s] != self.pat[p] {
+            return Ok(None);
+        }
+        let (b, e) = (self.pat[p], self.pat[p + 1]);
+        let mut cont = 1;
+        let mut s = s + 1;
+        while s < self.src.len() {
+            let c = self.src[s];
+            if c == e {
+                cont -= 1;
+                if cont == 0 {
+                    return Ok(Some(s + 1));
+                }
+            } else if c == b {
+                cont += 1;
+            }
+            s += 1;
+        }
+        Ok(None)                /* string ends out of balance */
+    }
+
+    fn maxExpand(&mut self, s: usize, p: usize, ep: usize) -> MatchResult<Option<usize>> {
+        let mut i = 0;          /* counts maximum expand for item */
+        while self.singleMatch(s + i, p, ep) {
+            i += 1;
+        }
+        loop {                  /* keeps trying to match with the maximum repetitions */
+            if let Some(res) = self.doMatch(s + i, ep + 1)? {
+                return Ok(Some(res));
+            }
+            if i == 0 {
+                return Ok(None);
+            }
+            i -= 1;             /* else didn't match; reduce 1 repetition to try again */
+        }
+    }
+
+    fn minExpand(&mut self, s: usize, p: usize, ep: usize) -> MatchResult<Option<usize>> {
+        let mut s = s;
+        loop {
+            if let Some(res) = self.doMatch(s, ep + 1)? {
+                return Ok(Some(res));
+            } else if self.singleMatch(s, p, ep) {
+                s += 1;         /* try with one more repetition */
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn startCapture(&mut self, s: usize, p: usize, what: isize) -> MatchResult<Option<usize>> {
+        let level = self.level;
+        if level >= LUA_MAXCAPTURES {
+            return Err(String::from("too many captures"));
+        }
+        self.capture[level] = (s, what);
+        self.level = level + 1;
+        let res = self.doMatch(s, p)?;
+        if res.is_none() {      /* match failed? */
+            self.level -= 1;    /* undo capture */
+        }
+        Ok(res)
+    }
+
+    fn endCapture(&mut self, s: usize, p: usize) -> MatchResult<Option<usize>> {
+        let l = self.captureToClose()?;
+        self.capture[l].1 = (s - self.capture[l].0) as isize;   /* close capture */
+        let res = self.doMatch(s, p)?;
+        if res.is_none() {      /* match failed? */
+            self.capture[l].1 = CAP_UNFINISHED;     /* undo capture */
+        }
+        Ok(res)
+    }
+
+    fn captureToClose(&self) -> MatchResult<usize> {
+        (0..self.level).rev().find(|&l| self.capture[l].1 == CAP_UNFINISHED)
+            .ok_or_else(|| String::from("invalid pattern capture"))
+    }
+
+    fn matchCapture(&self, s: usize, l: u8) -> MatchResult<Option<usize>> {
+        let l = self.checkCapture(l)?;
+        let (init, len) = self.capture[l];
+        if len < 0 {            /* a position capture never matches */
+            return Ok(None);
+        }
+        let len = len as usize;
+        if self.src.len() - s >= len && self.src[init..init + len] == self.src[s..s + len] {
+            Ok(Some(s + len))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn checkCapture(&self, l: u8) -> MatchResult<usize> {
+        let l = l as isize - b'1' as isize;
+        if l < 0 || l as usize >= self.level || self.capture[l as usize].1 == CAP_UNFINISHED {
+            return Err(format!("invalid capture index %{}", l + 1));
+        }
+        Ok(l as usize)
+    }
+}
+
+fn matchClass(c: u8, cl: u8) -> bool {
+    let res = match cl.to_ascii_lowercase() {
+        b'a' => c.is_ascii_alphabetic(),
+        b'c' => c.is_ascii_control(),
+        b'd' => c.is_ascii_digit(),
+        b'g' => c.is_ascii_graphic(),
+        b'l' => c.is_ascii_lowercase(),
+        b'p' => c.is_ascii_punctuation(),
+        b's' => c.is_ascii_whitespace() || c == 0x0b,
+        b'u' => c.is_ascii_uppercase(),
+        b'w' => c.is_ascii_alphanumeric(),
+        b'x' => c.is_ascii_hexdigit(),
+        _ => return cl == c,
+    };
+    if cl.is_ascii_uppercase() {
+        !res
+    } else {
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capture, MatchState};
+
+    // Returns the whole match and the captures of the first match in `s`.
+    fn find(s: &str, p: &str) -> Option<Vec<String>> {
+        let (src, pat) = (s.as_bytes(), p.as_bytes());
+        let mut ms = MatchState::new(src, pat);
+        for init in 0..=src.len() {
+            ms.reprep();
+            if let Some(e) = ms.doMatch(init, 0).unwrap() {
+                let mut caps = vec![String::from(&s[init..e])];
+                for i in 0..ms.level() {
+                    caps.push(match ms.getCapture(i, init, e).unwrap() {
+                        Capture::Str(b, e) => String::from(&s[b..e]),
+                        Capture::Position(n) => n.to_string(),
+                    });
+                }
+                return Some(caps);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(find("hello world", "o w"), Some(vec![String::from("o w")]));
+        assert_eq!(find("key = value", "(%w+)%s*=%s*(%w+)").unwrap()[1..], ["key", "value"]);
+        assert_eq!(find("f(a(b)c)d", "%b()").unwrap()[0], "(a(b)c)");
+        assert_eq!(find("THE (quick) fox", "%f[%a]%a+%f[%A]").unwrap()[0], "THE");
+        assert_eq!(find("abc", "()b()").unwrap()[1..], ["2", "3"]);
+        assert_eq!(find("x = 'a\"b'", "([\"'])(.-)%1").unwrap()[2], "a\"b");
+        assert_eq!(find("aaab", "a-b").unwrap()[0], "aaab");
+        assert_eq!(find("[]", "[]]").unwrap()[0], "]");
+        assert_eq!(find("2024-01-02", "(%d+)-(%d%d)").unwrap()[1..], ["2024", "01"]);
+        assert!(find("abc", "[^%l]").is_none());
+        assert!(find("abc", "x*$").is_some());
+    }
+
+    #[test]
+    fn test_malformed_patterns() {
+        let errs = [("%", "malformed pattern (ends with '%')"), ("[a", "malformed pattern (missing ']')"),
+            ("(a", "unfinished capture"), ("a)", "invalid pattern capture"), ("%1", "invalid capture index %1"),
+            ("%b", "malformed pattern (missing arguments to '%b')"), ("%fa", "missing '[' after '%f' in pattern")];
+        for (p, msg) in errs.iter() {
+            let mut ms = MatchState::new(b"a", p.as_bytes());
+            let res = ms.doMatch(0, 0).and_then(|e| ms.getCapture(0, 0, e.unwrap_or(0)).map(|_| ()));
+            assert_eq!(res.err().as_deref(), Some(*msg), "{}", p);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::api::{consts::*, lua_auxlib::LuaAuxLib, lua_state::LuaAPI};
+use crate::state::lua_state::LuaState;
+
+// Fixtures of the library tests: chunks are run as "=test", so errors
+// read like "test:1: ...".
+
+// Runs `src` in a new state with all the standard libraries, returns its
+// first result or the error message.
+pub fn run(src: &str) -> Result<String, String> {
+    let mut ls = LuaState::new();
+    ls.OpenLibs();
+    run_in(&mut ls, src, "=test")
+}
+
+// Runs `src` as the chunk `chunk_name` in a state prepared by the test.
+// The result must be a string or nothing (""): numbers are not converted
+// here, so that a test sees what the chunk really returns.
+pub fn run_in(ls: &mut LuaState, src: &str, chunk_name: &str) -> Result<String, String> {
+    ls.Load(src.as_bytes().to_vec(), chunk_name, "bt");
+    let status = ls.PCall(0, 1, 0);
+    let res = match ls.Type(-1) {
+        LUA_TSTRING => ls.ToString(-1),
+        LUA_TNIL if status == LUA_OK => String::new(),
+        tp if status == LUA_OK => panic!("chunk returned a {}, not a string", ls.TypeName(tp)),
+        _ => ls.ToStringX(-1).unwrap_or_default(),
+    };
+    ls.pop(1);
+    if status == LUA_OK { Ok(res) } else { Err(res) }
+}
+
+// The value of the expression `exp`, which must not fail, as converted by
+// `tostring`.
+pub fn eval(exp: &str) -> String {
+    run(&format!("return tostring({})", exp)).unwrap()
+}
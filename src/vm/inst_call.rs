@@ -1,5 +1,5 @@
 use crate::api::lua_vm::LuaVM;
-use super::instruction::Instruction;
+use super::{instruction::Instruction, opcodes::{OP_CALL, OP_TAILCALL, OP_TFORCALL}};
 
 pub fn closure(i: &Instruction, vm: &mut dyn LuaVM) {
     let (mut a, bx) = i.ABx();
@@ -13,8 +13,21 @@ pub fn call(i: &Instruction, vm: &mut dyn LuaVM) {
     a += 1;
 
     let nArgs = _pushFuncAndArgs(a, b, vm);
-    vm.Call(nArgs, c - 1);
-    _popResults(a, c, vm);
+    if vm.PreCall(nArgs, c - 1) {
+        _popResults(a, c, vm);
+    }
+}
+
+// Completes a call instruction whose callee ran in a frame of its own.
+pub fn finishCall(i: &Instruction, vm: &mut dyn LuaVM) {
+    let (mut a, _, c) = i.ABC();
+    a += 1;
+    match i.Opcode() as u8 {
+        OP_CALL => _popResults(a, c, vm),
+        OP_TAILCALL => _popResults(a, 0, vm),
+        OP_TFORCALL => _popResults(a + 3, c + 1, vm),
+        _ => {},
+    }
 }
 
 fn _popResults(a: i32, c: i32, vm: &mut dyn LuaVM) {
@@ -80,8 +93,9 @@ pub fn tailcall(i: &Instruction, vm: &mut dyn LuaVM) {
     a += 1;
     let c = 0;
     let nArgs = _pushFuncAndArgs(a, b, vm);
-    vm.Call(nArgs, c - 1);
-    _popResults(a, c, vm);
+    if vm.PreCall(nArgs, c - 1) {
+        _popResults(a, c, vm);
+    }
 }
 
 pub fn _self(i: &Instruction, vm: &mut dyn LuaVM) {
@@ -98,8 +112,9 @@ pub fn tForCall(i: &Instruction, vm: &mut dyn LuaVM) {
     let (mut a, _, c) = i.ABC();
     a += 1;
     _pushFuncAndArgs(a, 3, vm);
-    vm.Call(2, c);
-    _popResults(a + 3, c + 1, vm);
+    if vm.PreCall(2, c) {
+        _popResults(a + 3, c + 1, vm);
+    }
 }
 
 pub fn tForLoop(i: &Instruction, vm: &mut dyn LuaVM) {
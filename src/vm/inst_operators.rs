@@ -90,6 +90,17 @@ fn compare(i: &Instruction, vm: &mut dyn LuaVM, op: u8) {
     vm.pop(2);
 }
 
+// Completes a comparison whose metamethod yielded: RK(B), RK(C) and the
+// result of the metamethod are on top of the stack.
+pub fn finishCompare(i: &Instruction, vm: &mut dyn LuaVM) {
+    let (a, _, _) = i.ABC();
+
+    if vm.ToBoolean(-1) != (a != 0) {
+        vm.AddPC(1);
+    }
+    vm.pop(3);
+}
+
 /* logical */
 
 // R(A) := not R(B)
@@ -151,4 +162,15 @@ pub fn concat(i: &Instruction, vm: &mut dyn LuaVM) {
     }
     vm.Concat(n);
     vm.Replace(a);
-}
\ No newline at end of file
+}
+
+// Completes a concatenation whose metamethod yielded: the values left to
+// concatenate are above the registers, the result of the metamethod last.
+pub fn finishConcat(i: &Instruction, vm: &mut dyn LuaVM) {
+    let (mut a, _, _) = i.ABC();
+    a += 1;
+
+    let n = vm.GetTop() - vm.RegisterCount();
+    vm.Concat(n);
+    vm.Replace(a);
+}
@@ -110,4 +110,19 @@ impl Instruction {
             }
         }
     }
+
+    // Completes the instruction after the callee or metamethod it called
+    // yielded and then returned, like `luaV_finishOp`: the result of the
+    // call is on top of the stack.
+    pub fn Finish(&self, vm: &mut dyn LuaVM) {
+        match self.Opcode() as u8 {
+            OP_GETTABUP | OP_GETTABLE | OP_SELF | OP_ADD | OP_SUB | OP_MUL | OP_MOD | OP_POW
+                | OP_DIV | OP_IDIV | OP_BAND | OP_BOR | OP_BXOR | OP_SHL | OP_SHR | OP_UNM
+                | OP_BNOT | OP_LEN => vm.Replace(self.ABC().0 + 1),
+            OP_CONCAT => finishConcat(self, vm),
+            OP_EQ | OP_LT | OP_LE => finishCompare(self, vm),
+            OP_CALL | OP_TAILCALL | OP_TFORCALL => finishCall(self, vm),
+            _ => {},        /* OP_SETTABUP, OP_SETTABLE: nothing to do */
+        }
+    }
 }
\ No newline at end of file